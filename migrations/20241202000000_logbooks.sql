@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS logbooks (
+    user_id INTEGER NOT NULL,
+    wallet_address TEXT NOT NULL,
+    pin BOOLEAN NOT NULL DEFAULT 0,
+    message_id INTEGER,
+    body TEXT NOT NULL DEFAULT '',
+    UNIQUE(user_id, wallet_address)
+);
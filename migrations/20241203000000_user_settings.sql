@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS user_settings (
+    user_id INTEGER PRIMARY KEY,
+    mirror_enabled BOOLEAN NOT NULL DEFAULT 0,
+    mirror_bankroll REAL NOT NULL DEFAULT 1000,
+    mirror_tolerance_pct REAL NOT NULL DEFAULT 0.5
+);
@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS users (
+    user_id INTEGER PRIMARY KEY,
+    last_seen DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    inactivity_warned_at DATETIME
+);
+
+-- Existing users start their inactivity clock from this migration
+INSERT OR IGNORE INTO users (user_id) SELECT DISTINCT user_id FROM tracked_wallets;
+
+-- Wallets soft-removed by the inactivity cleanup keep their row so they can be restored
+ALTER TABLE tracked_wallets ADD COLUMN removed_at DATETIME;
@@ -0,0 +1 @@
+ALTER TABLE user_settings ADD COLUMN margin_alerts BOOLEAN NOT NULL DEFAULT 0;
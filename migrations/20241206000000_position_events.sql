@@ -0,0 +1,16 @@
+CREATE TABLE IF NOT EXISTS position_events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    wallet_address TEXT NOT NULL,
+    coin TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    is_long BOOLEAN NOT NULL,
+    size REAL NOT NULL,
+    size_delta REAL NOT NULL,
+    entry_price REAL NOT NULL,
+    -- Mark price when the change was detected, NULL if it wasn't available
+    mark_price REAL,
+    pnl REAL NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_position_events_wallet_coin ON position_events(wallet_address, coin, created_at);
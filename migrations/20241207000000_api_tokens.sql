@@ -0,0 +1,6 @@
+-- One API token per user, only the SHA-256 hash is stored
+CREATE TABLE IF NOT EXISTS api_tokens (
+    user_id INTEGER PRIMARY KEY,
+    token_hash TEXT NOT NULL UNIQUE,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,2 @@
+ALTER TABLE user_settings ADD COLUMN weekly_suggestions BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE user_settings ADD COLUMN suggestions_sent_at DATETIME;
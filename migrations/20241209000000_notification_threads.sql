@@ -0,0 +1,9 @@
+ALTER TABLE user_settings ADD COLUMN threaded_notifications BOOLEAN NOT NULL DEFAULT 0;
+
+-- Last notification sent per (chat, wallet), new ones reply to it
+CREATE TABLE IF NOT EXISTS notification_threads (
+    user_id INTEGER NOT NULL,
+    wallet_address TEXT NOT NULL,
+    message_id INTEGER NOT NULL,
+    PRIMARY KEY (user_id, wallet_address)
+);
@@ -0,0 +1,16 @@
+-- Non-funding ledger updates (deposits, withdrawals, transfers) of tracked wallets
+CREATE TABLE IF NOT EXISTS ledger_updates (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    wallet_address TEXT NOT NULL,
+    -- Milliseconds since epoch, as reported by Hyperliquid
+    time INTEGER NOT NULL,
+    hash TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    -- Other side of a transfer between Hyperliquid accounts, NULL for deposits/withdrawals
+    counterparty TEXT,
+    incoming BOOLEAN NOT NULL,
+    amount REAL NOT NULL,
+    UNIQUE (wallet_address, hash, kind)
+);
+
+CREATE INDEX IF NOT EXISTS idx_ledger_updates_wallet ON ledger_updates(wallet_address, time);
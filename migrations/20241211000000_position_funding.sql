@@ -0,0 +1,15 @@
+ALTER TABLE tracked_wallets ADD COLUMN collect_funding BOOLEAN NOT NULL DEFAULT 0;
+
+-- Funding accumulated by each open position of wallets with funding collection on
+CREATE TABLE IF NOT EXISTS position_funding (
+    wallet_address TEXT NOT NULL,
+    coin TEXT NOT NULL,
+    -- Milliseconds since epoch the position opened, or when collection started for it
+    opened_at INTEGER NOT NULL,
+    funding REAL NOT NULL DEFAULT 0,
+    -- The position was already open when collection started
+    since_tracking BOOLEAN NOT NULL,
+    -- Time of the last funding payment included, in milliseconds
+    last_time INTEGER NOT NULL,
+    PRIMARY KEY (wallet_address, coin)
+);
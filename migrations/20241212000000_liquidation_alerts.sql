@@ -0,0 +1 @@
+ALTER TABLE tracked_wallets ADD COLUMN liquidation_alerts BOOLEAN NOT NULL DEFAULT 0;
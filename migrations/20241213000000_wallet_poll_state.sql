@@ -0,0 +1,6 @@
+-- When the monitor last processed each wallet, so a restart resumes with the stalest wallets
+CREATE TABLE IF NOT EXISTS wallet_poll_state (
+    wallet_address TEXT PRIMARY KEY,
+    -- Milliseconds since epoch
+    processed_at INTEGER NOT NULL
+);
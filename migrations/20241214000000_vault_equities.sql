@@ -0,0 +1,11 @@
+ALTER TABLE tracked_wallets ADD COLUMN include_vaults BOOLEAN NOT NULL DEFAULT 0;
+
+-- Last known equity of each vault a wallet has deposited into
+CREATE TABLE IF NOT EXISTS vault_equities (
+    wallet_address TEXT NOT NULL,
+    vault_address TEXT NOT NULL,
+    equity REAL NOT NULL,
+    -- Milliseconds since epoch the equity was fetched
+    fetched_at INTEGER NOT NULL,
+    PRIMARY KEY (wallet_address, vault_address)
+);
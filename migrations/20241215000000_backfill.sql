@@ -0,0 +1,20 @@
+-- Events reconstructed from fill history rather than detected by the monitor
+ALTER TABLE position_events ADD COLUMN backfilled BOOLEAN NOT NULL DEFAULT 0;
+
+-- Fill history imports, one row per requested window
+CREATE TABLE IF NOT EXISTS backfill_runs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    wallet_address TEXT NOT NULL,
+    -- Window in milliseconds since epoch, end exclusive
+    start_time INTEGER NOT NULL,
+    end_time INTEGER NOT NULL,
+    -- Fills before this time (ms) have been processed
+    cursor INTEGER NOT NULL,
+    -- Round trips still open at the cursor, as JSON, so an interrupted run can resume
+    open_trips TEXT NOT NULL DEFAULT '{}',
+    fills INTEGER NOT NULL DEFAULT 0,
+    completed BOOLEAN NOT NULL DEFAULT 0,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_backfill_runs_wallet ON backfill_runs(wallet_address);
@@ -0,0 +1,17 @@
+-- Closed positions per wallet, pruned to the most recent ones
+CREATE TABLE IF NOT EXISTS closed_positions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    wallet_address TEXT NOT NULL,
+    coin TEXT NOT NULL,
+    is_long BOOLEAN NOT NULL,
+    size REAL NOT NULL,
+    entry_price REAL NOT NULL,
+    -- Mark price when the close was detected, NULL if it wasn't available
+    exit_price REAL,
+    realized_pnl REAL NOT NULL,
+    -- When the matching open was recorded, NULL if it happened before tracking
+    opened_at DATETIME,
+    closed_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_closed_positions_wallet ON closed_positions(wallet_address, id);
@@ -0,0 +1 @@
+ALTER TABLE tracked_wallets ADD COLUMN muted BOOLEAN NOT NULL DEFAULT 0;
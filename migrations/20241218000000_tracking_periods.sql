@@ -0,0 +1,16 @@
+-- When each user tracked each wallet, so history can show the gaps nobody was watching.
+-- Analytics tables are keyed by wallet address and survive removal, these periods
+-- are what tells tracked and untracked stretches apart.
+CREATE TABLE IF NOT EXISTS tracking_periods (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    wallet_address TEXT NOT NULL,
+    started_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    -- NULL while the wallet is still tracked
+    ended_at DATETIME
+);
+
+CREATE INDEX IF NOT EXISTS idx_tracking_periods_wallet ON tracking_periods(wallet_address, started_at);
+
+INSERT INTO tracking_periods (user_id, wallet_address, started_at, ended_at)
+SELECT user_id, wallet_address, COALESCE(created_at, CURRENT_TIMESTAMP), removed_at FROM tracked_wallets;
@@ -0,0 +1,3 @@
+ALTER TABLE user_settings ADD COLUMN notifications_paused BOOLEAN NOT NULL DEFAULT 0;
+-- Unix timestamp the pause ends at, NULL pauses until /resume
+ALTER TABLE user_settings ADD COLUMN paused_until INTEGER;
@@ -0,0 +1,6 @@
+-- First time the monitor saw each perp in the exchange meta.
+-- NULL for assets that were already listed when the table was first filled.
+CREATE TABLE perp_listings (
+    coin TEXT PRIMARY KEY,
+    listed_at INTEGER
+);
@@ -0,0 +1 @@
+ALTER TABLE user_settings ADD COLUMN compact_notifications BOOLEAN NOT NULL DEFAULT 0;
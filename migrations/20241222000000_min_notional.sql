@@ -0,0 +1 @@
+ALTER TABLE user_settings ADD COLUMN min_notional REAL NOT NULL DEFAULT 0;
@@ -0,0 +1,12 @@
+-- One-shot price alerts, deleted once they fire
+CREATE TABLE IF NOT EXISTS price_alerts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    coin TEXT NOT NULL,
+    -- 1 fires when the mid reaches the price from below, 0 from above
+    above BOOLEAN NOT NULL,
+    price REAL NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_price_alerts_user ON price_alerts(user_id);
@@ -0,0 +1,2 @@
+-- Distance (percent of the mark) to a liquidation price that triggers a warning, 0 turns them off
+ALTER TABLE user_settings ADD COLUMN liquidation_proximity_pct REAL NOT NULL DEFAULT 5;
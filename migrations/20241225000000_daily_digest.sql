@@ -0,0 +1,12 @@
+ALTER TABLE user_settings ADD COLUMN daily_digest BOOLEAN NOT NULL DEFAULT 0;
+-- Minutes after midnight UTC the digest is sent at
+ALTER TABLE user_settings ADD COLUMN digest_minute INTEGER NOT NULL DEFAULT 480;
+ALTER TABLE user_settings ADD COLUMN digest_sent_at DATETIME;
+
+-- Unrealized PnL of each wallet at a user's last digest, for the change since
+CREATE TABLE IF NOT EXISTS digest_snapshots (
+    user_id INTEGER NOT NULL,
+    wallet_address TEXT NOT NULL,
+    unrealized_pnl REAL NOT NULL,
+    PRIMARY KEY (user_id, wallet_address)
+);
@@ -0,0 +1,11 @@
+-- One row per notification delivered to a user, for /stats
+CREATE TABLE IF NOT EXISTS notification_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    -- NULL for notifications not about a wallet, like price alerts
+    wallet_address TEXT,
+    kind TEXT NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_notification_log_user ON notification_log (user_id, created_at);
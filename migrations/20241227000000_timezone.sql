@@ -0,0 +1,2 @@
+-- IANA zone name or fixed offset like "UTC+02:00" that times are shown in
+ALTER TABLE user_settings ADD COLUMN timezone TEXT NOT NULL DEFAULT 'UTC';
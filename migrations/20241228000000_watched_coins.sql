@@ -0,0 +1,7 @@
+-- Coins a user gets a wallet's notifications for, wallets without rows are unfiltered
+CREATE TABLE IF NOT EXISTS watched_coins (
+    user_id INTEGER NOT NULL,
+    wallet_address TEXT NOT NULL,
+    coin TEXT NOT NULL,
+    PRIMARY KEY (user_id, wallet_address, coin)
+);
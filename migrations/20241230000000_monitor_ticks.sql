@@ -0,0 +1,9 @@
+-- API calls the monitor made per tick and how many failed, for the error rate in /globalstats
+CREATE TABLE IF NOT EXISTS monitor_ticks (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    api_calls INTEGER NOT NULL,
+    api_errors INTEGER NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_monitor_ticks_created ON monitor_ticks (created_at);
@@ -0,0 +1,6 @@
+-- Coins a user never gets notifications for, across all their wallets
+CREATE TABLE IF NOT EXISTS ignored_coins (
+    user_id INTEGER NOT NULL,
+    coin TEXT NOT NULL COLLATE NOCASE,
+    PRIMARY KEY (user_id, coin)
+);
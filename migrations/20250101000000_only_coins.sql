@@ -0,0 +1,6 @@
+-- Coins a user in whitelist mode gets notifications for, users without rows get every coin
+CREATE TABLE IF NOT EXISTS only_coins (
+    user_id INTEGER NOT NULL,
+    coin TEXT NOT NULL COLLATE NOCASE,
+    PRIMARY KEY (user_id, coin)
+);
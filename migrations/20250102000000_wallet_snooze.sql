@@ -0,0 +1,2 @@
+-- Unix seconds until which a wallet's notifications are suppressed, NULL when not snoozed
+ALTER TABLE tracked_wallets ADD COLUMN snoozed_until INTEGER;
@@ -0,0 +1,10 @@
+-- Alerts on a wallet's total unrealized PnL leaving the band ±threshold
+CREATE TABLE IF NOT EXISTS pnl_alerts (
+    user_id INTEGER NOT NULL,
+    wallet_address TEXT NOT NULL,
+    threshold REAL NOT NULL,
+    -- Cleared when the alert fires, set again once the PnL is back inside the band
+    armed BOOLEAN NOT NULL DEFAULT 1,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (user_id, wallet_address)
+);
@@ -0,0 +1,2 @@
+-- The user's local date of their last digest, so moving the time never sends two in a day
+ALTER TABLE user_settings ADD COLUMN digest_sent_date TEXT;
@@ -0,0 +1,7 @@
+-- Tags a user groups their wallets by, a wallet can carry several
+CREATE TABLE IF NOT EXISTS wallet_tags (
+    user_id INTEGER NOT NULL,
+    wallet_address TEXT NOT NULL,
+    tag TEXT NOT NULL COLLATE NOCASE,
+    PRIMARY KEY (user_id, wallet_address, tag)
+);
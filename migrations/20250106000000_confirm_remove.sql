@@ -0,0 +1,2 @@
+-- Whether /remove asks for confirmation before deleting a wallet
+ALTER TABLE user_settings ADD COLUMN confirm_remove BOOLEAN NOT NULL DEFAULT 1;
@@ -0,0 +1,2 @@
+-- Margin usage bands (percent) that trigger margin alerts, re-armed 5 points below each
+ALTER TABLE user_settings ADD COLUMN margin_bands TEXT NOT NULL DEFAULT '60,80,90';
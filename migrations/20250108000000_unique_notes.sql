@@ -0,0 +1,22 @@
+-- A note picks out one wallet per user, compared case-insensitively like /add does.
+-- Triggers rather than a unique index, so rows that already share a note stay untouched.
+CREATE TRIGGER IF NOT EXISTS tracked_wallets_unique_note_insert
+BEFORE INSERT ON tracked_wallets
+WHEN NEW.note IS NOT NULL AND EXISTS (
+    SELECT 1 FROM tracked_wallets
+    WHERE user_id = NEW.user_id AND LOWER(note) = LOWER(NEW.note) AND removed_at IS NULL
+)
+BEGIN
+    SELECT RAISE(ABORT, 'note already in use');
+END;
+
+CREATE TRIGGER IF NOT EXISTS tracked_wallets_unique_note_update
+BEFORE UPDATE OF note ON tracked_wallets
+WHEN NEW.note IS NOT NULL AND EXISTS (
+    SELECT 1 FROM tracked_wallets
+    WHERE user_id = NEW.user_id AND LOWER(note) = LOWER(NEW.note) AND removed_at IS NULL
+      AND id != NEW.id
+)
+BEGIN
+    SELECT RAISE(ABORT, 'note already in use');
+END;
@@ -0,0 +1,6 @@
+-- Coins a user was told are no longer listed perps, so each is only reported once
+CREATE TABLE IF NOT EXISTS unlisted_coin_notices (
+    user_id INTEGER NOT NULL,
+    coin TEXT NOT NULL,
+    PRIMARY KEY (user_id, coin)
+);
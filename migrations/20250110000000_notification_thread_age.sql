@@ -0,0 +1,2 @@
+-- When the message a thread replies to was sent, threads from before this have none and start over
+ALTER TABLE notification_threads ADD COLUMN sent_at DATETIME;
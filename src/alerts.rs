@@ -0,0 +1,178 @@
+/// Most bands a user can configure
+pub const MAX_BANDS: usize = 5;
+
+/// Threshold bands that fire once when a value moves through them.
+///
+/// A band only fires again after the value has moved `hysteresis` back past its
+/// threshold, so a value hovering around a threshold doesn't spam alerts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BandedAlert {
+    /// Thresholds in the order they're crossed, negated for falling values
+    thresholds: Vec<f64>,
+    hysteresis: f64,
+    falling: bool,
+}
+
+impl BandedAlert {
+    /// Bands for a value that alerts as it climbs, like margin usage
+    pub fn rising(mut thresholds: Vec<f64>, hysteresis: f64) -> Self {
+        thresholds.sort_by(f64::total_cmp);
+        Self {
+            thresholds,
+            hysteresis,
+            falling: false,
+        }
+    }
+
+    /// Bands for a value that alerts as it drops, like the distance to a liquidation price
+    pub fn falling(thresholds: Vec<f64>, hysteresis: f64) -> Self {
+        let mut thresholds: Vec<f64> = thresholds.into_iter().map(|t| -t).collect();
+        thresholds.sort_by(f64::total_cmp);
+        Self {
+            thresholds,
+            hysteresis,
+            falling: true,
+        }
+    }
+
+    /// Parse user-entered rising bands in percent like "60,80,90" or "50 75".
+    ///
+    /// Thresholds must be above 0, at most 100 and more than `hysteresis` apart,
+    /// otherwise a band couldn't re-arm without re-arming the one below it too.
+    pub fn parse_percent_bands(input: &str, hysteresis: f64) -> Result<Self, String> {
+        let mut thresholds = Vec::new();
+        for part in input
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|p| !p.is_empty())
+        {
+            let value: f64 = part
+                .trim_end_matches('%')
+                .parse()
+                .map_err(|_| format!("\"{}\" is not a number", part))?;
+            if !(value > 0.0 && value <= 100.0) {
+                return Err(format!("{}% is outside 0-100%", part.trim_end_matches('%')));
+            }
+            thresholds.push(value);
+        }
+        if thresholds.is_empty() {
+            return Err("no thresholds given".to_string());
+        }
+        if thresholds.len() > MAX_BANDS {
+            return Err(format!("at most {} thresholds are allowed", MAX_BANDS));
+        }
+
+        let bands = Self::rising(thresholds, hysteresis);
+        if bands
+            .thresholds
+            .windows(2)
+            .any(|pair| pair[1] - pair[0] <= hysteresis)
+        {
+            return Err(format!(
+                "thresholds must be more than {} points apart",
+                hysteresis
+            ));
+        }
+        Ok(bands)
+    }
+
+    /// Thresholds in the order they're crossed
+    pub fn thresholds(&self) -> Vec<f64> {
+        self.thresholds.iter().map(|t| self.unkey(*t)).collect()
+    }
+
+    /// Rising thresholds as stored in settings, like "60,80,90"
+    pub fn to_setting(&self) -> String {
+        self.thresholds()
+            .iter()
+            .map(|t| t.to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    fn key(&self, value: f64) -> f64 {
+        if self.falling { -value } else { value }
+    }
+
+    fn unkey(&self, key: f64) -> f64 {
+        if self.falling { -key } else { key }
+    }
+
+    /// Number of bands the value is at or past
+    pub fn level_for(&self, value: f64) -> usize {
+        let value = self.key(value);
+        self.thresholds.iter().filter(|t| value >= **t).count()
+    }
+
+    /// Advance from the previously triggered level given a new value.
+    ///
+    /// Returns the new level and, if the value crossed a band in the alerting
+    /// direction, the furthest threshold crossed.
+    pub fn update(&self, level: usize, value: f64) -> (usize, Option<f64>) {
+        let reached = self.level_for(value);
+        if reached > level {
+            return (reached, Some(self.unkey(self.thresholds[reached - 1])));
+        }
+
+        // Re-arm bands the value has moved clearly back past
+        let value = self.key(value);
+        let mut level = level.min(self.thresholds.len());
+        while level > 0 && value < self.thresholds[level - 1] - self.hysteresis {
+            level -= 1;
+        }
+        (level, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rising_bands_fire_once_per_band() {
+        let bands = BandedAlert::rising(vec![60.0, 80.0, 90.0], 5.0);
+        assert_eq!(bands.update(0, 50.0), (0, None));
+        assert_eq!(bands.update(0, 65.0), (1, Some(60.0)));
+        assert_eq!(bands.update(1, 70.0), (1, None));
+        // Jumping over several bands reports the highest one
+        assert_eq!(bands.update(1, 95.0), (3, Some(90.0)));
+    }
+
+    #[test]
+    fn rising_bands_rearm_below_hysteresis() {
+        let bands = BandedAlert::rising(vec![60.0, 80.0, 90.0], 5.0);
+        // Within the hysteresis the band stays triggered
+        assert_eq!(bands.update(1, 57.0), (1, None));
+        assert_eq!(bands.update(1, 54.0), (0, None));
+        assert_eq!(bands.update(0, 61.0), (1, Some(60.0)));
+        // Falling far re-arms every band passed on the way down
+        assert_eq!(bands.update(3, 10.0), (0, None));
+    }
+
+    #[test]
+    fn falling_bands_fire_as_value_drops() {
+        let bands = BandedAlert::falling(vec![5.0], 5.0);
+        assert_eq!(bands.level_for(8.0), 0);
+        assert_eq!(bands.update(0, 4.0), (1, Some(5.0)));
+        assert_eq!(bands.update(1, 3.0), (1, None));
+        // Re-armed only once the value is back past twice the threshold
+        assert_eq!(bands.update(1, 9.0), (1, None));
+        assert_eq!(bands.update(1, 10.5), (0, None));
+    }
+
+    #[test]
+    fn parses_percent_bands() {
+        let bands = BandedAlert::parse_percent_bands("90, 60 80%", 5.0).unwrap();
+        assert_eq!(bands.thresholds(), vec![60.0, 80.0, 90.0]);
+        assert_eq!(bands.to_setting(), "60,80,90");
+    }
+
+    #[test]
+    fn rejects_invalid_percent_bands() {
+        assert!(BandedAlert::parse_percent_bands("", 5.0).is_err());
+        assert!(BandedAlert::parse_percent_bands("abc", 5.0).is_err());
+        assert!(BandedAlert::parse_percent_bands("0", 5.0).is_err());
+        assert!(BandedAlert::parse_percent_bands("120", 5.0).is_err());
+        assert!(BandedAlert::parse_percent_bands("60,63", 5.0).is_err());
+        assert!(BandedAlert::parse_percent_bands("10,20,30,40,50,60", 5.0).is_err());
+    }
+}
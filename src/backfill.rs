@@ -0,0 +1,157 @@
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+
+use crate::db;
+use crate::hyperliquid::{self, FILLS_PAGE_SIZE, Fill};
+
+/// Longest window /backfill imports
+pub const MAX_BACKFILL_DAYS: i64 = 30;
+/// Sizes below this count as flat
+const FLAT_EPSILON: f64 = 1e-9;
+
+/// A round trip that has opened but not closed yet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct OpenTrip {
+    opened_at: i64,
+    is_long: bool,
+    opening_size: f64,
+    opening_price: f64,
+    peak_size: f64,
+    /// Sum of price × size over every fill that grew the position, for the average entry
+    entry_notional: f64,
+    entry_size: f64,
+    pnl: f64,
+}
+
+impl OpenTrip {
+    fn start(fill: &Fill, position: f64, price: f64) -> Self {
+        Self {
+            opened_at: fill.time,
+            is_long: position > 0.0,
+            opening_size: position.abs(),
+            opening_price: price,
+            peak_size: position.abs(),
+            entry_notional: price * position.abs(),
+            entry_size: position.abs(),
+            pnl: 0.0,
+        }
+    }
+
+    /// The opened and closed events of a finished round trip
+    fn into_events(self, coin: &str, closed_at: i64) -> [db::BackfilledEvent; 2] {
+        let entry_price = if self.entry_size > 0.0 {
+            self.entry_notional / self.entry_size
+        } else {
+            self.opening_price
+        };
+        [
+            db::BackfilledEvent {
+                coin: coin.to_string(),
+                kind: "opened",
+                is_long: self.is_long,
+                size: self.opening_size,
+                size_delta: self.opening_size,
+                entry_price: self.opening_price,
+                pnl: 0.0,
+                time: self.opened_at,
+            },
+            db::BackfilledEvent {
+                coin: coin.to_string(),
+                kind: "closed",
+                is_long: self.is_long,
+                size: 0.0,
+                size_delta: self.peak_size,
+                entry_price,
+                pnl: self.pnl,
+                time: closed_at,
+            },
+        ]
+    }
+}
+
+/// Apply fills in time order to the open round trips, returning the trips they finished.
+///
+/// Positions already open when the window starts have no known entry, so their fills
+/// are skipped until the wallet is flat (or flips) in that coin.
+fn apply_fills(trips: &mut HashMap<String, OpenTrip>, fills: &[Fill]) -> Vec<db::BackfilledEvent> {
+    let mut events = Vec::new();
+
+    for fill in fills {
+        let size: f64 = fill.sz.parse().unwrap_or(0.0);
+        let price: f64 = fill.px.parse().unwrap_or(0.0);
+        let before: f64 = fill.start_position.parse().unwrap_or(0.0);
+        let delta = if fill.side == "B" { size } else { -size };
+        let after = before + delta;
+        let flat_before = before.abs() < FLAT_EPSILON;
+        let flat_after = after.abs() < FLAT_EPSILON;
+        let flipped = !flat_before && !flat_after && before.signum() != after.signum();
+
+        match trips.remove(&fill.coin) {
+            Some(mut trip) => {
+                trip.pnl += fill.closed_pnl.parse::<f64>().unwrap_or(0.0);
+                if flat_after || flipped {
+                    events.extend(trip.into_events(&fill.coin, fill.time));
+                    if flipped {
+                        trips.insert(fill.coin.clone(), OpenTrip::start(fill, after, price));
+                    }
+                } else {
+                    if after.abs() > before.abs() {
+                        trip.entry_notional += price * (after.abs() - before.abs());
+                        trip.entry_size += after.abs() - before.abs();
+                        trip.peak_size = trip.peak_size.max(after.abs());
+                    }
+                    trips.insert(fill.coin.clone(), trip);
+                }
+            }
+            None if !flat_after && (flat_before || flipped) => {
+                trips.insert(fill.coin.clone(), OpenTrip::start(fill, after, price));
+            }
+            None => {}
+        }
+    }
+
+    events
+}
+
+/// Import the next page of fills of a run, returns how many round trips it finished.
+///
+/// Events and the run's cursor are saved together, so an interrupted run resumes
+/// exactly where the last saved page ended.
+pub async fn import_page(
+    client: &Client,
+    pool: &SqlitePool,
+    wallet_address: &str,
+    run: &mut db::BackfillRun,
+) -> anyhow::Result<usize> {
+    let mut fills =
+        hyperliquid::fetch_user_fills_by_time(client, wallet_address, run.cursor, run.end_time - 1)
+            .await?;
+    fills.sort_by_key(|f| f.time);
+
+    let full = fills.len() >= FILLS_PAGE_SIZE;
+    let last_time = fills.last().map(|f| f.time);
+    let next_cursor = match last_time {
+        // A full page may have cut fills sharing the last millisecond, fetch them again
+        // with the next page unless the whole page is that one millisecond
+        Some(last) if full && fills.first().is_some_and(|f| f.time < last) => {
+            fills.retain(|f| f.time < last);
+            last
+        }
+        Some(last) if full => last + 1,
+        _ => run.end_time,
+    };
+
+    let mut trips: HashMap<String, OpenTrip> =
+        serde_json::from_str(&run.open_trips).unwrap_or_default();
+    let events = apply_fills(&mut trips, &fills);
+
+    run.cursor = next_cursor;
+    run.open_trips = serde_json::to_string(&trips)?;
+    run.fills += fills.len() as i64;
+    run.completed = next_cursor >= run.end_time;
+    db::save_backfill_page(pool, wallet_address, run, &events).await?;
+
+    Ok(events.len() / 2)
+}
@@ -1,16 +1,148 @@
-use log::{error, info};
+use log::{error, info, warn};
 use reqwest::Client;
 use sqlx::SqlitePool;
-use std::time::Duration;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
 use teloxide::{
+    net::Download,
     prelude::*,
     sugar::request::RequestReplyExt,
-    types::{Message, ParseMode},
+    types::{
+        InlineKeyboardButton, InlineKeyboardMarkup, InputFile, Me, Message, MessageEntityKind,
+        ParseMode,
+    },
     utils::{command::BotCommands, html},
 };
+use tokio::sync::RwLock;
 
+use crate::alerts::{self, BandedAlert};
+use crate::backfill;
+use crate::broadcast;
 use crate::db;
+use crate::dry_run;
 use crate::hyperliquid;
+use crate::ledger;
+use crate::price_alerts;
+use crate::server;
+use crate::timezone::UserTimezone;
+use crate::vaults;
+use crate::wallet_file;
+
+/// Share of combined equity in one coin and direction that /risk flags by default
+const DEFAULT_RISK_LIMIT_PCT: f64 = 40.0;
+/// Periods /roi accepts, listed when an unknown one is given
+const ROI_PERIODS: &str = "day, week, month, allTime";
+
+/// Days of fills /winrate and /volume look at by default and at most
+const DEFAULT_FILL_STATS_DAYS: i64 = 30;
+const MAX_FILL_STATS_DAYS: i64 = 90;
+/// Most fills /winrate and /volume read, the exchange only keeps the latest 10000 anyway
+const MAX_FILL_STATS_FILLS: usize = 10_000;
+/// Smallest side of a /qr image in pixels, large enough to scan off another screen
+const QR_MIN_SIZE: u32 = 400;
+/// Coins /trending ranks, and the most events it reads
+const TRENDING_COINS_SHOWN: usize = 10;
+const MAX_TRENDING_EVENTS: i64 = 20_000;
+/// Trades listed by /best and /worst
+const EXTREME_TRADES_SHOWN: usize = 5;
+/// Coins listed by /volume and /fees
+const VOLUME_TOP_COINS: usize = 5;
+
+/// Positions closest to liquidation that /liq highlights
+const LIQ_HIGHLIGHTED: usize = 3;
+
+/// Most orders listed by /orders, keeps the message well under Telegram's length limit
+const MAX_ORDERS_SHOWN: usize = 40;
+/// Spot balances worth less than this are dust, counted by /spot but not listed
+const MIN_SPOT_VALUE: f64 = 1.0;
+/// Positions listed by /vault, largest first
+const MAX_VAULT_POSITIONS_SHOWN: usize = 15;
+/// Fills listed by /fills when no count is given, and the most it accepts
+const DEFAULT_FILLS_SHOWN: usize = 10;
+const MAX_FILLS_SHOWN: usize = 50;
+/// Roll long lists over into another message before Telegram's 4096 character limit
+const MESSAGE_SPLIT_LIMIT: usize = 3500;
+/// Taker fee of the base tier, used by /entry to estimate the cost of closing
+const CLOSE_FEE_RATE: f64 = 0.00045;
+/// Not-found reply for commands that take any wallet, tracked or not
+const WALLET_NOT_FOUND: &str =
+    "❌ Wallet not found. Provide a valid address, index (1-10), or note.";
+/// How long /ping waits for the Hyperliquid API before reporting a timeout
+const PING_TIMEOUT: Duration = Duration::from_secs(10);
+/// Longest wallet tag accepted by /tag
+const MAX_TAG_LEN: usize = 32;
+/// Largest file /import downloads, far above what a full wallet list needs
+const MAX_IMPORT_FILE_BYTES: u32 = 256 * 1024;
+/// Closed positions listed by /history when no count is given, and the most it accepts
+const DEFAULT_HISTORY_SHOWN: i64 = 10;
+const MAX_HISTORY_SHOWN: i64 = 50;
+/// Positions listed by /top when no count is given, and the most it accepts
+const DEFAULT_TOP_SHOWN: usize = 10;
+const MAX_TOP_SHOWN: usize = 30;
+/// Window of /funding when no day count is given, and the longest it accepts
+const DEFAULT_FUNDING_DAYS: i64 = 7;
+const MAX_FUNDING_DAYS: i64 = 90;
+/// Coins per side listed by /fundingrates when no count is given, and the most it accepts
+const DEFAULT_FUNDING_RATES_SHOWN: usize = 5;
+const MAX_FUNDING_RATES_SHOWN: usize = 20;
+/// Pause between fill pages of /backfill, to stay well inside the API rate limit
+const BACKFILL_PAGE_DELAY: Duration = Duration::from_millis(500);
+/// /status warns when the monitor's last successful tick is older than this
+const STATUS_STALE_SECS: i64 = 60;
+/// How long the last wallet a user referenced stands in for a missing identifier
+const LAST_WALLET_TTL: Duration = Duration::from_secs(15 * 60);
+
+/// When the process started, forced in main so /version counts from startup
+pub static STARTED_AT: LazyLock<chrono::DateTime<chrono::Utc>> = LazyLock::new(chrono::Utc::now);
+
+/// Last wallet each user referenced in a command and when
+static LAST_WALLET: LazyLock<Mutex<HashMap<i64, (String, Instant)>>> =
+    LazyLock::new(Default::default);
+
+/// HTTP client for every command that queries Hyperliquid, so they share its connection pool
+static HTTP_CLIENT: LazyLock<Client> = LazyLock::new(|| {
+    Client::builder()
+        .timeout(Duration::from_secs(30))
+        .build()
+        .expect("Failed to create HTTP client")
+});
+
+/// Chat /feedback is forwarded to, feedback is turned off without it
+static ADMIN_CHAT_ID: LazyLock<Option<i64>> = LazyLock::new(|| {
+    std::env::var("ADMIN_CHAT_ID")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+});
+/// Most /feedback messages a user can send per FEEDBACK_WINDOW
+const MAX_FEEDBACK_PER_WINDOW: usize = 3;
+const FEEDBACK_WINDOW: Duration = Duration::from_secs(3600);
+/// When each user's recent /feedback messages were sent
+static FEEDBACK_SENT: LazyLock<Mutex<HashMap<i64, VecDeque<Instant>>>> =
+    LazyLock::new(Default::default);
+
+/// Margin committed to one coin and direction, and which wallets it comes from
+#[derive(Default)]
+struct CoinExposure {
+    margin: f64,
+    /// (wallet index, margin)
+    by_wallet: Vec<(usize, f64)>,
+}
+
+/// One coin's positions netted across a user's wallets
+#[derive(Default)]
+struct CoinHolding {
+    /// Signed, positive when net long
+    net_size: f64,
+    net_notional: f64,
+    unrealized_pnl: f64,
+    wallets: usize,
+    long_size: f64,
+    /// Sum of size × entry price, for the average entry
+    long_cost: f64,
+    short_size: f64,
+    short_cost: f64,
+}
 
 #[derive(BotCommands, Clone)]
 #[command(rename_rule = "lowercase")]
@@ -18,18 +150,180 @@ pub enum Command {
     #[command(description = "Display this help message")]
     Help,
     #[command(description = "Start the bot")]
-    Start,
+    Start(String),
     #[command(description = "Add a wallet to track")]
     Add(String),
     #[command(description = "Remove a tracked wallet")]
     Remove(String),
-    #[command(description = "List all tracked wallets")]
-    List,
+    #[command(description = "List all tracked wallets (full adds live account data)")]
+    List(String),
     #[command(description = "Show open positions for a wallet")]
     Positions(String),
+    #[command(description = "Collect a wallet's activity in one edited message (on|pin|off)")]
+    Logbook(String),
+    #[command(description = "Add a copy-trade block to open notifications")]
+    Mirror(String),
+    #[command(description = "Alert when a wallet's margin usage gets high (on|off)")]
+    MarginAlert(String),
+    #[command(description = "Show a wallet's recorded entries in a coin and how they performed")]
+    Entries(String),
+    #[command(description = "Get or revoke a token for the read-only HTTP API")]
+    Token(String),
+    #[command(description = "Get weekly suggestions of top wallets similar to yours (on|off)")]
+    Suggest(String),
+    #[command(description = "Show margin concentration per coin, or a wallet's liquidation risk")]
+    Risk(String),
+    #[command(description = "Reply to the previous notification for the same wallet (on|off)")]
+    Threads(String),
+    #[command(description = "Look for tracked wallets that might belong to the same person")]
+    Cluster,
+    #[command(description = "Include funding in closed positions' PnL for a wallet (on|off)")]
+    FundingPnl(String),
+    #[command(
+        description = "Alert when a wallet's liquidation prices drift toward the mark (on|off)"
+    )]
+    LiqWatch(String),
+    #[command(description = "Show unrealized PnL and account value across all your wallets")]
+    Pnl,
+    #[command(description = "Show your positions merged by coin across all wallets")]
+    Portfolio,
+    #[command(description = "Show a wallet's account value and margin usage")]
+    Balance(String),
+    #[command(description = "Include a wallet's vault deposits in its balance (on|off)")]
+    Vaults(String),
+    #[command(description = "Show a wallet's resting open orders")]
+    Orders(String),
+    #[command(description = "Show a wallet's most recent trade fills")]
+    Fills(String),
+    #[command(description = "Sum a wallet's funding payments per coin")]
+    Funding(String),
+    #[command(description = "Import a wallet's recent closed trades from its fill history")]
+    Backfill(String),
+    #[command(description = "List a wallet's recently closed positions")]
+    History(String),
+    #[command(description = "Stop notifications for a wallet but keep tracking it")]
+    Mute(String),
+    #[command(description = "Turn notifications for a muted wallet back on")]
+    Unmute(String),
+    #[command(description = "Add a tag to a wallet: <wallet> <tag>")]
+    Tag(String),
+    #[command(description = "Remove a tag from a wallet: <wallet> <tag>")]
+    Untag(String),
+    #[command(description = "Show open positions of the wallets with a tag, or list your tags")]
+    Group(String),
+    #[command(description = "Mute every wallet with a tag")]
+    MuteGroup(String),
+    #[command(description = "Unmute every wallet with a tag")]
+    UnmuteGroup(String),
+    #[command(
+        description = "Only notify about some coins of a wallet: <wallet> <coin,coin> or clear"
+    )]
+    WatchCoin(String),
+    #[command(description = "Never notify about a coin on any wallet, or list ignored coins")]
+    IgnoreCoin(String),
+    #[command(description = "Notify about an ignored coin again")]
+    UnignoreCoin(String),
+    #[command(description = "Only notify about these coins on any wallet: <coin,coin> or off")]
+    OnlyCoins(String),
+    #[command(description = "Silence a wallet for a while: <wallet> <30m|4h|2d> or off")]
+    Snooze(String),
+    #[command(
+        description = "Alert when a wallet's unrealized PnL passes ±amount: <wallet> <amount|off> or list"
+    )]
+    AlertPnl(String),
+    #[command(description = "Pause all notifications, optionally for a while (e.g. 8h)")]
+    Pause(String),
+    #[command(description = "Turn paused notifications back on")]
+    Resume,
+    #[command(description = "Download your tracked wallets as a JSON or CSV file")]
+    Export(String),
+    #[command(description = "Track wallets from an /export file or a list of addresses")]
+    Import(String),
+    #[command(description = "Show and toggle your notification settings")]
+    Settings,
+    #[command(description = "Skip notifications for positions worth less than an amount")]
+    Threshold(String),
+    #[command(description = "Get a one-time alert when a coin crosses a price")]
+    Alert(String),
+    #[command(description = "List your active price alerts")]
+    Alerts,
+    #[command(description = "Remove a price alert by its id")]
+    AlertRemove(String),
+    #[command(description = "Warn when a position gets within a percentage of liquidation")]
+    LiqAlert(String),
+    #[command(description = "Show the largest open positions across all your wallets")]
+    Top(String),
+    #[command(description = "Compare two wallets side by side")]
+    Compare(String),
+    #[command(description = "Get a daily summary of your wallets: on, off or a time like 08:00")]
+    Digest(String),
+    #[command(description = "Show a wallet's spot token balances")]
+    Spot(String),
+    #[command(description = "Show a vault's details, your deposits and its positions")]
+    Vault(String),
+    #[command(description = "List a wallet's running TWAP orders")]
+    Twap(String),
+    #[command(description = "Find which of your wallets hold a coin")]
+    Search(String),
+    #[command(description = "Show how many notifications you got and from which wallets")]
+    Stats,
+    #[command(description = "Show whether the monitor and the Hyperliquid API are healthy")]
+    Status,
+    #[command(description = "Show the bot's version, commit, build date and uptime")]
+    Version,
+    #[command(description = "Measure Telegram and Hyperliquid API latency")]
+    Ping,
+    #[command(description = "Set the timezone times are shown in, like Europe/Warsaw or UTC+2")]
+    Timezone(String),
+    #[command(description = "Rank your wallets by PnL over 7d, 30d or alltime")]
+    Leaderboard(String),
+    #[command(description = "Stop tracking all your wallets, asks for confirmation first")]
+    Clear(String),
+    #[command(description = "Show a perp's price, 24h change, funding and open interest")]
+    Price(String),
+    #[command(description = "List the perps with the highest and most negative funding")]
+    FundingRates(String),
+    #[command(description = "Find which of your wallets a note or partial address is")]
+    Whois(String),
+    #[command(description = "Show a wallet's address as a QR code")]
+    Qr(String),
+    #[command(description = "List a wallet's sub-accounts with buttons to track them")]
+    SubAccounts(String),
+    #[command(description = "List liquidation prices across all your wallets, closest first")]
+    Liq,
+    #[command(description = "Show a wallet's margin breakdown per position")]
+    Margin(String),
+    #[command(
+        description = "Show a position's average entry and breakeven after funding and fees: <wallet> <coin>"
+    )]
+    Entry(String),
+    #[command(description = "Show a wallet's return and max drawdown over a period")]
+    Roi(String),
+    #[command(description = "Show a wallet's max and current drawdown over 30 days")]
+    Drawdown(String),
+    #[command(description = "Show a wallet's win rate from closed fills over the last days")]
+    Winrate(String),
+    #[command(description = "Show a wallet's five most profitable closed trades")]
+    Best(String),
+    #[command(description = "Rank the coins your wallets opened or added to recently")]
+    Trending(String),
+    #[command(description = "Show a wallet's five most losing closed trades")]
+    Worst(String),
+    #[command(description = "List what your wallets closed today with realized PnL")]
+    ClosedToday,
+    #[command(description = "Show a wallet's traded volume over the last days")]
+    Volume(String),
+    #[command(description = "Show the trading fees a wallet paid over the last days")]
+    Fees(String),
+    #[command(description = "Send feedback or a bug report to the bot's admin")]
+    Feedback(String),
+    #[command(hide)]
+    Broadcast(String),
+    #[command(hide)]
+    GlobalStats,
 }
 
-pub async fn run(bot: Bot, pool: SqlitePool) {
+pub async fn run(bot: Bot, pool: SqlitePool, state: Arc<RwLock<hyperliquid::PositionTracker>>) {
     // Register commands with Telegram
     if let Err(e) = bot.set_my_commands(Command::bot_commands()).await {
         error!("Failed to register commands: {}", e);
@@ -37,18 +331,554 @@ pub async fn run(bot: Bot, pool: SqlitePool) {
         info!("Bot commands registered successfully");
     }
 
-    Command::repl(bot, move |bot: Bot, msg: Message, cmd: Command| {
-        let pool = pool.clone();
-        async move {
-            // Only respond to private messages (DMs)
-            if !msg.chat.is_private() {
-                return Ok(());
+    let handler = dptree::entry()
+        .branch(
+            Update::filter_message()
+                .filter_map(|msg: Message, me: Me| parse_command(&msg, &me))
+                .endpoint(
+                    |bot: Bot,
+                     msg: Message,
+                     cmd: Command,
+                     pool: SqlitePool,
+                     state: Arc<RwLock<hyperliquid::PositionTracker>>| async move {
+                        // Only respond to private messages (DMs)
+                        if !msg.chat.is_private() {
+                            return Ok(());
+                        }
+                        if !dry_run::allows_user(msg.chat.id.0) {
+                            return Ok(());
+                        }
+
+                        handle_command(bot, msg, cmd, pool, state).await
+                    },
+                ),
+        )
+        .branch(
+            // Commands in a caption aren't parsed, so files sent for /import need their own branch
+            Update::filter_message()
+                .filter(|msg: Message| {
+                    msg.document().is_some() && msg.caption().is_some_and(is_import_caption)
+                })
+                .endpoint(
+                    |bot: Bot,
+                     msg: Message,
+                     pool: SqlitePool,
+                     state: Arc<RwLock<hyperliquid::PositionTracker>>| async move {
+                        if !msg.chat.is_private() {
+                            return Ok(());
+                        }
+                        if !dry_run::allows_user(msg.chat.id.0) {
+                            return Ok(());
+                        }
+
+                        handle_command(bot, msg, Command::Import(String::new()), pool, state).await
+                    },
+                ),
+        )
+        .branch(
+            // Anything that isn't a command, a pasted address shows that wallet
+            Update::filter_message()
+                .filter_map(|msg: Message| pasted_address(&msg))
+                .endpoint(handle_pasted_address),
+        )
+        .branch(Update::filter_callback_query().endpoint(handle_callback));
+
+    Dispatcher::builder(bot, handler)
+        .dependencies(dptree::deps![pool, state])
+        .enable_ctrlc_handler()
+        .build()
+        .dispatch()
+        .await;
+}
+
+/// Handle presses on inline keyboard buttons
+async fn handle_callback(
+    bot: Bot,
+    q: CallbackQuery,
+    pool: SqlitePool,
+    state: Arc<RwLock<hyperliquid::PositionTracker>>,
+) -> ResponseResult<()> {
+    let user_id = q.from.id.0 as i64;
+    let Some(data) = q.data.as_deref() else {
+        return Ok(());
+    };
+    if !dry_run::allows_user(user_id) {
+        return Ok(());
+    }
+
+    if let Err(e) = db::touch_user(&pool, user_id).await {
+        error!("Failed to record activity for {}: {}", user_id, e);
+    }
+
+    if let Some(wallet) = data.strip_prefix("track:") {
+        let text = track_suggested_wallet(&pool, user_id, wallet).await;
+        bot.answer_callback_query(q.id.clone()).text(text).await?;
+    } else if data == "ignore"
+        && let Some(message) = q.regular_message()
+    {
+        bot.answer_callback_query(q.id.clone()).await?;
+        // Without a markup the buttons are removed and the positions stay readable
+        bot.edit_message_reply_markup(message.chat.id, message.id)
+            .await?;
+    } else if let Some(key) = data.strip_prefix("settings:")
+        && let Some(message) = q.regular_message()
+    {
+        match toggle_setting(&pool, user_id, key).await {
+            Ok(true) => {
+                bot.answer_callback_query(q.id.clone()).await?;
+                match settings_menu(&pool, user_id).await {
+                    Ok((text, keyboard)) => {
+                        bot.edit_message_text(message.chat.id, message.id, text)
+                            .parse_mode(ParseMode::Html)
+                            .reply_markup(keyboard)
+                            .await?;
+                    }
+                    Err(e) => error!("Failed to reload settings for {}: {}", user_id, e),
+                }
+            }
+            Ok(false) => {
+                bot.answer_callback_query(q.id.clone())
+                    .text(EXPIRED_BUTTON_TEXT)
+                    .await?;
+            }
+            Err(e) => {
+                error!("Failed to toggle setting {} for {}: {}", key, user_id, e);
+                bot.answer_callback_query(q.id.clone())
+                    .text("❌ Failed to update the setting. Please try again.")
+                    .await?;
+            }
+        }
+    } else if let Some(pick) = data.strip_prefix("pick:")
+        && let Some(message) = q.regular_message()
+    {
+        handle_wallet_pick(&bot, &q, message, &pool, &state, user_id, pick).await?;
+    } else if let Some(answer) = data.strip_prefix("rmc:")
+        && let Some(message) = q.regular_message()
+    {
+        handle_remove_confirmation(&bot, &q, message, &pool, &state, user_id, answer).await?;
+    } else if let Some(action) = data.strip_prefix("clear:")
+        && let Some(message) = q.regular_message()
+    {
+        let age = chrono::Utc::now() - message.date;
+        let text = if action == "cancel" {
+            "👍 Cancelled, your wallets are untouched.".to_string()
+        } else if action != user_id.to_string() {
+            bot.answer_callback_query(q.id.clone())
+                .text("⚠️ This confirmation belongs to someone else.")
+                .await?;
+            return Ok(());
+        } else if age.num_seconds() > CLEAR_CONFIRM_SECS {
+            "⚠️ This confirmation has expired, nothing was removed. Send /clear again.".to_string()
+        } else {
+            match clear_all_wallets(&pool, &state, user_id).await {
+                Ok(removed) => format!("✅ Stopped tracking all {} wallet(s).", removed),
+                Err(e) => {
+                    error!("Failed to clear wallets of {}: {}", user_id, e);
+                    "❌ Failed to remove your wallets. Please try again.".to_string()
+                }
+            }
+        };
+        bot.answer_callback_query(q.id.clone()).await?;
+        // Editing the text also drops the buttons, so they can't be pressed again
+        bot.edit_message_text(message.chat.id, message.id, text)
+            .parse_mode(ParseMode::Html)
+            .await?;
+    } else {
+        bot.answer_callback_query(q.id.clone())
+            .text(EXPIRED_BUTTON_TEXT)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Buttons for /positions ("pos") or /remove ("rm") without a wallet, `None` when the user tracks none.
+///
+/// Buttons carry the wallet's row id, an address wouldn't fit Telegram's 64 byte limit.
+async fn wallet_picker(
+    pool: &SqlitePool,
+    user_id: i64,
+    action: &str,
+) -> Option<(String, InlineKeyboardMarkup)> {
+    let wallets = db::get_user_wallets_with_ids(pool, user_id)
+        .await
+        .unwrap_or_else(|e| {
+            error!("Failed to fetch wallets for the picker: {}", e);
+            Vec::new()
+        });
+    if wallets.is_empty() {
+        return None;
+    }
+
+    let mut rows: Vec<Vec<InlineKeyboardButton>> = Vec::new();
+    if action == "pos" {
+        rows.push(vec![InlineKeyboardButton::callback(
+            "📊 All wallets",
+            "pick:pos:all",
+        )]);
+    }
+    rows.extend(wallets.iter().map(|(id, w)| {
+        let label = match &w.note {
+            Some(note) => note.clone(),
+            None => format!(
+                "{}...{}",
+                &w.wallet_address[..6],
+                &w.wallet_address[w.wallet_address.len() - 4..]
+            ),
+        };
+        vec![InlineKeyboardButton::callback(
+            label,
+            format!("pick:{}:{}", action, id),
+        )]
+    }));
+
+    let text = if action == "rm" {
+        "🗑 <b>Which wallet should I stop tracking?</b>"
+    } else {
+        "📊 <b>Which wallet's positions?</b>"
+    };
+    Some((text.to_string(), InlineKeyboardMarkup::new(rows)))
+}
+
+/// Run /positions or /remove for the wallet picked from `wallet_picker`, in place of the buttons
+async fn handle_wallet_pick(
+    bot: &Bot,
+    q: &CallbackQuery,
+    message: &Message,
+    pool: &SqlitePool,
+    state: &Arc<RwLock<hyperliquid::PositionTracker>>,
+    user_id: i64,
+    pick: &str,
+) -> ResponseResult<()> {
+    let Some((action, id)) = pick.split_once(':') else {
+        bot.answer_callback_query(q.id.clone())
+            .text(EXPIRED_BUTTON_TEXT)
+            .await?;
+        return Ok(());
+    };
+
+    if action == "pos" && id == "all" {
+        let wallets = db::get_user_wallets(pool, user_id)
+            .await
+            .unwrap_or_else(|e| {
+                error!("Failed to fetch wallets for positions: {}", e);
+                Vec::new()
+            });
+        if wallets.is_empty() {
+            bot.answer_callback_query(q.id.clone())
+                .text("⚠️ You're not tracking any wallets anymore.")
+                .await?;
+            return Ok(());
+        }
+        bot.answer_callback_query(q.id.clone()).await?;
+        let mut messages = all_positions_messages(&wallets).await.into_iter();
+        if let Some(first) = messages.next() {
+            bot.edit_message_text(message.chat.id, message.id, first)
+                .parse_mode(ParseMode::Html)
+                .await?;
+        }
+        for text in messages {
+            bot.send_message(message.chat.id, text)
+                .parse_mode(ParseMode::Html)
+                .await?;
+        }
+        return Ok(());
+    }
+
+    let wallet = match id.parse::<i64>() {
+        Ok(id) => db::get_wallet_by_id(pool, user_id, id).await,
+        Err(_) => Ok(None),
+    };
+    let wallet = match wallet {
+        Ok(Some(wallet)) => wallet,
+        Ok(None) => {
+            bot.answer_callback_query(q.id.clone())
+                .text("⚠️ Wallet no longer tracked.")
+                .await?;
+            return Ok(());
+        }
+        Err(e) => {
+            error!("Failed to look up picked wallet: {}", e);
+            bot.answer_callback_query(q.id.clone())
+                .text("❌ Something went wrong. Please try again.")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let text = match action {
+        "pos" => {
+            bot.answer_callback_query(q.id.clone()).await?;
+            wallet_positions_text(state, &wallet.wallet_address, wallet.note.as_deref()).await
+        }
+        "rm" if confirm_removals(pool, user_id).await => {
+            bot.answer_callback_query(q.id.clone()).await?;
+            let target = removal_target(pool, user_id, wallet.wallet_address, wallet.note).await;
+            let (text, keyboard) = removal_prompt(user_id, vec![(String::new(), target)]);
+            let edit = bot
+                .edit_message_text(message.chat.id, message.id, text)
+                .parse_mode(ParseMode::Html);
+            match keyboard {
+                Some(keyboard) => edit.reply_markup(keyboard).await?,
+                None => edit.await?,
+            };
+            return Ok(());
+        }
+        "rm" => {
+            bot.answer_callback_query(q.id.clone()).await?;
+            remove_wallet_text(
+                pool,
+                state,
+                user_id,
+                &wallet.wallet_address,
+                wallet.note.as_deref(),
+            )
+            .await
+        }
+        _ => {
+            bot.answer_callback_query(q.id.clone())
+                .text(EXPIRED_BUTTON_TEXT)
+                .await?;
+            return Ok(());
+        }
+    };
+    // Editing the text also drops the buttons, so a removal can't be pressed twice
+    bot.edit_message_text(message.chat.id, message.id, text)
+        .parse_mode(ParseMode::Html)
+        .await?;
+    Ok(())
+}
+
+/// Confirm or cancel a /remove from `removal_prompt`'s buttons
+async fn handle_remove_confirmation(
+    bot: &Bot,
+    q: &CallbackQuery,
+    message: &Message,
+    pool: &SqlitePool,
+    state: &Arc<RwLock<hyperliquid::PositionTracker>>,
+    user_id: i64,
+    answer: &str,
+) -> ResponseResult<()> {
+    let (confirmed, token) = match answer.split_once(':') {
+        Some(("yes", token)) => (true, token.parse::<u64>().ok()),
+        Some(("no", token)) => (false, token.parse::<u64>().ok()),
+        _ => (false, None),
+    };
+    let taken = token.map(|token| take_pending_removal(token, user_id));
+    let pending = match taken {
+        Some(Err(())) => {
+            bot.answer_callback_query(q.id.clone())
+                .text("⚠️ This confirmation belongs to someone else.")
+                .await?;
+            return Ok(());
+        }
+        Some(Ok(pending)) => pending,
+        None => None,
+    };
+
+    let text = match pending {
+        None => {
+            "⚠️ This confirmation was already used or has expired, nothing was removed.".to_string()
+        }
+        Some(p) if p.expired(Instant::now()) => {
+            "⚠️ This confirmation has expired, nothing was removed. Send /remove again.".to_string()
+        }
+        Some(_) if !confirmed => "👍 Cancelled, nothing was removed.".to_string(),
+        Some(p) => apply_removals(pool, state, user_id, p.targets).await,
+    };
+    bot.answer_callback_query(q.id.clone()).await?;
+    // Editing the text also drops the buttons, so they can't be pressed again
+    bot.edit_message_text(message.chat.id, message.id, text)
+        .parse_mode(ParseMode::Html)
+        .await?;
+    Ok(())
+}
+
+/// How long the /remove confirmation buttons stay valid
+const REMOVE_CONFIRM_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// How long the /clear confirmation buttons stay valid
+const CLEAR_CONFIRM_SECS: i64 = 5 * 60;
+
+/// Remove all of a user's wallets and whatever the monitor cached for them, returns how many
+async fn clear_all_wallets(
+    pool: &SqlitePool,
+    state: &Arc<RwLock<hyperliquid::PositionTracker>>,
+    user_id: i64,
+) -> anyhow::Result<usize> {
+    let cleared = db::clear_wallets(pool, user_id).await?;
+
+    let mut tracker = state.write().await;
+    for wallet in &cleared.orphaned {
+        tracker.forget_wallet(wallet);
+    }
+    tracker.forget_user(user_id);
+    drop(tracker);
+
+    if let Ok(mut last_wallet) = LAST_WALLET.lock() {
+        last_wallet.remove(&user_id);
+    }
+    info!(
+        "User {} cleared {} wallet(s), {} no longer tracked by anyone",
+        user_id,
+        cleared.removed,
+        cleared.orphaned.len()
+    );
+    Ok(cleared.removed)
+}
+
+/// Minimum position values the /settings button cycles through
+const MIN_NOTIONAL_PRESETS: [f64; 4] = [0.0, 1_000.0, 10_000.0, 100_000.0];
+
+/// Toast for buttons whose action no longer exists
+const EXPIRED_BUTTON_TEXT: &str = "⚠️ This button has expired. Send /settings again.";
+
+/// Text and buttons of the /settings menu
+async fn settings_menu(
+    pool: &SqlitePool,
+    user_id: i64,
+) -> anyhow::Result<(String, InlineKeyboardMarkup)> {
+    let settings = db::get_user_settings(pool, user_id).await?;
+    let pause = db::get_notifications_pause(pool, user_id).await?;
+
+    let notifications = match pause {
+        None => "🟢 On".to_string(),
+        Some(None) => "⏸ Paused".to_string(),
+        Some(Some(until)) => format!(
+            "⏸ Paused for {}",
+            format_duration(until - chrono::Utc::now().timestamp())
+        ),
+    };
+    let status = |enabled: bool| if enabled { "🟢 On" } else { "⚪ Off" };
+    let options = [
+        ("notifications", "🔔 Notifications", notifications),
+        (
+            "compact",
+            "📝 Compact mode",
+            status(settings.compact_notifications).to_string(),
+        ),
+        (
+            "notional",
+            "💵 Min position",
+            if settings.min_notional > 0.0 {
+                hyperliquid::format_usd_compact(settings.min_notional)
+            } else {
+                "⚪ Off".to_string()
+            },
+        ),
+        (
+            "threads",
+            "🧵 Threads",
+            status(settings.threaded_notifications).to_string(),
+        ),
+        (
+            "margin",
+            "⚠️ Margin alerts",
+            status(settings.margin_alerts).to_string(),
+        ),
+        (
+            "mirror",
+            "🪞 Mirror block",
+            status(settings.mirror_enabled).to_string(),
+        ),
+        (
+            "suggestions",
+            "🔭 Weekly suggestions",
+            status(settings.weekly_suggestions).to_string(),
+        ),
+        (
+            "confirm_remove",
+            "🗑 Confirm /remove",
+            status(settings.confirm_remove).to_string(),
+        ),
+    ];
+
+    let lines: Vec<String> = options
+        .iter()
+        .map(|(_, label, value)| format!("{}: {}", label, value))
+        .collect();
+    let text = format!(
+        "<b>⚙️ Settings</b>\n\n{}\n\n<i>Tap a button to toggle it. Compact mode sends each notification as a single line, min position cycles through a few presets (set any amount with /threshold).</i>",
+        lines.join("\n")
+    );
+    let keyboard = InlineKeyboardMarkup::new(options.iter().map(|(key, label, value)| {
+        vec![InlineKeyboardButton::callback(
+            format!("{}: {}", label, value),
+            format!("settings:{}", key),
+        )]
+    }));
+
+    Ok((text, keyboard))
+}
+
+/// Flip one /settings option, returns false for keys the menu doesn't have
+async fn toggle_setting(pool: &SqlitePool, user_id: i64, key: &str) -> anyhow::Result<bool> {
+    let settings = db::get_user_settings(pool, user_id).await?;
+    match key {
+        "notifications" => {
+            if !db::resume_notifications(pool, user_id).await? {
+                db::pause_notifications(pool, user_id, None).await?;
             }
+        }
+        "compact" => {
+            db::set_compact_notifications(pool, user_id, !settings.compact_notifications).await?
+        }
+        "notional" => {
+            // Step to the next preset, wrapping to off; custom values come from /threshold
+            let next = MIN_NOTIONAL_PRESETS
+                .iter()
+                .copied()
+                .find(|preset| *preset > settings.min_notional)
+                .unwrap_or(0.0);
+            db::set_min_notional(pool, user_id, next).await?
+        }
+        "threads" => {
+            db::set_threaded_notifications(pool, user_id, !settings.threaded_notifications).await?
+        }
+        "margin" => db::set_margin_alerts(pool, user_id, !settings.margin_alerts).await?,
+        "confirm_remove" => db::set_confirm_remove(pool, user_id, !settings.confirm_remove).await?,
+        "mirror" => db::set_mirror_enabled(pool, user_id, !settings.mirror_enabled).await?,
+        "suggestions" => {
+            db::set_weekly_suggestions(pool, user_id, !settings.weekly_suggestions).await?
+        }
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+/// Start tracking a wallet from a suggestion's "Track" button
+async fn track_suggested_wallet(pool: &SqlitePool, user_id: i64, wallet: &str) -> String {
+    if !is_valid_address(wallet) {
+        return "❌ Invalid wallet address.".to_string();
+    }
+
+    match db::is_tracking(pool, user_id, wallet).await {
+        Ok(true) => return "ℹ️ You're already tracking this wallet.".to_string(),
+        Ok(false) => {}
+        Err(e) => {
+            error!("Failed to check tracked wallet: {}", e);
+            return "❌ Failed to add wallet. Please try again.".to_string();
+        }
+    }
+
+    let existing_count = db::get_user_wallet_count(pool, user_id).await.unwrap_or(0);
+    if existing_count >= db::MAX_WALLETS_PER_USER {
+        return format!(
+            "❌ You've reached the maximum limit of {} tracked wallets.",
+            db::MAX_WALLETS_PER_USER
+        );
+    }
 
-            handle_command(bot, msg, cmd, pool).await
+    match db::add_wallet(pool, user_id, wallet, None).await {
+        Ok(_) => {
+            info!("User {} added suggested wallet {}", user_id, wallet);
+            "✅ Now tracking this wallet.".to_string()
         }
-    })
-    .await;
+        Err(e) => {
+            error!("Failed to add suggested wallet: {}", e);
+            "❌ Failed to add wallet. Please try again.".to_string()
+        }
+    }
 }
 
 async fn handle_command(
@@ -56,20 +886,76 @@ async fn handle_command(
     msg: Message,
     cmd: Command,
     pool: SqlitePool,
+    state: Arc<RwLock<hyperliquid::PositionTracker>>,
 ) -> ResponseResult<()> {
     let user_id = msg.from.as_ref().map(|u| u.id.0 as i64).unwrap_or(0);
 
+    // Any command counts as activity for the inactivity cleanup
+    match db::touch_user(&pool, user_id).await {
+        Ok(0) => {}
+        Ok(restored) => {
+            info!(
+                "Restored {} wallets for returning user {}",
+                restored, user_id
+            );
+            bot.send_message(
+                msg.chat.id,
+                format!(
+                    "♻️ Welcome back! Tracking resumed for {} wallet(s) that were paused due to inactivity.",
+                    restored
+                ),
+            )
+            .parse_mode(ParseMode::Html)
+            .await?;
+        }
+        Err(e) => error!("Failed to record activity for {}: {}", user_id, e),
+    }
+
     match cmd {
         Command::Help => {
             bot.send_message(
                 msg.chat.id,
-                format!("<b>📚 Help</b>\n{}", Command::descriptions()),
+                format!(
+                    "<b>📚 Help</b>\n{}\n\n<b>Shortcuts:</b> {}",
+                    Command::descriptions(),
+                    COMMAND_ALIASES
+                        .iter()
+                        .map(|(alias, command)| format!("/{} → /{}", alias, command))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
             )
             .reply_to(msg.id)
             .parse_mode(ParseMode::Html)
             .await?;
         }
-        Command::Start => {
+        Command::Start(payload) => {
+            // Deep links like t.me/<bot>?start=0x… show the wallet, tracking it takes a tap
+            let shared = normalize_address(&payload).to_lowercase();
+            if is_valid_address(&shared) {
+                let text = wallet_positions_text(&state, &shared, None).await;
+                let tracking = db::is_tracking(&pool, user_id, &shared)
+                    .await
+                    .unwrap_or_else(|e| {
+                        error!("Failed to check tracked wallet: {}", e);
+                        false
+                    });
+                let reply = bot
+                    .send_message(msg.chat.id, text)
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html);
+                if tracking {
+                    reply.await?;
+                } else {
+                    let keyboard = InlineKeyboardMarkup::new([[InlineKeyboardButton::callback(
+                        "➕ Track this wallet",
+                        format!("track:{}", shared),
+                    )]]);
+                    reply.reply_markup(keyboard).await?;
+                }
+                return Ok(());
+            }
+
             let welcome = format!(
                 "<b>👋 Welcome to Hyperliquid Position Tracker!</b>\n\n\
                  I'll notify you when wallets you're tracking open or close positions on Hyperliquid.\n\
@@ -96,15 +982,29 @@ async fn handle_command(
                 return Ok(());
             }
 
+            let entries = parse_add_list(args);
+            if entries.len() > 1 {
+                let summary = add_wallets(&pool, user_id, entries).await;
+                bot.send_message(msg.chat.id, summary)
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                return Ok(());
+            }
+
             // Parse wallet and optional note
             let parts: Vec<&str> = args.splitn(2, ' ').collect();
-            let wallet = parts[0];
+            let normalized = normalize_address(parts[0]);
+            let wallet = normalized.as_str();
             let note = parts.get(1).map(|s| s.trim()).filter(|s| !s.is_empty());
 
             if !is_valid_address(wallet) {
                 bot.send_message(
                     msg.chat.id,
-                    "❌ Invalid wallet address format. Please provide a valid Ethereum address.",
+                    format!(
+                        "❌ Invalid wallet address format. Please provide a valid Ethereum address.\n\nParsed: <code>{}</code>",
+                        html::escape(wallet)
+                    ),
                 )
                 .reply_to(msg.id)
                 .parse_mode(ParseMode::Html)
@@ -174,15 +1074,11 @@ async fn handle_command(
             match db::add_wallet(&pool, user_id, wallet, note).await {
                 Ok(db::AddWalletResult::Added) => {
                     info!("User {} added wallet {}", user_id, wallet);
-                    let note_text = note
-                        .map(|n| format!(" ({})", html::escape(n)))
-                        .unwrap_or_default();
                     bot.send_message(
                         msg.chat.id,
                         format!(
-                            "✅ Now tracking wallet{}:\n<code>{}</code>",
-                            note_text,
-                            wallet.to_lowercase()
+                            "✅ Now tracking wallet:\n{}",
+                            wallet_confirmation(&pool, user_id, wallet, note).await
                         ),
                     )
                     .reply_to(msg.id)
@@ -191,15 +1087,12 @@ async fn handle_command(
                 }
                 Ok(db::AddWalletResult::Updated) => {
                     info!("User {} updated note for wallet {}", user_id, wallet);
-                    let note_text = note
-                        .map(|n| format!(" to '{}'", html::escape(n)))
-                        .unwrap_or_else(|| " (removed)".to_string());
                     bot.send_message(
                         msg.chat.id,
                         format!(
-                            "✅ Updated note{}:\n<code>{}</code>",
-                            note_text,
-                            wallet.to_lowercase()
+                            "✅ {} note:\n{}",
+                            if note.is_some() { "Updated" } else { "Removed" },
+                            wallet_confirmation(&pool, user_id, wallet, note).await
                         ),
                     )
                     .reply_to(msg.id)
@@ -215,6 +1108,15 @@ async fn handle_command(
                     .parse_mode(ParseMode::Html)
                     .await?;
                 }
+                Ok(db::AddWalletResult::NoteInUse) => {
+                    bot.send_message(
+                        msg.chat.id,
+                        "❌ You already have a wallet with this note. Please use a different note.",
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                }
                 Err(e) => {
                     error!("Failed to add wallet: {}", e);
                     bot.send_message(msg.chat.id, "❌ Failed to add wallet. Please try again.")
@@ -227,9 +1129,17 @@ async fn handle_command(
         Command::Remove(identifier) => {
             let identifier = identifier.trim();
             if identifier.is_empty() {
+                if let Some((text, keyboard)) = wallet_picker(&pool, user_id, "rm").await {
+                    bot.send_message(msg.chat.id, text)
+                        .reply_to(msg.id)
+                        .parse_mode(ParseMode::Html)
+                        .reply_markup(keyboard)
+                        .await?;
+                    return Ok(());
+                }
                 bot.send_message(
                     msg.chat.id,
-                    "❌ Please provide a wallet address, index (1-10), or note.\n\nUsage: <code>/remove &lt;address|index|note&gt;</code>",
+                    "❌ Please provide a wallet address, index (1-10), or note.\n\nUsage: <code>/remove &lt;address|index|note&gt; [more…]</code>",
                 )
                 .reply_to(msg.id)
                 .parse_mode(ParseMode::Html)
@@ -237,283 +1147,8618 @@ async fn handle_command(
                 return Ok(());
             }
 
+            // Several identifiers, unless the whole text names one wallet (a note with spaces)
+            let identifiers: Vec<&str> = identifier.split_whitespace().collect();
+            if identifiers.len() > 1
+                && matches!(
+                    resolve_identifier(&pool, user_id, identifier).await,
+                    Ok(None)
+                )
+            {
+                let targets = resolve_removals(&pool, user_id, &identifiers).await;
+                send_removal(&bot, &msg, &pool, &state, user_id, targets).await?;
+                return Ok(());
+            }
+
             // Resolve the identifier to a wallet address
-            let resolved = match resolve_wallet_identifier(&pool, user_id, identifier).await {
-                Ok(Some((addr, _))) => addr,
-                Ok(None) => {
-                    bot.send_message(
-                        msg.chat.id,
-                        "❌ Wallet not found. Use <code>/list</code> to see your tracked wallets.",
-                    )
-                    .reply_to(msg.id)
-                    .parse_mode(ParseMode::Html)
-                    .await?;
-                    return Ok(());
-                }
-                Err(e) => {
-                    error!("Failed to resolve wallet identifier: {}", e);
-                    bot.send_message(msg.chat.id, "❌ Failed to remove wallet. Please try again.")
-                        .reply_to(msg.id)
-                        .parse_mode(ParseMode::Html)
-                        .await?;
-                    return Ok(());
-                }
+            let Some((resolved, note)) = resolve_or_raw_address(
+                &bot,
+                &msg,
+                &pool,
+                user_id,
+                identifier,
+                "❌ Wallet not found. Use <code>/list</code> to see your tracked wallets.",
+                "❌ Failed to remove wallet. Please try again.",
+            )
+            .await?
+            else {
+                return Ok(());
             };
 
-            match db::remove_wallet(&pool, user_id, &resolved).await {
-                Ok(true) => {
-                    info!("User {} removed wallet {}", user_id, resolved);
+            let target = removal_target(&pool, user_id, resolved, note).await;
+            send_removal(
+                &bot,
+                &msg,
+                &pool,
+                &state,
+                user_id,
+                vec![(identifier.to_string(), target)],
+            )
+            .await?;
+        }
+        Command::List(mode) => {
+            let full = match mode.trim() {
+                "" => false,
+                mode if mode.eq_ignore_ascii_case("full") => true,
+                _ => {
                     bot.send_message(
                         msg.chat.id,
-                        format!("✅ Stopped tracking wallet:\n<code>{}</code>", resolved),
+                        "❌ Unknown option.\n\nUsage: <code>/list</code> or <code>/list full</code> for live account data",
                     )
                     .reply_to(msg.id)
                     .parse_mode(ParseMode::Html)
                     .await?;
+                    return Ok(());
                 }
-                Ok(false) => {
-                    bot.send_message(msg.chat.id, "⚠️ This wallet was not being tracked.")
-                        .reply_to(msg.id)
-                        .parse_mode(ParseMode::Html)
-                        .await?;
-                }
-                Err(e) => {
-                    error!("Failed to remove wallet: {}", e);
-                    bot.send_message(msg.chat.id, "❌ Failed to remove wallet. Please try again.")
+            };
+            match db::get_user_wallets(&pool, user_id).await {
+                Ok(wallets) => {
+                    let coin_filters = db::get_user_watched_coins(&pool, user_id)
+                        .await
+                        .unwrap_or_else(|e| {
+                            error!("Failed to fetch coin filters: {}", e);
+                            HashMap::new()
+                        });
+                    let tags = db::get_user_wallet_tags(&pool, user_id)
+                        .await
+                        .unwrap_or_else(|e| {
+                            error!("Failed to fetch wallet tags: {}", e);
+                            HashMap::new()
+                        });
+                    if wallets.is_empty() {
+                        bot.send_message(
+                            msg.chat.id,
+                            "📋 You're not tracking any wallets yet.\n\nUse <code>/add &lt;wallet&gt; [note]</code> to start tracking.",
+                        )
                         .reply_to(msg.id)
                         .parse_mode(ParseMode::Html)
                         .await?;
-                }
-            }
-        }
-        Command::List => match db::get_user_wallets(&pool, user_id).await {
-            Ok(wallets) => {
-                if wallets.is_empty() {
-                    bot.send_message(
+                    } else {
+                        let now = chrono::Utc::now().timestamp();
+                        let mut wallet_list: Vec<String> = wallets
+                            .iter()
+                            .enumerate()
+                            .map(|(i, w)| {
+                                let display = format_wallet_display(
+                                    &w.wallet_address,
+                                    w.note.as_deref(),
+                                    true,
+                                );
+                                let muted = if w.muted { " 🔕" } else { "" };
+                                let snoozed = w
+                                    .snoozed_for(now)
+                                    .map(|left| format!(" 💤 {}", format_duration(left)))
+                                    .unwrap_or_default();
+                                let filter = coin_filters
+                                    .get(&w.wallet_address)
+                                    .map(|coins| format!(" 🎯 {}", coins.join(", ")))
+                                    .unwrap_or_default();
+                                let tags = tags
+                                    .get(&w.wallet_address)
+                                    .map(|tags| format!(" 🏷 {}", html::escape(&tags.join(", "))))
+                                    .unwrap_or_default();
+                                format!(
+                                    "{}. {}{}{}{}{}",
+                                    i + 1,
+                                    display,
+                                    muted,
+                                    snoozed,
+                                    filter,
+                                    tags
+                                )
+                            })
+                            .collect();
+
+                        // Only the full list calls the API, the plain one stays instant
+                        let mut footer = String::new();
+                        if full {
+                            let states = fetch_wallet_states(&wallets).await;
+                            let (mut total_value, mut total_pnl, mut total_positions) =
+                                (0.0, 0.0, 0);
+                            for ((line, w), state) in
+                                wallet_list.iter_mut().zip(&wallets).zip(states)
+                            {
+                                match state {
+                                    Ok(state) => {
+                                        let value: f64 = state
+                                            .margin_summary
+                                            .account_value
+                                            .parse()
+                                            .unwrap_or(0.0);
+                                        let pnl: f64 = state
+                                            .asset_positions
+                                            .iter()
+                                            .map(|ap| {
+                                                ap.position
+                                                    .unrealized_pnl
+                                                    .parse::<f64>()
+                                                    .unwrap_or(0.0)
+                                            })
+                                            .sum();
+                                        let positions = state.asset_positions.len();
+                                        total_value += value;
+                                        total_pnl += pnl;
+                                        total_positions += positions;
+                                        line.push_str(&format!(
+                                            "\n   💼 ${:.2} · 📊 {} open · {}",
+                                            value,
+                                            positions,
+                                            hyperliquid::format_pnl(pnl)
+                                        ));
+                                    }
+                                    Err(e) => {
+                                        warn!(
+                                            "Failed to fetch state of {} for /list: {}",
+                                            w.wallet_address, e
+                                        );
+                                        line.push_str("\n   (unavailable)");
+                                    }
+                                }
+                            }
+                            footer = format!(
+                                "\n\n<b>Total:</b> 💼 ${:.2} · 📊 {} open · {}",
+                                total_value,
+                                total_positions,
+                                hyperliquid::format_pnl(total_pnl)
+                            );
+                        }
+
+                        bot.send_message(
                             msg.chat.id,
-                            "📋 You're not tracking any wallets yet.\n\nUse <code>/add &lt;wallet&gt; [note]</code> to start tracking.",
+                            format!(
+                                "<b>📋 Your tracked wallets:</b>\n\n{}{}",
+                                wallet_list.join("\n"),
+                                footer
+                            ),
                         )
                         .reply_to(msg.id)
                         .parse_mode(ParseMode::Html)
                         .await?;
-                } else {
-                    let wallet_list: String = wallets
-                        .iter()
-                        .enumerate()
-                        .map(|(i, w)| {
-                            let display =
-                                format_wallet_display(&w.wallet_address, w.note.as_deref(), true);
-                            format!("{}. {}", i + 1, display)
-                        })
-                        .collect::<Vec<_>>()
-                        .join("\n");
-
+                    }
+                }
+                Err(e) => {
+                    error!("Failed to list wallets: {}", e);
                     bot.send_message(
                         msg.chat.id,
-                        format!("<b>📋 Your tracked wallets:</b>\n\n{}", wallet_list),
+                        "❌ Failed to retrieve wallets. Please try again.",
                     )
                     .reply_to(msg.id)
                     .parse_mode(ParseMode::Html)
                     .await?;
                 }
             }
-            Err(e) => {
-                error!("Failed to list wallets: {}", e);
-                bot.send_message(
+        }
+        Command::Positions(identifier) => {
+            // Without a wallet, offer the tracked ones; users tracking none fall back to the last wallet
+            if identifier.trim().is_empty()
+                && let Some((text, keyboard)) = wallet_picker(&pool, user_id, "pos").await
+            {
+                bot.send_message(msg.chat.id, text)
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .reply_markup(keyboard)
+                    .await?;
+                return Ok(());
+            }
+
+            let Some((identifier, assumed)) = identifier_or_last(user_id, &identifier) else {
+                bot.send_message(
                     msg.chat.id,
-                    "❌ Failed to retrieve wallets. Please try again.",
+                    "❌ Please provide a wallet address, index (1-10), or note.\n\nUsage: <code>/positions &lt;address|index|note&gt;</code>",
                 )
                 .reply_to(msg.id)
                 .parse_mode(ParseMode::Html)
                 .await?;
+                return Ok(());
+            };
+            let identifier = identifier.as_str();
+
+            // Resolve the identifier to a wallet address
+            let Some((wallet, note)) = resolve_or_raw_address(
+                &bot,
+                &msg,
+                &pool,
+                user_id,
+                identifier,
+                WALLET_NOT_FOUND,
+                "❌ Failed to fetch positions. Please try again.",
+            )
+            .await?
+            else {
+                return Ok(());
+            };
+
+            if assumed {
+                send_assumed_wallet_notice(&bot, &msg, &wallet, note.as_deref()).await?;
             }
-        },
-        Command::Positions(identifier) => {
+
+            let text = wallet_positions_text(&state, &wallet, note.as_deref()).await;
+            bot.send_message(msg.chat.id, text)
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+        }
+        Command::Logbook(args) => {
+            let usage = "❌ Please provide a wallet and a mode.\n\nUsage: <code>/logbook &lt;address|index|note&gt; &lt;on|pin|off&gt;</code>";
+            let Some((identifier, mode)) = args.trim().rsplit_once(char::is_whitespace) else {
+                bot.send_message(msg.chat.id, usage)
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                return Ok(());
+            };
             let identifier = identifier.trim();
-            if identifier.is_empty() {
+            let mode = mode.to_lowercase();
+            if !matches!(mode.as_str(), "on" | "pin" | "off") {
+                bot.send_message(msg.chat.id, usage)
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                return Ok(());
+            }
+
+            // Resolve the identifier to a tracked wallet
+            let resolved = match resolve_wallet_identifier(&pool, user_id, identifier).await {
+                Ok(Some((addr, note))) => match db::is_tracking(&pool, user_id, &addr).await {
+                    Ok(true) => Some((addr, note)),
+                    Ok(false) => None,
+                    Err(e) => {
+                        error!("Failed to check tracked wallet: {}", e);
+                        None
+                    }
+                },
+                Ok(None) => None,
+                Err(e) => {
+                    error!("Failed to resolve wallet identifier: {}", e);
+                    None
+                }
+            };
+            let Some((wallet, note)) = resolved else {
                 bot.send_message(
                     msg.chat.id,
-                    "❌ Please provide a wallet address, index (1-10), or note.\n\nUsage: <code>/positions &lt;address|index|note&gt;</code>",
+                    "❌ Wallet not found. Use <code>/list</code> to see your tracked wallets.",
                 )
                 .reply_to(msg.id)
                 .parse_mode(ParseMode::Html)
                 .await?;
                 return Ok(());
-            }
+            };
 
-            // Resolve the identifier to a wallet address
-            let (wallet, note) = match resolve_wallet_identifier(&pool, user_id, identifier).await {
-                Ok(Some((addr, note))) => (addr, note),
-                Ok(None) => {
-                    // If not found in user's wallets but looks like a valid address, use it directly
-                    if is_valid_address(identifier) {
-                        (identifier.to_lowercase(), None)
+            let confirmation = wallet_confirmation(&pool, user_id, &wallet, note.as_deref()).await;
+            let result = if mode == "off" {
+                db::disable_logbook(&pool, user_id, &wallet).await.map(|removed| {
+                    if removed {
+                        format!(
+                            "✅ Logbook disabled for wallet:\n{}\n\nNotifications will be sent as separate messages again.",
+                            confirmation
+                        )
                     } else {
+                        format!("⚠️ Logbook mode was not enabled for wallet:\n{}", confirmation)
+                    }
+                })
+            } else {
+                let pin = mode == "pin";
+                db::enable_logbook(&pool, user_id, &wallet, pin)
+                    .await
+                    .map(|_| {
+                        let pin_text = if pin { " and kept pinned" } else { "" };
+                        format!(
+                            "📒 Logbook enabled for wallet:\n{}\n\nNew activity will be appended to a single message{}.",
+                            confirmation, pin_text
+                        )
+                    })
+            };
+
+            match result {
+                Ok(text) => {
+                    info!(
+                        "User {} set logbook {} for wallet {}",
+                        user_id, mode, wallet
+                    );
+                    bot.send_message(msg.chat.id, text)
+                        .reply_to(msg.id)
+                        .parse_mode(ParseMode::Html)
+                        .await?;
+                }
+                Err(e) => {
+                    error!("Failed to update logbook: {}", e);
+                    bot.send_message(
+                        msg.chat.id,
+                        "❌ Failed to update logbook. Please try again.",
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                }
+            }
+        }
+        Command::Mirror(args) => {
+            let usage = "Usage:\n\
+                         <code>/mirror on|off</code>\n\
+                         <code>/mirror bankroll &lt;usd&gt;</code>\n\
+                         <code>/mirror tolerance &lt;percent&gt;</code>";
+            let parts: Vec<String> = args.split_whitespace().map(|s| s.to_lowercase()).collect();
+
+            let result = match parts.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+                [] => Ok(None),
+                ["on"] => db::set_mirror_enabled(&pool, user_id, true).await.map(Some),
+                ["off"] => db::set_mirror_enabled(&pool, user_id, false)
+                    .await
+                    .map(Some),
+                ["bankroll", value] => match parse_amount(value).filter(|v| *v > 0.0) {
+                    Some(bankroll) => db::set_mirror_bankroll(&pool, user_id, bankroll)
+                        .await
+                        .map(Some),
+                    None => {
                         bot.send_message(
                             msg.chat.id,
-                            "❌ Wallet not found. Provide a valid address, index (1-10), or note.",
+                            "❌ Bankroll must be a positive USD amount, e.g. <code>/mirror bankroll 5000</code>",
                         )
                         .reply_to(msg.id)
                         .parse_mode(ParseMode::Html)
                         .await?;
                         return Ok(());
                     }
+                },
+                ["tolerance", value] => {
+                    match parse_amount(value.trim_end_matches('%'))
+                        .filter(|v| (0.0..=10.0).contains(v))
+                    {
+                        Some(tolerance) => db::set_mirror_tolerance(&pool, user_id, tolerance)
+                            .await
+                            .map(Some),
+                        None => {
+                            bot.send_message(
+                                msg.chat.id,
+                                "❌ Tolerance must be a percentage between 0 and 10, e.g. <code>/mirror tolerance 0.5</code>",
+                            )
+                            .reply_to(msg.id)
+                            .parse_mode(ParseMode::Html)
+                            .await?;
+                            return Ok(());
+                        }
+                    }
+                }
+                _ => {
+                    bot.send_message(msg.chat.id, format!("❌ Unknown option.\n\n{}", usage))
+                        .reply_to(msg.id)
+                        .parse_mode(ParseMode::Html)
+                        .await?;
+                    return Ok(());
+                }
+            };
+
+            if let Err(e) = result {
+                error!("Failed to update mirror settings: {}", e);
+                bot.send_message(
+                    msg.chat.id,
+                    "❌ Failed to update mirror settings. Please try again.",
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            }
+
+            match db::get_user_settings(&pool, user_id).await {
+                Ok(settings) => {
+                    let status = if settings.mirror_enabled {
+                        "🟢 On"
+                    } else {
+                        "⚪ Off"
+                    };
+                    bot.send_message(
+                        msg.chat.id,
+                        format!(
+                            "<b>🪞 Mirror Settings</b>\n\n\
+                             Status: {}\n\
+                             Bankroll: {}\n\
+                             Entry tolerance: ±{}%\n\n\
+                             <i>When on, opened and increased notifications include a copy-paste block sized to your bankroll.</i>\n\n\
+                             {}",
+                            status,
+                            hyperliquid::format_usd_compact(settings.mirror_bankroll),
+                            settings.mirror_tolerance_pct,
+                            usage
+                        ),
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
                 }
                 Err(e) => {
-                    error!("Failed to resolve wallet identifier: {}", e);
+                    error!("Failed to load mirror settings: {}", e);
                     bot.send_message(
                         msg.chat.id,
-                        "❌ Failed to fetch positions. Please try again.",
+                        "❌ Failed to load mirror settings. Please try again.",
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                }
+            }
+        }
+        Command::MarginAlert(args) => {
+            let result = match args.trim().to_lowercase().as_str() {
+                "" => Ok(()),
+                "on" => db::set_margin_alerts(&pool, user_id, true).await,
+                "off" => db::set_margin_alerts(&pool, user_id, false).await,
+                bands => {
+                    match BandedAlert::parse_percent_bands(bands, db::MARGIN_BAND_HYSTERESIS) {
+                        Ok(bands) => {
+                            // Levels of the old bands mean nothing for the new ones
+                            state
+                                .write()
+                                .await
+                                .margin_levels
+                                .retain(|(level_user, _), _| *level_user != user_id);
+                            db::set_margin_bands(&pool, user_id, &bands).await
+                        }
+                        Err(reason) => {
+                            bot.send_message(
+                                msg.chat.id,
+                                format!(
+                                    "❌ Invalid bands: {}.\n\nUsage: <code>/marginalert on|off</code> or <code>/marginalert 60,80,90</code> (up to {} thresholds, 1-100%, more than {} points apart)",
+                                    html::escape(&reason),
+                                    alerts::MAX_BANDS,
+                                    db::MARGIN_BAND_HYSTERESIS
+                                ),
+                            )
+                            .reply_to(msg.id)
+                            .parse_mode(ParseMode::Html)
+                            .await?;
+                            return Ok(());
+                        }
+                    }
+                }
+            };
+
+            match result.and(db::get_user_settings(&pool, user_id).await) {
+                Ok(settings) => {
+                    let status = if settings.margin_alerts {
+                        "🟢 On"
+                    } else {
+                        "⚪ Off"
+                    };
+                    let bands = settings
+                        .margin_bands()
+                        .thresholds()
+                        .iter()
+                        .map(|t| format!("{}%", t))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    bot.send_message(
+                        msg.chat.id,
+                        format!(
+                            "<b>⚠️ Margin Alerts</b>\n\n\
+                             Status: {}\n\
+                             Bands: {}\n\n\
+                             <i>When on, you're alerted when a tracked wallet's margin usage climbs past one of these shares of its account value, again once it dropped {} points below.</i>\n\n\
+                             Usage: <code>/marginalert on|off</code>, <code>/marginalert 60,80,90</code> to change the bands",
+                            status,
+                            bands,
+                            db::MARGIN_BAND_HYSTERESIS
+                        ),
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                }
+                Err(e) => {
+                    error!("Failed to update margin alerts: {}", e);
+                    bot.send_message(
+                        msg.chat.id,
+                        "❌ Failed to update margin alerts. Please try again.",
                     )
                     .reply_to(msg.id)
                     .parse_mode(ParseMode::Html)
                     .await?;
+                }
+            }
+        }
+        Command::Entries(args) => {
+            let Some((identifier, coin)) = args.trim().rsplit_once(char::is_whitespace) else {
+                bot.send_message(
+                    msg.chat.id,
+                    "❌ Please provide a wallet and a coin.\n\nUsage: <code>/entries &lt;address|index|note&gt; &lt;coin&gt;</code>",
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            };
+            let identifier = identifier.trim();
+
+            let Some((wallet, note)) = resolve_or_raw_address(
+                &bot,
+                &msg,
+                &pool,
+                user_id,
+                identifier,
+                WALLET_NOT_FOUND,
+                "❌ Failed to fetch entries. Please try again.",
+            )
+            .await?
+            else {
+                return Ok(());
+            };
+            let wallet_display = format_wallet_display(&wallet, note.as_deref(), false);
+
+            let events = match db::get_entry_events(&pool, &wallet, coin, 20).await {
+                Ok(events) => events,
+                Err(e) => {
+                    error!("Failed to fetch entries for {}: {}", wallet, e);
+                    bot.send_message(msg.chat.id, "❌ Failed to fetch entries. Please try again.")
+                        .reply_to(msg.id)
+                        .parse_mode(ParseMode::Html)
+                        .await?;
+                    return Ok(());
+                }
+            };
+
+            if events.is_empty() {
+                let coin_hint = match hyperliquid::renamed_perp(coin) {
+                    Some(name) => format!(
+                        "\n\n⚠️ <b>{}</b> isn't a perp on Hyperliquid. Did you mean <b>{}</b>?",
+                        html::escape(coin),
+                        html::escape(&name)
+                    ),
+                    None if hyperliquid::is_listed_perp(coin) == Some(false) => format!(
+                        "\n\n⚠️ <b>{}</b> isn't a listed perp on Hyperliquid (spot-only or delisted coins never show up in positions).",
+                        html::escape(coin)
+                    ),
+                    None => String::new(),
+                };
+                bot.send_message(
+                    msg.chat.id,
+                    format!(
+                        "<b>📝 {} Entries</b>\n\n\
+                         👛 Wallet: {}\n\n\
+                         <i>No recorded opens or increases yet. Entries are recorded while a wallet is tracked.</i>{}",
+                        html::escape(coin),
+                        wallet_display,
+                        coin_hint
+                    ),
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            }
+
+            // Current price from the monitor's cache, falling back to a fresh fetch
+            let coin_name = events[0].coin.clone();
+            let cached_price = state.read().await.mids.get(&coin_name).copied();
+            let price_now = match cached_price {
+                Some(price) => Some(price),
+                None => hyperliquid::fetch_all_mids(&HTTP_CLIENT)
+                    .await
+                    .ok()
+                    .and_then(|mids| mids.get(&coin_name).copied()),
+            };
+
+            let mut message = format!(
+                "<b>📝 {} Entries</b>\n\n\
+                 👛 Wallet: {}\n\
+                 📍 Now: {}\n",
+                coin_name,
+                wallet_display,
+                price_now
+                    .map(|p| hyperliquid::format_coin_price(&coin_name, p))
+                    .unwrap_or_else(|| "n/a".to_string())
+            );
+            let tz = user_timezone(&pool, user_id).await;
+            for event in &events {
+                let direction_emoji = if event.is_long { "🟢" } else { "🔴" };
+                let then_str = event
+                    .mark_price
+                    .map(|p| hyperliquid::format_coin_price(&coin_name, p))
+                    .unwrap_or_else(|| "n/a".to_string());
+                // How the decision has played out since, from the trader's side
+                let performance = match (event.mark_price, price_now) {
+                    (Some(then), Some(now)) if then > 0.0 => {
+                        let change = (now - then) / then * 100.0;
+                        let change = if event.is_long { change } else { -change };
+                        if change >= 0.0 {
+                            format!(" (+{:.2}%)", change)
+                        } else {
+                            format!(" ({:.2}%)", change)
+                        }
+                    }
+                    _ => String::new(),
+                };
+                message.push_str(&format!(
+                    "\n{} <b>{} {}</b> +{} → {} · {}\n\
+                     💰 Avg entry: {}\n\
+                     🕰 Price then: {}{}\n",
+                    direction_emoji,
+                    hyperliquid::direction_str(event.is_long),
+                    event.kind,
+                    hyperliquid::format_size(event.size_delta),
+                    hyperliquid::format_size(event.size),
+                    tz.format_stored(&event.created_at),
+                    hyperliquid::format_coin_price(&coin_name, event.entry_price),
+                    then_str,
+                    performance
+                ));
+            }
+
+            bot.send_message(msg.chat.id, message)
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+        }
+        Command::Token(args) => {
+            if server::ServerConfig::from_env().is_none() {
+                bot.send_message(
+                    msg.chat.id,
+                    "❌ The HTTP API isn't enabled on this instance.",
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            }
+
+            match args.trim().to_lowercase().as_str() {
+                "" => {
+                    let token = server::generate_token();
+                    match db::set_api_token(&pool, user_id, &server::hash_token(&token)).await {
+                        Ok(()) => {
+                            bot.send_message(
+                                msg.chat.id,
+                                format!(
+                                    "<b>🔑 API Token</b>\n\n\
+                                     <code>{}</code>\n\n\
+                                     Send it as <code>Authorization: Bearer &lt;token&gt;</code> to:\n\
+                                     • <code>GET /api/wallets</code>\n\
+                                     • <code>GET /api/positions/&lt;wallet&gt;</code>\n\
+                                     • <code>GET /api/events?since=&lt;unix seconds&gt;</code>\n\n\
+                                     <i>The token is only shown once and replaces any previous one. Use /token revoke to disable it.</i>",
+                                    token
+                                ),
+                            )
+                            .reply_to(msg.id)
+                            .parse_mode(ParseMode::Html)
+                            .await?;
+                        }
+                        Err(e) => {
+                            error!("Failed to issue API token: {}", e);
+                            bot.send_message(
+                                msg.chat.id,
+                                "❌ Failed to create a token. Please try again.",
+                            )
+                            .reply_to(msg.id)
+                            .parse_mode(ParseMode::Html)
+                            .await?;
+                        }
+                    }
+                }
+                "revoke" => {
+                    let text = match db::revoke_api_token(&pool, user_id).await {
+                        Ok(true) => "✅ API token revoked.",
+                        Ok(false) => "ℹ️ You don't have an API token.",
+                        Err(e) => {
+                            error!("Failed to revoke API token: {}", e);
+                            "❌ Failed to revoke the token. Please try again."
+                        }
+                    };
+                    bot.send_message(msg.chat.id, text)
+                        .reply_to(msg.id)
+                        .parse_mode(ParseMode::Html)
+                        .await?;
+                }
+                _ => {
+                    bot.send_message(msg.chat.id, "❌ Usage: <code>/token [revoke]</code>")
+                        .reply_to(msg.id)
+                        .parse_mode(ParseMode::Html)
+                        .await?;
+                }
+            }
+        }
+        Command::Suggest(args) => {
+            let result = match args.trim().to_lowercase().as_str() {
+                "" => Ok(()),
+                "on" => db::set_weekly_suggestions(&pool, user_id, true).await,
+                "off" => db::set_weekly_suggestions(&pool, user_id, false).await,
+                _ => {
+                    bot.send_message(msg.chat.id, "❌ Usage: <code>/suggest on|off</code>")
+                        .reply_to(msg.id)
+                        .parse_mode(ParseMode::Html)
+                        .await?;
                     return Ok(());
                 }
             };
 
-            let client = Client::builder()
-                .timeout(Duration::from_secs(30))
-                .build()
-                .expect("Failed to create HTTP client");
+            match result.and(db::get_user_settings(&pool, user_id).await) {
+                Ok(settings) => {
+                    let status = if settings.weekly_suggestions {
+                        "🟢 On"
+                    } else {
+                        "⚪ Off"
+                    };
+                    bot.send_message(
+                        msg.chat.id,
+                        format!(
+                            "<b>🔭 Weekly Suggestions</b>\n\n\
+                             Status: {}\n\n\
+                             <i>When on, you get up to three top wallets of the week once a week, picked for trading the same coins or similar sizes as your tracked wallets.</i>\n\n\
+                             Usage: <code>/suggest on|off</code>",
+                            status
+                        ),
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                }
+                Err(e) => {
+                    error!("Failed to update weekly suggestions: {}", e);
+                    bot.send_message(
+                        msg.chat.id,
+                        "❌ Failed to update weekly suggestions. Please try again.",
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                }
+            }
+        }
+        Command::Risk(args) => {
+            // A plain number past the wallet indexes or one ending in % is a concentration limit,
+            // anything else names a wallet
+            let args = args.trim();
+            let is_limit = args.is_empty()
+                || args.ends_with('%')
+                || args
+                    .parse::<f64>()
+                    .is_ok_and(|n| n > db::MAX_WALLETS_PER_USER as f64 || n.fract() != 0.0);
+            if !is_limit {
+                return send_wallet_risk(&bot, &msg, &pool, user_id, args).await;
+            }
+
+            let args = args.trim_end_matches('%');
+            let limit_pct = if args.is_empty() {
+                DEFAULT_RISK_LIMIT_PCT
+            } else {
+                match args.parse::<f64>() {
+                    Ok(pct) if pct > 0.0 && pct <= 100.0 => pct,
+                    _ => {
+                        bot.send_message(
+                            msg.chat.id,
+                            "❌ Please provide a limit between 0 and 100.\n\nUsage: <code>/risk [percent%]</code>",
+                        )
+                        .reply_to(msg.id)
+                        .parse_mode(ParseMode::Html)
+                        .await?;
+                        return Ok(());
+                    }
+                }
+            };
+
+            let wallets = match db::get_user_wallets(&pool, user_id).await {
+                Ok(wallets) => wallets,
+                Err(e) => {
+                    error!("Failed to fetch wallets: {}", e);
+                    bot.send_message(msg.chat.id, "❌ Failed to fetch risk. Please try again.")
+                        .reply_to(msg.id)
+                        .parse_mode(ParseMode::Html)
+                        .await?;
+                    return Ok(());
+                }
+            };
+
+            if wallets.is_empty() {
+                bot.send_message(
+                    msg.chat.id,
+                    "📋 You're not tracking any wallets yet.\n\nUse <code>/add &lt;wallet&gt; [note]</code> to start tracking.",
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            }
+
+            // Margin per (coin, is_long), with each wallet's share of it
+            let mut exposure: HashMap<(String, bool), CoinExposure> = HashMap::new();
+            let mut equity = 0.0;
+            let mut not_fetched = 0;
+            {
+                let tracker = state.read().await;
+                for (i, wallet) in wallets.iter().enumerate() {
+                    let Some(snapshot) = tracker.snapshots.get(&wallet.wallet_address) else {
+                        not_fetched += 1;
+                        continue;
+                    };
+                    equity += snapshot
+                        .margin_summary
+                        .account_value
+                        .parse::<f64>()
+                        .unwrap_or(0.0);
+                    for ap in &snapshot.asset_positions {
+                        let size: f64 = ap.position.szi.parse().unwrap_or(0.0);
+                        let margin: f64 = ap.position.margin_used.parse().unwrap_or(0.0);
+                        if size == 0.0 || margin <= 0.0 {
+                            continue;
+                        }
+                        let entry = exposure
+                            .entry((ap.position.coin.clone(), size > 0.0))
+                            .or_default();
+                        entry.margin += margin;
+                        entry.by_wallet.push((i, margin));
+                    }
+                }
+            }
+
+            let not_fetched_note = if not_fetched > 0 {
+                format!(
+                    "\n\n<i>{} wallet(s) haven't been fetched yet and aren't included.</i>",
+                    not_fetched
+                )
+            } else {
+                String::new()
+            };
+
+            if exposure.is_empty() || equity <= 0.0 {
+                bot.send_message(
+                    msg.chat.id,
+                    format!(
+                        "<b>⚖️ Risk Concentration</b>\n\n\
+                         ✅ No open risk: none of your tracked wallets have open positions.{}",
+                        not_fetched_note
+                    ),
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            }
+
+            let mut ranked: Vec<((String, bool), CoinExposure)> = exposure.into_iter().collect();
+            ranked.sort_by(|a, b| {
+                b.1.margin
+                    .total_cmp(&a.1.margin)
+                    .then_with(|| a.0.cmp(&b.0))
+            });
+
+            let lines = ranked
+                .iter()
+                .take(10)
+                .enumerate()
+                .map(|(rank, ((coin, is_long), exposure))| {
+                    let share = exposure.margin / equity * 100.0;
+                    let flag = if share > limit_pct { "⚠️ " } else { "" };
+                    format!(
+                        "{}. {}{} {}: ${:.2} ({:.1}%)",
+                        rank + 1,
+                        flag,
+                        coin,
+                        hyperliquid::direction_str(*is_long),
+                        exposure.margin,
+                        share
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            let ((top_coin, top_long), top) = &ranked[0];
+            let top_share = top.margin / equity * 100.0;
+            let summary = if top_share > limit_pct {
+                format!(
+                    "⚠️ {:.1}% of your tracked equity is committed to {} {}, above your {:.0}% limit.",
+                    top_share,
+                    top_coin,
+                    hyperliquid::direction_str(*top_long),
+                    limit_pct
+                )
+            } else {
+                format!(
+                    "✅ No coin and direction is above {:.0}% of your tracked equity.",
+                    limit_pct
+                )
+            };
+            let contributor_note = top
+                .by_wallet
+                .iter()
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(i, margin)| {
+                    let wallet = &wallets[*i];
+                    format!(
+                        "\n\n<i>Most of the {} {} margin comes from</i> {} (${:.2}, {:.0}%)",
+                        top_coin,
+                        hyperliquid::direction_str(*top_long),
+                        format_wallet_display(
+                            &wallet.wallet_address,
+                            wallet.note.as_deref(),
+                            false
+                        ),
+                        margin,
+                        margin / top.margin * 100.0
+                    )
+                })
+                .unwrap_or_default();
+
+            bot.send_message(
+                msg.chat.id,
+                format!(
+                    "<b>⚖️ Risk Concentration</b>\n\n\
+                     💰 Combined equity: ${:.2}\n\n\
+                     <b>Margin by coin:</b>\n\
+                     {}\n\n\
+                     {}{}{}",
+                    equity, lines, summary, contributor_note, not_fetched_note
+                ),
+            )
+            .reply_to(msg.id)
+            .parse_mode(ParseMode::Html)
+            .await?;
+        }
+        Command::Threads(args) => {
+            let result = match args.trim().to_lowercase().as_str() {
+                "" => Ok(()),
+                "on" => db::set_threaded_notifications(&pool, user_id, true).await,
+                "off" => db::set_threaded_notifications(&pool, user_id, false).await,
+                _ => {
+                    bot.send_message(msg.chat.id, "❌ Usage: <code>/threads on|off</code>")
+                        .reply_to(msg.id)
+                        .parse_mode(ParseMode::Html)
+                        .await?;
+                    return Ok(());
+                }
+            };
+
+            match result.and(db::get_user_settings(&pool, user_id).await) {
+                Ok(settings) => {
+                    let status = if settings.threaded_notifications {
+                        "🟢 On"
+                    } else {
+                        "⚪ Off"
+                    };
+                    bot.send_message(
+                        msg.chat.id,
+                        format!(
+                            "<b>🧵 Threaded Notifications</b>\n\n\
+                             Status: {}\n\n\
+                             <i>When on, each notification replies to the previous one for the same wallet, so every wallet's activity forms its own chain.</i>\n\n\
+                             Usage: <code>/threads on|off</code>",
+                            status
+                        ),
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                }
+                Err(e) => {
+                    error!("Failed to update threaded notifications: {}", e);
+                    bot.send_message(
+                        msg.chat.id,
+                        "❌ Failed to update threaded notifications. Please try again.",
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                }
+            }
+        }
+        Command::Cluster => {
+            let wallets = match db::get_user_wallets(&pool, user_id).await {
+                Ok(wallets) => wallets,
+                Err(e) => {
+                    error!("Failed to fetch wallets: {}", e);
+                    bot.send_message(
+                        msg.chat.id,
+                        "❌ Failed to analyze wallets. Please try again.",
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                    return Ok(());
+                }
+            };
+
+            if wallets.len() < 2 {
+                bot.send_message(
+                    msg.chat.id,
+                    "📋 Track at least two wallets to look for links between them.\n\nUse <code>/add &lt;wallet&gt; [note]</code> to start tracking.",
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            }
+
+            let mut ledgers = HashMap::new();
+            for wallet in &wallets {
+                match db::get_ledger_entries(&pool, &wallet.wallet_address).await {
+                    Ok(entries) => {
+                        ledgers.insert(wallet.wallet_address.clone(), entries);
+                    }
+                    Err(e) => {
+                        error!(
+                            "Failed to fetch ledger for {}: {}",
+                            wallet.wallet_address, e
+                        );
+                        bot.send_message(
+                            msg.chat.id,
+                            "❌ Failed to analyze wallets. Please try again.",
+                        )
+                        .reply_to(msg.id)
+                        .parse_mode(ParseMode::Html)
+                        .await?;
+                        return Ok(());
+                    }
+                }
+            }
+
+            bot.send_message(
+                msg.chat.id,
+                ledger::format_cluster_report(
+                    &wallets,
+                    &ledgers,
+                    &user_timezone(&pool, user_id).await,
+                ),
+            )
+            .reply_to(msg.id)
+            .parse_mode(ParseMode::Html)
+            .await?;
+        }
+        Command::FundingPnl(args) => {
+            let args = args.trim();
+            let (identifier, toggle) = match args.rsplit_once(char::is_whitespace) {
+                Some((identifier, toggle))
+                    if toggle.eq_ignore_ascii_case("on") || toggle.eq_ignore_ascii_case("off") =>
+                {
+                    (identifier.trim(), Some(toggle.eq_ignore_ascii_case("on")))
+                }
+                _ => (args, None),
+            };
+
+            if identifier.is_empty() {
+                bot.send_message(
+                    msg.chat.id,
+                    "❌ Please provide a wallet.\n\nUsage: <code>/fundingpnl &lt;address|index|note&gt; [on|off]</code>",
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            }
+
+            let Some((wallet, note)) = resolve_or_raw_address(
+                &bot,
+                &msg,
+                &pool,
+                user_id,
+                identifier,
+                WALLET_NOT_FOUND,
+                "❌ Failed to update funding collection. Please try again.",
+            )
+            .await?
+            else {
+                return Ok(());
+            };
+
+            let result = match toggle {
+                Some(enabled) => db::set_collect_funding(&pool, user_id, &wallet, enabled)
+                    .await
+                    .map(|_| ()),
+                None => Ok(()),
+            };
+
+            match result.and(db::is_collecting_funding(&pool, user_id, &wallet).await) {
+                Ok(enabled) => {
+                    let status = if enabled { "🟢 On" } else { "⚪ Off" };
+                    bot.send_message(
+                        msg.chat.id,
+                        format!(
+                            "<b>🏦 Funding PnL</b>\n\n\
+                             👛 Wallet: {}\n\
+                             Status: {}\n\n\
+                             <i>When on, funding paid and received is added up for each open position and closed notifications show the net result after funding. Positions that were already open only count funding since collection started.</i>\n\n\
+                             Usage: <code>/fundingpnl &lt;wallet&gt; on|off</code>",
+                            format_wallet_display(&wallet, note.as_deref(), false),
+                            status
+                        ),
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                }
+                Err(e) => {
+                    error!("Failed to update funding collection: {}", e);
+                    bot.send_message(
+                        msg.chat.id,
+                        "❌ Failed to update funding collection. Please try again.",
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                }
+            }
+        }
+        Command::LiqWatch(args) => {
+            let args = args.trim();
+            let (identifier, toggle) = match args.rsplit_once(char::is_whitespace) {
+                Some((identifier, toggle))
+                    if toggle.eq_ignore_ascii_case("on") || toggle.eq_ignore_ascii_case("off") =>
+                {
+                    (identifier.trim(), Some(toggle.eq_ignore_ascii_case("on")))
+                }
+                _ => (args, None),
+            };
+
+            if identifier.is_empty() {
+                bot.send_message(
+                    msg.chat.id,
+                    "❌ Please provide a wallet.\n\nUsage: <code>/liqwatch &lt;address|index|note&gt; [on|off]</code>",
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            }
+
+            let Some((wallet, note)) = resolve_or_raw_address(
+                &bot,
+                &msg,
+                &pool,
+                user_id,
+                identifier,
+                WALLET_NOT_FOUND,
+                "❌ Failed to update liquidation alerts. Please try again.",
+            )
+            .await?
+            else {
+                return Ok(());
+            };
+
+            let result = match toggle {
+                Some(enabled) => db::set_liquidation_alerts(&pool, user_id, &wallet, enabled)
+                    .await
+                    .map(|_| ()),
+                None => Ok(()),
+            };
+
+            match result.and(db::get_liquidation_alerts(&pool, user_id, &wallet).await) {
+                Ok(enabled) => {
+                    let status = if enabled { "🟢 On" } else { "⚪ Off" };
+                    bot.send_message(
+                        msg.chat.id,
+                        format!(
+                            "<b>🩸 Liquidation Alerts</b>\n\n\
+                             👛 Wallet: {}\n\
+                             Status: {}\n\n\
+                             <i>When on, you get a silent alert when a cross position's liquidation price moves {}% or more toward the mark while the position itself stays the same, e.g. because of losses elsewhere in the account.</i>\n\n\
+                             Usage: <code>/liqwatch &lt;wallet&gt; on|off</code>",
+                            format_wallet_display(&wallet, note.as_deref(), false),
+                            status,
+                            hyperliquid::liquidation_move_pct()
+                        ),
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                }
+                Err(e) => {
+                    error!("Failed to update liquidation alerts: {}", e);
+                    bot.send_message(
+                        msg.chat.id,
+                        "❌ Failed to update liquidation alerts. Please try again.",
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                }
+            }
+        }
+        Command::Vaults(args) => {
+            let args = args.trim();
+            let (identifier, toggle) = match args.rsplit_once(char::is_whitespace) {
+                Some((identifier, toggle))
+                    if toggle.eq_ignore_ascii_case("on") || toggle.eq_ignore_ascii_case("off") =>
+                {
+                    (identifier.trim(), Some(toggle.eq_ignore_ascii_case("on")))
+                }
+                _ => (args, None),
+            };
+
+            if identifier.is_empty() {
+                bot.send_message(
+                    msg.chat.id,
+                    "❌ Please provide a wallet.\n\nUsage: <code>/vaults &lt;address|index|note&gt; [on|off]</code>",
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            }
+
+            let Some((wallet, note)) = resolve_or_raw_address(
+                &bot,
+                &msg,
+                &pool,
+                user_id,
+                identifier,
+                WALLET_NOT_FOUND,
+                "❌ Failed to update vault equity. Please try again.",
+            )
+            .await?
+            else {
+                return Ok(());
+            };
+
+            let result = match toggle {
+                Some(enabled) => db::set_include_vaults(&pool, user_id, &wallet, enabled)
+                    .await
+                    .map(|_| {
+                        // Fetch right away instead of waiting for the next sync
+                        if enabled {
+                            vaults::spawn_sync(pool.clone(), wallet.clone());
+                        }
+                    }),
+                None => Ok(()),
+            };
+
+            match result.and(db::get_include_vaults(&pool, user_id, &wallet).await) {
+                Ok(enabled) => {
+                    let status = if enabled { "🟢 On" } else { "⚪ Off" };
+                    let equity = if enabled {
+                        match db::get_vault_equities(&pool, &wallet).await {
+                            Ok(equities) => vaults::summarize(&equities)
+                                .map(|(_, line)| format!("{}\n", line))
+                                .unwrap_or_default(),
+                            Err(e) => {
+                                error!("Failed to load vault equities for {}: {}", wallet, e);
+                                String::new()
+                            }
+                        }
+                    } else {
+                        String::new()
+                    };
+                    bot.send_message(
+                        msg.chat.id,
+                        format!(
+                            "<b>🏦 Vault Equity</b>\n\n\
+                             👛 Wallet: {}\n\
+                             Status: {}\n\
+                             {}\n\
+                             <i>When on, /balance, /drawdown and margin alerts also count what this wallet has deposited into vaults, refreshed every 30 minutes, as part of its total equity.</i>\n\n\
+                             Usage: <code>/vaults &lt;wallet&gt; on|off</code>",
+                            format_wallet_display(&wallet, note.as_deref(), false),
+                            status,
+                            equity
+                        ),
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                }
+                Err(e) => {
+                    error!("Failed to update vault equity: {}", e);
+                    bot.send_message(
+                        msg.chat.id,
+                        "❌ Failed to update vault equity. Please try again.",
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                }
+            }
+        }
+        Command::Pnl => {
+            let wallets = match db::get_user_wallets(&pool, user_id).await {
+                Ok(wallets) => wallets,
+                Err(e) => {
+                    error!("Failed to fetch wallets for PnL: {}", e);
+                    bot.send_message(msg.chat.id, "❌ Failed to fetch PnL. Please try again.")
+                        .reply_to(msg.id)
+                        .parse_mode(ParseMode::Html)
+                        .await?;
+                    return Ok(());
+                }
+            };
+
+            if wallets.is_empty() {
+                bot.send_message(
+                    msg.chat.id,
+                    "📋 You're not tracking any wallets yet.\n\nUse <code>/add &lt;wallet&gt; [note]</code> to start tracking.",
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            }
+
+            let states = fetch_wallet_states(&wallets).await;
+
+            let mut lines = Vec::new();
+            let mut total_pnl = 0.0;
+            let mut total_account_value = 0.0;
+            let mut unavailable = 0;
+            for (i, (w, user_state)) in wallets.iter().zip(states).enumerate() {
+                let display = format_wallet_display(&w.wallet_address, w.note.as_deref(), false);
+                match user_state {
+                    Ok(user_state) => {
+                        let pnl: f64 = user_state
+                            .asset_positions
+                            .iter()
+                            .map(|ap| ap.position.unrealized_pnl.parse::<f64>().unwrap_or(0.0))
+                            .sum();
+                        let account_value: f64 = user_state
+                            .margin_summary
+                            .account_value
+                            .parse()
+                            .unwrap_or(0.0);
+                        total_pnl += pnl;
+                        total_account_value += account_value;
+                        lines.push(format!(
+                            "{}. {}\n   📊 {} · 💼 ${:.2}",
+                            i + 1,
+                            display,
+                            hyperliquid::format_pnl(pnl),
+                            account_value
+                        ));
+                    }
+                    Err(e) => {
+                        warn!(
+                            "Failed to fetch state of {} for PnL: {}",
+                            w.wallet_address, e
+                        );
+                        unavailable += 1;
+                        lines.push(format!("{}. {}\n   ⚠️ Unavailable", i + 1, display));
+                    }
+                }
+            }
+
+            let unavailable_note = if unavailable > 0 {
+                format!(
+                    "\n\n<i>{} wallet(s) unavailable and not included in the total.</i>",
+                    unavailable
+                )
+            } else {
+                String::new()
+            };
+
+            bot.send_message(
+                msg.chat.id,
+                format!(
+                    "<b>💰 Unrealized PnL</b>\n\n\
+                     {}\n\n\
+                     <b>Total</b>\n\
+                     📊 Unrealized PnL: {}\n\
+                     💼 Account value: ${:.2}{}",
+                    lines.join("\n\n"),
+                    hyperliquid::format_pnl(total_pnl),
+                    total_account_value,
+                    unavailable_note
+                ),
+            )
+            .reply_to(msg.id)
+            .parse_mode(ParseMode::Html)
+            .await?;
+        }
+        Command::Portfolio => {
+            let wallets = match db::get_user_wallets(&pool, user_id).await {
+                Ok(wallets) => wallets,
+                Err(e) => {
+                    error!("Failed to fetch wallets for portfolio: {}", e);
+                    bot.send_message(
+                        msg.chat.id,
+                        "❌ Failed to fetch portfolio. Please try again.",
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                    return Ok(());
+                }
+            };
+
+            if wallets.is_empty() {
+                bot.send_message(
+                    msg.chat.id,
+                    "📋 You're not tracking any wallets yet.\n\nUse <code>/add &lt;wallet&gt; [note]</code> to start tracking.",
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            }
+
+            let mut holdings: HashMap<String, CoinHolding> = HashMap::new();
+            let mut unavailable = 0;
+            for (w, user_state) in wallets.iter().zip(fetch_wallet_states(&wallets).await) {
+                let user_state = match user_state {
+                    Ok(user_state) => user_state,
+                    Err(e) => {
+                        warn!(
+                            "Failed to fetch state of {} for portfolio: {}",
+                            w.wallet_address, e
+                        );
+                        unavailable += 1;
+                        continue;
+                    }
+                };
+
+                for ap in &user_state.asset_positions {
+                    let position = &ap.position;
+                    let size: f64 = position.szi.parse().unwrap_or(0.0);
+                    if size == 0.0 {
+                        continue;
+                    }
+                    let entry_price: f64 = position
+                        .entry_px
+                        .as_ref()
+                        .and_then(|p| p.parse().ok())
+                        .unwrap_or(0.0);
+                    let notional: f64 = position.position_value.parse().unwrap_or(0.0);
+
+                    let holding = holdings.entry(position.coin.clone()).or_default();
+                    holding.net_size += size;
+                    holding.net_notional += notional.copysign(size);
+                    holding.unrealized_pnl += position.unrealized_pnl.parse().unwrap_or(0.0);
+                    holding.wallets += 1;
+                    if size > 0.0 {
+                        holding.long_size += size;
+                        holding.long_cost += size * entry_price;
+                    } else {
+                        holding.short_size += size.abs();
+                        holding.short_cost += size.abs() * entry_price;
+                    }
+                }
+            }
+
+            if holdings.is_empty() {
+                let text = if unavailable > 0 {
+                    "❌ Failed to fetch portfolio. Please try again."
+                } else {
+                    "📭 None of your wallets have open positions."
+                };
+                bot.send_message(msg.chat.id, text)
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                return Ok(());
+            }
+
+            let mut holdings: Vec<(String, CoinHolding)> = holdings.into_iter().collect();
+            holdings.sort_by(|a, b| {
+                b.1.net_notional
+                    .abs()
+                    .total_cmp(&a.1.net_notional.abs())
+                    .then_with(|| a.0.cmp(&b.0))
+            });
+
+            let total_notional: f64 = holdings.iter().map(|(_, h)| h.net_notional.abs()).sum();
+            let total_pnl: f64 = holdings.iter().map(|(_, h)| h.unrealized_pnl).sum();
+
+            let lines = holdings
+                .iter()
+                .map(|(coin, h)| {
+                    let is_long = h.net_size > 0.0;
+                    // Average entry of the side that's left after netting
+                    let (side_size, side_cost) = if is_long {
+                        (h.long_size, h.long_cost)
+                    } else {
+                        (h.short_size, h.short_cost)
+                    };
+                    let entry = if h.net_size.abs() > 0.0 && side_size > 0.0 {
+                        hyperliquid::format_coin_price(coin, side_cost / side_size)
+                    } else {
+                        "—".to_string()
+                    };
+                    let direction = if h.net_size == 0.0 {
+                        "Flat"
+                    } else {
+                        hyperliquid::direction_str(is_long)
+                    };
+                    let hedged = if h.long_size > 0.0 && h.short_size > 0.0 {
+                        " (hedged)"
+                    } else {
+                        ""
+                    };
+                    format!(
+                        "<b>{}</b> {}, {} wallet{}{}\n\
+                         📊 Net size: {} {} (${:.2})\n\
+                         💰 Avg entry: {}\n\
+                         💵 uPnL: {}",
+                        coin,
+                        direction,
+                        h.wallets,
+                        if h.wallets == 1 { "" } else { "s" },
+                        hedged,
+                        hyperliquid::format_size(h.net_size.abs()),
+                        coin,
+                        h.net_notional.abs(),
+                        entry,
+                        hyperliquid::format_pnl(h.unrealized_pnl)
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n\n");
+
+            let unavailable_note = if unavailable > 0 {
+                format!(
+                    "\n\n<i>{} wallet(s) unavailable and not included.</i>",
+                    unavailable
+                )
+            } else {
+                String::new()
+            };
+
+            bot.send_message(
+                msg.chat.id,
+                format!(
+                    "<b>🗂 Portfolio</b>\n\n\
+                     {}\n\n\
+                     <b>Total</b>\n\
+                     📊 Net notional: ${:.2}\n\
+                     💵 uPnL: {}{}",
+                    lines,
+                    total_notional,
+                    hyperliquid::format_pnl(total_pnl),
+                    unavailable_note
+                ),
+            )
+            .reply_to(msg.id)
+            .parse_mode(ParseMode::Html)
+            .await?;
+        }
+        Command::Balance(identifier) => {
+            let Some((identifier, assumed)) = identifier_or_last(user_id, &identifier) else {
+                bot.send_message(
+                    msg.chat.id,
+                    "❌ Please provide a wallet address, index (1-10), or note.\n\nUsage: <code>/balance &lt;address|index|note&gt;</code>",
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            };
+            let identifier = identifier.as_str();
+
+            let Some((wallet, note)) = resolve_or_raw_address(
+                &bot,
+                &msg,
+                &pool,
+                user_id,
+                identifier,
+                WALLET_NOT_FOUND,
+                "❌ Failed to fetch balance. Please try again.",
+            )
+            .await?
+            else {
+                return Ok(());
+            };
+
+            if assumed {
+                send_assumed_wallet_notice(&bot, &msg, &wallet, note.as_deref()).await?;
+            }
+
+            let user_state = match hyperliquid::fetch_user_state(&HTTP_CLIENT, &wallet).await {
+                Ok(user_state) => user_state,
+                Err(e) => {
+                    error!("Failed to fetch balance for {}: {}", wallet, e);
+                    bot.send_message(msg.chat.id, "❌ Failed to fetch balance. Please try again.")
+                        .reply_to(msg.id)
+                        .parse_mode(ParseMode::Html)
+                        .await?;
+                    return Ok(());
+                }
+            };
+
+            let summary = &user_state.margin_summary;
+            let account_value: f64 = summary.account_value.parse().unwrap_or(0.0);
+            let margin_used: f64 = summary.total_margin_used.parse().unwrap_or(0.0);
+            let open_positions = user_state
+                .asset_positions
+                .iter()
+                .filter(|ap| ap.position.szi.parse::<f64>().unwrap_or(0.0) != 0.0)
+                .count();
+
+            let vault_summary = vaults::included_equity(&pool, user_id, &wallet).await;
+
+            let mut details = if account_value <= 0.0 {
+                "<i>This wallet has no funds in its perp account.</i>".to_string()
+            } else {
+                format!(
+                    "💼 Account value: ${:.2}\n\
+                     🔒 Margin used: ${:.2}\n\
+                     🆓 Free margin: ${:.2}\n\
+                     📊 Margin usage: {:.1}%\n\
+                     📈 Open positions: {}",
+                    account_value,
+                    margin_used,
+                    (account_value - margin_used).max(0.0),
+                    hyperliquid::margin_utilization(summary).unwrap_or(0.0),
+                    open_positions
+                )
+            };
+            if let Some((vault_equity, line)) = vault_summary {
+                details.push_str(&format!(
+                    "\n\n{}\n💎 Total equity: ${:.2}",
+                    line,
+                    account_value.max(0.0) + vault_equity
+                ));
+            }
+
+            bot.send_message(
+                msg.chat.id,
+                format!(
+                    "<b>💰 Balance</b>\n\n\
+                     👛 Wallet: {}\n\n\
+                     {}",
+                    format_wallet_display(&wallet, note.as_deref(), false),
+                    details
+                ),
+            )
+            .reply_to(msg.id)
+            .parse_mode(ParseMode::Html)
+            .await?;
+        }
+        Command::Orders(identifier) => {
+            let Some((identifier, assumed)) = identifier_or_last(user_id, &identifier) else {
+                bot.send_message(
+                    msg.chat.id,
+                    "❌ Please provide a wallet address, index (1-10), or note.\n\nUsage: <code>/orders &lt;address|index|note&gt;</code>",
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            };
+            let identifier = identifier.as_str();
+
+            let Some((wallet, note)) = resolve_or_raw_address(
+                &bot,
+                &msg,
+                &pool,
+                user_id,
+                identifier,
+                WALLET_NOT_FOUND,
+                "❌ Failed to fetch orders. Please try again.",
+            )
+            .await?
+            else {
+                return Ok(());
+            };
+
+            if assumed {
+                send_assumed_wallet_notice(&bot, &msg, &wallet, note.as_deref()).await?;
+            }
+
+            let orders = match hyperliquid::fetch_open_orders(&HTTP_CLIENT, &wallet).await {
+                Ok(orders) => orders,
+                Err(e) => {
+                    error!("Failed to fetch orders for {}: {}", wallet, e);
+                    bot.send_message(msg.chat.id, "❌ Failed to fetch orders. Please try again.")
+                        .reply_to(msg.id)
+                        .parse_mode(ParseMode::Html)
+                        .await?;
+                    return Ok(());
+                }
+            };
+
+            let wallet_display = format_wallet_display(&wallet, note.as_deref(), false);
+            if orders.is_empty() {
+                bot.send_message(
+                    msg.chat.id,
+                    format!(
+                        "<b>📋 Open Orders</b>\n\n\
+                         👛 Wallet: {}\n\n\
+                         <i>No open orders</i>",
+                        wallet_display
+                    ),
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            }
+
+            // Group per coin, highest price first within a coin
+            let mut by_coin: BTreeMap<&str, Vec<&hyperliquid::FrontendOrder>> = BTreeMap::new();
+            for order in &orders {
+                by_coin.entry(order.coin.as_str()).or_default().push(order);
+            }
+
+            let mut sections = Vec::new();
+            let mut shown = 0;
+            for (coin, mut coin_orders) in by_coin {
+                if shown >= MAX_ORDERS_SHOWN {
+                    break;
+                }
+                coin_orders.sort_by(|a, b| {
+                    let a_px: f64 = a.limit_px.parse().unwrap_or(0.0);
+                    let b_px: f64 = b.limit_px.parse().unwrap_or(0.0);
+                    b_px.total_cmp(&a_px)
+                });
+
+                let lines: Vec<String> = coin_orders
+                    .iter()
+                    .take(MAX_ORDERS_SHOWN - shown)
+                    .map(|order| {
+                        let (emoji, side) = if order.side == "B" {
+                            ("🟢", "Buy")
+                        } else {
+                            ("🔴", "Sell")
+                        };
+                        let size: f64 = order.sz.parse().unwrap_or(0.0);
+                        let limit_px: f64 = order.limit_px.parse().unwrap_or(0.0);
+                        let trigger = if order.is_trigger {
+                            let trigger_px: f64 = order.trigger_px.parse().unwrap_or(0.0);
+                            let label = if order.order_type.starts_with("Take Profit") {
+                                "🎯 TP"
+                            } else {
+                                "🛑 SL"
+                            };
+                            format!(
+                                " · {} at {}",
+                                label,
+                                hyperliquid::format_coin_price(coin, trigger_px)
+                            )
+                        } else {
+                            String::new()
+                        };
+                        let reduce_only = if order.reduce_only {
+                            " · reduce-only"
+                        } else {
+                            ""
+                        };
+                        format!(
+                            "{} {} {} @ {}{}{}",
+                            emoji,
+                            side,
+                            hyperliquid::format_size(size),
+                            hyperliquid::format_coin_price(coin, limit_px),
+                            trigger,
+                            reduce_only
+                        )
+                    })
+                    .collect();
+                shown += lines.len();
+                sections.push(format!("<b>{}</b>\n{}", coin, lines.join("\n")));
+            }
+
+            let more = if orders.len() > shown {
+                format!("\n\n…and {} more", orders.len() - shown)
+            } else {
+                String::new()
+            };
+
+            bot.send_message(
+                msg.chat.id,
+                format!(
+                    "<b>📋 Open Orders ({})</b>\n\n\
+                     👛 Wallet: {}\n\n\
+                     {}{}",
+                    orders.len(),
+                    wallet_display,
+                    sections.join("\n\n"),
+                    more
+                ),
+            )
+            .reply_to(msg.id)
+            .parse_mode(ParseMode::Html)
+            .await?;
+        }
+        Command::Fills(args) => {
+            let args = args.trim();
+            let (identifier, count) = match args.rsplit_once(char::is_whitespace) {
+                Some((identifier, count)) if count.parse::<usize>().is_ok() => (
+                    identifier.trim(),
+                    count.parse::<usize>().unwrap_or(DEFAULT_FILLS_SHOWN),
+                ),
+                _ => (args, DEFAULT_FILLS_SHOWN),
+            };
+            let count = count.clamp(1, MAX_FILLS_SHOWN);
+
+            let Some((identifier, assumed)) = identifier_or_last(user_id, identifier) else {
+                bot.send_message(
+                    msg.chat.id,
+                    "❌ Please provide a wallet address, index (1-10), or note.\n\nUsage: <code>/fills &lt;address|index|note&gt; [count]</code>",
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            };
+            let identifier = identifier.as_str();
+
+            let Some((wallet, note)) = resolve_or_raw_address(
+                &bot,
+                &msg,
+                &pool,
+                user_id,
+                identifier,
+                WALLET_NOT_FOUND,
+                "❌ Failed to fetch fills. Please try again.",
+            )
+            .await?
+            else {
+                return Ok(());
+            };
+
+            if assumed {
+                send_assumed_wallet_notice(&bot, &msg, &wallet, note.as_deref()).await?;
+            }
+
+            let fills = match hyperliquid::fetch_user_fills(&HTTP_CLIENT, &wallet).await {
+                Ok(fills) => fills,
+                Err(e) => {
+                    error!("Failed to fetch fills for {}: {}", wallet, e);
+                    bot.send_message(msg.chat.id, "❌ Failed to fetch fills. Please try again.")
+                        .reply_to(msg.id)
+                        .parse_mode(ParseMode::Html)
+                        .await?;
+                    return Ok(());
+                }
+            };
+
+            let wallet_display = format_wallet_display(&wallet, note.as_deref(), false);
+            if fills.is_empty() {
+                bot.send_message(
+                    msg.chat.id,
+                    format!(
+                        "<b>🧾 Recent Fills</b>\n\n\
+                         👛 Wallet: {}\n\n\
+                         <i>No fills found</i>",
+                        wallet_display
+                    ),
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            }
+
+            let now = chrono::Utc::now().timestamp_millis();
+            let tz = user_timezone(&pool, user_id).await;
+            let lines: Vec<String> = fills
+                .iter()
+                .take(count)
+                .map(|fill| {
+                    let (emoji, side) = if fill.side == "B" {
+                        ("🟢", "Buy")
+                    } else {
+                        ("🔴", "Sell")
+                    };
+                    let size: f64 = fill.sz.parse().unwrap_or(0.0);
+                    let price: f64 = fill.px.parse().unwrap_or(0.0);
+                    let closed_pnl: f64 = fill.closed_pnl.parse().unwrap_or(0.0);
+                    let fee: f64 = fill.fee.parse().unwrap_or(0.0);
+                    let pnl = if closed_pnl >= 0.0 {
+                        format!("+${:.2}", closed_pnl)
+                    } else {
+                        format!("-${:.2}", closed_pnl.abs())
+                    };
+                    let time = tz.format_millis(fill.time);
+                    format!(
+                        "{} <b>{}</b> {} {} @ {} ({})\n    💵 PnL: {} · Fee: ${:.2} · {}, {}",
+                        emoji,
+                        fill.coin,
+                        side,
+                        hyperliquid::format_size(size),
+                        hyperliquid::format_coin_price(&fill.coin, price),
+                        fill.dir,
+                        pnl,
+                        fee,
+                        format_age((now - fill.time) / 1000),
+                        time
+                    )
+                })
+                .collect();
+
+            // 50 fills don't fit in one Telegram message
+            let header = format!(
+                "<b>🧾 Recent Fills ({})</b>\n\n👛 Wallet: {}\n",
+                lines.len(),
+                wallet_display
+            );
+            for message in split_lines(header, lines) {
+                bot.send_message(msg.chat.id, message)
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+            }
+        }
+        Command::Funding(args) => {
+            let args = args.trim();
+            let (identifier, days) = match args.rsplit_once(char::is_whitespace) {
+                Some((identifier, days)) if days.parse::<i64>().is_ok() => (
+                    identifier.trim(),
+                    days.parse::<i64>().unwrap_or(DEFAULT_FUNDING_DAYS),
+                ),
+                _ => (args, DEFAULT_FUNDING_DAYS),
+            };
+            let days = days.clamp(1, MAX_FUNDING_DAYS);
+
+            let Some((identifier, assumed)) = identifier_or_last(user_id, identifier) else {
+                bot.send_message(
+                    msg.chat.id,
+                    "❌ Please provide a wallet address, index (1-10), or note.\n\nUsage: <code>/funding &lt;address|index|note&gt; [days]</code>",
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            };
+            let identifier = identifier.as_str();
+
+            let Some((wallet, note)) = resolve_or_raw_address(
+                &bot,
+                &msg,
+                &pool,
+                user_id,
+                identifier,
+                WALLET_NOT_FOUND,
+                "❌ Failed to fetch funding. Please try again.",
+            )
+            .await?
+            else {
+                return Ok(());
+            };
+
+            if assumed {
+                send_assumed_wallet_notice(&bot, &msg, &wallet, note.as_deref()).await?;
+            }
+
+            let start_time = chrono::Utc::now().timestamp_millis() - days * 24 * 3600 * 1000;
+            let payments = match hyperliquid::fetch_all_user_funding(
+                &HTTP_CLIENT,
+                &wallet,
+                start_time,
+            )
+            .await
+            {
+                Ok(payments) => payments,
+                Err(e) => {
+                    error!("Failed to fetch funding for {}: {}", wallet, e);
+                    bot.send_message(msg.chat.id, "❌ Failed to fetch funding. Please try again.")
+                        .reply_to(msg.id)
+                        .parse_mode(ParseMode::Html)
+                        .await?;
+                    return Ok(());
+                }
+            };
+
+            let wallet_display = format_wallet_display(&wallet, note.as_deref(), false);
+            if payments.is_empty() {
+                bot.send_message(
+                    msg.chat.id,
+                    format!(
+                        "<b>💸 Funding</b>\n\n\
+                         👛 Wallet: {}\n\n\
+                         <i>No funding payments in the last {} days</i>",
+                        wallet_display, days
+                    ),
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            }
+
+            let mut per_coin: BTreeMap<&str, f64> = BTreeMap::new();
+            for payment in &payments {
+                *per_coin.entry(payment.delta.coin.as_str()).or_default() +=
+                    payment.delta.usdc.parse::<f64>().unwrap_or(0.0);
+            }
+            let total: f64 = per_coin.values().sum();
+
+            let mut per_coin: Vec<_> = per_coin.into_iter().collect();
+            per_coin.sort_by(|a, b| b.1.abs().total_cmp(&a.1.abs()));
+            let pnl_emoji = |amount: f64| if amount >= 0.0 { "🟢" } else { "🔴" };
+            let lines: Vec<String> = per_coin
+                .iter()
+                .map(|(coin, amount)| {
+                    format!(
+                        "{} <b>{}</b>: {}",
+                        pnl_emoji(*amount),
+                        coin,
+                        hyperliquid::format_pnl(*amount)
+                    )
+                })
+                .collect();
+
+            bot.send_message(
+                msg.chat.id,
+                format!(
+                    "<b>💸 Funding (last {} days)</b>\n\n\
+                     👛 Wallet: {}\n\n\
+                     {}\n\n\
+                     {} <b>Total: {}</b>\n\n\
+                     <i>Positive means the wallet received funding.</i>",
+                    days,
+                    wallet_display,
+                    lines.join("\n"),
+                    pnl_emoji(total),
+                    hyperliquid::format_pnl(total)
+                ),
+            )
+            .reply_to(msg.id)
+            .parse_mode(ParseMode::Html)
+            .await?;
+        }
+        Command::Backfill(args) => {
+            let args = args.trim();
+            let (identifier, days) = match args.rsplit_once(char::is_whitespace) {
+                Some((identifier, days)) if days.parse::<i64>().is_ok() => (
+                    identifier.trim(),
+                    days.parse::<i64>().unwrap_or(backfill::MAX_BACKFILL_DAYS),
+                ),
+                _ => (args, backfill::MAX_BACKFILL_DAYS),
+            };
+            let days = days.clamp(1, backfill::MAX_BACKFILL_DAYS);
+
+            if identifier.is_empty() {
+                bot.send_message(
+                    msg.chat.id,
+                    "❌ Please provide a wallet.\n\nUsage: <code>/backfill &lt;address|index|note&gt; [days]</code>",
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            }
+
+            let Some((wallet, note)) = resolve_or_raw_address(
+                &bot,
+                &msg,
+                &pool,
+                user_id,
+                identifier,
+                WALLET_NOT_FOUND,
+                "❌ Failed to start backfill. Please try again.",
+            )
+            .await?
+            else {
+                return Ok(());
+            };
+            let wallet_display = format_wallet_display(&wallet, note.as_deref(), false);
+
+            let existing = match db::get_backfill_runs(&pool, &wallet).await {
+                Ok(runs) => runs,
+                Err(e) => {
+                    error!("Failed to load backfill runs for {}: {}", wallet, e);
+                    bot.send_message(
+                        msg.chat.id,
+                        "❌ Failed to start backfill. Please try again.",
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                    return Ok(());
+                }
+            };
+
+            let tz = user_timezone(&pool, user_id).await;
+            let format_day = |time: i64| {
+                chrono::DateTime::from_timestamp_millis(time)
+                    .map(|t| tz.format_date(t))
+                    .unwrap_or_default()
+            };
+
+            // An interrupted run is finished first, whatever window was asked for now
+            let (mut run, resumed) = match existing.iter().find(|r| !r.completed) {
+                Some(run) => (run.clone(), true),
+                None => {
+                    let now = chrono::Utc::now().timestamp_millis();
+                    // Stop where live tracking began, those events are already recorded
+                    let end_time = match db::get_first_live_event_time(&pool, &wallet).await {
+                        Ok(first_live) => first_live.unwrap_or(now).min(now),
+                        Err(e) => {
+                            error!("Failed to load first live event for {}: {}", wallet, e);
+                            bot.send_message(
+                                msg.chat.id,
+                                "❌ Failed to start backfill. Please try again.",
+                            )
+                            .reply_to(msg.id)
+                            .parse_mode(ParseMode::Html)
+                            .await?;
+                            return Ok(());
+                        }
+                    };
+                    let start_time = now - days * 24 * 3600 * 1000;
+
+                    if start_time >= end_time {
+                        bot.send_message(
+                            msg.chat.id,
+                            format!(
+                                "ℹ️ Nothing to backfill for {}: live tracking already covers the last {} days.",
+                                wallet_display, days
+                            ),
+                        )
+                        .reply_to(msg.id)
+                        .parse_mode(ParseMode::Html)
+                        .await?;
+                        return Ok(());
+                    }
+
+                    if let Some(overlap) = existing
+                        .iter()
+                        .find(|r| r.start_time < end_time && start_time < r.end_time)
+                    {
+                        bot.send_message(
+                            msg.chat.id,
+                            format!(
+                                "❌ {} was already backfilled from {} to {}. Importing an overlapping window would count those trades twice.",
+                                wallet_display,
+                                format_day(overlap.start_time),
+                                format_day(overlap.end_time)
+                            ),
+                        )
+                        .reply_to(msg.id)
+                        .parse_mode(ParseMode::Html)
+                        .await?;
+                        return Ok(());
+                    }
+
+                    match db::create_backfill_run(&pool, &wallet, start_time, end_time).await {
+                        Ok(run) => (run, false),
+                        Err(e) => {
+                            error!("Failed to create backfill run for {}: {}", wallet, e);
+                            bot.send_message(
+                                msg.chat.id,
+                                "❌ Failed to start backfill. Please try again.",
+                            )
+                            .reply_to(msg.id)
+                            .parse_mode(ParseMode::Html)
+                            .await?;
+                            return Ok(());
+                        }
+                    }
+                }
+            };
+
+            let window = format!(
+                "{} to {}",
+                format_day(run.start_time),
+                format_day(run.end_time)
+            );
+            let header = if resumed {
+                format!(
+                    "<b>📥 Backfill</b>\n\n👛 Wallet: {}\n🔁 Resuming the interrupted import of {}",
+                    wallet_display, window
+                )
+            } else {
+                format!(
+                    "<b>📥 Backfill</b>\n\n👛 Wallet: {}\n📅 {}",
+                    wallet_display, window
+                )
+            };
+
+            let progress = bot
+                .send_message(
+                    msg.chat.id,
+                    format!(
+                        "{}\n\n⏳ Imported {} fills…",
+                        header,
+                        format_count(run.fills as usize)
+                    ),
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+
+            let mut round_trips = 0;
+            while !run.completed {
+                match backfill::import_page(&HTTP_CLIENT, &pool, &wallet, &mut run).await {
+                    Ok(trips) => round_trips += trips,
+                    Err(e) => {
+                        error!("Backfill of {} stopped: {}", wallet, e);
+                        bot.edit_message_text(
+                            msg.chat.id,
+                            progress.id,
+                            format!(
+                                "{}\n\n❌ Stopped after {} fills. Run <code>/backfill {}</code> again to resume.",
+                                header,
+                                format_count(run.fills as usize),
+                                wallet
+                            ),
+                        )
+                        .parse_mode(ParseMode::Html)
+                        .await?;
+                        return Ok(());
+                    }
+                }
+
+                if !run.completed {
+                    bot.edit_message_text(
+                        msg.chat.id,
+                        progress.id,
+                        format!(
+                            "{}\n\n⏳ Imported {} fills…",
+                            header,
+                            format_count(run.fills as usize)
+                        ),
+                    )
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                    tokio::time::sleep(BACKFILL_PAGE_DELAY).await;
+                }
+            }
+
+            bot.edit_message_text(
+                msg.chat.id,
+                progress.id,
+                format!(
+                    "{}\n\n✅ Imported {} fills, {} closed round trips this run.\n\n\
+                     <i>Only trades opened and closed inside the window are recorded. Hyperliquid serves at most the 10,000 most recent fills.</i>",
+                    header,
+                    format_count(run.fills as usize),
+                    round_trips
+                ),
+            )
+            .parse_mode(ParseMode::Html)
+            .await?;
+        }
+        Command::History(args) => {
+            let args = args.trim();
+            let (identifier, count) = match args.rsplit_once(char::is_whitespace) {
+                Some((identifier, count)) if count.parse::<i64>().is_ok() => (
+                    identifier.trim(),
+                    count.parse::<i64>().unwrap_or(DEFAULT_HISTORY_SHOWN),
+                ),
+                _ => (args, DEFAULT_HISTORY_SHOWN),
+            };
+            let count = count.clamp(1, MAX_HISTORY_SHOWN);
+
+            let Some((identifier, assumed)) = identifier_or_last(user_id, identifier) else {
+                bot.send_message(
+                    msg.chat.id,
+                    "❌ Please provide a wallet address, index (1-10), or note.\n\nUsage: <code>/history &lt;address|index|note&gt; [count]</code>",
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            };
+            let identifier = identifier.as_str();
+
+            let Some((wallet, note)) = resolve_or_raw_address(
+                &bot,
+                &msg,
+                &pool,
+                user_id,
+                identifier,
+                WALLET_NOT_FOUND,
+                "❌ Failed to fetch history. Please try again.",
+            )
+            .await?
+            else {
+                return Ok(());
+            };
+
+            if assumed {
+                send_assumed_wallet_notice(&bot, &msg, &wallet, note.as_deref()).await?;
+            }
+
+            let closed = match db::get_closed_positions(&pool, &wallet, count).await {
+                Ok(closed) => closed,
+                Err(e) => {
+                    error!("Failed to fetch closed positions for {}: {}", wallet, e);
+                    bot.send_message(msg.chat.id, "❌ Failed to fetch history. Please try again.")
+                        .reply_to(msg.id)
+                        .parse_mode(ParseMode::Html)
+                        .await?;
+                    return Ok(());
+                }
+            };
+
+            let wallet_display = format_wallet_display(&wallet, note.as_deref(), false);
+            if closed.is_empty() {
+                bot.send_message(
+                    msg.chat.id,
+                    format!(
+                        "<b>📜 Closed Positions</b>\n\n\
+                         👛 Wallet: {}\n\n\
+                         <i>No closed positions recorded yet</i>",
+                        wallet_display
+                    ),
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            }
+
+            // Stored as SQLite CURRENT_TIMESTAMP, which is UTC
+            let parse_time = |time: &str| {
+                chrono::NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M:%S")
+                    .ok()
+                    .map(|t| t.and_utc())
+            };
+            let now = chrono::Utc::now();
+            let total: f64 = closed.iter().map(|c| c.realized_pnl).sum();
+
+            // Newest first, like the rows, each shown above the first close before it
+            let mut gaps = db::get_tracking_gaps(&pool, &wallet)
+                .await
+                .unwrap_or_else(|e| {
+                    error!("Failed to load tracking gaps for {}: {}", wallet, e);
+                    Vec::new()
+                });
+            gaps.reverse();
+            let mut gaps = gaps.into_iter().peekable();
+            let tz = user_timezone(&pool, user_id).await;
+
+            let mut lines = Vec::new();
+            for c in &closed {
+                let closed_at = parse_time(&c.closed_at);
+                while let Some((start, end)) =
+                    gaps.next_if(|(start, _)| closed_at.is_some_and(|t| start.and_utc() >= t))
+                {
+                    lines.push(format!(
+                        "⏸ <i>Not tracked from {} to {}</i>",
+                        tz.format_date(start.and_utc()),
+                        tz.format_date(end.and_utc())
+                    ));
+                }
+                let emoji = if c.realized_pnl >= 0.0 {
+                    "🟢"
+                } else {
+                    "🔴"
+                };
+                let exit = c
+                    .exit_price
+                    .map(|p| format!(" → ~{}", hyperliquid::format_coin_price(&c.coin, p)))
+                    .unwrap_or_default();
+                let held = match (c.opened_at.as_deref().and_then(parse_time), closed_at) {
+                    (Some(opened), Some(closed)) => {
+                        format!(
+                            ", held {}",
+                            format_duration((closed - opened).num_seconds())
+                        )
+                    }
+                    _ => String::new(),
+                };
+                let ago = closed_at
+                    .map(|t| format_age((now - t).num_seconds()))
+                    .unwrap_or_default();
+                lines.push(format!(
+                    "{} <b>{} {}</b> {} @ {}{}\n    💵 {} · {}{}",
+                    emoji,
+                    c.coin,
+                    if c.is_long { "Long" } else { "Short" },
+                    hyperliquid::format_size(c.size),
+                    hyperliquid::format_coin_price(&c.coin, c.entry_price),
+                    exit,
+                    hyperliquid::format_pnl(c.realized_pnl),
+                    ago,
+                    held
+                ));
+            }
+            lines.push(format!(
+                "\n{} <b>Total: {}</b>",
+                if total >= 0.0 { "🟢" } else { "🔴" },
+                hyperliquid::format_pnl(total)
+            ));
+
+            let header = format!(
+                "<b>📜 Closed Positions ({})</b>\n\n👛 Wallet: {}\n",
+                closed.len(),
+                wallet_display
+            );
+            for message in split_lines(header, lines) {
+                bot.send_message(msg.chat.id, message)
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+            }
+        }
+        Command::Mute(identifier) => {
+            set_wallet_muted(&bot, &msg, &pool, user_id, identifier.trim(), true).await?;
+        }
+        Command::Unmute(identifier) => {
+            set_wallet_muted(&bot, &msg, &pool, user_id, identifier.trim(), false).await?;
+        }
+        Command::Pause(duration) => {
+            let duration = duration.trim();
+            let secs = if duration.is_empty() {
+                None
+            } else {
+                match parse_duration(duration) {
+                    Some(secs) => Some(secs),
+                    None => {
+                        bot.send_message(
+                            msg.chat.id,
+                            "❌ Invalid duration.\n\nUsage: <code>/pause [30m|8h|2d]</code>",
+                        )
+                        .reply_to(msg.id)
+                        .parse_mode(ParseMode::Html)
+                        .await?;
+                        return Ok(());
+                    }
+                }
+            };
+
+            let paused_until = secs.map(|secs| chrono::Utc::now().timestamp() + secs);
+            let text = match db::pause_notifications(&pool, user_id, paused_until).await {
+                Ok(()) => match secs {
+                    Some(secs) => format!(
+                        "⏸ Notifications paused for {}. They resume on their own, or use <code>/resume</code> sooner.",
+                        format_duration(secs)
+                    ),
+                    None => {
+                        "⏸ Notifications paused. Use <code>/resume</code> to turn them back on."
+                            .to_string()
+                    }
+                },
+                Err(e) => {
+                    error!("Failed to pause notifications: {}", e);
+                    "❌ Failed to pause notifications. Please try again.".to_string()
+                }
+            };
+            bot.send_message(msg.chat.id, text)
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+        }
+        Command::Resume => {
+            let text = match db::resume_notifications(&pool, user_id).await {
+                Ok(true) => "▶️ Notifications resumed.",
+                Ok(false) => "ℹ️ Notifications aren't paused.",
+                Err(e) => {
+                    error!("Failed to resume notifications: {}", e);
+                    "❌ Failed to resume notifications. Please try again."
+                }
+            };
+            bot.send_message(msg.chat.id, text)
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+        }
+        Command::Export(format) => {
+            let format = format.trim().to_lowercase();
+            if !matches!(format.as_str(), "" | "json" | "csv") {
+                bot.send_message(msg.chat.id, "❌ Usage: <code>/export [json|csv]</code>")
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                return Ok(());
+            }
+
+            let wallets = match db::get_user_wallets(&pool, user_id).await {
+                Ok(wallets) => wallets,
+                Err(e) => {
+                    error!("Failed to list wallets for export: {}", e);
+                    bot.send_message(
+                        msg.chat.id,
+                        "❌ Failed to export wallets. Please try again.",
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                    return Ok(());
+                }
+            };
+            if wallets.is_empty() {
+                bot.send_message(
+                    msg.chat.id,
+                    "📋 You're not tracking any wallets yet.\n\nUse <code>/add &lt;wallet&gt; [note]</code> to start tracking.",
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            }
+
+            let exported: Vec<wallet_file::ExportedWallet> = wallets
+                .iter()
+                .map(wallet_file::ExportedWallet::from)
+                .collect();
+            let file = if format == "csv" {
+                Ok((wallet_file::to_csv(&exported), "wallets.csv"))
+            } else {
+                wallet_file::to_json(&exported).map(|json| (json, "wallets.json"))
+            };
+            let (contents, file_name) = match file {
+                Ok(file) => file,
+                Err(e) => {
+                    error!("Failed to serialize wallets for export: {}", e);
+                    bot.send_message(
+                        msg.chat.id,
+                        "❌ Failed to export wallets. Please try again.",
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                    return Ok(());
+                }
+            };
+
+            info!("User {} exported {} wallets", user_id, exported.len());
+            bot.send_document(
+                msg.chat.id,
+                InputFile::memory(contents.into_bytes()).file_name(file_name),
+            )
+            .caption(format!("📦 {} tracked wallet(s). Send this file with <code>/import</code> as its caption to restore it.", exported.len()))
+            .reply_to(msg.id)
+            .parse_mode(ParseMode::Html)
+            .await?;
+        }
+        Command::Import(args) => {
+            let contents = match import_contents(&bot, &msg, &args).await {
+                Ok(Some(contents)) => contents,
+                Ok(None) => {
+                    bot.send_message(
+                        msg.chat.id,
+                        "❌ Nothing to import.\n\nSend an /export file with <code>/import</code> as its caption, or reply <code>/import</code> to a message with one <code>address [note]</code> per line.",
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                    return Ok(());
+                }
+                Err(e) => {
+                    warn!("Failed to read import for {}: {}", user_id, e);
+                    bot.send_message(
+                        msg.chat.id,
+                        format!(
+                            "❌ Couldn't read the import: {}",
+                            html::escape(&e.to_string())
+                        ),
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                    return Ok(());
+                }
+            };
+
+            let entries = match wallet_file::parse(&contents) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    bot.send_message(
+                        msg.chat.id,
+                        format!(
+                            "❌ Couldn't parse the file: {}",
+                            html::escape(&e.to_string())
+                        ),
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                    return Ok(());
+                }
+            };
+
+            let summary = import_wallets(&pool, user_id, entries).await;
+            bot.send_message(msg.chat.id, summary)
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+        }
+        Command::Settings => match settings_menu(&pool, user_id).await {
+            Ok((text, keyboard)) => {
+                bot.send_message(msg.chat.id, text)
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .reply_markup(keyboard)
+                    .await?;
+            }
+            Err(e) => {
+                error!("Failed to load settings: {}", e);
+                bot.send_message(msg.chat.id, "❌ Failed to load settings. Please try again.")
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+            }
+        },
+        Command::Threshold(args) => {
+            let args = args.trim();
+            let result = if args.is_empty() {
+                Ok(())
+            } else {
+                match parse_amount(args).filter(|amount| *amount >= 0.0) {
+                    Some(amount) => db::set_min_notional(&pool, user_id, amount).await,
+                    None => {
+                        bot.send_message(
+                            msg.chat.id,
+                            "❌ Please provide a USD amount, or 0 to turn the filter off.\n\nUsage: <code>/threshold [usd]</code>",
+                        )
+                        .reply_to(msg.id)
+                        .parse_mode(ParseMode::Html)
+                        .await?;
+                        return Ok(());
+                    }
+                }
+            };
+
+            match result.and(db::get_user_settings(&pool, user_id).await) {
+                Ok(settings) => {
+                    let status = if settings.min_notional > 0.0 {
+                        format!("🟢 ${:.2}", settings.min_notional)
+                    } else {
+                        "⚪ Off".to_string()
+                    };
+                    bot.send_message(
+                        msg.chat.id,
+                        format!(
+                            "<b>💵 Minimum Position Value</b>\n\n\
+                             Threshold: {}\n\n\
+                             <i>Opens, increases and decreases of positions worth less than this aren't notified. Closes are always sent, and so are opens in perps listed within the last day.</i>\n\n\
+                             Usage: <code>/threshold [usd]</code>, <code>/threshold 0</code> to turn it off",
+                            status
+                        ),
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                }
+                Err(e) => {
+                    error!("Failed to update minimum position value: {}", e);
+                    bot.send_message(
+                        msg.chat.id,
+                        "❌ Failed to update the threshold. Please try again.",
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                }
+            }
+        }
+        Command::Alert(args) => {
+            let usage = "Usage: <code>/alert &lt;coin&gt; &lt;above|below&gt; &lt;price&gt;</code>";
+            let parts: Vec<&str> = args.split_whitespace().collect();
+            let (coin, above, price) = match parts.as_slice() {
+                [coin, direction, price] => {
+                    let above = match direction.to_lowercase().as_str() {
+                        "above" | ">" => Some(true),
+                        "below" | "<" => Some(false),
+                        _ => None,
+                    };
+                    match (above, parse_amount(price).filter(|p| *p > 0.0)) {
+                        (Some(above), Some(price)) => (*coin, above, price),
+                        _ => {
+                            bot.send_message(
+                                msg.chat.id,
+                                format!(
+                                    "❌ Please give a direction and a positive price.\n\n{}",
+                                    usage
+                                ),
+                            )
+                            .reply_to(msg.id)
+                            .parse_mode(ParseMode::Html)
+                            .await?;
+                            return Ok(());
+                        }
+                    }
+                }
+                _ => {
+                    bot.send_message(msg.chat.id, format!("❌ {}", usage))
+                        .reply_to(msg.id)
+                        .parse_mode(ParseMode::Html)
+                        .await?;
+                    return Ok(());
+                }
+            };
+
+            let coin = match resolve_perp(coin) {
+                Ok(coin) => coin,
+                Err(text) => {
+                    bot.send_message(msg.chat.id, text)
+                        .reply_to(msg.id)
+                        .parse_mode(ParseMode::Html)
+                        .await?;
+                    return Ok(());
+                }
+            };
+            let requested = price;
+            let price = match price_alerts::snap_threshold(&coin, price) {
+                Ok(price) => price,
+                Err(text) => {
+                    bot.send_message(msg.chat.id, text)
+                        .reply_to(msg.id)
+                        .parse_mode(ParseMode::Html)
+                        .await?;
+                    return Ok(());
+                }
+            };
+            let rounded = if price != requested {
+                format!(
+                    "\n\nℹ️ Rounded from {} to {}'s price step.",
+                    hyperliquid::format_price(requested),
+                    coin
+                )
+            } else {
+                String::new()
+            };
+
+            // An alert that would fire on the next tick is almost certainly a typo
+            let mid = state.read().await.mids.get(&coin).copied();
+            if let Some(mid) = mid
+                && price_alerts::is_triggered(above, price, mid)
+            {
+                bot.send_message(
+                    msg.chat.id,
+                    format!(
+                        "ℹ️ {} is already {} {} (currently {}).",
+                        coin,
+                        price_alerts::direction_str(above),
+                        hyperliquid::format_coin_price(&coin, price),
+                        hyperliquid::format_coin_price(&coin, mid)
+                    ),
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            }
+
+            let existing = db::get_user_price_alerts(&pool, user_id)
+                .await
+                .unwrap_or_else(|e| {
+                    error!("Failed to fetch price alerts: {}", e);
+                    Vec::new()
+                });
+
+            // Another level for the same coin and side moves that alert instead of adding one
+            if let Some(alert) = existing.iter().find(|a| a.coin == coin && a.above == above) {
+                let text = match db::update_price_alert(&pool, user_id, alert.id, price).await {
+                    Ok(true) => {
+                        info!(
+                            "User {} moved price alert {} on {} {} to {}",
+                            user_id,
+                            alert.id,
+                            coin,
+                            price_alerts::direction_str(above),
+                            price
+                        );
+                        format!(
+                            "✏️ Alert #{} moved: {} {} {} (was {}){}",
+                            alert.id,
+                            coin,
+                            price_alerts::direction_str(above),
+                            hyperliquid::format_coin_price(&coin, price),
+                            hyperliquid::format_coin_price(&coin, alert.price),
+                            rounded
+                        )
+                    }
+                    Ok(false) => "⚠️ That alert just fired or was removed, send /alert again to set a new one.".to_string(),
+                    Err(e) => {
+                        error!("Failed to update price alert {}: {}", alert.id, e);
+                        "❌ Failed to update the alert. Please try again.".to_string()
+                    }
+                };
+                bot.send_message(msg.chat.id, text)
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                return Ok(());
+            }
+
+            if existing.len() >= price_alerts::MAX_ALERTS_PER_USER {
+                bot.send_message(
+                    msg.chat.id,
+                    format!(
+                        "❌ You've reached the maximum of {} price alerts.\n\nUse <code>/alertremove &lt;id&gt;</code> to remove one first.",
+                        price_alerts::MAX_ALERTS_PER_USER
+                    ),
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            }
+
+            let text = match db::create_price_alert(&pool, user_id, &coin, above, price).await {
+                Ok(id) => {
+                    info!(
+                        "User {} set price alert {} on {} {} {}",
+                        user_id,
+                        id,
+                        coin,
+                        price_alerts::direction_str(above),
+                        price
+                    );
+                    format!(
+                        "🔔 Alert #{} set: {} {} {}{}{}\n\nIt fires once and is then removed.",
+                        id,
+                        coin,
+                        price_alerts::direction_str(above),
+                        hyperliquid::format_coin_price(&coin, price),
+                        mid.map(|mid| format!(
+                            " (now {})",
+                            hyperliquid::format_coin_price(&coin, mid)
+                        ))
+                        .unwrap_or_default(),
+                        rounded
+                    )
+                }
+                Err(e) => {
+                    error!("Failed to create price alert: {}", e);
+                    "❌ Failed to set the alert. Please try again.".to_string()
+                }
+            };
+            bot.send_message(msg.chat.id, text)
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+        }
+        Command::Alerts => {
+            let alerts = match db::get_user_price_alerts(&pool, user_id).await {
+                Ok(alerts) => alerts,
+                Err(e) => {
+                    error!("Failed to list price alerts: {}", e);
+                    bot.send_message(
+                        msg.chat.id,
+                        "❌ Failed to retrieve alerts. Please try again.",
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                    return Ok(());
+                }
+            };
+            if alerts.is_empty() {
+                bot.send_message(
+                    msg.chat.id,
+                    "🔔 You have no price alerts.\n\nUse <code>/alert &lt;coin&gt; &lt;above|below&gt; &lt;price&gt;</code> to set one.",
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            }
+
+            let tracker = state.read().await;
+            let lines: Vec<String> = alerts
+                .iter()
+                .map(|alert| {
+                    let now = tracker
+                        .mids
+                        .get(&alert.coin)
+                        .map(|mid| {
+                            format!(
+                                " (now {})",
+                                hyperliquid::format_coin_price(&alert.coin, *mid)
+                            )
+                        })
+                        .unwrap_or_default();
+                    format!(
+                        "<code>#{}</code> <b>{}</b> {} {}{}",
+                        alert.id,
+                        alert.coin,
+                        price_alerts::direction_str(alert.above),
+                        hyperliquid::format_coin_price(&alert.coin, alert.price),
+                        now
+                    )
+                })
+                .collect();
+            drop(tracker);
+
+            bot.send_message(
+                msg.chat.id,
+                format!(
+                    "<b>🔔 Price Alerts ({})</b>\n\n{}\n\n<i>Remove one with <code>/alertremove &lt;id&gt;</code>.</i>",
+                    alerts.len(),
+                    lines.join("\n")
+                ),
+            )
+            .reply_to(msg.id)
+            .parse_mode(ParseMode::Html)
+            .await?;
+        }
+        Command::AlertRemove(id) => {
+            let Ok(id) = id.trim().trim_start_matches('#').parse::<i64>() else {
+                bot.send_message(
+                    msg.chat.id,
+                    "❌ Usage: <code>/alertremove &lt;id&gt;</code>",
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            };
+
+            let text = match db::delete_price_alert(&pool, user_id, id).await {
+                Ok(true) => format!("✅ Alert #{} removed.", id),
+                Ok(false) => format!(
+                    "⚠️ You have no alert #{}. Use /alerts to see your alerts.",
+                    id
+                ),
+                Err(e) => {
+                    error!("Failed to remove price alert: {}", e);
+                    "❌ Failed to remove the alert. Please try again.".to_string()
+                }
+            };
+            bot.send_message(msg.chat.id, text)
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+        }
+        Command::LiqAlert(args) => {
+            let args = args.trim();
+            let result = if args.is_empty() {
+                Ok(())
+            } else {
+                match parse_amount(args.trim_end_matches('%'))
+                    .filter(|pct| (0.0..=50.0).contains(pct))
+                {
+                    Some(pct) => db::set_liquidation_proximity(&pool, user_id, pct).await,
+                    None => {
+                        bot.send_message(
+                            msg.chat.id,
+                            "❌ Please provide a percentage between 0 and 50, or 0 to turn warnings off.\n\nUsage: <code>/liqalert [percent]</code>",
+                        )
+                        .reply_to(msg.id)
+                        .parse_mode(ParseMode::Html)
+                        .await?;
+                        return Ok(());
+                    }
+                }
+            };
+
+            match result.and(db::get_user_settings(&pool, user_id).await) {
+                Ok(settings) => {
+                    let status = if settings.liquidation_proximity_pct > 0.0 {
+                        format!("🟢 Within {}%", settings.liquidation_proximity_pct)
+                    } else {
+                        "⚪ Off".to_string()
+                    };
+                    bot.send_message(
+                        msg.chat.id,
+                        format!(
+                            "<b>⚠️ Near Liquidation Warnings</b>\n\n\
+                             Status: {}\n\n\
+                             <i>You get one warning when the price comes this close to a tracked position's liquidation price. It re-arms once the distance recovers past twice the threshold.</i>\n\n\
+                             Usage: <code>/liqalert [percent]</code>, <code>/liqalert 0</code> to turn it off",
+                            status
+                        ),
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                }
+                Err(e) => {
+                    error!("Failed to update liquidation warnings: {}", e);
+                    bot.send_message(
+                        msg.chat.id,
+                        "❌ Failed to update liquidation warnings. Please try again.",
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                }
+            }
+        }
+        Command::Top(args) => {
+            let args = args.trim();
+            let count = if args.is_empty() {
+                DEFAULT_TOP_SHOWN
+            } else {
+                match args.parse::<usize>() {
+                    Ok(count) => count.clamp(1, MAX_TOP_SHOWN),
+                    Err(_) => {
+                        bot.send_message(msg.chat.id, "❌ Usage: <code>/top [count]</code>")
+                            .reply_to(msg.id)
+                            .parse_mode(ParseMode::Html)
+                            .await?;
+                        return Ok(());
+                    }
+                }
+            };
+
+            let wallets = match db::get_user_wallets(&pool, user_id).await {
+                Ok(wallets) => wallets,
+                Err(e) => {
+                    error!("Failed to fetch wallets for top positions: {}", e);
+                    bot.send_message(
+                        msg.chat.id,
+                        "❌ Failed to fetch positions. Please try again.",
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                    return Ok(());
+                }
+            };
+
+            if wallets.is_empty() {
+                bot.send_message(
+                    msg.chat.id,
+                    "📋 You're not tracking any wallets yet.\n\nUse <code>/add &lt;wallet&gt; [note]</code> to start tracking.",
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            }
+
+            let states = fetch_wallet_states(&wallets).await;
+
+            let mut positions = Vec::new();
+            let mut unavailable = 0;
+            for (w, user_state) in wallets.iter().zip(&states) {
+                match user_state {
+                    Ok(user_state) => positions.extend(
+                        user_state
+                            .asset_positions
+                            .iter()
+                            .map(|ap| &ap.position)
+                            .filter(|pos| pos.szi.parse::<f64>().unwrap_or(0.0) != 0.0)
+                            .map(|pos| (w, pos)),
+                    ),
+                    Err(e) => {
+                        warn!(
+                            "Failed to fetch state of {} for top positions: {}",
+                            w.wallet_address, e
+                        );
+                        unavailable += 1;
+                    }
+                }
+            }
+
+            // Largest first, ties by coin and then in wallet list order (the sort is stable)
+            let notional =
+                |pos: &hyperliquid::Position| -> f64 { pos.position_value.parse().unwrap_or(0.0) };
+            positions.sort_by(|a, b| {
+                notional(b.1)
+                    .total_cmp(&notional(a.1))
+                    .then_with(|| a.1.coin.cmp(&b.1.coin))
+            });
+            let total = positions.len();
+            positions.truncate(count);
+
+            let unavailable_note = if unavailable > 0 {
+                format!(
+                    "\n<i>⚠️ {} wallet(s) unavailable and not included.</i>",
+                    unavailable
+                )
+            } else {
+                String::new()
+            };
+
+            if positions.is_empty() {
+                bot.send_message(
+                    msg.chat.id,
+                    format!(
+                        "<b>🏆 Top Positions</b>\n\nNone of your wallets have open positions.{}",
+                        unavailable_note
+                    ),
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            }
+
+            let lines: Vec<String> = positions
+                .iter()
+                .enumerate()
+                .map(|(rank, (w, pos))| {
+                    format!(
+                        "\n<b>{}.</b> 👛 {}{}",
+                        rank + 1,
+                        format_wallet_display(&w.wallet_address, w.note.as_deref(), false),
+                        position_entry(pos)
+                    )
+                })
+                .collect();
+            let header = format!(
+                "<b>🏆 Top {} of {} Positions</b>\n<i>Across all your wallets, by position value</i>{}",
+                positions.len(),
+                total,
+                unavailable_note
+            );
+            for message in split_lines(header, lines) {
+                bot.send_message(msg.chat.id, message)
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+            }
+        }
+        Command::Compare(args) => {
+            let identifiers: Vec<&str> = args.split_whitespace().collect();
+            let [first, second] = identifiers.as_slice() else {
+                bot.send_message(
+                    msg.chat.id,
+                    "❌ Please provide two wallets.\n\nUsage: <code>/compare &lt;address|index|note&gt; &lt;address|index|note&gt;</code>",
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            };
+
+            let mut resolved = Vec::new();
+            for identifier in [*first, *second] {
+                let Some(wallet) = resolve_or_raw_address(
+                    &bot,
+                    &msg,
+                    &pool,
+                    user_id,
+                    identifier,
+                    &format!(
+                        "❌ Wallet <b>{}</b> not found. Provide a valid address, index (1-10), or note.",
+                        html::escape(identifier)
+                    ),
+                    "❌ Failed to compare wallets. Please try again.",
+                )
+                .await?
+                else {
+                    return Ok(());
+                };
+                resolved.push(wallet);
+            }
+            let (wallet_a, note_a) = &resolved[0];
+            let (wallet_b, note_b) = &resolved[1];
+            if wallet_a == wallet_b {
+                bot.send_message(msg.chat.id, "⚠️ Both identifiers are the same wallet.")
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                return Ok(());
+            }
+
+            let (state_a, state_b) = tokio::join!(
+                hyperliquid::fetch_user_state(&HTTP_CLIENT, wallet_a),
+                hyperliquid::fetch_user_state(&HTTP_CLIENT, wallet_b)
+            );
+            let (state_a, state_b) = match (state_a, state_b) {
+                (Ok(a), Ok(b)) => (a, b),
+                (Err(e), _) | (_, Err(e)) => {
+                    error!("Failed to fetch wallets for comparison: {}", e);
+                    bot.send_message(
+                        msg.chat.id,
+                        "❌ Failed to fetch wallet data from Hyperliquid. Please try again.",
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                    return Ok(());
+                }
+            };
+
+            let messages = format_comparison(
+                &format_wallet_display(wallet_a, note_a.as_deref(), false),
+                &state_a,
+                &format_wallet_display(wallet_b, note_b.as_deref(), false),
+                &state_b,
+            );
+            for message in messages {
+                bot.send_message(msg.chat.id, message)
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+            }
+        }
+        Command::Digest(args) => {
+            let args = args.trim().to_lowercase();
+            let result = match args.as_str() {
+                "" => Ok(()),
+                "on" => db::set_daily_digest(&pool, user_id, true, None).await,
+                "off" => db::set_daily_digest(&pool, user_id, false, None).await,
+                time => match parse_time_of_day(time) {
+                    Some(minute) => db::set_daily_digest(&pool, user_id, true, Some(minute)).await,
+                    None => {
+                        bot.send_message(
+                            msg.chat.id,
+                            "❌ Please provide <code>on</code>, <code>off</code> or a time like <code>08:00</code> in your /timezone.\n\nUsage: <code>/digest [on|off|HH:MM]</code>",
+                        )
+                        .reply_to(msg.id)
+                        .parse_mode(ParseMode::Html)
+                        .await?;
+                        return Ok(());
+                    }
+                },
+            };
+
+            match result.and(db::get_user_settings(&pool, user_id).await) {
+                Ok(settings) => {
+                    let time = format!(
+                        "{:02}:{:02} {}",
+                        settings.digest_minute / 60,
+                        settings.digest_minute % 60,
+                        settings.tz().name()
+                    );
+                    let status = if settings.daily_digest {
+                        format!("🟢 On, daily at {}", time)
+                    } else {
+                        format!("⚪ Off ({} when on)", time)
+                    };
+                    bot.send_message(
+                        msg.chat.id,
+                        format!(
+                            "<b>🗞 Daily Digest</b>\n\n\
+                             Status: {}\n\n\
+                             <i>Once a day: your open positions, what opened and closed since the last digest, realized PnL and how unrealized PnL changed.</i>\n\n\
+                             Usage: <code>/digest on</code>, <code>/digest off</code> or <code>/digest HH:MM</code> to pick the time, /timezone to change the zone",
+                            status
+                        ),
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                }
+                Err(e) => {
+                    error!("Failed to update daily digest: {}", e);
+                    bot.send_message(
+                        msg.chat.id,
+                        "❌ Failed to update the daily digest. Please try again.",
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                }
+            }
+        }
+        Command::Spot(identifier) => {
+            let Some((identifier, assumed)) = identifier_or_last(user_id, &identifier) else {
+                bot.send_message(
+                    msg.chat.id,
+                    "❌ Please provide a wallet address, index (1-10), or note.\n\nUsage: <code>/spot &lt;address|index|note&gt;</code>",
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            };
+            let identifier = identifier.as_str();
+
+            let Some((wallet, note)) = resolve_or_raw_address(
+                &bot,
+                &msg,
+                &pool,
+                user_id,
+                identifier,
+                WALLET_NOT_FOUND,
+                "❌ Failed to fetch spot balances. Please try again.",
+            )
+            .await?
+            else {
+                return Ok(());
+            };
+
+            if assumed {
+                send_assumed_wallet_notice(&bot, &msg, &wallet, note.as_deref()).await?;
+            }
+
+            let (spot_state, meta, mids) = tokio::join!(
+                hyperliquid::fetch_spot_state(&HTTP_CLIENT, &wallet),
+                hyperliquid::fetch_spot_meta(&HTTP_CLIENT),
+                hyperliquid::fetch_all_mids(&HTTP_CLIENT)
+            );
+            let holdings = match (spot_state, meta, mids) {
+                (Ok(spot_state), Ok(meta), Ok(mids)) => {
+                    hyperliquid::spot_holdings(&spot_state, &meta, &mids)
+                }
+                (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) => {
+                    error!("Failed to fetch spot balances for {}: {}", wallet, e);
+                    bot.send_message(
+                        msg.chat.id,
+                        "❌ Failed to fetch spot balances. Please try again.",
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                    return Ok(());
+                }
+            };
+
+            let (shown, dust): (Vec<_>, Vec<_>) = holdings
+                .iter()
+                .partition(|h| h.value.is_none_or(|value| value >= MIN_SPOT_VALUE));
+            let mut lines: Vec<String> = shown
+                .iter()
+                .map(|h| match h.value {
+                    Some(value) => format!(
+                        "<b>{}</b>: {} · ${:.2}",
+                        html::escape(&h.name),
+                        hyperliquid::format_size(h.total),
+                        value
+                    ),
+                    None => format!(
+                        "<b>{}</b>: {} · <i>no USDC price</i>",
+                        html::escape(&h.name),
+                        hyperliquid::format_size(h.total)
+                    ),
+                })
+                .collect();
+            let total: f64 = holdings.iter().filter_map(|h| h.value).sum();
+            let dust_suffix = if dust.is_empty() {
+                String::new()
+            } else {
+                format!(" (+{} dust balances)", dust.len())
+            };
+
+            let wallet_display = format_wallet_display(&wallet, note.as_deref(), false);
+            let header = format!(
+                "<b>🪙 Spot Balances</b>\n\n\
+                 👛 Wallet: {}\n",
+                wallet_display
+            );
+            if lines.is_empty() && dust.is_empty() {
+                lines.push("<i>No spot balances</i>".to_string());
+            } else if lines.is_empty() {
+                lines.push(format!("<i>Only dust</i>{}", dust_suffix));
+            } else {
+                lines.push(format!("\n💼 Total: ${:.2}{}", total, dust_suffix));
+            }
+            for message in split_lines(header, lines) {
+                bot.send_message(msg.chat.id, message)
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+            }
+        }
+        Command::Vault(identifier) => {
+            let Some((identifier, assumed)) = identifier_or_last(user_id, &identifier) else {
+                bot.send_message(
+                    msg.chat.id,
+                    "❌ Please provide a vault address, or the index (1-10) or note of a tracked vault.\n\nUsage: <code>/vault &lt;address|index|note&gt;</code>",
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            };
+            let identifier = identifier.as_str();
+
+            let Some((vault, note)) = resolve_or_raw_address(
+                &bot,
+                &msg,
+                &pool,
+                user_id,
+                identifier,
+                WALLET_NOT_FOUND,
+                "❌ Failed to fetch vault details. Please try again.",
+            )
+            .await?
+            else {
+                return Ok(());
+            };
+
+            if assumed {
+                send_assumed_wallet_notice(&bot, &msg, &vault, note.as_deref()).await?;
+            }
+
+            let (details, vault_state) = tokio::join!(
+                hyperliquid::fetch_vault_details(&HTTP_CLIENT, &vault),
+                hyperliquid::fetch_user_state(&HTTP_CLIENT, &vault)
+            );
+            let details = match details {
+                Ok(Some(details)) => details,
+                Ok(None) => {
+                    bot.send_message(
+                        msg.chat.id,
+                        format!(
+                            "ℹ️ {} isn't a vault.\n\nUse /positions for a regular wallet.",
+                            format_wallet_display(&vault, note.as_deref(), false)
+                        ),
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                    return Ok(());
+                }
+                Err(e) => {
+                    error!("Failed to fetch vault details for {}: {}", vault, e);
+                    bot.send_message(
+                        msg.chat.id,
+                        "❌ Failed to fetch vault details. Please try again.",
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                    return Ok(());
+                }
+            };
+
+            let wallets = db::get_user_wallets(&pool, user_id)
+                .await
+                .unwrap_or_else(|e| {
+                    error!("Failed to fetch wallets for vault deposits: {}", e);
+                    Vec::new()
+                });
+            let deposits: Vec<String> = wallets
+                .iter()
+                .filter_map(|w| {
+                    let follower = details
+                        .followers
+                        .iter()
+                        .find(|f| f.user.eq_ignore_ascii_case(&w.wallet_address))?;
+                    let equity: f64 = follower.vault_equity.parse().ok()?;
+                    (equity > 0.0).then(|| {
+                        format!(
+                            "💎 {}: ${:.2}",
+                            format_wallet_display(&w.wallet_address, w.note.as_deref(), false),
+                            equity
+                        )
+                    })
+                })
+                .collect();
+
+            let (tvl, positions) = match &vault_state {
+                Ok(vault_state) => {
+                    let tvl = vault_state.margin_summary.account_value.parse::<f64>().ok();
+                    let mut positions: Vec<&hyperliquid::Position> = vault_state
+                        .asset_positions
+                        .iter()
+                        .map(|ap| &ap.position)
+                        .filter(|pos| pos.szi.parse::<f64>().unwrap_or(0.0) != 0.0)
+                        .collect();
+                    let notional = |pos: &hyperliquid::Position| -> f64 {
+                        pos.position_value.parse().unwrap_or(0.0)
+                    };
+                    positions.sort_by(|a, b| {
+                        notional(b)
+                            .total_cmp(&notional(a))
+                            .then_with(|| a.coin.cmp(&b.coin))
+                    });
+                    let mut lines: Vec<String> = positions
+                        .iter()
+                        .take(MAX_VAULT_POSITIONS_SHOWN)
+                        .map(|pos| {
+                            format!(
+                                "{} {} {} · {}",
+                                pos.coin,
+                                hyperliquid::direction_str(
+                                    pos.szi.parse::<f64>().unwrap_or(0.0) > 0.0
+                                ),
+                                hyperliquid::format_usd_compact(notional(pos)),
+                                hyperliquid::format_pnl(pos.unrealized_pnl.parse().unwrap_or(0.0))
+                            )
+                        })
+                        .collect();
+                    if positions.len() > MAX_VAULT_POSITIONS_SHOWN {
+                        lines.push(format!(
+                            "…and {} more",
+                            positions.len() - MAX_VAULT_POSITIONS_SHOWN
+                        ));
+                    }
+                    if lines.is_empty() {
+                        lines.push("<i>No open positions</i>".to_string());
+                    }
+                    (tvl, lines.join("\n"))
+                }
+                Err(e) => {
+                    warn!("Failed to fetch positions of vault {}: {}", vault, e);
+                    (None, "<i>Positions unavailable right now</i>".to_string())
+                }
+            };
+
+            let closed = if details.is_closed { " (closed)" } else { "" };
+            let tvl = tvl
+                .map(|tvl| format!("${:.2}", tvl))
+                .unwrap_or_else(|| "unknown".to_string());
+            let deposits = if deposits.is_empty() {
+                String::new()
+            } else {
+                format!("\n\n<b>Your deposits</b>\n{}", deposits.join("\n"))
+            };
+
+            bot.send_message(
+                msg.chat.id,
+                format!(
+                    "<b>🏦 {}{}</b>\n\n\
+                     👛 Vault: {}\n\
+                     👑 Leader: {}\n\
+                     💰 TVL: {}\n\
+                     📈 APR: {:.1}%\n\
+                     👥 Depositors: {}{}\n\n\
+                     <b>Positions</b>\n{}",
+                    html::escape(&details.name),
+                    closed,
+                    format_wallet_display(&details.vault_address, note.as_deref(), false),
+                    format_wallet_display(&details.leader, None, false),
+                    tvl,
+                    details.apr * 100.0,
+                    details.followers.len(),
+                    deposits,
+                    positions
+                ),
+            )
+            .reply_to(msg.id)
+            .parse_mode(ParseMode::Html)
+            .await?;
+        }
+        Command::Twap(identifier) => {
+            let Some((identifier, assumed)) = identifier_or_last(user_id, &identifier) else {
+                bot.send_message(
+                    msg.chat.id,
+                    "❌ Please provide a wallet address, index (1-10), or note.\n\nUsage: <code>/twap &lt;address|index|note&gt;</code>",
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            };
+            let identifier = identifier.as_str();
+
+            let Some((wallet, note)) = resolve_or_raw_address(
+                &bot,
+                &msg,
+                &pool,
+                user_id,
+                identifier,
+                WALLET_NOT_FOUND,
+                "❌ Failed to fetch TWAPs. Please try again.",
+            )
+            .await?
+            else {
+                return Ok(());
+            };
+
+            if assumed {
+                send_assumed_wallet_notice(&bot, &msg, &wallet, note.as_deref()).await?;
+            }
+
+            let twaps = match hyperliquid::fetch_twap_states(&HTTP_CLIENT, &wallet).await {
+                Ok(twaps) => twaps,
+                Err(e) => {
+                    error!("Failed to fetch TWAPs for {}: {}", wallet, e);
+                    bot.send_message(msg.chat.id, "❌ Failed to fetch TWAPs. Please try again.")
+                        .reply_to(msg.id)
+                        .parse_mode(ParseMode::Html)
+                        .await?;
+                    return Ok(());
+                }
+            };
+
+            let wallet_display = format_wallet_display(&wallet, note.as_deref(), false);
+            if twaps.is_empty() {
+                bot.send_message(
+                    msg.chat.id,
+                    format!(
+                        "<b>⏳ TWAP Orders</b>\n\n\
+                         👛 Wallet: {}\n\n\
+                         <i>No active TWAPs</i>",
+                        wallet_display
+                    ),
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            }
+
+            let now = chrono::Utc::now().timestamp_millis();
+            let lines: Vec<String> = twaps
+                .iter()
+                .map(|(_, twap)| {
+                    let (emoji, side) = if twap.is_buy() {
+                        ("🟢", "Buy")
+                    } else {
+                        ("🔴", "Sell")
+                    };
+                    let size: f64 = twap.sz.parse().unwrap_or(0.0);
+                    let executed: f64 = twap.executed_sz.parse().unwrap_or(0.0);
+                    let average = twap
+                        .average_price()
+                        .map(|px| format!(" @ {}", hyperliquid::format_coin_price(&twap.coin, px)))
+                        .unwrap_or_default();
+                    let mut flags = Vec::new();
+                    if twap.randomize {
+                        flags.push("randomized");
+                    }
+                    if twap.reduce_only {
+                        flags.push("reduce-only");
+                    }
+                    let flags = if flags.is_empty() {
+                        String::new()
+                    } else {
+                        format!(" · {}", flags.join(", "))
+                    };
+                    format!(
+                        "\n{} <b>{}</b> {} {}\n\
+                         📊 {}/{} filled ({:.0}%){}\n\
+                         ⏱️ {} total · {} left{}",
+                        emoji,
+                        twap.coin,
+                        side,
+                        hyperliquid::format_size(size),
+                        hyperliquid::format_size(executed),
+                        hyperliquid::format_size(size),
+                        twap.progress_pct(),
+                        average,
+                        format_duration(twap.minutes as i64 * 60),
+                        format_duration((twap.ends_at() - now) / 1000),
+                        flags
+                    )
+                })
+                .collect();
+            let header = format!(
+                "<b>⏳ TWAP Orders ({})</b>\n\n\
+                 👛 Wallet: {}",
+                twaps.len(),
+                wallet_display
+            );
+            for message in split_lines(header, lines) {
+                bot.send_message(msg.chat.id, message)
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+            }
+        }
+        Command::Search(coin) => {
+            let query = coin.trim();
+            if query.is_empty() {
+                bot.send_message(
+                    msg.chat.id,
+                    "❌ Please provide a coin.\n\nUsage: <code>/search &lt;coin&gt;</code>",
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            }
+
+            let wallets = match db::get_user_wallets(&pool, user_id).await {
+                Ok(wallets) => wallets,
+                Err(e) => {
+                    error!("Failed to fetch wallets for search: {}", e);
+                    bot.send_message(
+                        msg.chat.id,
+                        "❌ Failed to search your wallets. Please try again.",
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                    return Ok(());
+                }
+            };
+
+            // The monitor already holds every polled wallet's positions, so only wallets it
+            // hasn't seen yet need an API call
+            let mut entries: Vec<(String, String)> = Vec::new();
+            let mut uncached = Vec::new();
+            {
+                let tracker = state.read().await;
+                for w in &wallets {
+                    let display =
+                        format_wallet_display(&w.wallet_address, w.note.as_deref(), false);
+                    if let Some(snapshot) = tracker.snapshots.get(&w.wallet_address) {
+                        entries.extend(
+                            snapshot
+                                .asset_positions
+                                .iter()
+                                .map(|ap| &ap.position)
+                                .filter(|pos| {
+                                    hyperliquid::coin_matches(query, &pos.coin)
+                                        && pos.szi.parse::<f64>().unwrap_or(0.0) != 0.0
+                                })
+                                .map(|pos| (display.clone(), position_entry(pos))),
+                        );
+                    } else if let Some(positions) = tracker.positions.get(&w.wallet_address) {
+                        entries.extend(
+                            positions
+                                .iter()
+                                .filter(|(coin, _)| hyperliquid::coin_matches(query, coin))
+                                .map(|(coin, pos)| {
+                                    (display.clone(), cached_position_entry(coin, pos))
+                                }),
+                        );
+                    } else {
+                        uncached.push(w.clone());
+                    }
+                }
+            }
+
+            let mut unavailable = 0;
+            let states = fetch_wallet_states(&uncached).await;
+            for (w, user_state) in uncached.iter().zip(&states) {
+                match user_state {
+                    Ok(user_state) => entries.extend(
+                        user_state
+                            .asset_positions
+                            .iter()
+                            .map(|ap| &ap.position)
+                            .filter(|pos| {
+                                hyperliquid::coin_matches(query, &pos.coin)
+                                    && pos.szi.parse::<f64>().unwrap_or(0.0) != 0.0
+                            })
+                            .map(|pos| {
+                                (
+                                    format_wallet_display(
+                                        &w.wallet_address,
+                                        w.note.as_deref(),
+                                        false,
+                                    ),
+                                    position_entry(pos),
+                                )
+                            }),
+                    ),
+                    Err(e) => {
+                        warn!(
+                            "Failed to fetch state of {} for search: {}",
+                            w.wallet_address, e
+                        );
+                        unavailable += 1;
+                    }
+                }
+            }
+
+            let unavailable_note = if unavailable > 0 {
+                format!(
+                    "\n<i>⚠️ {} wallet(s) unavailable and not searched.</i>",
+                    unavailable
+                )
+            } else {
+                String::new()
+            };
+
+            if entries.is_empty() {
+                bot.send_message(
+                    msg.chat.id,
+                    format!(
+                        "🔍 None of your tracked wallets hold <b>{}</b>{}",
+                        html::escape(&query.to_uppercase()),
+                        unavailable_note
+                    ),
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            }
+
+            let lines: Vec<String> = entries
+                .iter()
+                .map(|(display, entry)| format!("\n👛 {}{}", display, entry))
+                .collect();
+            let header = format!(
+                "<b>🔍 {} positions in {}</b>{}",
+                entries.len(),
+                html::escape(&query.to_uppercase()),
+                unavailable_note
+            );
+            for message in split_lines(header, lines) {
+                bot.send_message(msg.chat.id, message)
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+            }
+        }
+        Command::Stats => {
+            let stats = async {
+                let wallet_count = db::get_user_wallet_count(&pool, user_id).await?;
+                let counts = db::get_notification_counts(&pool, user_id).await?;
+                let noisiest = match db::get_noisiest_wallet(&pool, user_id).await? {
+                    Some((wallet, count)) => {
+                        let note = db::get_wallet_note(&pool, user_id, &wallet).await?;
+                        Some((wallet, note, count))
+                    }
+                    None => None,
+                };
+
+                // Wallets the user stopped tracking for a while this week get fewer notifications
+                let week_ago = (chrono::Utc::now() - chrono::Duration::days(7)).naive_utc();
+                let mut untracked = Vec::new();
+                for (wallet, gaps) in db::get_user_tracking_gaps(&pool, user_id).await? {
+                    let secs: i64 = db::gaps_since(&gaps, week_ago)
+                        .iter()
+                        .map(|(start, end)| (*end - *start).num_seconds())
+                        .sum();
+                    if secs > 0 {
+                        let note = db::get_wallet_note(&pool, user_id, &wallet).await?;
+                        untracked.push((wallet, note, secs));
+                    }
+                }
+                untracked.sort_by_key(|(_, _, secs)| std::cmp::Reverse(*secs));
+                anyhow::Ok((wallet_count, counts, noisiest, untracked))
+            }
+            .await;
+
+            let (wallet_count, counts, noisiest, untracked) = match stats {
+                Ok(stats) => stats,
+                Err(e) => {
+                    error!("Failed to load stats for {}: {}", user_id, e);
+                    bot.send_message(
+                        msg.chat.id,
+                        "❌ Failed to load your stats. Please try again.",
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                    return Ok(());
+                }
+            };
+
+            let notifications = if counts.is_empty() {
+                "<i>No notifications in the last 7 days</i>".to_string()
+            } else {
+                let mut lines: Vec<String> = counts
+                    .iter()
+                    .map(|c| {
+                        format!(
+                            "{}: {} / {}",
+                            notification_kind_label(&c.kind),
+                            format_count(c.last_day as usize),
+                            format_count(c.last_week as usize)
+                        )
+                    })
+                    .collect();
+                lines.push(format!(
+                    "<b>Total</b>: {} / {}",
+                    format_count(counts.iter().map(|c| c.last_day).sum::<i64>() as usize),
+                    format_count(counts.iter().map(|c| c.last_week).sum::<i64>() as usize)
+                ));
+                lines.join("\n")
+            };
+            let noisiest = noisiest
+                .map(|(wallet, note, count)| {
+                    format!(
+                        "\n\n🔊 Most active wallet: {} with {} this week",
+                        format_wallet_display(&wallet, note.as_deref(), false),
+                        format_count(count as usize)
+                    )
+                })
+                .unwrap_or_default();
+            let untracked = if untracked.is_empty() {
+                String::new()
+            } else {
+                let lines: Vec<String> = untracked
+                    .iter()
+                    .map(|(wallet, note, secs)| {
+                        format!(
+                            "⏸ {} wasn't tracked for {}",
+                            format_wallet_display(wallet, note.as_deref(), false),
+                            format_duration(*secs)
+                        )
+                    })
+                    .collect();
+                format!(
+                    "\n\n{}\n<i>Counts only cover the time you tracked these wallets this week.</i>",
+                    lines.join("\n")
+                )
+            };
+
+            bot.send_message(
+                msg.chat.id,
+                format!(
+                    "<b>📈 Your Stats</b>\n\n\
+                     👛 Tracked wallets: {}\n\n\
+                     <b>Notifications</b> <i>(24h / 7d)</i>\n\
+                     {}{}{}",
+                    wallet_count, notifications, noisiest, untracked
+                ),
+            )
+            .reply_to(msg.id)
+            .parse_mode(ParseMode::Html)
+            .await?;
+        }
+        Command::Status => {
+            let (health, average_age, wallets) = {
+                let tracker = state.read().await;
+                (
+                    tracker.health.clone(),
+                    tracker.average_data_age_secs(),
+                    tracker.refreshed_at.len(),
+                )
+            };
+            let now = chrono::Utc::now();
+            let since_success = health.last_success_at.map(|t| (now - t).num_seconds());
+
+            let warning = match since_success {
+                Some(secs) if secs <= STATUS_STALE_SECS => String::new(),
+                Some(secs) => format!(
+                    "⚠️ <b>No successful monitor tick in {}</b>\n\n",
+                    format_duration(secs)
+                ),
+                None => "⚠️ <b>No successful monitor tick yet</b>\n\n".to_string(),
+            };
+            let last_tick = since_success
+                .map(format_age)
+                .unwrap_or_else(|| "never".to_string());
+            let failed = if health.last_failed > 0 {
+                format!(" ({} failed)", health.last_failed)
+            } else {
+                String::new()
+            };
+            let average_age = average_age
+                .map(|secs| format_duration(secs.round() as i64))
+                .unwrap_or_else(|| "n/a".to_string());
+
+            bot.send_message(
+                msg.chat.id,
+                format!(
+                    "{}<b>🩺 Bot Status</b>\n\n\
+                     ✅ Last successful tick: {}\n\
+                     👛 Wallets polled in it: {}{}\n\
+                     ❌ API failures ({}): {}\n\
+                     🕰️ Average data age: {} over {} wallets\n\
+                     ⏱️ Uptime: {}",
+                    warning,
+                    last_tick,
+                    health.last_polled,
+                    failed,
+                    format_duration(hyperliquid::HEALTH_FAILURE_WINDOW_SECS),
+                    health.recent_failure_count(),
+                    average_age,
+                    wallets,
+                    format_duration((now - health.started_at).num_seconds())
+                ),
+            )
+            .reply_to(msg.id)
+            .parse_mode(ParseMode::Html)
+            .await?;
+        }
+        Command::Timezone(args) => {
+            let args = args.trim();
+            let result = if args.is_empty() {
+                Ok(())
+            } else {
+                match UserTimezone::parse(args) {
+                    Some(tz) => db::set_timezone(&pool, user_id, &tz.name()).await,
+                    None => {
+                        bot.send_message(
+                            msg.chat.id,
+                            format!(
+                                "❌ <code>{}</code> isn't a timezone I know.\n\n\
+                                 Use an IANA name like <code>Europe/Warsaw</code> or <code>America/New_York</code>, \
+                                 or a UTC offset like <code>UTC+2</code>, <code>UTC-5</code> or <code>+05:30</code>.\n\n\
+                                 Usage: <code>/timezone [zone]</code>",
+                                html::escape(args)
+                            ),
+                        )
+                        .reply_to(msg.id)
+                        .parse_mode(ParseMode::Html)
+                        .await?;
+                        return Ok(());
+                    }
+                }
+            };
+
+            match result.and(db::get_user_settings(&pool, user_id).await) {
+                Ok(settings) => {
+                    let tz = settings.tz();
+                    bot.send_message(
+                        msg.chat.id,
+                        format!(
+                            "<b>🕐 Timezone</b>\n\n\
+                             Current: <code>{}</code> (now {})\n\n\
+                             <i>Used for digest times, fills, history and logbook entries.</i>\n\n\
+                             Examples: <code>/timezone Europe/Warsaw</code>, <code>/timezone America/New_York</code>, \
+                             <code>/timezone UTC+2</code>, <code>/timezone UTC</code>",
+                            tz.name(),
+                            tz.format(chrono::Utc::now(), "%H:%M %Z")
+                        ),
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                }
+                Err(e) => {
+                    error!("Failed to update timezone: {}", e);
+                    bot.send_message(
+                        msg.chat.id,
+                        "❌ Failed to update your timezone. Please try again.",
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                }
+            }
+        }
+        Command::Leaderboard(args) => {
+            let (window, label) = match args.trim().to_lowercase().as_str() {
+                "" | "7d" | "week" => ("week", "7 Days"),
+                "30d" | "month" => ("month", "30 Days"),
+                "alltime" | "all" => ("allTime", "All Time"),
+                _ => {
+                    bot.send_message(
+                        msg.chat.id,
+                        "❌ Usage: <code>/leaderboard [7d|30d|alltime]</code>",
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                    return Ok(());
+                }
+            };
+
+            let wallets = match db::get_user_wallets(&pool, user_id).await {
+                Ok(wallets) => wallets,
+                Err(e) => {
+                    error!("Failed to fetch wallets for leaderboard: {}", e);
+                    bot.send_message(
+                        msg.chat.id,
+                        "❌ Failed to fetch your wallets. Please try again.",
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                    return Ok(());
+                }
+            };
+
+            if wallets.is_empty() {
+                bot.send_message(
+                    msg.chat.id,
+                    "📋 You're not tracking any wallets yet.\n\nUse <code>/add &lt;wallet&gt; [note]</code> to start tracking.",
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            }
+
+            let portfolios = fetch_wallet_portfolios(&wallets, window).await;
+
+            let mut ranked = Vec::new();
+            let mut unavailable = Vec::new();
+            for (w, portfolio) in wallets.iter().zip(&portfolios) {
+                match portfolio.as_ref().map(|p| (p.pnl(), p.return_pct())) {
+                    Ok((Some(pnl), pct)) => ranked.push((w, pnl, pct)),
+                    Ok((None, _)) => unavailable.push(w),
+                    Err(e) => {
+                        warn!(
+                            "Failed to fetch portfolio of {} for leaderboard: {}",
+                            w.wallet_address, e
+                        );
+                        unavailable.push(w);
+                    }
+                }
+            }
+
+            // Best first, ties keep wallet list order (the sort is stable)
+            ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+            let mut lines: Vec<String> = ranked
+                .iter()
+                .enumerate()
+                .map(|(rank, (w, pnl, pct))| {
+                    let place = match rank {
+                        0 => "🥇".to_string(),
+                        1 => "🥈".to_string(),
+                        2 => "🥉".to_string(),
+                        _ => format!("<b>{}.</b>", rank + 1),
+                    };
+                    let pct = pct
+                        .map(|pct| format!(" ({:+.2}%)", pct))
+                        .unwrap_or_default();
+                    format!(
+                        "\n{} 👛 {}\n   💰 {}{}",
+                        place,
+                        format_wallet_display(&w.wallet_address, w.note.as_deref(), false),
+                        hyperliquid::format_pnl(*pnl),
+                        pct
+                    )
+                })
+                .collect();
+            lines.extend(unavailable.iter().map(|w| {
+                format!(
+                    "\n➖ 👛 {}\n   💰 n/a",
+                    format_wallet_display(&w.wallet_address, w.note.as_deref(), false)
+                )
+            }));
+
+            let header = format!(
+                "<b>🏆 Wallet Leaderboard · {}</b>\n<i>Your wallets by PnL, percent of the account value at the start</i>",
+                label
+            );
+            for message in split_lines(header, lines) {
+                bot.send_message(msg.chat.id, message)
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+            }
+        }
+        Command::Clear(args) => {
+            match args.trim().to_lowercase().as_str() {
+                "" => {}
+                "confirm" => {
+                    let text = match clear_all_wallets(&pool, &state, user_id).await {
+                        Ok(0) => "📋 You're not tracking any wallets.".to_string(),
+                        Ok(removed) => format!("✅ Stopped tracking all {} wallet(s).", removed),
+                        Err(e) => {
+                            error!("Failed to clear wallets of {}: {}", user_id, e);
+                            "❌ Failed to remove your wallets. Please try again.".to_string()
+                        }
+                    };
+                    bot.send_message(msg.chat.id, text)
+                        .reply_to(msg.id)
+                        .parse_mode(ParseMode::Html)
+                        .await?;
+                    return Ok(());
+                }
+                _ => {
+                    bot.send_message(msg.chat.id, "❌ Usage: <code>/clear [confirm]</code>")
+                        .reply_to(msg.id)
+                        .parse_mode(ParseMode::Html)
+                        .await?;
+                    return Ok(());
+                }
+            }
+
+            let count = match db::get_user_wallet_count(&pool, user_id).await {
+                Ok(count) => count,
+                Err(e) => {
+                    error!("Failed to count wallets for clear: {}", e);
+                    bot.send_message(
+                        msg.chat.id,
+                        "❌ Failed to fetch your wallets. Please try again.",
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                    return Ok(());
+                }
+            };
+            if count == 0 {
+                bot.send_message(msg.chat.id, "📋 You're not tracking any wallets.")
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                return Ok(());
+            }
+
+            let keyboard = InlineKeyboardMarkup::new([[
+                InlineKeyboardButton::callback("🗑 Yes, remove all", format!("clear:{}", user_id)),
+                InlineKeyboardButton::callback("Cancel", "clear:cancel"),
+            ]]);
+            bot.send_message(
+                msg.chat.id,
+                format!(
+                    "⚠️ <b>Remove all {} of your wallets?</b>\n\n\
+                     Their logbooks and notification threads go too. This can't be undone, \
+                     /export first if you want a copy.\n\n\
+                     <i>The buttons expire in {} minutes.</i>",
+                    count,
+                    CLEAR_CONFIRM_SECS / 60
+                ),
+            )
+            .reply_to(msg.id)
+            .parse_mode(ParseMode::Html)
+            .reply_markup(keyboard)
+            .await?;
+        }
+        Command::WatchCoin(args) => {
+            let args = args.trim();
+            let (identifier, coins) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+            let coins = coins.trim();
+            if identifier.is_empty() {
+                bot.send_message(
+                    msg.chat.id,
+                    "❌ Please provide a wallet and the coins to notify about.\n\nUsage: <code>/watchcoin &lt;address|index|note&gt; &lt;coin[,coin…]&gt;</code> or <code>/watchcoin &lt;wallet&gt; clear</code>",
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            }
+
+            // Resolve the identifier to a tracked wallet
+            let resolved = match resolve_wallet_identifier(&pool, user_id, identifier).await {
+                Ok(Some((addr, note))) => match db::is_tracking(&pool, user_id, &addr).await {
+                    Ok(true) => Some((addr, note)),
+                    Ok(false) => None,
+                    Err(e) => {
+                        error!("Failed to check tracked wallet: {}", e);
+                        None
+                    }
+                },
+                Ok(None) => None,
+                Err(e) => {
+                    error!("Failed to resolve wallet identifier: {}", e);
+                    None
+                }
+            };
+            let Some((wallet, note)) = resolved else {
+                bot.send_message(
+                    msg.chat.id,
+                    wallet_not_found_text(
+                        &pool,
+                        user_id,
+                        identifier,
+                        "❌ Wallet not found. Use <code>/list</code> to see your tracked wallets.",
+                    )
+                    .await,
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            };
+
+            let result = if coins.is_empty() {
+                Ok(())
+            } else if coins.eq_ignore_ascii_case("clear") {
+                db::set_watched_coins(&pool, user_id, &wallet, &[]).await
+            } else {
+                let watched = match resolve_perps(coins) {
+                    Ok(watched) => watched,
+                    Err(e) => {
+                        bot.send_message(msg.chat.id, e)
+                            .reply_to(msg.id)
+                            .parse_mode(ParseMode::Html)
+                            .await?;
+                        return Ok(());
+                    }
+                };
+                db::set_watched_coins(&pool, user_id, &wallet, &watched).await
+            };
+
+            let filters = match result.and(db::get_user_watched_coins(&pool, user_id).await) {
+                Ok(filters) => filters,
+                Err(e) => {
+                    error!("Failed to update coin filter: {}", e);
+                    bot.send_message(
+                        msg.chat.id,
+                        "❌ Failed to update the coin filter. Please try again.",
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                    return Ok(());
+                }
+            };
+
+            let confirmation = wallet_confirmation(&pool, user_id, &wallet, note.as_deref()).await;
+            let status = match filters.get(&wallet) {
+                Some(coins) => format!(
+                    "🎯 Only notifying about: <b>{}</b>\n\n<i>Other coins of this wallet are skipped for you. Use <code>/watchcoin {} clear</code> to get every coin again.</i>",
+                    coins.join(", "),
+                    html::escape(identifier)
+                ),
+                None => "🌐 Notifying about every coin\n\n<i>Limit it with <code>/watchcoin &lt;wallet&gt; BTC,ETH</code></i>"
+                    .to_string(),
+            };
+            bot.send_message(msg.chat.id, format!("{}\n\n{}", confirmation, status))
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+        }
+        Command::Price(args) => {
+            let input = args.trim();
+            if input.is_empty() {
+                bot.send_message(
+                    msg.chat.id,
+                    "❌ Please provide a coin.\n\nUsage: <code>/price &lt;coin&gt;</code>, e.g. <code>/price BTC</code>",
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            }
+
+            let (mids, assets) = tokio::join!(
+                hyperliquid::fetch_all_mids(&HTTP_CLIENT),
+                hyperliquid::fetch_asset_ctxs(&HTTP_CLIENT)
+            );
+            let assets = match assets {
+                Ok(assets) => assets,
+                Err(e) => {
+                    error!("Failed to fetch asset contexts: {}", e);
+                    bot.send_message(msg.chat.id, "❌ Failed to fetch prices. Please try again.")
+                        .reply_to(msg.id)
+                        .parse_mode(ParseMode::Html)
+                        .await?;
+                    return Ok(());
+                }
+            };
+
+            let names: Vec<&str> = assets.iter().map(|(meta, _)| meta.name.as_str()).collect();
+            let Some(coin) = find_perp(input, &names) else {
+                let suggestions = closest_names(input, &names, 3);
+                let hint = if suggestions.is_empty() {
+                    "Use the perp's ticker, like <code>/price BTC</code>.".to_string()
+                } else {
+                    format!(
+                        "Did you mean {}?",
+                        suggestions
+                            .iter()
+                            .map(|name| format!("<b>{}</b>", html::escape(name)))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )
+                };
+                bot.send_message(
+                    msg.chat.id,
+                    format!(
+                        "❌ <b>{}</b> isn't a perp on Hyperliquid. {}",
+                        html::escape(input),
+                        hint
+                    ),
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            };
+            let ctx = &assets
+                .iter()
+                .find(|(meta, _)| meta.name == coin)
+                .expect("coin comes from the asset list")
+                .1;
+
+            let mark: f64 = ctx.mark_px.parse().unwrap_or(0.0);
+            let price = mids
+                .map_err(|e| warn!("Failed to fetch mids for /price: {}", e))
+                .ok()
+                .and_then(|mids| mids.get(coin).copied())
+                .unwrap_or(mark);
+            let prev_day: f64 = ctx.prev_day_px.parse().unwrap_or(0.0);
+            let change = if prev_day > 0.0 {
+                format!(" ({:+.2}% 24h)", (price - prev_day) / prev_day * 100.0)
+            } else {
+                String::new()
+            };
+            let funding: f64 = ctx.funding.parse().unwrap_or(0.0);
+            let open_interest: f64 = ctx.open_interest.parse().unwrap_or(0.0);
+            let volume: f64 = ctx.day_ntl_vlm.parse().unwrap_or(0.0);
+
+            // k-coins are quoted per 1000 units, show the single-unit price too
+            let (title, unit_price) = match coin.strip_prefix('k') {
+                Some(base) if base.chars().next().is_some_and(|c| c.is_ascii_uppercase()) => (
+                    format!("{} (1000 {})", coin, base),
+                    format!(
+                        "\n🔹 Per {}: {}",
+                        base,
+                        hyperliquid::format_price(price / 1000.0)
+                    ),
+                ),
+                _ => (coin.to_string(), String::new()),
+            };
+
+            bot.send_message(
+                msg.chat.id,
+                format!(
+                    "<b>💲 {}</b>\n\n\
+                     💵 Price: <b>{}</b>{}{}\n\
+                     🏷 Mark: {}\n\
+                     💸 Funding: {}\n\
+                     📦 Open interest: {}\n\
+                     📊 24h volume: {}",
+                    html::escape(&title),
+                    hyperliquid::format_coin_price(coin, price),
+                    change,
+                    unit_price,
+                    hyperliquid::format_coin_price(coin, mark),
+                    format_funding_rate(funding),
+                    hyperliquid::format_usd_compact(open_interest * mark),
+                    hyperliquid::format_usd_compact(volume)
+                ),
+            )
+            .reply_to(msg.id)
+            .parse_mode(ParseMode::Html)
+            .await?;
+        }
+        Command::FundingRates(args) => {
+            let args = args.trim();
+            let count = if args.is_empty() {
+                DEFAULT_FUNDING_RATES_SHOWN
+            } else {
+                match args.parse::<usize>() {
+                    Ok(count) => count.clamp(1, MAX_FUNDING_RATES_SHOWN),
+                    Err(_) => {
+                        bot.send_message(
+                            msg.chat.id,
+                            "❌ Usage: <code>/fundingrates [count]</code>",
+                        )
+                        .reply_to(msg.id)
+                        .parse_mode(ParseMode::Html)
+                        .await?;
+                        return Ok(());
+                    }
+                }
+            };
+
+            let assets = match hyperliquid::fetch_asset_ctxs(&HTTP_CLIENT).await {
+                Ok(assets) => assets,
+                Err(e) => {
+                    error!("Failed to fetch asset contexts: {}", e);
+                    bot.send_message(
+                        msg.chat.id,
+                        "❌ Failed to fetch funding rates. Please try again.",
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                    return Ok(());
+                }
+            };
+
+            // Delisted perps keep a funding rate but have no open interest
+            let mut rates: Vec<(&str, f64, f64)> = assets
+                .iter()
+                .filter_map(|(meta, ctx)| {
+                    let funding: f64 = ctx.funding.parse().ok()?;
+                    let open_interest =
+                        ctx.open_interest.parse::<f64>().ok()? * ctx.mark_px.parse::<f64>().ok()?;
+                    (open_interest > 0.0).then_some((meta.name.as_str(), funding, open_interest))
+                })
+                .collect();
+            rates.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+            let line = |(rank, (coin, funding, open_interest)): (usize, &(&str, f64, f64))| {
+                format!(
+                    "<b>{}.</b> {} · {} · OI {}",
+                    rank + 1,
+                    html::escape(coin),
+                    format_funding_rate(*funding),
+                    hyperliquid::format_usd_compact(*open_interest)
+                )
+            };
+            let longs_pay: Vec<String> = rates
+                .iter()
+                .filter(|(_, funding, _)| *funding > 0.0)
+                .take(count)
+                .enumerate()
+                .map(line)
+                .collect();
+            let shorts_pay: Vec<String> = rates
+                .iter()
+                .rev()
+                .filter(|(_, funding, _)| *funding < 0.0)
+                .take(count)
+                .enumerate()
+                .map(line)
+                .collect();
+            let section = |lines: Vec<String>| {
+                if lines.is_empty() {
+                    "<i>None right now</i>".to_string()
+                } else {
+                    lines.join("\n")
+                }
+            };
+
+            bot.send_message(
+                msg.chat.id,
+                format!(
+                    "<b>💸 Funding Rates</b>\n\n\
+                     <b>🟢 Longs pay</b>\n{}\n\n\
+                     <b>🔴 Shorts pay</b>\n{}\n\n\
+                     <i>Current hourly rates, APR assumes they stay the same for a year</i>",
+                    section(longs_pay),
+                    section(shorts_pay)
+                ),
+            )
+            .reply_to(msg.id)
+            .parse_mode(ParseMode::Html)
+            .await?;
+        }
+        Command::Whois(text) => {
+            let text = text.trim();
+            if text.is_empty() {
+                bot.send_message(
+                    msg.chat.id,
+                    "❌ Please provide part of a note or address.\n\nUsage: <code>/whois &lt;text&gt;</code>, e.g. <code>/whois 0x1a2b…9f3c</code>",
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            }
+
+            match db::search_user_wallets(&pool, user_id, text).await {
+                Ok(matches) if matches.is_empty() => {
+                    bot.send_message(
+                        msg.chat.id,
+                        format!(
+                            "🔍 None of your wallets match <code>{}</code>.\n\nUse <code>/list</code> to see your tracked wallets.",
+                            html::escape(text)
+                        ),
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                }
+                Ok(matches) => {
+                    let entries: Vec<String> = matches
+                        .iter()
+                        .map(|(index, w)| {
+                            format_wallet_confirmation(
+                                &w.wallet_address,
+                                w.note.as_deref(),
+                                Some(*index),
+                            )
+                        })
+                        .collect();
+                    bot.send_message(
+                        msg.chat.id,
+                        format!(
+                            "<b>🔍 {} match(es) for</b> <code>{}</code>\n\n{}",
+                            matches.len(),
+                            html::escape(text),
+                            entries.join("\n\n")
+                        ),
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                }
+                Err(e) => {
+                    error!("Failed to search wallets: {}", e);
+                    bot.send_message(
+                        msg.chat.id,
+                        "❌ Failed to search your wallets. Please try again.",
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                }
+            }
+        }
+        Command::Feedback(text) => {
+            let text = text.trim();
+            let Some(admin_chat) = *ADMIN_CHAT_ID else {
+                bot.send_message(
+                    msg.chat.id,
+                    "⚠️ Feedback isn't set up on this bot instance.",
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            };
+            if text.is_empty() {
+                bot.send_message(
+                    msg.chat.id,
+                    "❌ Please write your feedback after the command.\n\nUsage: <code>/feedback &lt;text&gt;</code>",
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            }
+            if !take_feedback_slot(user_id) {
+                bot.send_message(
+                    msg.chat.id,
+                    format!(
+                        "⏳ You can send up to {} feedback messages per hour. Please try again later.",
+                        MAX_FEEDBACK_PER_WINDOW
+                    ),
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            }
+
+            let username = msg
+                .from
+                .as_ref()
+                .and_then(|u| u.username.as_deref())
+                .map(|username| format!(" (@{})", html::escape(username)))
+                .unwrap_or_default();
+            let forwarded = bot
+                .send_message(
+                    ChatId(admin_chat),
+                    format!(
+                        "<b>📬 Feedback</b> from <code>{}</code>{}\n\n{}",
+                        user_id,
+                        username,
+                        html::escape(text)
+                    ),
+                )
+                .parse_mode(ParseMode::Html)
+                .await;
+            let reply = match forwarded {
+                Ok(_) => {
+                    info!("Forwarded feedback from user {}", user_id);
+                    "✅ Thanks! Your feedback was sent to the admin."
+                }
+                Err(e) => {
+                    error!("Failed to forward feedback from {}: {}", user_id, e);
+                    "❌ Failed to send your feedback. Please try again later."
+                }
+            };
+            bot.send_message(msg.chat.id, reply)
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+        }
+        Command::Broadcast(text) => {
+            // Not even an error for anyone else, the command shouldn't look like it exists
+            if !broadcast::is_admin(user_id) {
+                return Ok(());
+            }
+            let text = text.trim();
+            if text.is_empty() {
+                bot.send_message(
+                    msg.chat.id,
+                    "❌ Usage: <code>/broadcast &lt;text&gt;</code>",
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            }
+
+            match db::create_broadcast(&pool, msg.chat.id.0, text).await {
+                Ok(created) => {
+                    info!("User {} started broadcast {}", user_id, created.id);
+                    bot.send_message(
+                        msg.chat.id,
+                        "📢 Broadcast started. I'll report back here when it's done.",
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                    tokio::spawn(broadcast::run_broadcast(pool.clone(), bot.clone(), created));
+                }
+                Err(e) => {
+                    error!("Failed to create broadcast: {}", e);
+                    bot.send_message(
+                        msg.chat.id,
+                        "❌ Failed to start the broadcast. Please try again.",
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                }
+            }
+        }
+        Command::GlobalStats => {
+            // Ignored like /broadcast, non-admins get no hint the command exists
+            if !broadcast::is_admin(user_id) {
+                return Ok(());
+            }
+
+            let stats = async {
+                let stats = db::get_global_stats(&pool).await?;
+                let counts = db::get_global_notification_counts(&pool).await?;
+                anyhow::Ok((stats, counts))
+            }
+            .await;
+            let (stats, counts) = match stats {
+                Ok(stats) => stats,
+                Err(e) => {
+                    error!("Failed to load global stats: {}", e);
+                    bot.send_message(msg.chat.id, "❌ Failed to load stats. Please try again.")
+                        .reply_to(msg.id)
+                        .parse_mode(ParseMode::Html)
+                        .await?;
+                    return Ok(());
+                }
+            };
+
+            let notifications = if counts.is_empty() {
+                "<i>None in the last 24 hours</i>".to_string()
+            } else {
+                let mut lines: Vec<String> = counts
+                    .iter()
+                    .map(|(kind, count)| {
+                        format!(
+                            "{}: {}",
+                            notification_kind_label(kind),
+                            format_count(*count as usize)
+                        )
+                    })
+                    .collect();
+                lines.push(format!(
+                    "<b>Total</b>: {}",
+                    format_count(counts.iter().map(|(_, count)| count).sum::<i64>() as usize)
+                ));
+                lines.join("\n")
+            };
+            let error_rate = if stats.api_calls > 0 {
+                format!(
+                    "{:.2}% ({} of {} calls)",
+                    stats.api_errors as f64 / stats.api_calls as f64 * 100.0,
+                    format_count(stats.api_errors as usize),
+                    format_count(stats.api_calls as usize)
+                )
+            } else {
+                "n/a, no calls recorded".to_string()
+            };
+
+            bot.send_message(
+                msg.chat.id,
+                format!(
+                    "<b>🌍 Global Stats</b>\n\n\
+                     👥 Users: {}\n\
+                     👛 Tracked wallets: {} ({} distinct)\n\
+                     🌐 API error rate (1h): {}\n\
+                     💾 Database: {}\n\n\
+                     <b>Notifications</b> <i>(24h)</i>\n\
+                     {}",
+                    format_count(stats.users as usize),
+                    format_count(stats.wallets as usize),
+                    format_count(stats.distinct_wallets as usize),
+                    error_rate,
+                    format_bytes(stats.database_bytes),
+                    notifications
+                ),
+            )
+            .reply_to(msg.id)
+            .parse_mode(ParseMode::Html)
+            .await?;
+        }
+        Command::SubAccounts(identifier) => {
+            let Some((identifier, assumed)) = identifier_or_last(user_id, &identifier) else {
+                bot.send_message(
+                    msg.chat.id,
+                    "❌ Please provide a wallet address, index (1-10), or note.\n\nUsage: <code>/subaccounts &lt;address|index|note&gt;</code>",
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            };
+            let identifier = identifier.as_str();
+
+            let Some((wallet, note)) = resolve_or_raw_address(
+                &bot,
+                &msg,
+                &pool,
+                user_id,
+                identifier,
+                WALLET_NOT_FOUND,
+                "❌ Failed to fetch sub-accounts. Please try again.",
+            )
+            .await?
+            else {
+                return Ok(());
+            };
+
+            if assumed {
+                send_assumed_wallet_notice(&bot, &msg, &wallet, note.as_deref()).await?;
+            }
+
+            let sub_accounts = match hyperliquid::fetch_sub_accounts(&HTTP_CLIENT, &wallet).await {
+                Ok(sub_accounts) => sub_accounts,
+                Err(e) => {
+                    error!("Failed to fetch sub-accounts for {}: {}", wallet, e);
+                    bot.send_message(
+                        msg.chat.id,
+                        "❌ Failed to fetch sub-accounts. Please try again.",
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                    return Ok(());
+                }
+            };
+
+            let wallet_display = format_wallet_display(&wallet, note.as_deref(), false);
+            if sub_accounts.is_empty() {
+                bot.send_message(
+                    msg.chat.id,
+                    format!("ℹ️ {} has no sub-accounts.", wallet_display),
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            }
+
+            let tracked: HashSet<String> = db::get_user_wallets(&pool, user_id)
+                .await
+                .map(|wallets| wallets.into_iter().map(|w| w.wallet_address).collect())
+                .unwrap_or_default();
+
+            let entries: Vec<String> = sub_accounts
+                .iter()
+                .enumerate()
+                .map(|(i, sub)| {
+                    let account_value: f64 = sub
+                        .clearinghouse_state
+                        .margin_summary
+                        .account_value
+                        .parse()
+                        .unwrap_or(0.0);
+                    let tracked_mark = if tracked.contains(&sub.sub_account_user.to_lowercase()) {
+                        " ✅"
+                    } else {
+                        ""
+                    };
+                    format!(
+                        "<b>{}.</b> {}{}\n<code>{}</code>\n💰 {}",
+                        i + 1,
+                        html::escape(&sub.name),
+                        tracked_mark,
+                        sub.sub_account_user.to_lowercase(),
+                        hyperliquid::format_usd_compact(account_value)
+                    )
+                })
+                .collect();
+
+            // Telegram allows 100 buttons, far more than anyone has sub-accounts
+            let buttons: Vec<Vec<InlineKeyboardButton>> = sub_accounts
+                .iter()
+                .enumerate()
+                .filter(|(_, sub)| !tracked.contains(&sub.sub_account_user.to_lowercase()))
+                .map(|(i, sub)| {
+                    vec![InlineKeyboardButton::callback(
+                        format!("➕ Track #{} {}", i + 1, sub.name),
+                        format!("track:{}", sub.sub_account_user.to_lowercase()),
+                    )]
+                })
+                .collect();
+
+            let footer = if buttons.is_empty() {
+                "<i>✅ marks sub-accounts you already track.</i>"
+            } else {
+                "<i>✅ marks sub-accounts you already track, tap a button to track another.</i>"
+            };
+            bot.send_message(
+                msg.chat.id,
+                format!(
+                    "<b>🗂 Sub-accounts</b>\n\n👛 Master: {}\n\n{}\n\n{}",
+                    wallet_display,
+                    entries.join("\n\n"),
+                    footer
+                ),
+            )
+            .reply_to(msg.id)
+            .parse_mode(ParseMode::Html)
+            .reply_markup(InlineKeyboardMarkup::new(buttons))
+            .await?;
+        }
+        Command::Liq => {
+            let wallets = match db::get_user_wallets(&pool, user_id).await {
+                Ok(wallets) => wallets,
+                Err(e) => {
+                    error!("Failed to fetch wallets: {}", e);
+                    bot.send_message(
+                        msg.chat.id,
+                        "❌ Failed to fetch liquidation prices. Please try again.",
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                    return Ok(());
+                }
+            };
+
+            if wallets.is_empty() {
+                bot.send_message(
+                    msg.chat.id,
+                    "📋 You're not tracking any wallets yet.\n\nUse <code>/add &lt;wallet&gt; [note]</code> to start tracking.",
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            }
+
+            for message in liquidation_messages(&wallets).await {
+                bot.send_message(msg.chat.id, message)
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+            }
+        }
+        Command::Margin(identifier) => {
+            let Some((identifier, assumed)) = identifier_or_last(user_id, &identifier) else {
+                bot.send_message(
+                    msg.chat.id,
+                    "❌ Please provide a wallet address, index (1-10), or note.\n\nUsage: <code>/margin &lt;address|index|note&gt;</code>",
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            };
+            let identifier = identifier.as_str();
+
+            let Some((wallet, note)) = resolve_or_raw_address(
+                &bot,
+                &msg,
+                &pool,
+                user_id,
+                identifier,
+                WALLET_NOT_FOUND,
+                "❌ Failed to fetch margin. Please try again.",
+            )
+            .await?
+            else {
+                return Ok(());
+            };
+
+            if assumed {
+                send_assumed_wallet_notice(&bot, &msg, &wallet, note.as_deref()).await?;
+            }
+
+            let user_state = match hyperliquid::fetch_user_state(&HTTP_CLIENT, &wallet).await {
+                Ok(user_state) => user_state,
+                Err(e) => {
+                    error!("Failed to fetch margin for {}: {}", wallet, e);
+                    bot.send_message(msg.chat.id, "❌ Failed to fetch margin. Please try again.")
+                        .reply_to(msg.id)
+                        .parse_mode(ParseMode::Html)
+                        .await?;
+                    return Ok(());
+                }
+            };
+
+            let wallet_display = format_wallet_display(&wallet, note.as_deref(), false);
+            bot.send_message(msg.chat.id, margin_message(&wallet_display, &user_state))
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+        }
+        Command::Roi(args) => {
+            let args = args.trim();
+            // The period is an optional last word, the rest names the wallet
+            let (identifier, period) = match roi_period(args) {
+                Some(period) => ("", Some(period)),
+                None => match args.rsplit_once(char::is_whitespace) {
+                    Some((head, tail)) => match roi_period(tail) {
+                        Some(period) => (head.trim(), Some(period)),
+                        None => (args, None),
+                    },
+                    None => (args, None),
+                },
+            };
+            let (window, label) = period.unwrap_or(("week", "7 Days"));
+
+            let Some((identifier, assumed)) = identifier_or_last(user_id, identifier) else {
+                bot.send_message(
+                    msg.chat.id,
+                    format!(
+                        "❌ Please provide a wallet address, index (1-10), or note.\n\nUsage: <code>/roi &lt;address|index|note&gt; [{}]</code>",
+                        ROI_PERIODS.replace(", ", "|")
+                    ),
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            };
+            let identifier = identifier.as_str();
+
+            // "<wallet> <word>" that doesn't resolve most likely has a mistyped period
+            let not_found = match identifier.rsplit_once(char::is_whitespace) {
+                Some((_, tail)) if period.is_none() => format!(
+                    "❌ Unknown period <code>{}</code>. Accepted periods: {}.",
+                    html::escape(tail),
+                    ROI_PERIODS
+                ),
+                _ => WALLET_NOT_FOUND.to_string(),
+            };
+            let Some((wallet, note)) = resolve_or_raw_address(
+                &bot,
+                &msg,
+                &pool,
+                user_id,
+                identifier,
+                &not_found,
+                "❌ Failed to fetch ROI. Please try again.",
+            )
+            .await?
+            else {
+                return Ok(());
+            };
+
+            if assumed {
+                send_assumed_wallet_notice(&bot, &msg, &wallet, note.as_deref()).await?;
+            }
+
+            let history = match hyperliquid::fetch_portfolio(&HTTP_CLIENT, &wallet).await {
+                Ok(portfolio) => portfolio
+                    .into_iter()
+                    .find(|(name, _)| name == window)
+                    .map(|(_, history)| history),
+                Err(e) => {
+                    error!("Failed to fetch portfolio for {}: {}", wallet, e);
+                    bot.send_message(msg.chat.id, "❌ Failed to fetch ROI. Please try again.")
+                        .reply_to(msg.id)
+                        .parse_mode(ParseMode::Html)
+                        .await?;
+                    return Ok(());
+                }
+            };
+
+            let wallet_display = format_wallet_display(&wallet, note.as_deref(), false);
+            let Some((history, (start, end))) =
+                history.and_then(|h| h.account_values().map(|values| (h, values)))
+            else {
+                bot.send_message(
+                    msg.chat.id,
+                    format!(
+                        "<b>📈 ROI · {}</b>\n\n👛 Wallet: {}\n\n<i>No portfolio history for this period.</i>",
+                        label, wallet_display
+                    ),
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            };
+
+            let pnl = history
+                .pnl()
+                .map(hyperliquid::format_pnl)
+                .unwrap_or_else(|| "n/a".to_string());
+            let return_pct = history
+                .return_pct()
+                .map(|pct| format!(" ({:+.2}%)", pct))
+                .unwrap_or_default();
+            let drawdown = history
+                .max_drawdown_pct()
+                .map(|pct| format!("-{:.2}%", pct))
+                .unwrap_or_else(|| "n/a".to_string());
+
+            bot.send_message(
+                msg.chat.id,
+                format!(
+                    "<b>📈 ROI · {}</b>\n\n\
+                     👛 Wallet: {}\n\n\
+                     💰 Account value: ${:.2} → ${:.2} ({})\n\
+                     💵 PnL: {}{}\n\
+                     📉 Max drawdown: {}",
+                    label,
+                    wallet_display,
+                    start,
+                    end,
+                    hyperliquid::format_pnl(end - start),
+                    pnl,
+                    return_pct,
+                    drawdown
+                ),
+            )
+            .reply_to(msg.id)
+            .parse_mode(ParseMode::Html)
+            .await?;
+        }
+        Command::Winrate(args) => {
+            let (identifier, days) = split_trailing_days(&args);
+            let days = days.unwrap_or(DEFAULT_FILL_STATS_DAYS);
+            if !(1..=MAX_FILL_STATS_DAYS).contains(&days) {
+                bot.send_message(
+                    msg.chat.id,
+                    format!(
+                        "❌ Please provide between 1 and {} days.\n\nUsage: <code>/winrate &lt;address|index|note&gt; [days]</code>",
+                        MAX_FILL_STATS_DAYS
+                    ),
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            }
+
+            let Some((identifier, assumed)) = identifier_or_last(user_id, identifier) else {
+                bot.send_message(
+                    msg.chat.id,
+                    "❌ Please provide a wallet address, index (1-10), or note.\n\nUsage: <code>/winrate &lt;address|index|note&gt; [days]</code>",
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            };
+            let identifier = identifier.as_str();
+
+            let Some((wallet, note)) = resolve_or_raw_address(
+                &bot,
+                &msg,
+                &pool,
+                user_id,
+                identifier,
+                WALLET_NOT_FOUND,
+                "❌ Failed to fetch fills. Please try again.",
+            )
+            .await?
+            else {
+                return Ok(());
+            };
+
+            if assumed {
+                send_assumed_wallet_notice(&bot, &msg, &wallet, note.as_deref()).await?;
+            }
+
+            let fills = match hyperliquid::fetch_recent_fills(
+                &HTTP_CLIENT,
+                &wallet,
+                days,
+                MAX_FILL_STATS_FILLS,
+            )
+            .await
+            {
+                Ok(fills) => fills,
+                Err(e) => {
+                    error!("Failed to fetch fills for {}: {}", wallet, e);
+                    bot.send_message(msg.chat.id, "❌ Failed to fetch fills. Please try again.")
+                        .reply_to(msg.id)
+                        .parse_mode(ParseMode::Html)
+                        .await?;
+                    return Ok(());
+                }
+            };
+
+            let (fills, truncated) = fills;
+            let wallet_display = format_wallet_display(&wallet, note.as_deref(), false);
+            let outcomes = realized_outcomes(&fills);
+            let mut footnote =
+                "<i>Based on closed PnL from fills before fees. Every closing execution, \
+                                partial closes included, counts as one outcome.</i>"
+                    .to_string();
+            if truncated {
+                let tz = user_timezone(&pool, user_id).await;
+                footnote.push_str(&truncated_fills_notice(&fills, &tz));
+            }
+            let since = (chrono::Utc::now() - chrono::Duration::days(days)).naive_utc();
+            let gaps = db::get_tracking_gaps(&pool, &wallet)
+                .await
+                .unwrap_or_else(|e| {
+                    error!("Failed to load tracking gaps for {}: {}", wallet, e);
+                    Vec::new()
+                });
+            for (start, end) in db::gaps_since(&gaps, since) {
+                footnote.push_str(&format!(
+                    "\n⏸ <i>Not tracked from {} to {}, fills come from the exchange so trades from then still count.</i>",
+                    start.format("%Y-%m-%d"),
+                    end.format("%Y-%m-%d")
+                ));
+            }
+            if outcomes.is_empty() {
+                bot.send_message(
+                    msg.chat.id,
+                    format!(
+                        "<b>🎯 Win Rate · {}d</b>\n\n👛 Wallet: {}\n\n<i>No closed trades in this period.</i>\n\n{}",
+                        days, wallet_display, footnote
+                    ),
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            }
+
+            let wins: Vec<f64> = outcomes.iter().copied().filter(|p| *p > 0.0).collect();
+            let losses: Vec<f64> = outcomes.iter().copied().filter(|p| *p < 0.0).collect();
+            let gross_win: f64 = wins.iter().sum();
+            let gross_loss: f64 = losses.iter().sum::<f64>().abs();
+            let average = |values: &[f64]| {
+                if values.is_empty() {
+                    "n/a".to_string()
+                } else {
+                    hyperliquid::format_pnl(values.iter().sum::<f64>() / values.len() as f64)
+                }
+            };
+            let profit_factor = if gross_loss > 0.0 {
+                format!("{:.2}", gross_win / gross_loss)
+            } else {
+                "∞".to_string()
+            };
+
+            bot.send_message(
+                msg.chat.id,
+                format!(
+                    "<b>🎯 Win Rate · {}d</b>\n\n\
+                     👛 Wallet: {}\n\n\
+                     🏆 Win rate: {:.1}% ({} of {})\n\
+                     🟢 Average win: {}\n\
+                     🔴 Average loss: {}\n\
+                     ⚖️ Profit factor: {}\n\
+                     💵 Net closed PnL: {}\n\n\
+                     {}",
+                    days,
+                    wallet_display,
+                    wins.len() as f64 / outcomes.len() as f64 * 100.0,
+                    wins.len(),
+                    outcomes.len(),
+                    average(&wins),
+                    average(&losses),
+                    profit_factor,
+                    hyperliquid::format_pnl(gross_win - gross_loss),
+                    footnote
+                ),
+            )
+            .reply_to(msg.id)
+            .parse_mode(ParseMode::Html)
+            .await?;
+        }
+        Command::Volume(args) => {
+            let (identifier, days) = split_trailing_days(&args);
+            let days = days.unwrap_or(DEFAULT_FILL_STATS_DAYS);
+            if !(1..=MAX_FILL_STATS_DAYS).contains(&days) {
+                bot.send_message(
+                    msg.chat.id,
+                    format!(
+                        "❌ Please provide between 1 and {} days.\n\nUsage: <code>/volume &lt;address|index|note&gt; [days]</code>",
+                        MAX_FILL_STATS_DAYS
+                    ),
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            }
+
+            let Some((identifier, assumed)) = identifier_or_last(user_id, identifier) else {
+                bot.send_message(
+                    msg.chat.id,
+                    "❌ Please provide a wallet address, index (1-10), or note.\n\nUsage: <code>/volume &lt;address|index|note&gt; [days]</code>",
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            };
+            let identifier = identifier.as_str();
+
+            let Some((wallet, note)) = resolve_or_raw_address(
+                &bot,
+                &msg,
+                &pool,
+                user_id,
+                identifier,
+                WALLET_NOT_FOUND,
+                "❌ Failed to fetch fills. Please try again.",
+            )
+            .await?
+            else {
+                return Ok(());
+            };
+
+            if assumed {
+                send_assumed_wallet_notice(&bot, &msg, &wallet, note.as_deref()).await?;
+            }
+
+            let (fills, truncated) = match hyperliquid::fetch_recent_fills(
+                &HTTP_CLIENT,
+                &wallet,
+                days,
+                MAX_FILL_STATS_FILLS,
+            )
+            .await
+            {
+                Ok(fills) => fills,
+                Err(e) => {
+                    error!("Failed to fetch fills for {}: {}", wallet, e);
+                    bot.send_message(msg.chat.id, "❌ Failed to fetch fills. Please try again.")
+                        .reply_to(msg.id)
+                        .parse_mode(ParseMode::Html)
+                        .await?;
+                    return Ok(());
+                }
+            };
+
+            let wallet_display = format_wallet_display(&wallet, note.as_deref(), false);
+            if fills.is_empty() {
+                bot.send_message(
+                    msg.chat.id,
+                    format!(
+                        "<b>📦 Volume · {}d</b>\n\n👛 Wallet: {}\n\n<i>No fills in this period.</i>",
+                        days, wallet_display
+                    ),
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            }
+
+            let mut maker = 0.0;
+            let mut taker = 0.0;
+            let mut by_coin: HashMap<&str, f64> = HashMap::new();
+            for fill in &fills {
+                let notional = fill.notional();
+                if fill.crossed {
+                    taker += notional;
+                } else {
+                    maker += notional;
+                }
+                *by_coin.entry(fill.coin.as_str()).or_default() += notional;
+            }
+            let total = maker + taker;
+            let share = |volume: f64| {
+                if total > 0.0 {
+                    volume / total * 100.0
+                } else {
+                    0.0
+                }
+            };
+
+            let mut by_coin: Vec<(&str, f64)> = by_coin.into_iter().collect();
+            by_coin.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+            let top_coins: Vec<String> = by_coin
+                .iter()
+                .take(VOLUME_TOP_COINS)
+                .enumerate()
+                .map(|(i, (coin, volume))| {
+                    format!(
+                        "{}. <b>{}</b> {} ({:.1}%)",
+                        i + 1,
+                        coin,
+                        hyperliquid::format_usd_compact(*volume),
+                        share(*volume)
+                    )
+                })
+                .collect();
+
+            let mut text = format!(
+                "<b>📦 Volume · {}d</b>\n\n\
+                 👛 Wallet: {}\n\n\
+                 💰 Total: {} over {} fill(s)\n\
+                 🧱 Maker: {} ({:.1}%)\n\
+                 ⚡ Taker: {} ({:.1}%)\n\n\
+                 <b>Top coins</b>\n{}",
+                days,
+                wallet_display,
+                hyperliquid::format_usd_compact(total),
+                fills.len(),
+                hyperliquid::format_usd_compact(maker),
+                share(maker),
+                hyperliquid::format_usd_compact(taker),
+                share(taker),
+                top_coins.join("\n")
+            );
+            if truncated {
+                let tz = user_timezone(&pool, user_id).await;
+                text.push_str(&format!("\n{}", truncated_fills_notice(&fills, &tz)));
+            }
+
+            bot.send_message(msg.chat.id, text)
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+        }
+        Command::Fees(args) => {
+            let (identifier, days) = split_trailing_days(&args);
+            let days = days.unwrap_or(DEFAULT_FILL_STATS_DAYS);
+            if !(1..=MAX_FILL_STATS_DAYS).contains(&days) {
+                bot.send_message(
+                    msg.chat.id,
+                    format!(
+                        "❌ Please provide between 1 and {} days.\n\nUsage: <code>/fees &lt;address|index|note&gt; [days]</code>",
+                        MAX_FILL_STATS_DAYS
+                    ),
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            }
+
+            let Some((identifier, assumed)) = identifier_or_last(user_id, identifier) else {
+                bot.send_message(
+                    msg.chat.id,
+                    "❌ Please provide a wallet address, index (1-10), or note.\n\nUsage: <code>/fees &lt;address|index|note&gt; [days]</code>",
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            };
+            let identifier = identifier.as_str();
+
+            let Some((wallet, note)) = resolve_or_raw_address(
+                &bot,
+                &msg,
+                &pool,
+                user_id,
+                identifier,
+                WALLET_NOT_FOUND,
+                "❌ Failed to fetch fills. Please try again.",
+            )
+            .await?
+            else {
+                return Ok(());
+            };
+
+            if assumed {
+                send_assumed_wallet_notice(&bot, &msg, &wallet, note.as_deref()).await?;
+            }
+
+            let (fills, truncated) = match hyperliquid::fetch_recent_fills(
+                &HTTP_CLIENT,
+                &wallet,
+                days,
+                MAX_FILL_STATS_FILLS,
+            )
+            .await
+            {
+                Ok(fills) => fills,
+                Err(e) => {
+                    error!("Failed to fetch fills for {}: {}", wallet, e);
+                    bot.send_message(msg.chat.id, "❌ Failed to fetch fills. Please try again.")
+                        .reply_to(msg.id)
+                        .parse_mode(ParseMode::Html)
+                        .await?;
+                    return Ok(());
+                }
+            };
+
+            let wallet_display = format_wallet_display(&wallet, note.as_deref(), false);
+            if fills.is_empty() {
+                bot.send_message(
+                    msg.chat.id,
+                    format!(
+                        "<b>🧾 Fees · {}d</b>\n\n👛 Wallet: {}\n\n<i>No fills in this period.</i>",
+                        days, wallet_display
+                    ),
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            }
+
+            // (fees, volume) in USD, negative fees are rebates
+            let mut maker = (0.0, 0.0);
+            let mut taker = (0.0, 0.0);
+            let mut by_coin: HashMap<&str, (f64, f64)> = HashMap::new();
+            for fill in &fills {
+                let fee: f64 = fill.fee.parse().unwrap_or(0.0);
+                let notional = fill.notional();
+                let side = if fill.crossed { &mut taker } else { &mut maker };
+                side.0 += fee;
+                side.1 += notional;
+                let coin = by_coin.entry(fill.coin.as_str()).or_default();
+                coin.0 += fee;
+                coin.1 += notional;
+            }
+            let total = (maker.0 + taker.0, maker.1 + taker.1);
+
+            let mut by_coin: Vec<(&str, (f64, f64))> = by_coin.into_iter().collect();
+            by_coin.sort_by(|a, b| {
+                b.1.0
+                    .abs()
+                    .total_cmp(&a.1.0.abs())
+                    .then_with(|| a.0.cmp(b.0))
+            });
+            let top_coins: Vec<String> = by_coin
+                .iter()
+                .take(VOLUME_TOP_COINS)
+                .enumerate()
+                .map(|(i, (coin, (fee, volume)))| {
+                    format!(
+                        "{}. <b>{}</b> {} · {}",
+                        i + 1,
+                        coin,
+                        format_fee(*fee),
+                        format_fee_rate(*fee, *volume)
+                    )
+                })
+                .collect();
+
+            let mut text = format!(
+                "<b>🧾 Fees · {}d</b>\n\n\
+                 👛 Wallet: {}\n\n\
+                 💸 Total: {} on {} volume\n\
+                 📐 Effective rate: {}\n\
+                 🧱 Maker: {} · {}\n\
+                 ⚡ Taker: {} · {}\n\n\
+                 <b>Top coins</b>\n{}",
+                days,
+                wallet_display,
+                format_fee(total.0),
+                hyperliquid::format_usd_compact(total.1),
+                format_fee_rate(total.0, total.1),
+                format_fee(maker.0),
+                format_fee_rate(maker.0, maker.1),
+                format_fee(taker.0),
+                format_fee_rate(taker.0, taker.1),
+                top_coins.join("\n")
+            );
+            if truncated {
+                let tz = user_timezone(&pool, user_id).await;
+                text.push_str(&format!("\n{}", truncated_fills_notice(&fills, &tz)));
+            }
+
+            bot.send_message(msg.chat.id, text)
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+        }
+        Command::IgnoreCoin(coin) => {
+            let coin = coin.trim();
+            if coin.is_empty() {
+                let text = match db::get_user_ignored_coins(&pool, user_id).await {
+                    Ok(coins) if coins.is_empty() => {
+                        "🔔 You aren't ignoring any coins.\n\n<i>Use <code>/ignorecoin &lt;coin&gt;</code> to stop notifications about a coin on all your wallets.</i>"
+                            .to_string()
+                    }
+                    Ok(coins) => format!(
+                        "🔕 Ignored coins: <b>{}</b>\n\n<i>Wallets with a /watchcoin filter still notify about the coins they watch. Use <code>/unignorecoin &lt;coin&gt;</code> to undo.</i>",
+                        html::escape(&coins.join(", "))
+                    ),
+                    Err(e) => {
+                        error!("Failed to fetch ignored coins: {}", e);
+                        "❌ Failed to fetch ignored coins. Please try again.".to_string()
+                    }
+                };
+                bot.send_message(msg.chat.id, text)
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                return Ok(());
+            }
+
+            let coin = match resolve_perp(coin) {
+                Ok(coin) => coin,
+                Err(e) => {
+                    bot.send_message(msg.chat.id, e)
+                        .reply_to(msg.id)
+                        .parse_mode(ParseMode::Html)
+                        .await?;
+                    return Ok(());
+                }
+            };
+
+            let text = match db::add_ignored_coin(&pool, user_id, &coin).await {
+                Ok(true) => format!(
+                    "🔕 Ignoring <b>{}</b> on all your wallets.\n\n<i>Wallets with a /watchcoin filter including it still notify. Use <code>/unignorecoin {}</code> to undo.</i>",
+                    html::escape(&coin),
+                    html::escape(&coin)
+                ),
+                Ok(false) => format!("ℹ️ <b>{}</b> is already ignored.", html::escape(&coin)),
+                Err(e) => {
+                    error!("Failed to ignore coin: {}", e);
+                    "❌ Failed to ignore the coin. Please try again.".to_string()
+                }
+            };
+            bot.send_message(msg.chat.id, text)
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+        }
+        Command::UnignoreCoin(coin) => {
+            let coin = coin.trim();
+            if coin.is_empty() {
+                bot.send_message(
+                    msg.chat.id,
+                    "❌ Please provide a coin.\n\nUsage: <code>/unignorecoin &lt;coin&gt;</code>",
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            }
+
+            // Match the stored spelling, so "pepe" removes kPEPE
+            let ignored = db::get_user_ignored_coins(&pool, user_id)
+                .await
+                .unwrap_or_else(|e| {
+                    error!("Failed to fetch ignored coins: {}", e);
+                    Vec::new()
+                });
+            let stored = ignored
+                .iter()
+                .find(|ignored| hyperliquid::coin_matches(coin, ignored))
+                .map(String::as_str)
+                .unwrap_or(coin);
+
+            let text = match db::remove_ignored_coin(&pool, user_id, stored).await {
+                Ok(true) => format!("🔔 Notifying about <b>{}</b> again.", html::escape(stored)),
+                Ok(false) => format!(
+                    "ℹ️ <b>{}</b> isn't ignored. Use <code>/ignorecoin</code> to see your ignored coins.",
+                    html::escape(coin)
+                ),
+                Err(e) => {
+                    error!("Failed to unignore coin: {}", e);
+                    "❌ Failed to update ignored coins. Please try again.".to_string()
+                }
+            };
+            bot.send_message(msg.chat.id, text)
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+        }
+        Command::OnlyCoins(args) => {
+            let args = args.trim();
+            let result = if args.is_empty() {
+                Ok(())
+            } else if args.eq_ignore_ascii_case("off") {
+                db::set_only_coins(&pool, user_id, &[]).await
+            } else {
+                let only = match resolve_perps(args) {
+                    Ok(only) => only,
+                    Err(e) => {
+                        bot.send_message(msg.chat.id, e)
+                            .reply_to(msg.id)
+                            .parse_mode(ParseMode::Html)
+                            .await?;
+                        return Ok(());
+                    }
+                };
+                db::set_only_coins(&pool, user_id, &only).await
+            };
+
+            let only = match result.and(db::get_user_only_coins(&pool, user_id).await) {
+                Ok(only) => only,
+                Err(e) => {
+                    error!("Failed to update coin whitelist: {}", e);
+                    bot.send_message(
+                        msg.chat.id,
+                        "❌ Failed to update the coin whitelist. Please try again.",
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                    return Ok(());
+                }
+            };
+
+            let text = if only.is_empty() {
+                "🌐 Notifying about every coin\n\n<i>Limit all your wallets to some coins with <code>/onlycoins BTC,ETH,HYPE</code></i>"
+                    .to_string()
+            } else {
+                format!(
+                    "🎯 Only notifying about: <b>{}</b>\n\n<i>Other coins are skipped on all your wallets, except wallets with their own /watchcoin filter. Use <code>/onlycoins off</code> to get every coin again.</i>",
+                    html::escape(&only.join(", "))
+                )
+            };
+            bot.send_message(msg.chat.id, text)
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+        }
+        Command::Snooze(args) => {
+            let usage = "❌ Please provide a wallet and a duration.\n\nUsage: <code>/snooze &lt;address|index|note&gt; &lt;30m|4h|2d|off&gt;</code>";
+            let Some((identifier, duration)) = args.trim().rsplit_once(char::is_whitespace) else {
+                bot.send_message(msg.chat.id, usage)
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                return Ok(());
+            };
+            let identifier = identifier.trim();
+            let secs = if duration.eq_ignore_ascii_case("off") {
+                None
+            } else {
+                match parse_duration(duration) {
+                    Some(secs) => Some(secs),
+                    None => {
+                        bot.send_message(msg.chat.id, usage)
+                            .reply_to(msg.id)
+                            .parse_mode(ParseMode::Html)
+                            .await?;
+                        return Ok(());
+                    }
+                }
+            };
+
+            // Resolve the identifier to a tracked wallet
+            let resolved = match resolve_wallet_identifier(&pool, user_id, identifier).await {
+                Ok(Some((addr, note))) => match db::is_tracking(&pool, user_id, &addr).await {
+                    Ok(true) => Some((addr, note)),
+                    Ok(false) => None,
+                    Err(e) => {
+                        error!("Failed to check tracked wallet: {}", e);
+                        None
+                    }
+                },
+                Ok(None) => None,
+                Err(e) => {
+                    error!("Failed to resolve wallet identifier: {}", e);
+                    None
+                }
+            };
+            let Some((wallet, note)) = resolved else {
+                bot.send_message(
+                    msg.chat.id,
+                    wallet_not_found_text(
+                        &pool,
+                        user_id,
+                        identifier,
+                        "❌ Wallet not found. Use <code>/list</code> to see your tracked wallets.",
+                    )
+                    .await,
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            };
+
+            let snoozed_until = secs.map(|secs| chrono::Utc::now().timestamp() + secs);
+            let wallet_display = format_wallet_display(&wallet, note.as_deref(), false);
+            let text = match db::set_wallet_snoozed_until(&pool, user_id, &wallet, snoozed_until)
+                .await
+            {
+                Ok(_) => match secs {
+                    Some(secs) => format!(
+                        "💤 {} snoozed for {}. Its notifications resume on their own, or use <code>/snooze {} off</code> sooner.",
+                        wallet_display,
+                        format_duration(secs),
+                        html::escape(identifier)
+                    ),
+                    None => format!("🔔 {} is no longer snoozed.", wallet_display),
+                },
+                Err(e) => {
+                    error!("Failed to snooze wallet: {}", e);
+                    "❌ Failed to snooze the wallet. Please try again.".to_string()
+                }
+            };
+            bot.send_message(msg.chat.id, text)
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+        }
+        Command::AlertPnl(args) => {
+            let args = args.trim();
+            if args.is_empty() || args.eq_ignore_ascii_case("list") {
+                let (alerts, wallets) = match tokio::try_join!(
+                    db::get_user_pnl_alerts(&pool, user_id),
+                    db::get_user_wallets(&pool, user_id)
+                ) {
+                    Ok(result) => result,
+                    Err(e) => {
+                        error!("Failed to fetch PnL alerts: {}", e);
+                        bot.send_message(
+                            msg.chat.id,
+                            "❌ Failed to fetch PnL alerts. Please try again.",
+                        )
+                        .reply_to(msg.id)
+                        .parse_mode(ParseMode::Html)
+                        .await?;
+                        return Ok(());
+                    }
+                };
+
+                let text = if alerts.is_empty() {
+                    "🔔 You have no PnL alerts.\n\nUse <code>/alertpnl &lt;wallet&gt; &lt;amount&gt;</code> to get alerted when a wallet's unrealized PnL passes ±amount."
+                        .to_string()
+                } else {
+                    let lines: Vec<String> = alerts
+                        .iter()
+                        .map(|alert| {
+                            let note = wallets
+                                .iter()
+                                .find(|w| w.wallet_address == alert.wallet_address)
+                                .and_then(|w| w.note.as_deref());
+                            let status = if alert.armed {
+                                "armed"
+                            } else {
+                                "fired, waiting to re-arm"
+                            };
+                            format!(
+                                "• {} ±${:.2} · <i>{}</i>",
+                                format_wallet_display(&alert.wallet_address, note, false),
+                                alert.threshold,
+                                status
+                            )
+                        })
+                        .collect();
+                    format!(
+                        "<b>🔔 PnL alerts</b>\n\n{}\n\n<i>Remove one with <code>/alertpnl &lt;wallet&gt; off</code></i>",
+                        lines.join("\n")
+                    )
+                };
+                bot.send_message(msg.chat.id, text)
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                return Ok(());
+            }
+
+            let usage = "❌ Please provide a wallet and an amount.\n\nUsage: <code>/alertpnl &lt;address|index|note&gt; &lt;amount|off&gt;</code> or <code>/alertpnl list</code>";
+            let Some((identifier, amount)) = args.rsplit_once(char::is_whitespace) else {
+                bot.send_message(msg.chat.id, usage)
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                return Ok(());
+            };
+            let identifier = identifier.trim();
+            let threshold = if amount.eq_ignore_ascii_case("off") {
+                None
+            } else {
+                match parse_amount(amount).map(f64::abs).filter(|a| *a > 0.0) {
+                    Some(threshold) => Some(threshold),
+                    None => {
+                        bot.send_message(msg.chat.id, usage)
+                            .reply_to(msg.id)
+                            .parse_mode(ParseMode::Html)
+                            .await?;
+                        return Ok(());
+                    }
+                }
+            };
+
+            // Resolve the identifier to a tracked wallet
+            let resolved = match resolve_wallet_identifier(&pool, user_id, identifier).await {
+                Ok(Some((addr, note))) => match db::is_tracking(&pool, user_id, &addr).await {
+                    Ok(true) => Some((addr, note)),
+                    Ok(false) => None,
+                    Err(e) => {
+                        error!("Failed to check tracked wallet: {}", e);
+                        None
+                    }
+                },
+                Ok(None) => None,
+                Err(e) => {
+                    error!("Failed to resolve wallet identifier: {}", e);
+                    None
+                }
+            };
+            let Some((wallet, note)) = resolved else {
+                bot.send_message(
+                    msg.chat.id,
+                    wallet_not_found_text(
+                        &pool,
+                        user_id,
+                        identifier,
+                        "❌ Wallet not found. Use <code>/list</code> to see your tracked wallets.",
+                    )
+                    .await,
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            };
+
+            let wallet_display = format_wallet_display(&wallet, note.as_deref(), false);
+            let text = match threshold {
+                Some(threshold) => {
+                    match db::set_pnl_alert(&pool, user_id, &wallet, threshold).await {
+                        Ok(()) => format!(
+                            "🔔 PnL alert armed for {}\n\nYou'll get one alert when its unrealized PnL goes above +${:.2} or below -${:.2}.",
+                            wallet_display, threshold, threshold
+                        ),
+                        Err(e) => {
+                            error!("Failed to set PnL alert: {}", e);
+                            "❌ Failed to set the PnL alert. Please try again.".to_string()
+                        }
+                    }
+                }
+                None => match db::delete_pnl_alert(&pool, user_id, &wallet).await {
+                    Ok(true) => format!("🔕 PnL alert removed for {}", wallet_display),
+                    Ok(false) => format!("ℹ️ {} has no PnL alert.", wallet_display),
+                    Err(e) => {
+                        error!("Failed to remove PnL alert: {}", e);
+                        "❌ Failed to remove the PnL alert. Please try again.".to_string()
+                    }
+                },
+            };
+            bot.send_message(msg.chat.id, text)
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+        }
+        Command::Tag(args) => {
+            set_wallet_tag(&bot, &msg, &pool, user_id, &args, true).await?;
+        }
+        Command::Untag(args) => {
+            set_wallet_tag(&bot, &msg, &pool, user_id, &args, false).await?;
+        }
+        Command::Group(tag) => {
+            let tag = tag.trim();
+            if tag.is_empty() {
+                let text = match db::get_user_wallet_tags(&pool, user_id).await {
+                    Ok(tags) if tags.is_empty() => {
+                        "🏷 You haven't tagged any wallets yet.\n\nUse <code>/tag &lt;wallet&gt; &lt;tag&gt;</code> to group them.".to_string()
+                    }
+                    Ok(tags) => {
+                        let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+                        for tag in tags.into_values().flatten() {
+                            *counts.entry(tag.to_lowercase()).or_default() += 1;
+                        }
+                        let lines: Vec<String> = counts
+                            .iter()
+                            .map(|(tag, count)| {
+                                format!("🏷 <b>{}</b> · {} wallet(s)", html::escape(tag), count)
+                            })
+                            .collect();
+                        format!(
+                            "<b>🏷 Your tags</b>\n\n{}\n\n<i>Use <code>/group &lt;tag&gt;</code> to see a group's positions.</i>",
+                            lines.join("\n")
+                        )
+                    }
+                    Err(e) => {
+                        error!("Failed to fetch wallet tags: {}", e);
+                        "❌ Failed to fetch your tags. Please try again.".to_string()
+                    }
+                };
+                bot.send_message(msg.chat.id, text)
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                return Ok(());
+            }
+
+            match db::get_wallets_by_tag(&pool, user_id, tag).await {
+                Ok(wallets) if wallets.is_empty() => {
+                    bot.send_message(msg.chat.id, no_tagged_wallets_text(tag))
+                        .reply_to(msg.id)
+                        .parse_mode(ParseMode::Html)
+                        .await?;
+                }
+                Ok(wallets) => {
+                    for message in all_positions_messages(&wallets).await {
+                        bot.send_message(msg.chat.id, message)
+                            .reply_to(msg.id)
+                            .parse_mode(ParseMode::Html)
+                            .await?;
+                    }
+                }
+                Err(e) => {
+                    error!("Failed to fetch wallets by tag: {}", e);
+                    bot.send_message(
+                        msg.chat.id,
+                        "❌ Failed to fetch the group. Please try again.",
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                }
+            }
+        }
+        Command::MuteGroup(tag) => {
+            set_group_muted(&bot, &msg, &pool, user_id, &tag, true).await?;
+        }
+        Command::UnmuteGroup(tag) => {
+            set_group_muted(&bot, &msg, &pool, user_id, &tag, false).await?;
+        }
+        Command::Version => {
+            let tz = user_timezone(&pool, user_id).await;
+            let built_at = env!("BUILD_TIMESTAMP")
+                .parse::<i64>()
+                .ok()
+                .and_then(|secs| chrono::DateTime::from_timestamp(secs, 0))
+                .map(|time| tz.format_datetime(time))
+                .unwrap_or_else(|| "unknown".to_string());
+            bot.send_message(
+                msg.chat.id,
+                format!(
+                    "<b>ℹ️ Version</b>\n\n\
+                     📦 Version: {}\n\
+                     🔖 Commit: <code>{}</code>\n\
+                     🛠 Built: {}\n\
+                     ⏱️ Uptime: {}",
+                    env!("CARGO_PKG_VERSION"),
+                    env!("GIT_HASH"),
+                    built_at,
+                    format_duration((chrono::Utc::now() - *STARTED_AT).num_seconds())
+                ),
+            )
+            .reply_to(msg.id)
+            .parse_mode(ParseMode::Html)
+            .await?;
+        }
+        Command::Ping => {
+            // Telegram only dates messages to the second
+            let processing = (chrono::Utc::now() - msg.date).num_seconds().max(0);
+
+            let started = Instant::now();
+            let reply = bot
+                .send_message(msg.chat.id, "🏓 Pinging…")
+                .reply_to(msg.id)
+                .await?;
+            let telegram = started.elapsed();
+
+            let started = Instant::now();
+            let hyperliquid =
+                match tokio::time::timeout(PING_TIMEOUT, hyperliquid::fetch_meta(&HTTP_CLIENT))
+                    .await
+                {
+                    Ok(Ok(_)) => format!("{} ms", started.elapsed().as_millis()),
+                    Ok(Err(e)) => {
+                        warn!("Ping to the Hyperliquid API failed: {}", e);
+                        format!("❌ failed after {} ms", started.elapsed().as_millis())
+                    }
+                    Err(_) => format!("⚠️ timed out after {}s", PING_TIMEOUT.as_secs()),
+                };
+
+            bot.edit_message_text(
+                msg.chat.id,
+                reply.id,
+                format!(
+                    "<b>🏓 Pong</b>\n\n\
+                     📨 Update processed after: {}s\n\
+                     ✈️ Telegram API: {} ms\n\
+                     📡 Hyperliquid API: {}",
+                    processing,
+                    telegram.as_millis(),
+                    hyperliquid
+                ),
+            )
+            .parse_mode(ParseMode::Html)
+            .await?;
+        }
+        Command::Entry(args) => {
+            let args = args.trim();
+            // The coin is the last word, the rest names the wallet
+            let (identifier, coin) = args.rsplit_once(char::is_whitespace).unwrap_or(("", args));
+            let coin = coin.trim();
+            let usage = "❌ Please provide a wallet and a coin.\n\nUsage: <code>/entry &lt;address|index|note&gt; &lt;coin&gt;</code>";
+            if coin.is_empty() {
+                bot.send_message(msg.chat.id, usage)
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                return Ok(());
+            }
+            let Some((identifier, assumed)) = identifier_or_last(user_id, identifier) else {
+                bot.send_message(msg.chat.id, usage)
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                return Ok(());
+            };
+            let identifier = identifier.as_str();
+
+            let Some((wallet, note)) = resolve_or_raw_address(
+                &bot,
+                &msg,
+                &pool,
+                user_id,
+                identifier,
+                WALLET_NOT_FOUND,
+                "❌ Failed to fetch the position. Please try again.",
+            )
+            .await?
+            else {
+                return Ok(());
+            };
+
+            if assumed {
+                send_assumed_wallet_notice(&bot, &msg, &wallet, note.as_deref()).await?;
+            }
+
+            let user_state = match hyperliquid::fetch_user_state(&HTTP_CLIENT, &wallet).await {
+                Ok(user_state) => user_state,
+                Err(e) => {
+                    error!("Failed to fetch position for {}: {}", wallet, e);
+                    bot.send_message(
+                        msg.chat.id,
+                        "❌ Failed to fetch the position. Please try again.",
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                    return Ok(());
+                }
+            };
+
+            let wallet_display = format_wallet_display(&wallet, note.as_deref(), false);
+            let text = match user_state
+                .asset_positions
+                .iter()
+                .map(|ap| &ap.position)
+                .find(|p| hyperliquid::coin_matches(coin, &p.coin))
+            {
+                Some(position) => entry_message(&wallet_display, position),
+                None => format!(
+                    "📭 {} has no open <b>{}</b> position.",
+                    wallet_display,
+                    html::escape(&coin.to_uppercase())
+                ),
+            };
+            bot.send_message(msg.chat.id, text)
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+        }
+        Command::Qr(args) => {
+            let usage =
+                "❌ Please provide a wallet.\n\nUsage: <code>/qr &lt;address|index|note&gt;</code>";
+            let Some((identifier, assumed)) = identifier_or_last(user_id, args.trim()) else {
+                bot.send_message(msg.chat.id, usage)
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                return Ok(());
+            };
+            let identifier = identifier.as_str();
+
+            let Some((wallet, note)) = resolve_or_raw_address(
+                &bot,
+                &msg,
+                &pool,
+                user_id,
+                identifier,
+                WALLET_NOT_FOUND,
+                "❌ Failed to find the wallet. Please try again.",
+            )
+            .await?
+            else {
+                return Ok(());
+            };
+
+            if assumed {
+                send_assumed_wallet_notice(&bot, &msg, &wallet, note.as_deref()).await?;
+            }
+
+            let caption = match note.as_deref() {
+                Some(note) => format!("📷 <b>{}</b>\n<code>{}</code>", html::escape(note), wallet),
+                None => format!("📷 <code>{}</code>", wallet),
+            };
+            let sent = match qr_png(&wallet) {
+                Ok(png) => bot
+                    .send_photo(
+                        msg.chat.id,
+                        InputFile::memory(png).file_name(format!("{}.png", wallet)),
+                    )
+                    .caption(caption.clone())
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await
+                    .map(|_| ())
+                    .map_err(anyhow::Error::from),
+                Err(e) => Err(e),
+            };
+            // The address is what matters, so it still goes out as text
+            if let Err(e) = sent {
+                warn!("Failed to send QR code for {}: {}", wallet, e);
+                bot.send_message(
+                    msg.chat.id,
+                    format!("{}\n\n<i>The QR code couldn't be sent.</i>", caption),
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+            }
+        }
+        Command::ClosedToday => {
+            let wallets = match db::get_user_wallets(&pool, user_id).await {
+                Ok(wallets) => wallets,
+                Err(e) => {
+                    error!("Failed to get wallets: {}", e);
+                    bot.send_message(msg.chat.id, "❌ Failed to retrieve wallets.")
+                        .reply_to(msg.id)
+                        .parse_mode(ParseMode::Html)
+                        .await?;
+                    return Ok(());
+                }
+            };
+            if wallets.is_empty() {
+                bot.send_message(
+                    msg.chat.id,
+                    "📭 You're not tracking any wallets.\n\nUse /add to start tracking a wallet.",
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            }
+
+            // With a timezone set "today" starts at the user's midnight, otherwise it's the last 24 hours
+            let tz = user_timezone(&pool, user_id).await;
+            let now = chrono::Utc::now();
+            let (since, window) = if tz == UserTimezone::default() {
+                (
+                    now - chrono::Duration::hours(24),
+                    "last 24 hours".to_string(),
+                )
+            } else {
+                let midnight = tz.last_occurrence(0, now);
+                (midnight, format!("since {}", tz.format_datetime(midnight)))
+            };
+            let since_ms = since.timestamp_millis();
+            let now_ms = now.timestamp_millis();
+
+            let handles: Vec<_> = wallets
+                .iter()
+                .map(|w| {
+                    let wallet = w.wallet_address.clone();
+                    tokio::spawn(async move {
+                        hyperliquid::fetch_all_user_fills_by_time(
+                            &HTTP_CLIENT,
+                            &wallet,
+                            since_ms,
+                            now_ms,
+                            MAX_FILL_STATS_FILLS,
+                        )
+                        .await
+                    })
+                })
+                .collect();
+
+            let mut lines = Vec::new();
+            let mut total = 0.0;
+            let mut trade_count = 0;
+            for (wallet, handle) in wallets.iter().zip(handles) {
+                let display =
+                    format_wallet_display(&wallet.wallet_address, wallet.note.as_deref(), false);
+                let (fills, truncated) = match handle.await {
+                    Ok(Ok(fills)) => fills,
+                    Ok(Err(e)) => {
+                        error!("Failed to fetch fills for {}: {}", wallet.wallet_address, e);
+                        lines.push(format!("\n👛 {}\n⚠️ Couldn't fetch fills", display));
+                        continue;
+                    }
+                    Err(e) => {
+                        error!("Fill fetch task failed: {}", e);
+                        lines.push(format!("\n👛 {}\n⚠️ Couldn't fetch fills", display));
+                        continue;
+                    }
+                };
+                let trades = closed_trades(&fills, &wallet.wallet_address);
+                if trades.is_empty() {
+                    continue;
+                }
+
+                let wallet_pnl: f64 = trades.iter().map(|t| t.pnl).sum();
+                total += wallet_pnl;
+                trade_count += trades.len();
+                let mut section =
+                    format!("\n👛 {} · {}", display, hyperliquid::format_pnl(wallet_pnl));
+                for trade in &trades {
+                    let icon = if trade.liquidated {
+                        "💀"
+                    } else if trade.pnl >= 0.0 {
+                        "🟢"
+                    } else {
+                        "🔴"
+                    };
+                    section.push_str(&format!(
+                        "\n{} {} {} {} · {}",
+                        icon,
+                        html::escape(&trade.coin),
+                        html::escape(&trade.dir),
+                        hyperliquid::format_pnl(trade.pnl),
+                        tz.format(
+                            chrono::DateTime::from_timestamp_millis(trade.time).unwrap_or_default(),
+                            "%H:%M"
+                        )
+                    ));
+                }
+                if truncated {
+                    section.push_str(&truncated_fills_notice(&fills, &tz));
+                }
+                lines.push(section);
+            }
+
+            let header = if trade_count == 0 {
+                format!(
+                    "<b>📕 Closed Today</b> ({})\n\nNone of your wallets closed anything.",
+                    window
+                )
+            } else {
+                format!(
+                    "<b>📕 Closed Today</b> ({})\n\n💵 Total: {} across {} trade(s)",
+                    window,
+                    hyperliquid::format_pnl(total),
+                    trade_count
+                )
+            };
+            lines.push(
+                "\n<i>Realized PnL from fills before fees, fills of one order combined. 💀 marks liquidations.</i>"
+                    .to_string(),
+            );
+            for message in split_lines(header, lines) {
+                bot.send_message(msg.chat.id, message)
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+            }
+        }
+        Command::Drawdown(args) => {
+            let Some((identifier, assumed)) = identifier_or_last(user_id, args.trim()) else {
+                bot.send_message(
+                    msg.chat.id,
+                    "❌ Please provide a wallet address, index (1-10), or note.\n\nUsage: <code>/drawdown &lt;address|index|note&gt;</code>",
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            };
+            let identifier = identifier.as_str();
+
+            let Some((wallet, note)) = resolve_or_raw_address(
+                &bot,
+                &msg,
+                &pool,
+                user_id,
+                identifier,
+                WALLET_NOT_FOUND,
+                "❌ Failed to fetch the drawdown. Please try again.",
+            )
+            .await?
+            else {
+                return Ok(());
+            };
+
+            if assumed {
+                send_assumed_wallet_notice(&bot, &msg, &wallet, note.as_deref()).await?;
+            }
+
+            let history = match hyperliquid::fetch_portfolio(&HTTP_CLIENT, &wallet).await {
+                Ok(portfolio) => portfolio
+                    .into_iter()
+                    .find(|(name, _)| name == "month")
+                    .map(|(_, history)| history),
+                Err(e) => {
+                    error!("Failed to fetch portfolio for {}: {}", wallet, e);
+                    bot.send_message(
+                        msg.chat.id,
+                        "❌ Failed to fetch the drawdown. Please try again.",
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                    return Ok(());
+                }
+            };
+
+            // Vault deposits count at their last known value, there is no history of them
+            let vault_summary = vaults::included_equity(&pool, user_id, &wallet).await;
+            let vault_equity = vault_summary.as_ref().map_or(0.0, |(equity, _)| *equity);
+            let wallet_display = format_wallet_display(&wallet, note.as_deref(), false);
+            let Some(drawdown) = history
+                .and_then(|h| h.equity_history())
+                .map(|equity| {
+                    equity
+                        .into_iter()
+                        .map(|(time, value)| (time, value + vault_equity))
+                        .collect::<Vec<_>>()
+                })
+                .and_then(|equity| hyperliquid::drawdown(&equity))
+            else {
+                bot.send_message(
+                    msg.chat.id,
+                    format!(
+                        "<b>📉 Drawdown · 30 Days</b>\n\n👛 Wallet: {}\n\n<i>No portfolio history for this period.</i>",
+                        wallet_display
+                    ),
+                )
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+                return Ok(());
+            };
+
+            let tz = user_timezone(&pool, user_id).await;
+            let max = if drawdown.max_pct > 0.0 {
+                format!(
+                    "📉 Max drawdown: -{:.2}%\n\
+                     🏔 Peak: ${:.2} on {}\n\
+                     🕳 Trough: ${:.2} on {}",
+                    drawdown.max_pct,
+                    drawdown.peak.1,
+                    tz.format_millis(drawdown.peak.0),
+                    drawdown.trough.1,
+                    tz.format_millis(drawdown.trough.0)
+                )
+            } else {
+                "📉 Max drawdown: none, the account only grew".to_string()
+            };
+            let current = if drawdown.current_pct > 0.0 {
+                format!(
+                    "↘️ Current: -{:.2}% from ${:.2} on {}",
+                    drawdown.current_pct,
+                    drawdown.recent_peak.1,
+                    tz.format_millis(drawdown.recent_peak.0)
+                )
+            } else {
+                "↗️ Current: at its peak".to_string()
+            };
+            let basis = match &vault_summary {
+                Some((_, line)) => format!(
+                    "{}\n\n<i>Account value plus vault equity at its current value, with deposits and withdrawals taken out.</i>",
+                    line
+                ),
+                None => "<i>Account value with deposits and withdrawals taken out.</i>".to_string(),
+            };
+
+            bot.send_message(
+                msg.chat.id,
+                format!(
+                    "<b>📉 Drawdown · 30 Days</b>\n\n\
+                     👛 Wallet: {}\n\n\
+                     {}\n\
+                     {}\n\n\
+                     {}",
+                    wallet_display, max, current, basis
+                ),
+            )
+            .reply_to(msg.id)
+            .parse_mode(ParseMode::Html)
+            .await?;
+        }
+        Command::Best(args) => {
+            send_extreme_trades(&bot, &msg, &pool, user_id, &args, true).await?;
+        }
+        Command::Worst(args) => {
+            send_extreme_trades(&bot, &msg, &pool, user_id, &args, false).await?;
+        }
+        Command::Trending(args) => {
+            let (hours, label) = match args.trim().to_lowercase().as_str() {
+                "" | "24h" | "1d" | "day" => (24, "24h"),
+                "7d" | "week" => (7 * 24, "7d"),
+                _ => {
+                    bot.send_message(
+                        msg.chat.id,
+                        "❌ Unknown window.\n\nUsage: <code>/trending [24h|7d]</code>",
+                    )
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                    return Ok(());
+                }
+            };
+
+            let start = chrono::Utc::now() - chrono::Duration::hours(hours);
+            // Same format SQLite uses for CURRENT_TIMESTAMP
+            let since = start.format("%Y-%m-%d %H:%M:%S").to_string();
+            let (events, wallets, first_event) = match tokio::try_join!(
+                db::get_user_events_since(&pool, user_id, &since, MAX_TRENDING_EVENTS),
+                db::get_user_wallets(&pool, user_id),
+                db::get_first_recorded_event_time(&pool),
+            ) {
+                Ok(results) => results,
+                Err(e) => {
+                    error!("Failed to load trending events for {}: {}", user_id, e);
+                    bot.send_message(msg.chat.id, "❌ Failed to load activity. Please try again.")
+                        .reply_to(msg.id)
+                        .parse_mode(ParseMode::Html)
+                        .await?;
+                    return Ok(());
+                }
+            };
+
+            // Events are only recorded while the bot runs, a younger history can't fill the window
+            let history_short = first_event.is_none_or(|first| first > start.timestamp_millis());
+            let notes: HashMap<&str, Option<&str>> = wallets
+                .iter()
+                .map(|w| (w.wallet_address.as_str(), w.note.as_deref()))
+                .collect();
+
+            let mut coins: HashMap<&str, TrendingCoin> = HashMap::new();
+            for event in events
+                .iter()
+                .filter(|e| !e.backfilled && (e.kind == "opened" || e.kind == "increased"))
+            {
+                let coin = coins.entry(event.coin.as_str()).or_default();
+                coin.events += 1;
+                if event.is_long {
+                    coin.longs += 1;
+                } else {
+                    coin.shorts += 1;
+                }
+                coin.wallets.insert(event.wallet_address.as_str());
+            }
+
+            if coins.is_empty() {
+                let text = if history_short {
+                    format!(
+                        "<b>🔥 Trending · {}</b>\n\n\
+                         📭 Not enough history yet. Opens and increases are recorded as the bot \
+                         sees them, so this fills up over time. Check back later.",
+                        label
+                    )
+                } else {
+                    format!(
+                        "<b>🔥 Trending · {}</b>\n\n\
+                         None of your wallets opened or added to a position in this window.",
+                        label
+                    )
+                };
+                bot.send_message(msg.chat.id, text)
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                return Ok(());
+            }
+
+            let mut ranked: Vec<(&str, TrendingCoin)> = coins.into_iter().collect();
+            ranked.sort_by(|a, b| {
+                b.1.wallets
+                    .len()
+                    .cmp(&a.1.wallets.len())
+                    .then(b.1.events.cmp(&a.1.events))
+                    .then(a.0.cmp(b.0))
+            });
+
+            let mut lines: Vec<String> = ranked
+                .iter()
+                .take(TRENDING_COINS_SHOWN)
+                .enumerate()
+                .map(|(i, (coin, stats))| {
+                    let wallets = stats
+                        .wallets
+                        .iter()
+                        .map(|wallet| {
+                            format_wallet_display(
+                                wallet,
+                                notes.get(wallet).copied().flatten(),
+                                false,
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    format!(
+                        "\n{}. <b>{}</b> · {} open/add(s) · 🟢 {} 🔴 {}\n    👛 {}",
+                        i + 1,
+                        html::escape(coin),
+                        stats.events,
+                        stats.longs,
+                        stats.shorts,
+                        wallets
+                    )
+                })
+                .collect();
+            if history_short {
+                lines.push(
+                    "\n<i>⚠️ History doesn't cover the whole window yet, it fills up over time.</i>"
+                        .to_string(),
+                );
+            }
+
+            let header = format!(
+                "<b>🔥 Trending · {}</b>\n\nCoins your wallets opened or added to most, by wallets involved:",
+                label
+            );
+            for message in split_lines(header, lines) {
+                bot.send_message(msg.chat.id, message)
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// One position block of the /positions message
+fn format_position_entry(
+    coin: &str,
+    size: f64,
+    entry_price: f64,
+    position_value: f64,
+    unrealized_pnl: f64,
+    leverage: u32,
+) -> String {
+    let is_long = size > 0.0;
+    let current_price = if size.abs() > 0.0 {
+        position_value / size.abs()
+    } else {
+        0.0
+    };
+    let direction_str = if is_long { "Long" } else { "Short" };
+    let direction_emoji = if is_long { "🟢" } else { "🔴" };
+    let pnl_str = if unrealized_pnl >= 0.0 {
+        format!("<b>+${:.2}</b>", unrealized_pnl)
+    } else {
+        format!("<b>-${:.2}</b>", unrealized_pnl.abs())
+    };
+    // Calculate PnL percentage (based on entry value)
+    let entry_value = entry_price * size.abs();
+    let pnl_pct = if entry_value > 0.0 {
+        (unrealized_pnl / entry_value) * 100.0
+    } else {
+        0.0
+    };
+    let pnl_pct_str = if pnl_pct >= 0.0 {
+        format!("+{:.2}%", pnl_pct)
+    } else {
+        format!("{:.2}%", pnl_pct)
+    };
+    // Round to avoid floating point artifacts like 2744.7999999999997
+    let current_price_rounded = (current_price * 10000000000.0).round() / 10000000000.0; // 10 decimal places
+    let entry_str = hyperliquid::format_coin_price(coin, entry_price);
+    let current_str = hyperliquid::format_coin_price(coin, current_price_rounded);
+    let size_str = hyperliquid::format_size(size.abs());
+
+    // Calculate price difference
+    let price_diff = current_price_rounded - entry_price;
+    let price_diff_str = if price_diff >= 0.0 {
+        format!("+${:.2}", price_diff)
+    } else {
+        format!("-${:.2}", price_diff.abs())
+    };
+
+    format!(
+        "\n{} <b>{}x {} {}</b>\n\
+     📊 Size: {} {} (${:.2})\n\
+     💰 Entry: {}\n\
+     📍 Current: {} ({})\n\
+     💵 PnL: {} ({})\n",
+        direction_emoji,
+        leverage,
+        coin,
+        direction_str,
+        size_str,
+        coin,
+        position_value,
+        entry_str,
+        current_str,
+        price_diff_str,
+        pnl_str,
+        pnl_pct_str
+    )
+}
+
+/// /compare messages: account summaries, then each coin either wallet holds with
+/// coins the two are on opposite sides of first
+fn format_comparison(
+    display_a: &str,
+    state_a: &hyperliquid::UserState,
+    display_b: &str,
+    state_b: &hyperliquid::UserState,
+) -> Vec<String> {
+    let summary = |state: &hyperliquid::UserState| {
+        let positions: BTreeMap<String, (f64, f64)> = state
+            .asset_positions
+            .iter()
+            .map(|ap| &ap.position)
+            .filter(|pos| pos.szi.parse::<f64>().unwrap_or(0.0) != 0.0)
+            .map(|pos| {
+                (
+                    pos.coin.clone(),
+                    (
+                        pos.szi.parse().unwrap_or(0.0),
+                        pos.position_value.parse().unwrap_or(0.0),
+                    ),
+                )
+            })
+            .collect();
+        let pnl: f64 = state
+            .asset_positions
+            .iter()
+            .map(|ap| ap.position.unrealized_pnl.parse::<f64>().unwrap_or(0.0))
+            .sum();
+        let account_value: f64 = state.margin_summary.account_value.parse().unwrap_or(0.0);
+        let margin_usage = hyperliquid::margin_utilization(&state.margin_summary).unwrap_or(0.0);
+        (positions, pnl, account_value, margin_usage)
+    };
+    let (positions_a, pnl_a, value_a, usage_a) = summary(state_a);
+    let (positions_b, pnl_b, value_b, usage_b) = summary(state_b);
+
+    let side = |position: Option<&(f64, f64)>| match position {
+        Some((size, value)) => format!(
+            "{} {}",
+            if *size > 0.0 {
+                "🟢 Long"
+            } else {
+                "🔴 Short"
+            },
+            hyperliquid::format_usd_compact(*value)
+        ),
+        None => "—".to_string(),
+    };
+    let coins: BTreeSet<&String> = positions_a.keys().chain(positions_b.keys()).collect();
+    let (mut opposite, mut rest) = (Vec::new(), Vec::new());
+    for coin in coins {
+        let a = positions_a.get(coin);
+        let b = positions_b.get(coin);
+        let line = format!("<b>{}</b>: A {} · B {}", coin, side(a), side(b));
+        match (a, b) {
+            (Some((size_a, _)), Some((size_b, _))) if size_a.signum() != size_b.signum() => {
+                opposite.push(format!("⚔️ {}", line))
+            }
+            _ => rest.push(line),
+        }
+    }
+    let opposite_count = opposite.len();
+    let mut lines: Vec<String> = opposite.into_iter().chain(rest).collect();
+    if lines.is_empty() {
+        lines.push("Neither wallet has open positions.".to_string());
+    } else if opposite_count > 0 {
+        lines.push(format!(
+            "\n<i>⚔️ {} coin(s) where the wallets are on opposite sides</i>",
+            opposite_count
+        ));
+    }
+
+    let header = format!(
+        "<b>⚖️ Wallet Comparison</b>\n\n\
+         <b>A</b>: {}\n\
+         <b>B</b>: {}\n\n\
+         💼 Account value: ${:.2} vs ${:.2}\n\
+         📊 Margin usage: {:.1}% vs {:.1}%\n\
+         📈 Positions: {} vs {}\n\
+         💵 Unrealized PnL: {} vs {}\n\n\
+         <b>Per coin</b>",
+        display_a,
+        display_b,
+        value_a,
+        value_b,
+        usage_a,
+        usage_b,
+        positions_a.len(),
+        positions_b.len(),
+        hyperliquid::format_pnl(pnl_a),
+        hyperliquid::format_pnl(pnl_b)
+    );
+    split_lines(header, lines)
+}
+
+/// Position block of a live position, shared by /positions and /top
+fn position_entry(pos: &hyperliquid::Position) -> String {
+    format_position_entry(
+        &pos.coin,
+        pos.szi.parse().unwrap_or(0.0),
+        pos.entry_px
+            .as_ref()
+            .and_then(|p| p.parse().ok())
+            .unwrap_or(0.0),
+        pos.position_value.parse().unwrap_or(0.0),
+        pos.unrealized_pnl.parse().unwrap_or(0.0),
+        pos.leverage.as_ref().map(|l| l.value).unwrap_or(1),
+    )
+}
+
+fn position_entries_from_state(user_state: &hyperliquid::UserState) -> Vec<String> {
+    user_state
+        .asset_positions
+        .iter()
+        .filter(|ap| ap.position.szi.parse::<f64>().unwrap_or(0.0) != 0.0)
+        .map(|ap| position_entry(&ap.position))
+        .collect()
+}
+
+/// Reply with one wallet's margin usage and liquidation distances
+async fn send_wallet_risk(
+    bot: &Bot,
+    msg: &Message,
+    pool: &SqlitePool,
+    user_id: i64,
+    identifier: &str,
+) -> ResponseResult<()> {
+    let Some((wallet, note)) = resolve_or_raw_address(
+        bot,
+        msg,
+        pool,
+        user_id,
+        identifier,
+        WALLET_NOT_FOUND,
+        "❌ Failed to fetch risk. Please try again.",
+    )
+    .await?
+    else {
+        return Ok(());
+    };
+
+    let user_state = match hyperliquid::fetch_user_state(&HTTP_CLIENT, &wallet).await {
+        Ok(user_state) => user_state,
+        Err(e) => {
+            error!("Failed to fetch margin data for {}: {}", wallet, e);
+            bot.send_message(msg.chat.id, "❌ Failed to fetch risk. Please try again.")
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let wallet_display = format_wallet_display(&wallet, note.as_deref(), false);
+    bot.send_message(msg.chat.id, risk_message(&wallet_display, &user_state))
+        .reply_to(msg.id)
+        .parse_mode(ParseMode::Html)
+        .await?;
+    Ok(())
+}
+
+/// How far an open position is from liquidation
+struct LiquidationRisk {
+    coin: String,
+    is_long: bool,
+    mark: f64,
+    /// Liquidation price and its distance from the mark in percent, `None` when fully collateralized
+    liquidation: Option<(f64, f64)>,
+}
+
+impl LiquidationRisk {
+    /// `None` for flat positions
+    fn from_position(pos: &hyperliquid::Position) -> Option<Self> {
+        let size: f64 = pos.szi.parse().unwrap_or(0.0);
+        if size == 0.0 {
+            return None;
+        }
+        // positionValue is the size at the mark price
+        let mark = pos.position_value.parse::<f64>().unwrap_or(0.0) / size.abs();
+        let liquidation = pos
+            .liquidation_px
+            .as_ref()
+            .and_then(|p| p.parse::<f64>().ok())
+            .filter(|p| *p > 0.0 && mark > 0.0)
+            .map(|p| (p, (mark - p).abs() / mark * 100.0));
+        Some(Self {
+            coin: pos.coin.clone(),
+            is_long: size > 0.0,
+            mark,
+            liquidation,
+        })
+    }
+
+    fn distance(&self) -> Option<f64> {
+        self.liquidation.map(|(_, distance)| distance)
+    }
+
+    /// Closest to liquidation first, positions that can't be liquidated last
+    fn cmp_distance(&self, other: &Self) -> std::cmp::Ordering {
+        match (self.distance(), other.distance()) {
+            (Some(a), Some(b)) => a.total_cmp(&b),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => std::cmp::Ordering::Equal,
+        }
+    }
+
+    /// "<b>BTC</b> 🟢 LONG · Mark $X" then the liquidation price and distance
+    fn format(&self) -> String {
+        let side = if self.is_long {
+            "🟢 LONG"
+        } else {
+            "🔴 SHORT"
+        };
+        let risk = match self.liquidation {
+            Some((liquidation, distance)) => {
+                let icon = if distance < 5.0 {
+                    "🚨"
+                } else if distance < 15.0 {
+                    "⚠️"
+                } else {
+                    "🛡"
+                };
+                format!(
+                    "{} Liq {} · {:.2}% away",
+                    icon,
+                    hyperliquid::format_price(liquidation),
+                    distance
+                )
+            }
+            None => "✅ Liq none · safe".to_string(),
+        };
+        format!(
+            "<b>{}</b> {} · Mark {}\n{}",
+            self.coin,
+            side,
+            hyperliquid::format_price(self.mark),
+            risk
+        )
+    }
+}
+
+/// Cross margin usage and each position's distance to liquidation, closest first
+fn risk_message(wallet_display: &str, user_state: &hyperliquid::UserState) -> String {
+    let account_value: f64 = user_state
+        .margin_summary
+        .account_value
+        .parse()
+        .unwrap_or(0.0);
+    let cross_account_value: f64 = user_state
+        .cross_margin_summary
+        .as_ref()
+        .and_then(|s| s.account_value.parse().ok())
+        .unwrap_or(account_value);
+    let maintenance: f64 = user_state
+        .cross_maintenance_margin_used
+        .as_ref()
+        .and_then(|m| m.parse().ok())
+        .unwrap_or(0.0);
+    let cross_ratio = if cross_account_value > 0.0 {
+        format!("{:.2}%", maintenance / cross_account_value * 100.0)
+    } else {
+        "n/a".to_string()
+    };
+
+    let mut risks: Vec<LiquidationRisk> = user_state
+        .asset_positions
+        .iter()
+        .filter_map(|ap| LiquidationRisk::from_position(&ap.position))
+        .collect();
+    risks.sort_by(LiquidationRisk::cmp_distance);
+
+    let positions = if risks.is_empty() {
+        "<i>No open positions</i>".to_string()
+    } else {
+        risks
+            .iter()
+            .map(LiquidationRisk::format)
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    };
+    format!(
+        "<b>🛟 Liquidation Risk</b>\n\n\
+         👛 Wallet: {}\n\n\
+         📐 Cross margin ratio: {}\n\
+         🧱 Maintenance margin: ${:.2} of ${:.2} account value\n\n\
+         {}",
+        wallet_display, cross_ratio, maintenance, account_value, positions
+    )
+}
+
+/// Average entry, breakeven after funding paid and closing fees, and the mark's distance to it
+fn entry_message(wallet_display: &str, position: &hyperliquid::Position) -> String {
+    let size: f64 = position.szi.parse().unwrap_or(0.0);
+    let entry: f64 = position
+        .entry_px
+        .as_deref()
+        .and_then(|px| px.parse().ok())
+        .unwrap_or(0.0);
+    let value: f64 = position.position_value.parse().unwrap_or(0.0);
+    let mark = if size != 0.0 { value / size.abs() } else { 0.0 };
+    let funding: f64 = position
+        .cum_funding
+        .as_ref()
+        .and_then(|f| f.since_open.parse().ok())
+        .unwrap_or(0.0);
+    let is_long = size > 0.0;
+    let size = size.abs();
+
+    // Closing at the breakeven price leaves a PnL of exactly the funding paid plus the fee
+    let breakeven = if is_long {
+        (size * entry + funding) / (size * (1.0 - CLOSE_FEE_RATE))
+    } else {
+        (size * entry - funding) / (size * (1.0 + CLOSE_FEE_RATE))
+    };
+    let close_fee = breakeven * size * CLOSE_FEE_RATE;
+    let distance = if mark > 0.0 {
+        (mark - breakeven) / mark * 100.0
+    } else {
+        0.0
+    };
+    let profitable = if is_long {
+        mark >= breakeven
+    } else {
+        mark <= breakeven
+    };
+    let status = if profitable {
+        format!("✅ In profit, {:.2}% past breakeven", distance.abs())
+    } else {
+        format!(
+            "⏳ Needs a {:.2}% move {} to break even",
+            distance.abs(),
+            if is_long { "up" } else { "down" }
+        )
+    };
+
+    format!(
+        "<b>🎯 {} {}</b>\n👛 {}\n\n\
+         📍 Average entry: {}\n\
+         💹 Mark: {}\n\
+         💸 Funding since open: {}\n\
+         🧾 Est. closing fee: ${:.2}\n\
+         ⚖️ Breakeven: {}\n\n\
+         {}\n\n\
+         <i>Closing fee assumes the {:.3}% base taker rate</i>",
+        if is_long { "🟢 Long" } else { "🔴 Short" },
+        html::escape(&position.coin),
+        wallet_display,
+        hyperliquid::format_coin_price(&position.coin, entry),
+        hyperliquid::format_coin_price(&position.coin, mark),
+        // Paid funding is a cost, show it with the sign it has on PnL
+        hyperliquid::format_pnl(-funding),
+        close_fee,
+        hyperliquid::format_coin_price(&position.coin, breakeven),
+        status,
+        CLOSE_FEE_RATE * 100.0
+    )
+}
+
+/// Account value, margin used and withdrawable, then each position's margin and mode
+fn margin_message(wallet_display: &str, user_state: &hyperliquid::UserState) -> String {
+    let summary = &user_state.margin_summary;
+    let account_value: f64 = summary.account_value.parse().unwrap_or(0.0);
+    let margin_used: f64 = summary.total_margin_used.parse().unwrap_or(0.0);
+    let withdrawable: Option<f64> = user_state
+        .withdrawable
+        .as_ref()
+        .and_then(|w| w.parse().ok());
+
+    let mut cross_margin = 0.0;
+    let mut isolated_margin = 0.0;
+    let mut positions: Vec<(f64, String)> = Vec::new();
+    for pos in user_state.asset_positions.iter().map(|ap| &ap.position) {
+        let size: f64 = pos.szi.parse().unwrap_or(0.0);
+        if size == 0.0 {
+            continue;
+        }
+        let margin: f64 = pos.margin_used.parse().unwrap_or(0.0);
+        let isolated = pos
+            .leverage
+            .as_ref()
+            .is_some_and(|l| l.leverage_type == "isolated");
+        let leverage = pos.leverage.as_ref().map(|l| l.value).unwrap_or(1);
+        let side = if size > 0.0 {
+            "🟢 LONG"
+        } else {
+            "🔴 SHORT"
+        };
+        let line = if isolated {
+            isolated_margin += margin;
+            format!(
+                "<b>{}</b> {} · {}x isolated\n🔒 Isolated margin ${:.2}",
+                pos.coin, side, leverage, margin
+            )
+        } else {
+            cross_margin += margin;
+            format!(
+                "<b>{}</b> {} · {}x cross\n🔗 Margin used ${:.2}",
+                pos.coin, side, leverage, margin
+            )
+        };
+        positions.push((margin, line));
+    }
+    positions.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    let usage = if account_value > 0.0 {
+        format!(" ({:.1}% of account)", margin_used / account_value * 100.0)
+    } else {
+        String::new()
+    };
+    let withdrawable = withdrawable
+        .map(|w| format!("${:.2}", w))
+        .unwrap_or_else(|| "n/a".to_string());
+    let positions = if positions.is_empty() {
+        "<i>No open positions</i>".to_string()
+    } else {
+        positions
+            .into_iter()
+            .map(|(_, line)| line)
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    };
+    format!(
+        "<b>🧮 Margin</b>\n\n\
+         👛 Wallet: {}\n\n\
+         💰 Account value: ${:.2}\n\
+         📊 Margin used: ${:.2}{}\n\
+         ├ Cross: ${:.2}\n\
+         └ Isolated: ${:.2}\n\
+         💸 Withdrawable: {}\n\n\
+         {}",
+        wallet_display,
+        account_value,
+        margin_used,
+        usage,
+        cross_margin,
+        isolated_margin,
+        withdrawable,
+        positions
+    )
+}
+
+/// Every open position across the wallets by distance to liquidation, the closest three highlighted
+async fn liquidation_messages(wallets: &[db::TrackedWallet]) -> Vec<String> {
+    let states = fetch_wallet_states(wallets).await;
+
+    let mut risks = Vec::new();
+    let mut failed = 0;
+    for (w, user_state) in wallets.iter().zip(&states) {
+        match user_state {
+            Ok(user_state) => risks.extend(
+                user_state
+                    .asset_positions
+                    .iter()
+                    .filter_map(|ap| LiquidationRisk::from_position(&ap.position))
+                    .map(|risk| (w, risk)),
+            ),
+            Err(e) => {
+                warn!("Failed to fetch positions for {}: {}", w.wallet_address, e);
+                failed += 1;
+            }
+        }
+    }
+    risks.sort_by(|(_, a), (_, b)| a.cmp_distance(b));
+
+    let mut header = "<b>🛟 Liquidation Prices</b>\n".to_string();
+    if failed > 0 {
+        header.push_str(&format!(
+            "<i>{} wallet(s) failed to fetch and aren't included.</i>\n",
+            failed
+        ));
+    }
+    if risks.is_empty() {
+        header.push_str("\n<i>None of your tracked wallets have open positions.</i>");
+        return vec![header];
+    }
+
+    let lines = risks
+        .iter()
+        .enumerate()
+        .map(|(i, (w, risk))| {
+            let display = format_wallet_display(&w.wallet_address, w.note.as_deref(), false);
+            // Only positions that can actually be liquidated are worth highlighting
+            let marker = if i < LIQ_HIGHLIGHTED && risk.liquidation.is_some() {
+                "🔥 "
+            } else {
+                ""
+            };
+            format!("\n{}{}\n👛 {}", marker, risk.format(), display)
+        })
+        .collect();
+    split_lines(header, lines)
+}
+
+/// Open positions of every wallet in as many messages as it takes.
+///
+/// Wallets are fetched concurrently, flat and failed ones get a single line.
+async fn all_positions_messages(wallets: &[db::TrackedWallet]) -> Vec<String> {
+    let states = fetch_wallet_states(wallets).await;
+
+    let mut lines = Vec::new();
+    let mut with_positions = 0;
+    for (w, user_state) in wallets.iter().zip(&states) {
+        let display = format_wallet_display(&w.wallet_address, w.note.as_deref(), false);
+        match user_state {
+            Ok(user_state) => {
+                let entries = position_entries_from_state(user_state);
+                if entries.is_empty() {
+                    lines.push(format!("⚪ {} · <i>no positions</i>", display));
+                } else {
+                    with_positions += 1;
+                    lines.push(format!("👛 <b>{}</b>", display));
+                    lines.extend(entries.iter().map(|e| e.trim_end().to_string()));
+                    lines.push(String::new());
+                }
+            }
+            Err(e) => {
+                warn!("Failed to fetch positions for {}: {}", w.wallet_address, e);
+                lines.push(format!("❌ {} · <i>failed to fetch</i>", display));
+            }
+        }
+    }
+
+    let header = format!(
+        "<b>📊 Open Positions</b>\n<i>{} of {} wallet(s) with open positions</i>\n",
+        with_positions,
+        wallets.len()
+    );
+    split_lines(header, lines)
+}
+
+/// One wallet's open positions for /positions, from the monitor's cache when the API fails
+async fn wallet_positions_text(
+    state: &Arc<RwLock<hyperliquid::PositionTracker>>,
+    wallet: &str,
+    note: Option<&str>,
+) -> String {
+    let wallet_display = format_wallet_display(wallet, note, false);
+
+    let hyperdash_link = format!(
+        "<a href=\"https://app.coinmarketman.com/hypertracker/wallet/{}\">Hyperdash</a>",
+        wallet
+    );
+
+    let live = hyperliquid::fetch_user_state(&HTTP_CLIENT, wallet).await;
+
+    // The monitor fetched this wallet moments ago, so fall back to what it saw
+    let (entries, notice) = match live {
+        Ok(user_state) => (position_entries_from_state(&user_state), None),
+        Err(e) => {
+            error!("Failed to fetch positions for {}: {}", wallet, e);
+            let tracker = state.read().await;
+            let age = tracker
+                .refreshed_at
+                .get(wallet)
+                .map(|t| format_age((chrono::Utc::now() - *t).num_seconds()));
+            let cached = match tracker.snapshots.get(wallet) {
+                Some(snapshot) => Some(position_entries_from_state(snapshot)),
+                None => tracker
+                    .positions
+                    .get(wallet)
+                    .map(position_entries_from_cache),
+            };
+            match cached {
+                Some(entries) => {
+                    let notice = match age {
+                        Some(age) => format!(
+                            "⚠️ Live data unavailable — showing cached state from {}",
+                            age
+                        ),
+                        None => {
+                            "⚠️ Live data unavailable — showing the last saved state".to_string()
+                        }
+                    };
+                    (entries, Some(notice))
+                }
+                None => return "❌ Failed to fetch positions. Please try again.".to_string(),
+            }
+        }
+    };
+
+    let notice = notice
+        .map(|n| format!("<i>{}</i>\n\n", n))
+        .unwrap_or_default();
+    if entries.is_empty() {
+        format!(
+            "<b>📊 Open Positions</b>\n\n\
+             {}👛 Wallet: {}\n\n\
+             <i>No open positions</i>\n\n\
+             {}",
+            notice, wallet_display, hyperdash_link
+        )
+    } else {
+        format!(
+            "<b>📊 Open Positions</b>\n\n\
+             {}👛 Wallet: {}\n{}\n{}",
+            notice,
+            wallet_display,
+            entries.concat(),
+            hyperdash_link
+        )
+    }
+}
+
+/// Position blocks from the monitor's cache, which only keeps size, entry and PnL
+fn position_entries_from_cache(
+    coins: &HashMap<Arc<str>, hyperliquid::CachedPosition>,
+) -> Vec<String> {
+    let mut coins: Vec<_> = coins.iter().collect();
+    coins.sort_by(|a, b| a.0.cmp(b.0));
+    coins
+        .into_iter()
+        .map(|(coin, pos)| cached_position_entry(coin, pos))
+        .collect()
+}
+
+fn cached_position_entry(coin: &str, pos: &hyperliquid::CachedPosition) -> String {
+    let size: f64 = pos.size.parse().unwrap_or(0.0);
+    let entry_price: f64 = pos.entry_px.parse().unwrap_or(0.0);
+    let unrealized_pnl: f64 = pos.unrealized_pnl.parse().unwrap_or(0.0);
+    // Unrealized PnL is marked against the current value, so it can be recovered from it
+    let position_value = entry_price * size.abs() + unrealized_pnl * size.signum();
+    format_position_entry(
+        coin,
+        size,
+        entry_price,
+        position_value,
+        unrealized_pnl,
+        pos.leverage,
+    )
+}
+
+/// Shared by /mute and /unmute
+async fn set_wallet_muted(
+    bot: &Bot,
+    msg: &Message,
+    pool: &SqlitePool,
+    user_id: i64,
+    identifier: &str,
+    muted: bool,
+) -> ResponseResult<()> {
+    let command = if muted { "mute" } else { "unmute" };
+    if identifier.is_empty() {
+        bot.send_message(
+            msg.chat.id,
+            format!(
+                "❌ Please provide a wallet address, index (1-10), or note.\n\nUsage: <code>/{} &lt;address|index|note&gt;</code>",
+                command
+            ),
+        )
+        .reply_to(msg.id)
+        .parse_mode(ParseMode::Html)
+        .await?;
+        return Ok(());
+    }
+
+    let Some((wallet, note)) = resolve_or_raw_address(
+        bot,
+        msg,
+        pool,
+        user_id,
+        identifier,
+        "❌ Wallet not found. Use <code>/list</code> to see your tracked wallets.",
+        &format!("❌ Failed to {} wallet. Please try again.", command),
+    )
+    .await?
+    else {
+        return Ok(());
+    };
+
+    let text = match db::set_wallet_muted(pool, user_id, &wallet, muted).await {
+        Ok(true) => {
+            info!("User {} {}d wallet {}", user_id, command, wallet);
+            let confirmation = wallet_confirmation(pool, user_id, &wallet, note.as_deref()).await;
+            if muted {
+                format!(
+                    "🔕 Muted wallet:\n{}\n\nIt stays in /list and /positions, but you won't get notifications for it. Use <code>/unmute</code> to turn them back on.",
+                    confirmation
+                )
+            } else {
+                format!("🔔 Unmuted wallet:\n{}", confirmation)
+            }
+        }
+        Ok(false) => "⚠️ This wallet was not being tracked.".to_string(),
+        Err(e) => {
+            error!("Failed to {} wallet: {}", command, e);
+            format!("❌ Failed to {} wallet. Please try again.", command)
+        }
+    };
+
+    bot.send_message(msg.chat.id, text)
+        .reply_to(msg.id)
+        .parse_mode(ParseMode::Html)
+        .await?;
+    Ok(())
+}
+
+/// Short names accepted for frequent commands, not registered with Telegram
+const COMMAND_ALIASES: &[(&str, &str)] = &[
+    ("a", "add"),
+    ("r", "remove"),
+    ("rm", "remove"),
+    ("l", "list"),
+    ("ls", "list"),
+    ("p", "positions"),
+];
+
+/// Parse a message's command, rewriting a `COMMAND_ALIASES` alias to its command first
+fn parse_command(msg: &Message, me: &Me) -> Option<Command> {
+    let text = msg.text()?;
+    let (name, rest) = text.split_at(text.find(char::is_whitespace).unwrap_or(text.len()));
+    let (name, mention) = match name.split_once('@') {
+        Some((name, username)) => (name, Some(username)),
+        None => (name, None),
+    };
+    let alias = name.strip_prefix('/').and_then(|name| {
+        COMMAND_ALIASES
+            .iter()
+            .find(|(alias, _)| alias.eq_ignore_ascii_case(name))
+    });
+
+    match alias {
+        Some((_, command)) => {
+            let mention = mention.map(|u| format!("@{}", u)).unwrap_or_default();
+            Command::parse(&format!("/{}{}{}", command, mention, rest), me.username()).ok()
+        }
+        None => Command::parse(text, me.username()).ok(),
+    }
+}
+
+/// The address of a private, non-command message holding exactly one valid address
+fn pasted_address(msg: &Message) -> Option<String> {
+    if !msg.chat.is_private() {
+        return None;
+    }
+    let text = msg.text()?;
+    let has_command = text.trim_start().starts_with('/')
+        || msg.entities().is_some_and(|entities| {
+            entities
+                .iter()
+                .any(|e| e.kind == MessageEntityKind::BotCommand)
+        });
+    if has_command {
+        return None;
+    }
+
+    let addresses: BTreeSet<String> = text
+        .split_whitespace()
+        .map(|token| normalize_address(token).to_lowercase())
+        .filter(|token| is_valid_address(token))
+        .collect();
+    match addresses.len() {
+        1 => addresses.into_iter().next(),
+        _ => None,
+    }
+}
+
+/// Reply to a pasted address with its positions and buttons to track it or dismiss them
+async fn handle_pasted_address(
+    bot: Bot,
+    msg: Message,
+    address: String,
+    pool: SqlitePool,
+    state: Arc<RwLock<hyperliquid::PositionTracker>>,
+) -> ResponseResult<()> {
+    if !dry_run::allows_user(msg.chat.id.0) {
+        return Ok(());
+    }
+    let user_id = msg.from.as_ref().map(|u| u.id.0 as i64).unwrap_or(0);
+    if let Err(e) = db::touch_user(&pool, user_id).await {
+        error!("Failed to record activity for {}: {}", user_id, e);
+    }
+
+    let tracking = db::is_tracking(&pool, user_id, &address)
+        .await
+        .unwrap_or_else(|e| {
+            error!("Failed to check tracked wallet: {}", e);
+            false
+        });
+    let note = if tracking {
+        db::get_wallet_note(&pool, user_id, &address)
+            .await
+            .ok()
+            .flatten()
+    } else {
+        None
+    };
+    let text = wallet_positions_text(&state, &address, note.as_deref()).await;
+
+    let mut buttons = Vec::new();
+    if !tracking {
+        buttons.push(InlineKeyboardButton::callback(
+            "➕ Track",
+            format!("track:{}", address),
+        ));
+    }
+    buttons.push(InlineKeyboardButton::callback("🙈 Ignore", "ignore"));
+
+    bot.send_message(msg.chat.id, text)
+        .reply_to(msg.id)
+        .parse_mode(ParseMode::Html)
+        .reply_markup(InlineKeyboardMarkup::new([buttons]))
+        .await?;
+    Ok(())
+}
+
+/// PNG of a QR code holding the address, for scanning it into a phone wallet
+fn qr_png(address: &str) -> anyhow::Result<Vec<u8>> {
+    let image = qrcode::QrCode::new(address)?
+        .render::<image::Luma<u8>>()
+        .min_dimensions(QR_MIN_SIZE, QR_MIN_SIZE)
+        .build();
+    let mut png = Vec::new();
+    image.write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)?;
+    Ok(png)
+}
+
+/// Whether a document caption is an /import command, with or without the bot's username
+fn is_import_caption(caption: &str) -> bool {
+    caption
+        .split_whitespace()
+        .next()
+        .and_then(|command| command.split('@').next())
+        .is_some_and(|command| command.eq_ignore_ascii_case("/import"))
+}
+
+/// What /import should read: a document sent with it or replied to, the text after the
+/// command, or the text of the message it replies to
+async fn import_contents(bot: &Bot, msg: &Message, args: &str) -> anyhow::Result<Option<String>> {
+    let document = msg
+        .document()
+        .or_else(|| msg.reply_to_message().and_then(|reply| reply.document()));
+    if let Some(document) = document {
+        if document.file.size > MAX_IMPORT_FILE_BYTES {
+            anyhow::bail!(
+                "the file is larger than {} KB",
+                MAX_IMPORT_FILE_BYTES / 1024
+            );
+        }
+        let file = bot.get_file(document.file.id.clone()).await?;
+        let mut contents = Vec::new();
+        bot.download_file(&file.path, &mut contents).await?;
+        return Ok(Some(String::from_utf8(contents)?));
+    }
+
+    if !args.trim().is_empty() {
+        return Ok(Some(args.to_string()));
+    }
+    Ok(msg
+        .reply_to_message()
+        .and_then(|reply| reply.text())
+        .map(str::to_string))
+}
+
+/// Add imported wallets one by one and summarize what happened.
+///
+/// Each wallet is saved on its own, so a failure halfway keeps everything added before it.
+async fn import_wallets(
+    pool: &SqlitePool,
+    user_id: i64,
+    entries: Vec<wallet_file::ExportedWallet>,
+) -> String {
+    let existing = match db::get_user_wallets(pool, user_id).await {
+        Ok(wallets) => wallets,
+        Err(e) => {
+            error!("Failed to list wallets for import: {}", e);
+            return "❌ Failed to import wallets. Please try again.".to_string();
+        }
+    };
+    let mut tracked: HashSet<String> = existing
+        .iter()
+        .map(|w| w.wallet_address.to_lowercase())
+        .collect();
+    let mut count = existing.len() as i64;
+
+    let (mut added, mut duplicates, mut invalid, mut over_limit, mut failed, mut dropped_notes) =
+        (0, 0, 0, 0, 0, 0);
+    for entry in entries {
+        let address = normalize_address(&entry.address).to_lowercase();
+        if !is_valid_address(&address) {
+            invalid += 1;
+            continue;
+        }
+        if !tracked.insert(address.clone()) {
+            duplicates += 1;
+            continue;
+        }
+        if count >= db::MAX_WALLETS_PER_USER {
+            over_limit += 1;
+            continue;
+        }
+
+        // Notes follow the /add rules, a wallet is still imported when its note can't be used
+        let mut note = entry.note.as_deref();
+        if let Some(n) = note
+            && (is_reserved_note(n)
+                || db::note_exists_for_user(pool, user_id, n, None)
+                    .await
+                    .unwrap_or(true))
+        {
+            note = None;
+            dropped_notes += 1;
+        }
+
+        match db::add_wallet(pool, user_id, &address, note).await {
+            Ok(db::AddWalletResult::NoteInUse) => failed += 1,
+            Ok(_) => {
+                added += 1;
+                count += 1;
+                if entry.muted
+                    && let Err(e) = db::set_wallet_muted(pool, user_id, &address, true).await
+                {
+                    error!("Failed to mute imported wallet {}: {}", address, e);
+                }
+            }
+            Err(e) => {
+                error!("Failed to import wallet {}: {}", address, e);
+                failed += 1;
+            }
+        }
+    }
+    info!(
+        "User {} imported {} wallets ({} duplicate, {} invalid, {} over the limit, {} failed)",
+        user_id, added, duplicates, invalid, over_limit, failed
+    );
+
+    let mut summary = format!("<b>📥 Import finished</b>\n\n✅ Added: {}", added);
+    if duplicates > 0 {
+        summary.push_str(&format!("\n⏭ Skipped (already tracked): {}", duplicates));
+    }
+    if invalid > 0 {
+        summary.push_str(&format!("\n❌ Skipped (invalid address): {}", invalid));
+    }
+    if over_limit > 0 {
+        summary.push_str(&format!(
+            "\n🚫 Skipped (limit of {} reached): {}",
+            db::MAX_WALLETS_PER_USER,
+            over_limit
+        ));
+    }
+    if failed > 0 {
+        summary.push_str(&format!("\n⚠️ Failed: {}", failed));
+    }
+    if dropped_notes > 0 {
+        summary.push_str(&format!(
+            "\n\n<i>{} note(s) were left out because they are reserved (1-10) or already used.</i>",
+            dropped_notes
+        ));
+    }
+    summary
+}
+
+/// (address, note) pairs of an /add message, one "0x… [note]" per line.
+///
+/// A line of nothing but addresses adds each of them without a note.
+fn parse_add_list(text: &str) -> Vec<(String, Option<String>)> {
+    let mut entries = Vec::new();
+    for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        if tokens.len() > 1
+            && tokens
+                .iter()
+                .all(|token| is_valid_address(&normalize_address(token)))
+        {
+            entries.extend(tokens.iter().map(|token| (token.to_string(), None)));
+            continue;
+        }
+        let (address, note) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let note = note.trim();
+        entries.push((
+            address.to_string(),
+            (!note.is_empty()).then(|| note.to_string()),
+        ));
+    }
+    entries
+}
+
+/// Add several wallets from one /add message and summarize what happened to them
+async fn add_wallets(
+    pool: &SqlitePool,
+    user_id: i64,
+    entries: Vec<(String, Option<String>)>,
+) -> String {
+    let existing = match db::get_user_wallets(pool, user_id).await {
+        Ok(wallets) => wallets,
+        Err(e) => {
+            error!("Failed to list wallets for bulk add: {}", e);
+            return "❌ Failed to add wallets. Please try again.".to_string();
+        }
+    };
+    let tracked: HashSet<String> = existing
+        .iter()
+        .map(|w| w.wallet_address.to_lowercase())
+        .collect();
+    let mut seen = HashSet::new();
+    let mut count = existing.len() as i64;
+
+    let (mut added, mut updated, mut unchanged, mut invalid, mut over_limit, mut failed) =
+        (0, 0, 0, 0, 0, 0);
+    let mut dropped_notes = 0;
+    for (address, note) in entries {
+        let address = normalize_address(&address).to_lowercase();
+        if !is_valid_address(&address) {
+            invalid += 1;
+            continue;
+        }
+        if !seen.insert(address.clone()) {
+            unchanged += 1;
+            continue;
+        }
+        let is_tracked = tracked.contains(&address);
+        if !is_tracked && count >= db::MAX_WALLETS_PER_USER {
+            over_limit += 1;
+            continue;
+        }
+
+        // Same note rules as a single /add, but a bad note only drops the note
+        let mut note = note.as_deref();
+        if let Some(n) = note
+            && (is_reserved_note(n)
+                || db::note_exists_for_user(pool, user_id, n, Some(&address))
+                    .await
+                    .unwrap_or(true))
+        {
+            note = None;
+            dropped_notes += 1;
+        }
+        // Unlike a single /add, leaving the note out keeps a tracked wallet's note
+        if is_tracked && note.is_none() {
+            unchanged += 1;
+            continue;
+        }
+
+        match db::add_wallet(pool, user_id, &address, note).await {
+            Ok(db::AddWalletResult::Added) => {
+                added += 1;
+                count += 1;
+            }
+            Ok(db::AddWalletResult::Updated) => updated += 1,
+            Ok(db::AddWalletResult::AlreadyExistsNoChange) => unchanged += 1,
+            Ok(db::AddWalletResult::NoteInUse) => failed += 1,
+            Err(e) => {
+                error!("Failed to add wallet {}: {}", address, e);
+                failed += 1;
+            }
+        }
+    }
+    info!(
+        "User {} bulk added {} wallets ({} updated, {} unchanged, {} invalid, {} over the limit, {} failed)",
+        user_id, added, updated, unchanged, invalid, over_limit, failed
+    );
+
+    let mut summary = format!("<b>➕ Wallets added</b>\n\n✅ Added: {}", added);
+    if updated > 0 {
+        summary.push_str(&format!("\n📝 Note updated: {}", updated));
+    }
+    if unchanged > 0 {
+        summary.push_str(&format!("\n⏭ Already tracked: {}", unchanged));
+    }
+    if invalid > 0 {
+        summary.push_str(&format!("\n❌ Invalid address: {}", invalid));
+    }
+    if over_limit > 0 {
+        summary.push_str(&format!(
+            "\n🚫 Skipped (limit of {} reached): {}",
+            db::MAX_WALLETS_PER_USER,
+            over_limit
+        ));
+    }
+    if failed > 0 {
+        summary.push_str(&format!("\n⚠️ Failed: {}", failed));
+    }
+    if dropped_notes > 0 {
+        summary.push_str(&format!(
+            "\n\n<i>{} note(s) were left out because they are reserved (1-10) or already used.</i>",
+            dropped_notes
+        ));
+    }
+    summary
+}
+
+/// A tag as given to /tag, or why it can't be one. Tags follow the note rules.
+fn parse_tag(tag: &str) -> Result<&str, String> {
+    let tag = tag.trim();
+    if tag.is_empty() {
+        Err("❌ Please provide a tag.".to_string())
+    } else if is_reserved_note(tag) {
+        Err("❌ Tags cannot be numbers 1-10 as these are reserved for wallet indexing.".to_string())
+    } else if tag.chars().count() > MAX_TAG_LEN {
+        Err(format!(
+            "❌ Tags can be at most {} characters long.",
+            MAX_TAG_LEN
+        ))
+    } else {
+        Ok(tag)
+    }
+}
+
+/// Handle /tag and /untag, the tag is the last word so notes with spaces still resolve
+async fn set_wallet_tag(
+    bot: &Bot,
+    msg: &Message,
+    pool: &SqlitePool,
+    user_id: i64,
+    args: &str,
+    add: bool,
+) -> ResponseResult<()> {
+    let command = if add { "tag" } else { "untag" };
+    let Some((identifier, tag)) = args.trim().rsplit_once(char::is_whitespace) else {
+        bot.send_message(
+            msg.chat.id,
+            format!(
+                "❌ Please provide a wallet and a tag.\n\nUsage: <code>/{} &lt;address|index|note&gt; &lt;tag&gt;</code>",
+                command
+            ),
+        )
+        .reply_to(msg.id)
+        .parse_mode(ParseMode::Html)
+        .await?;
+        return Ok(());
+    };
+    let identifier = identifier.trim();
+    let tag = match parse_tag(tag) {
+        Ok(tag) => tag,
+        Err(text) => {
+            bot.send_message(msg.chat.id, text)
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+            return Ok(());
+        }
+    };
+
+    // Resolve the identifier to a tracked wallet
+    let resolved = match resolve_wallet_identifier(pool, user_id, identifier).await {
+        Ok(Some((addr, note))) => match db::is_tracking(pool, user_id, &addr).await {
+            Ok(true) => Some((addr, note)),
+            Ok(false) => None,
+            Err(e) => {
+                error!("Failed to check tracked wallet: {}", e);
+                None
+            }
+        },
+        Ok(None) => None,
+        Err(e) => {
+            error!("Failed to resolve wallet identifier: {}", e);
+            None
+        }
+    };
+    let Some((wallet, note)) = resolved else {
+        bot.send_message(
+            msg.chat.id,
+            wallet_not_found_text(
+                pool,
+                user_id,
+                identifier,
+                "❌ Wallet not found. Use <code>/list</code> to see your tracked wallets.",
+            )
+            .await,
+        )
+        .reply_to(msg.id)
+        .parse_mode(ParseMode::Html)
+        .await?;
+        return Ok(());
+    };
+
+    let result = if add {
+        db::add_wallet_tag(pool, user_id, &wallet, tag).await
+    } else {
+        db::remove_wallet_tag(pool, user_id, &wallet, tag).await
+    };
+    let confirmation = wallet_confirmation(pool, user_id, &wallet, note.as_deref()).await;
+    let escaped = html::escape(tag);
+    let text = match result {
+        Ok(true) if add => {
+            info!("User {} tagged wallet {} as {}", user_id, wallet, tag);
+            format!(
+                "🏷 Tagged <b>{}</b>:\n{}\n\n<i>See the group with <code>/group {}</code>.</i>",
+                escaped, confirmation, escaped
+            )
+        }
+        Ok(true) => format!("🏷 Removed tag <b>{}</b> from:\n{}", escaped, confirmation),
+        Ok(false) if add => format!("ℹ️ This wallet is already tagged <b>{}</b>.", escaped),
+        Ok(false) => format!("ℹ️ This wallet isn't tagged <b>{}</b>.", escaped),
+        Err(e) => {
+            error!("Failed to {} wallet: {}", command, e);
+            format!("❌ Failed to {} wallet. Please try again.", command)
+        }
+    };
+    bot.send_message(msg.chat.id, text)
+        .reply_to(msg.id)
+        .parse_mode(ParseMode::Html)
+        .await?;
+    Ok(())
+}
+
+/// Handle /mutegroup and /unmutegroup
+async fn set_group_muted(
+    bot: &Bot,
+    msg: &Message,
+    pool: &SqlitePool,
+    user_id: i64,
+    tag: &str,
+    muted: bool,
+) -> ResponseResult<()> {
+    let command = if muted { "mutegroup" } else { "unmutegroup" };
+    let tag = tag.trim();
+    if tag.is_empty() {
+        bot.send_message(
+            msg.chat.id,
+            format!(
+                "❌ Please provide a tag.\n\nUsage: <code>/{} &lt;tag&gt;</code>",
+                command
+            ),
+        )
+        .reply_to(msg.id)
+        .parse_mode(ParseMode::Html)
+        .await?;
+        return Ok(());
+    }
+
+    let wallets = match db::get_wallets_by_tag(pool, user_id, tag).await {
+        Ok(wallets) => wallets,
+        Err(e) => {
+            error!("Failed to fetch wallets by tag: {}", e);
+            bot.send_message(
+                msg.chat.id,
+                "❌ Failed to update the group. Please try again.",
+            )
+            .reply_to(msg.id)
+            .parse_mode(ParseMode::Html)
+            .await?;
+            return Ok(());
+        }
+    };
+    if wallets.is_empty() {
+        bot.send_message(msg.chat.id, no_tagged_wallets_text(tag))
+            .reply_to(msg.id)
+            .parse_mode(ParseMode::Html)
+            .await?;
+        return Ok(());
+    }
+
+    let text = match db::set_tag_muted(pool, user_id, tag, muted).await {
+        Ok(changed) => {
+            info!(
+                "User {} {}d {} wallet(s) tagged {}",
+                user_id, command, changed, tag
+            );
+            let (emoji, verb) = if muted {
+                ("🔕", "muted")
+            } else {
+                ("🔔", "unmuted")
+            };
+            let mut text = format!(
+                "{} {} {} wallet(s) tagged <b>{}</b>.",
+                emoji,
+                if muted { "Muted" } else { "Unmuted" },
+                changed,
+                html::escape(tag)
+            );
+            let unchanged = wallets.len() as u64 - changed;
+            if unchanged > 0 {
+                text.push_str(&format!("\n\n<i>{} were already {}.</i>", unchanged, verb));
+            }
+            text
+        }
+        Err(e) => {
+            error!("Failed to {} wallets: {}", command, e);
+            "❌ Failed to update the group. Please try again.".to_string()
+        }
+    };
+    bot.send_message(msg.chat.id, text)
+        .reply_to(msg.id)
+        .parse_mode(ParseMode::Html)
+        .await?;
+    Ok(())
+}
+
+fn no_tagged_wallets_text(tag: &str) -> String {
+    format!(
+        "🏷 None of your wallets are tagged <b>{}</b>.\n\nUse <code>/tag &lt;wallet&gt; {}</code> to add one.",
+        html::escape(tag),
+        html::escape(tag)
+    )
+}
+
+/// Stop tracking a wallet, dropping its cached positions when no other user tracks it
+async fn stop_tracking(
+    pool: &SqlitePool,
+    state: &Arc<RwLock<hyperliquid::PositionTracker>>,
+    user_id: i64,
+    wallet: &str,
+) -> anyhow::Result<bool> {
+    let removed = db::remove_wallet(pool, user_id, wallet).await?;
+    if removed {
+        match db::forget_orphaned_wallet(pool, wallet).await {
+            Ok(true) => state.write().await.forget_wallet(&wallet.to_lowercase()),
+            Ok(false) => {}
+            Err(e) => error!(
+                "Failed to drop positions of removed wallet {}: {}",
+                wallet, e
+            ),
+        }
+    }
+    Ok(removed)
+}
+
+/// Stop tracking one wallet for /remove and say how it went
+async fn remove_wallet_text(
+    pool: &SqlitePool,
+    state: &Arc<RwLock<hyperliquid::PositionTracker>>,
+    user_id: i64,
+    wallet: &str,
+    note: Option<&str>,
+) -> String {
+    // Taken before removing, the index is gone afterwards
+    let confirmation = wallet_confirmation(pool, user_id, wallet, note).await;
+    match stop_tracking(pool, state, user_id, wallet).await {
+        Ok(true) => {
+            info!("User {} removed wallet {}", user_id, wallet);
+            format!("✅ Stopped tracking wallet:\n{}", confirmation)
+        }
+        Ok(false) => "⚠️ This wallet was not being tracked.".to_string(),
+        Err(e) => {
+            error!("Failed to remove wallet: {}", e);
+            "❌ Failed to remove wallet. Please try again.".to_string()
+        }
+    }
+}
+
+/// What one identifier of a /remove resolved to, before anything is removed
+enum RemovalTarget {
+    Wallet {
+        address: String,
+        note: Option<String>,
+        confirmation: String,
+    },
+    /// Resolved to a wallet an earlier identifier already named
+    Duplicate,
+    NotFound,
+    Failed,
+}
+
+/// The removal target of a resolved wallet, not found unless the user tracks it
+async fn removal_target(
+    pool: &SqlitePool,
+    user_id: i64,
+    address: String,
+    note: Option<String>,
+) -> RemovalTarget {
+    match db::is_tracking(pool, user_id, &address).await {
+        Ok(true) => {
+            // Taken before removing, the index is gone afterwards
+            let confirmation = wallet_confirmation(pool, user_id, &address, note.as_deref()).await;
+            RemovalTarget::Wallet {
+                address,
+                note,
+                confirmation,
+            }
+        }
+        Ok(false) => RemovalTarget::NotFound,
+        Err(e) => {
+            error!("Failed to check tracked wallet: {}", e);
+            RemovalTarget::Failed
+        }
+    }
+}
+
+/// Resolve every identifier of a /remove message before the first removal,
+/// so indexes refer to the list as it was
+async fn resolve_removals(
+    pool: &SqlitePool,
+    user_id: i64,
+    identifiers: &[&str],
+) -> Vec<(String, RemovalTarget)> {
+    let mut seen = HashSet::new();
+    let mut targets = Vec::with_capacity(identifiers.len());
+    for identifier in identifiers {
+        let target = match resolve_identifier(pool, user_id, identifier).await {
+            Ok(Some((address, _))) if seen.contains(&address) => RemovalTarget::Duplicate,
+            Ok(Some((address, note))) => {
+                seen.insert(address.clone());
+                removal_target(pool, user_id, address, note).await
+            }
+            Ok(None) => RemovalTarget::NotFound,
+            Err(e) => {
+                error!("Failed to resolve wallet identifier: {}", e);
+                RemovalTarget::Failed
+            }
+        };
+        targets.push((identifier.to_string(), target));
+    }
+    targets
+}
+
+/// Whether a user wants /remove to ask first, the default when settings can't be read
+async fn confirm_removals(pool: &SqlitePool, user_id: i64) -> bool {
+    db::get_user_settings(pool, user_id)
+        .await
+        .map(|settings| settings.confirm_remove)
+        .unwrap_or_else(|e| {
+            error!("Failed to fetch settings for /remove: {}", e);
+            true
+        })
+}
+
+/// Reply to /remove with confirmation buttons, or remove right away when the user turned them off
+async fn send_removal(
+    bot: &Bot,
+    msg: &Message,
+    pool: &SqlitePool,
+    state: &Arc<RwLock<hyperliquid::PositionTracker>>,
+    user_id: i64,
+    targets: Vec<(String, RemovalTarget)>,
+) -> ResponseResult<()> {
+    let (text, keyboard) = if confirm_removals(pool, user_id).await {
+        removal_prompt(user_id, targets)
+    } else {
+        (apply_removals(pool, state, user_id, targets).await, None)
+    };
+    let reply = bot
+        .send_message(msg.chat.id, text)
+        .reply_to(msg.id)
+        .parse_mode(ParseMode::Html);
+    match keyboard {
+        Some(keyboard) => reply.reply_markup(keyboard).await?,
+        None => reply.await?,
+    };
+    Ok(())
+}
+
+/// /remove confirmations waiting for their button, by the token in the button's data.
+///
+/// Taking one out is what makes a confirmation single-use.
+static PENDING_REMOVALS: LazyLock<Mutex<HashMap<u64, PendingRemoval>>> =
+    LazyLock::new(Default::default);
+
+struct PendingRemoval {
+    user_id: i64,
+    targets: Vec<(String, RemovalTarget)>,
+    created_at: Instant,
+}
+
+impl PendingRemoval {
+    fn expired(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.created_at) > REMOVE_CONFIRM_TTL
+    }
+}
+
+/// Take a /remove confirmation out by its token, `None` if it was already used.
+///
+/// Someone else's confirmation is left in place and errs.
+fn take_pending_removal(token: u64, user_id: i64) -> Result<Option<PendingRemoval>, ()> {
+    let Ok(mut pending) = PENDING_REMOVALS.lock() else {
+        return Ok(None);
+    };
+    match pending.get(&token) {
+        Some(p) if p.user_id != user_id => Err(()),
+        _ => Ok(pending.remove(&token)),
+    }
+}
+
+/// Line of a removal prompt or summary for an identifier that won't be removed
+fn skipped_removal_line(identifier: &str, target: &RemovalTarget) -> String {
+    let identifier = html::escape(identifier);
+    match target {
+        RemovalTarget::Wallet { .. } => String::new(),
+        RemovalTarget::Duplicate => format!("⏭ <code>{}</code>: same wallet as above", identifier),
+        RemovalTarget::NotFound => format!("❓ <code>{}</code>: not found", identifier),
+        RemovalTarget::Failed => {
+            format!("⚠️ <code>{}</code>: failed, please try again", identifier)
+        }
+    }
+}
+
+/// Ask before removing, with buttons unless nothing can be removed
+fn removal_prompt(
+    user_id: i64,
+    targets: Vec<(String, RemovalTarget)>,
+) -> (String, Option<InlineKeyboardMarkup>) {
+    let lines: Vec<String> = targets
+        .iter()
+        .map(|(identifier, target)| match target {
+            RemovalTarget::Wallet { confirmation, .. } if targets.len() == 1 => {
+                confirmation.clone()
+            }
+            RemovalTarget::Wallet { confirmation, .. } => format!(
+                "🗑 <code>{}</code>\n{}",
+                html::escape(identifier),
+                confirmation
+            ),
+            _ => skipped_removal_line(identifier, target),
+        })
+        .collect();
+    let count = targets
+        .iter()
+        .filter(|(_, target)| matches!(target, RemovalTarget::Wallet { .. }))
+        .count();
+
+    if count == 0 {
+        let text = match targets.as_slice() {
+            [(_, RemovalTarget::NotFound)] => "⚠️ This wallet was not being tracked.".to_string(),
+            [(_, RemovalTarget::Failed)] => {
+                "❌ Failed to remove wallet. Please try again.".to_string()
+            }
+            _ => format!(
+                "<b>➖ Nothing to remove</b>\n\n{}\n\n<i>Use <code>/list</code> to see your tracked wallets.</i>",
+                lines.join("\n\n")
+            ),
+        };
+        return (text, None);
+    }
+
+    let header = if targets.len() == 1 {
+        "<b>🗑 Stop tracking this wallet?</b>".to_string()
+    } else {
+        format!("<b>🗑 Stop tracking {} wallet(s)?</b>", count)
+    };
+    let text = format!(
+        "{}\n\n{}\n\n<i>The buttons expire in {} minutes.</i>",
+        header,
+        lines.join("\n\n"),
+        REMOVE_CONFIRM_TTL.as_secs() / 60
+    );
+
+    let token: u64 = rand::random();
+    if let Ok(mut pending) = PENDING_REMOVALS.lock() {
+        let now = Instant::now();
+        pending.retain(|_, p| !p.expired(now));
+        pending.insert(
+            token,
+            PendingRemoval {
+                user_id,
+                targets,
+                created_at: Instant::now(),
+            },
+        );
+    }
+    let keyboard = InlineKeyboardMarkup::new([[
+        InlineKeyboardButton::callback("✅ Remove", format!("rmc:yes:{}", token)),
+        InlineKeyboardButton::callback("❌ Cancel", format!("rmc:no:{}", token)),
+    ]]);
+    (text, Some(keyboard))
+}
+
+/// Remove the resolved wallets of a /remove and summarize what happened to each
+async fn apply_removals(
+    pool: &SqlitePool,
+    state: &Arc<RwLock<hyperliquid::PositionTracker>>,
+    user_id: i64,
+    targets: Vec<(String, RemovalTarget)>,
+) -> String {
+    // A single wallet gets the plain /remove reply
+    if let [(_, RemovalTarget::Wallet { address, note, .. })] = targets.as_slice() {
+        return remove_wallet_text(pool, state, user_id, address, note.as_deref()).await;
+    }
+    if targets.len() == 1 {
+        // Nothing to remove, so this only words why without storing a confirmation
+        return removal_prompt(user_id, targets).0;
+    }
+
+    let total = targets.len();
+    let mut removed = 0;
+    let mut lines = Vec::with_capacity(total);
+    for (identifier, target) in targets {
+        let line = match target {
+            RemovalTarget::Wallet {
+                address,
+                confirmation,
+                ..
+            } => {
+                let escaped = html::escape(&identifier);
+                match stop_tracking(pool, state, user_id, &address).await {
+                    Ok(true) => {
+                        info!("User {} removed wallet {}", user_id, address);
+                        removed += 1;
+                        format!("✅ <code>{}</code>: removed\n{}", escaped, confirmation)
+                    }
+                    Ok(false) => format!("❓ <code>{}</code>: not tracked", escaped),
+                    Err(e) => {
+                        error!("Failed to remove wallet: {}", e);
+                        format!("⚠️ <code>{}</code>: failed, please try again", escaped)
+                    }
+                }
+            }
+            target => skipped_removal_line(&identifier, &target),
+        };
+        lines.push(line);
+    }
+
+    format!(
+        "<b>➖ Removed {} of {} wallet(s)</b>\n\n{}\n\n<i>Use <code>/list</code> to see your tracked wallets.</i>",
+        removed,
+        total,
+        lines.join("\n\n")
+    )
+}
+
+/// Join lines under a header, rolling over into more messages when they get too long
+pub fn split_lines(header: String, lines: Vec<String>) -> Vec<String> {
+    let mut messages = vec![header];
+    for line in lines {
+        let current = messages.last_mut().expect("messages is never empty");
+        if current.len() + line.len() > MESSAGE_SPLIT_LIMIT {
+            messages.push(line);
+        } else {
+            current.push('\n');
+            current.push_str(&line);
+        }
+    }
+    messages
+}
+
+/// Minutes after midnight for a time like "08:00" or "8:30"
+fn parse_time_of_day(input: &str) -> Option<i64> {
+    let (hours, minutes) = input.split_once(':')?;
+    let hours: i64 = hours.parse().ok()?;
+    let minutes: i64 = minutes.parse().ok()?;
+    ((0..24).contains(&hours) && (0..60).contains(&minutes)).then_some(hours * 60 + minutes)
+}
+
+/// File size with a binary unit, like "12.3 MB"
+fn format_bytes(bytes: i64) -> String {
+    let bytes = bytes as f64;
+    if bytes >= 1024.0 * 1024.0 * 1024.0 {
+        format!("{:.1} GB", bytes / (1024.0 * 1024.0 * 1024.0))
+    } else if bytes >= 1024.0 * 1024.0 {
+        format!("{:.1} MB", bytes / (1024.0 * 1024.0))
+    } else {
+        format!("{:.1} KB", bytes / 1024.0)
+    }
+}
+
+/// Readable name of a notification kind logged for /stats
+fn notification_kind_label(kind: &str) -> String {
+    match kind {
+        "opened" => "🟢 Opened".to_string(),
+        "closed" => "🔴 Closed".to_string(),
+        "increased" => "📈 Increased".to_string(),
+        "decreased" => "📉 Decreased".to_string(),
+        "margin" => "📊 Margin usage".to_string(),
+        "liquidation_moved" => "🩸 Liquidation price moved".to_string(),
+        "near_liquidation" => "⚠️ Near liquidation".to_string(),
+        "price_alert" => "🔔 Price alerts".to_string(),
+        "daily_digest" => "🗞 Daily digests".to_string(),
+        other => html::escape(other),
+    }
+}
+
+/// The user's timezone, UTC when it can't be loaded
+async fn user_timezone(pool: &SqlitePool, user_id: i64) -> UserTimezone {
+    db::get_user_settings(pool, user_id)
+        .await
+        .map(|settings| settings.tz())
+        .unwrap_or_else(|e| {
+            error!("Failed to load timezone for {}: {}", user_id, e);
+            UserTimezone::default()
+        })
+}
+
+/// Count with thousands separators, e.g. 1,240
+fn format_count(count: usize) -> String {
+    let digits = count.to_string();
+    let mut out = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Relative age like "18s ago" or "3h ago"
+fn format_age(secs: i64) -> String {
+    format!("{} ago", format_duration(secs))
+}
+
+/// Coarse duration like "18s", "5m" or "3h"
+pub fn format_duration(secs: i64) -> String {
+    let secs = secs.max(0);
+    if secs < 60 {
+        format!("{}s", secs)
+    } else if secs < 3600 {
+        format!("{}m", secs / 60)
+    } else if secs < 86400 {
+        format!("{}h", secs / 3600)
+    } else {
+        format!("{}d", secs / 86400)
+    }
+}
+
+/// Parse a duration like "30m", "8h" or "2d" into seconds
+fn parse_duration(input: &str) -> Option<i64> {
+    let input = input.trim().to_lowercase();
+    let unit = input.chars().last()?;
+    let amount = &input[..input.len() - unit.len_utf8()];
+    let amount: i64 = amount.trim().parse().ok().filter(|n| *n > 0)?;
+    let unit_secs = match unit {
+        'm' => 60,
+        'h' => 3600,
+        'd' => 86400,
+        _ => return None,
+    };
+    amount.checked_mul(unit_secs)
+}
 
-            let wallet_display = format_wallet_display(&wallet, note.as_deref(), false);
+/// Explorer URL fragments that are directly followed by a wallet address
+const EXPLORER_ADDRESS_PATTERNS: &[&str] = &[
+    "hypurrscan.io/address/",
+    "app.hyperliquid.xyz/explorer/address/",
+    "hyperdash.info/trader/",
+    "app.coinmarketman.com/hypertracker/wallet/",
+    "arbiscan.io/address/",
+    "etherscan.io/address/",
+];
 
-            let hyperdash_link = format!(
-                "<a href=\"https://app.coinmarketman.com/hypertracker/wallet/{}\">Hyperdash</a>",
-                wallet
-            );
+/// Characters that survive copy-paste from mobile clients but are invisible
+fn is_invisible(c: char) -> bool {
+    matches!(
+        c,
+        '\u{00AD}' | '\u{200B}'..='\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2060}'..='\u{2064}' | '\u{FEFF}'
+    )
+}
 
-            match hyperliquid::fetch_user_state(&client, &wallet).await {
-                Ok(user_state) => {
-                    let positions: Vec<_> = user_state
-                        .asset_positions
-                        .iter()
-                        .filter(|ap| ap.position.szi.parse::<f64>().unwrap_or(0.0) != 0.0)
-                        .collect();
+/// Clean up a pasted wallet address before validation.
+///
+/// Strips invisible characters and surrounding punctuation (markdown, quotes,
+/// trailing periods), extracts the address from known explorer URLs and
+/// lowercases an uppercase "0X" prefix. The result still has to pass
+/// `is_valid_address`.
+fn normalize_address(input: &str) -> String {
+    let cleaned: String = input.chars().filter(|c| !is_invisible(*c)).collect();
+    let cleaned = cleaned.trim();
 
-                    if positions.is_empty() {
-                        bot.send_message(
-                            msg.chat.id,
-                            format!(
-                                "<b>📊 Open Positions</b>\n\n\
-                                 👛 Wallet: {}\n\n\
-                                 <i>No open positions</i>\n\n\
-                                 {}",
-                                wallet_display, hyperdash_link
-                            ),
-                        )
-                        .reply_to(msg.id)
-                        .parse_mode(ParseMode::Html)
-                        .await?;
-                    } else {
-                        let mut message = format!(
-                            "<b>📊 Open Positions</b>\n\n\
-                             👛 Wallet: {}\n",
-                            wallet_display
-                        );
-                        for ap in positions {
-                            let pos = &ap.position;
-                            let size: f64 = pos.szi.parse().unwrap_or(0.0);
-                            let is_long = size > 0.0;
-                            let entry_price: f64 = pos
-                                .entry_px
-                                .as_ref()
-                                .and_then(|p| p.parse().ok())
-                                .unwrap_or(0.0);
-                            let position_value: f64 = pos.position_value.parse().unwrap_or(0.0);
-                            let current_price = if size.abs() > 0.0 {
-                                position_value / size.abs()
-                            } else {
-                                0.0
-                            };
-                            let unrealized_pnl: f64 = pos.unrealized_pnl.parse().unwrap_or(0.0);
-                            let leverage = pos.leverage.as_ref().map(|l| l.value).unwrap_or(1);
-                            let direction_str = if is_long { "Long" } else { "Short" };
-                            let direction_emoji = if is_long { "🟢" } else { "🔴" };
-                            let pnl_str = if unrealized_pnl >= 0.0 {
-                                format!("<b>+${:.2}</b>", unrealized_pnl)
-                            } else {
-                                format!("<b>-${:.2}</b>", unrealized_pnl.abs())
-                            };
-                            // Calculate PnL percentage (based on entry value)
-                            let entry_value = entry_price * size.abs();
-                            let pnl_pct = if entry_value > 0.0 {
-                                (unrealized_pnl / entry_value) * 100.0
-                            } else {
-                                0.0
-                            };
-                            let pnl_pct_str = if pnl_pct >= 0.0 {
-                                format!("+{:.2}%", pnl_pct)
-                            } else {
-                                format!("{:.2}%", pnl_pct)
-                            };
-                            // Round to avoid floating point artifacts like 2744.7999999999997
-                            let current_price_rounded =
-                                (current_price * 10000000000.0).round() / 10000000000.0; // 10 decimal places
-                            let entry_str = format!("{}", entry_price)
-                                .trim_end_matches('0')
-                                .trim_end_matches('.')
-                                .to_string();
-                            let current_str = format!("{}", current_price_rounded)
-                                .trim_end_matches('0')
-                                .trim_end_matches('.')
-                                .to_string();
-                            let size_str = format!("{}", size.abs())
-                                .trim_end_matches('0')
-                                .trim_end_matches('.')
-                                .to_string();
-
-                            // Calculate price difference
-                            let price_diff = current_price_rounded - entry_price;
-                            let price_diff_str = if price_diff >= 0.0 {
-                                format!("+${:.2}", price_diff)
-                            } else {
-                                format!("-${:.2}", price_diff.abs())
-                            };
+    // ASCII lowercasing keeps byte offsets intact for slicing the original
+    let lower = cleaned.to_ascii_lowercase();
+    let extracted = EXPLORER_ADDRESS_PATTERNS
+        .iter()
+        .find_map(|pattern| lower.find(pattern).map(|idx| idx + pattern.len()))
+        .map(|start| {
+            cleaned[start..]
+                .chars()
+                .take_while(|c| c.is_ascii_alphanumeric())
+                .collect::<String>()
+        })
+        .unwrap_or_else(|| {
+            cleaned
+                .trim_matches(|c: char| !c.is_ascii_alphanumeric())
+                .to_string()
+        });
 
-                            message.push_str(&format!(
-                                "\n{} <b>{}x {} {}</b>\n\
-                                 📊 Size: {} {} (${:.2})\n\
-                                 💰 Entry: ${}\n\
-                                 📍 Current: ${} ({})\n\
-                                 💵 PnL: {} ({})\n",
-                                direction_emoji,
-                                leverage,
-                                pos.coin,
-                                direction_str,
-                                size_str,
-                                pos.coin,
-                                position_value,
-                                entry_str,
-                                current_str,
-                                price_diff_str,
-                                pnl_str,
-                                pnl_pct_str
-                            ));
-                        }
-                        message.push_str(&format!("\n{}", hyperdash_link));
-                        bot.send_message(msg.chat.id, message)
-                            .reply_to(msg.id)
-                            .parse_mode(ParseMode::Html)
-                            .await?;
-                    }
-                }
-                Err(e) => {
-                    error!("Failed to fetch positions for {}: {}", wallet, e);
-                    bot.send_message(
-                        msg.chat.id,
-                        "❌ Failed to fetch positions. Please try again.",
-                    )
-                    .reply_to(msg.id)
-                    .parse_mode(ParseMode::Html)
-                    .await?;
-                }
+    match extracted.strip_prefix("0X") {
+        Some(rest) => format!("0x{}", rest),
+        None => extracted,
+    }
+}
+
+/// Listed perp name for a coin as typed, tolerating case and the k-prefix.
+///
+/// The error is the reply to send when it can't be resolved.
+fn resolve_perp(input: &str) -> Result<String, String> {
+    match hyperliquid::is_listed_perp(input) {
+        Some(true) => Ok(input.to_string()),
+        Some(false) => hyperliquid::renamed_perp(input).ok_or_else(|| {
+            if hyperliquid::is_spot_only(input) {
+                format!(
+                    "❌ <b>{}</b> only trades on spot on Hyperliquid. Coin filters and alerts only match perps.",
+                    html::escape(input)
+                )
+            } else {
+                format!(
+                    "❌ <b>{}</b> isn't a listed perp on Hyperliquid.",
+                    html::escape(input)
+                )
             }
+        }),
+        None => {
+            Err("❌ The asset list isn't loaded yet. Please try again in a minute.".to_string())
         }
     }
+}
 
-    Ok(())
+/// `resolve_perp` for a comma or space separated list, sorted and deduplicated.
+///
+/// The error lists every coin that couldn't be resolved.
+fn resolve_perps(input: &str) -> Result<Vec<String>, String> {
+    let mut coins = Vec::new();
+    let mut errors = Vec::new();
+    for coin in input
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|c| !c.is_empty())
+    {
+        match resolve_perp(coin) {
+            Ok(coin) => coins.push(coin),
+            Err(e) if !errors.contains(&e) => errors.push(e),
+            Err(_) => {}
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors.join("\n"));
+    }
+    if coins.is_empty() {
+        return Err(
+            "❌ Please provide perp names like <code>BTC</code> or <code>BTC,ETH,HYPE</code>."
+                .to_string(),
+        );
+    }
+    coins.sort();
+    coins.dedup();
+    Ok(coins)
+}
+
+/// The perp a typed symbol refers to, among `names`.
+///
+/// Accepts any case, "$BTC", pair and perp suffixes like "BTC-PERP" or "ETHUSDT", and both the
+/// bare ("PEPE") and 1000x ("1000PEPE", "kPEPE") names of k-prefixed coins.
+fn find_perp<'a>(input: &str, names: &[&'a str]) -> Option<&'a str> {
+    let raw = input.trim().trim_start_matches('$');
+    let upper = raw.to_uppercase();
+    let stripped = [
+        "-PERP", "PERP", "/USDC", "/USDT", "/USD", "-USDC", "-USDT", "-USD", "USDC", "USDT", "USD",
+    ]
+    .iter()
+    .find_map(|suffix| upper.strip_suffix(suffix).filter(|rest| !rest.is_empty()))
+    .unwrap_or(&upper);
+    let candidates = [
+        raw.to_string(),
+        stripped.to_string(),
+        stripped
+            .strip_prefix("1000")
+            .map(|base| format!("k{}", base))
+            .unwrap_or_default(),
+    ];
+    candidates
+        .iter()
+        .filter(|candidate| !candidate.is_empty())
+        .find_map(|candidate| {
+            names
+                .iter()
+                .find(|name| hyperliquid::coin_matches(candidate, name))
+        })
+        .copied()
+}
+
+/// Up to `limit` names closest to a typo, nearest first
+fn closest_names<'a>(input: &str, names: &[&'a str], limit: usize) -> Vec<&'a str> {
+    let input = input.trim().trim_start_matches('$').to_uppercase();
+    // Allow roughly one typo per three letters
+    let max_distance = (input.len() / 3).max(1);
+    let mut scored: Vec<(usize, &str)> = names
+        .iter()
+        .map(|name| {
+            let upper = name.to_uppercase();
+            let bare = upper.strip_prefix('K').filter(|_| name.starts_with('k'));
+            let distance = bare
+                .map(|bare| edit_distance(&input, bare))
+                .unwrap_or(usize::MAX)
+                .min(edit_distance(&input, &upper));
+            (distance, *name)
+        })
+        .filter(|(distance, _)| *distance <= max_distance)
+        .collect();
+    scored.sort();
+    scored
+        .into_iter()
+        .take(limit)
+        .map(|(_, name)| name)
+        .collect()
+}
+
+/// Hourly funding rate with its annualized equivalent, like "+0.0013%/h (+11.4% APR)"
+fn format_funding_rate(hourly: f64) -> String {
+    format!(
+        "{:+.4}%/h ({:+.1}% APR)",
+        hourly * 100.0,
+        hourly * 100.0 * 24.0 * 365.0
+    )
+}
+
+/// Count a /feedback message against the user's hourly limit, false when it's used up
+fn take_feedback_slot(user_id: i64) -> bool {
+    let Ok(mut sent) = FEEDBACK_SENT.lock() else {
+        return true;
+    };
+    let times = sent.entry(user_id).or_default();
+    while times
+        .front()
+        .is_some_and(|at| at.elapsed() > FEEDBACK_WINDOW)
+    {
+        times.pop_front();
+    }
+    if times.len() >= MAX_FEEDBACK_PER_WINDOW {
+        return false;
+    }
+    times.push_back(Instant::now());
+    true
+}
+
+/// Levenshtein distance between two strings, by characters
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, b) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(a != *b);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Not-found reply for an identifier, echoing what was parsed when it looked like an address
+/// and listing the candidates when it's part of several notes or shortened addresses
+async fn wallet_not_found_text(
+    pool: &SqlitePool,
+    user_id: i64,
+    identifier: &str,
+    fallback: &str,
+) -> String {
+    let parsed = normalize_address(identifier);
+    if let Some((prefix, suffix)) = partial_address(&parsed) {
+        let candidates = db::find_wallets_by_address_part(pool, user_id, &prefix, &suffix)
+            .await
+            .unwrap_or_else(|e| {
+                error!("Failed to look up partial addresses: {}", e);
+                Vec::new()
+            });
+        if candidates.len() > 1 {
+            return ambiguous_wallets_text(identifier, &candidates, "more of the address");
+        }
+        return format!(
+            "❌ None of your tracked wallets start with <code>{}</code>{}.",
+            html::escape(&prefix),
+            if suffix.is_empty() {
+                String::new()
+            } else {
+                format!(" and end with <code>{}</code>", html::escape(&suffix))
+            }
+        );
+    }
+    if parsed.to_ascii_lowercase().starts_with("0x") {
+        return format!(
+            "❌ Couldn't read a valid wallet address from that.\n\nParsed: <code>{}</code>",
+            html::escape(&parsed)
+        );
+    }
+
+    let candidates = db::find_wallets_by_note_part(pool, user_id, identifier)
+        .await
+        .unwrap_or_else(|e| {
+            error!("Failed to look up partial notes: {}", e);
+            Vec::new()
+        });
+    if candidates.len() < 2 {
+        return fallback.to_string();
+    }
+    ambiguous_wallets_text(identifier, &candidates, "more of the note")
+}
+
+/// Reply listing the wallets an identifier could mean
+fn ambiguous_wallets_text(
+    identifier: &str,
+    candidates: &[(usize, db::TrackedWallet)],
+    hint: &str,
+) -> String {
+    let lines: Vec<String> = candidates
+        .iter()
+        .map(|(index, w)| {
+            format!(
+                "{}. {}",
+                index,
+                format_wallet_display(&w.wallet_address, w.note.as_deref(), true)
+            )
+        })
+        .collect();
+    format!(
+        "❓ <b>{}</b> matches several wallets:\n\n{}\n\n<i>Use the index or {}.</i>",
+        html::escape(identifier),
+        lines.join("\n"),
+        hint
+    )
+}
+
+/// Shortest address prefix, "0x" included, accepted as a wallet identifier
+const MIN_ADDRESS_PREFIX_LEN: usize = 6;
+
+/// Split a shortened address into its lowercase (prefix, suffix).
+///
+/// Accepts a bare prefix like "0x1a2b3c" or the "0x1a2b…9f3c" form notifications show,
+/// where the suffix is empty for a bare prefix. Full addresses aren't partial.
+fn partial_address(input: &str) -> Option<(String, String)> {
+    let lower = input.trim().to_ascii_lowercase();
+    let (prefix, suffix) = match lower.split_once('…').or_else(|| lower.split_once("...")) {
+        Some((prefix, suffix)) => (prefix.trim(), suffix.trim()),
+        None => (lower.as_str(), ""),
+    };
+    let hex = |s: &str| s.chars().all(|c| c.is_ascii_hexdigit());
+    let digits = prefix.strip_prefix("0x")?;
+    (prefix.len() >= MIN_ADDRESS_PREFIX_LEN
+        && prefix.len() + suffix.len() < 42
+        && hex(digits)
+        && hex(suffix))
+    .then(|| (prefix.to_string(), suffix.to_string()))
 }
 
 fn is_valid_address(address: &str) -> bool {
@@ -522,6 +9767,17 @@ fn is_valid_address(address: &str) -> bool {
         && address[2..].chars().all(|c| c.is_ascii_hexdigit())
 }
 
+/// Parse a user-entered USD amount, tolerating "$" and thousands separators
+fn parse_amount(input: &str) -> Option<f64> {
+    input
+        .trim()
+        .trim_start_matches('$')
+        .replace(',', "")
+        .parse::<f64>()
+        .ok()
+        .filter(|v| v.is_finite())
+}
+
 fn is_reserved_note(note: &str) -> bool {
     // Notes cannot be numbers 1-10
     if let Ok(n) = note.parse::<u32>() {
@@ -531,16 +9787,345 @@ fn is_reserved_note(note: &str) -> bool {
     }
 }
 
-/// Resolve a wallet identifier which can be:
+/// Fetch the state of several wallets concurrently, results in the same order
+async fn fetch_wallet_states(
+    wallets: &[db::TrackedWallet],
+) -> Vec<anyhow::Result<hyperliquid::UserState>> {
+    // Spawn all at once, a slow wallet shouldn't hold up the others
+    let handles: Vec<_> = wallets
+        .iter()
+        .map(|w| {
+            let wallet = w.wallet_address.clone();
+            tokio::spawn(async move { hyperliquid::fetch_user_state(&HTTP_CLIENT, &wallet).await })
+        })
+        .collect();
+
+    let mut states = Vec::with_capacity(handles.len());
+    for handle in handles {
+        states.push(handle.await.unwrap_or_else(|e| Err(e.into())));
+    }
+    states
+}
+
+/// A fee total as paid, or as earned when rebates outweigh it
+fn format_fee(fee: f64) -> String {
+    if fee < 0.0 {
+        format!("${:.2} earned", fee.abs())
+    } else {
+        format!("${:.2} paid", fee)
+    }
+}
+
+/// Fees over volume in basis points, negative for a net rebate
+fn format_fee_rate(fee: f64, volume: f64) -> String {
+    if volume > 0.0 {
+        format!("{:.2} bps", fee / volume * 10_000.0)
+    } else {
+        "n/a".to_string()
+    }
+}
+
+/// A wallet identifier followed by an optional number of days like "30" or "30d"
+fn split_trailing_days(args: &str) -> (&str, Option<i64>) {
+    let args = args.trim();
+    match args.rsplit_once(char::is_whitespace) {
+        Some((head, tail)) => match tail.trim_end_matches('d').parse::<i64>() {
+            Ok(days) => (head.trim(), Some(days)),
+            Err(_) => (args, None),
+        },
+        None => (args, None),
+    }
+}
+
+/// Footnote for stats cut off at `MAX_FILL_STATS_FILLS`, saying how far they reach
+fn truncated_fills_notice(fills: &[hyperliquid::Fill], tz: &UserTimezone) -> String {
+    let until = fills
+        .last()
+        .map(|f| format!(" up to {}", tz.format_millis(f.time)))
+        .unwrap_or_default();
+    format!(
+        "\n<i>⚠️ Truncated: only the first {} fills of the window were counted{}.</i>",
+        MAX_FILL_STATS_FILLS, until
+    )
+}
+
+/// One closing execution of a wallet, see `closed_trades`
+struct ClosedTrade {
+    coin: String,
+    /// e.g. "Close Long", as reported by the first fill
+    dir: String,
+    size: f64,
+    time: i64,
+    pnl: f64,
+    liquidated: bool,
+}
+
+/// Closing executions in time order, fills of one coin at the same millisecond combined.
+///
+/// An order sweeping several price levels fills many times at once, counting each
+/// of those as its own trade would inflate the number of wins and losses. Flagged
+/// liquidated when a fill liquidated this wallet.
+fn closed_trades(fills: &[hyperliquid::Fill], wallet_address: &str) -> Vec<ClosedTrade> {
+    let mut trades: Vec<ClosedTrade> = Vec::new();
+    for fill in fills {
+        let pnl: f64 = fill.closed_pnl.parse().unwrap_or(0.0);
+        let size: f64 = fill.sz.parse().unwrap_or(0.0);
+        if pnl == 0.0 && !fill.dir.starts_with("Close") {
+            continue;
+        }
+        let liquidated = fill.liquidation.as_ref().is_some_and(|l| {
+            l.liquidated_user
+                .as_deref()
+                .is_none_or(|user| user.eq_ignore_ascii_case(wallet_address))
+        });
+        match trades
+            .iter_mut()
+            .rev()
+            .find(|t| t.coin == fill.coin && t.time == fill.time)
+        {
+            Some(trade) => {
+                trade.pnl += pnl;
+                trade.size += size;
+                trade.liquidated |= liquidated;
+            }
+            None => trades.push(ClosedTrade {
+                coin: fill.coin.clone(),
+                dir: fill.dir.clone(),
+                size,
+                time: fill.time,
+                pnl,
+                liquidated,
+            }),
+        }
+    }
+    trades
+}
+
+/// Opens and increases of one coin for /trending
+#[derive(Default)]
+struct TrendingCoin<'a> {
+    events: usize,
+    longs: usize,
+    shorts: usize,
+    wallets: BTreeSet<&'a str>,
+}
+
+/// Realized PnL per closing execution, see `closed_trades`
+fn realized_outcomes(fills: &[hyperliquid::Fill]) -> Vec<f64> {
+    closed_trades(fills, "")
+        .into_iter()
+        .map(|trade| trade.pnl)
+        .filter(|pnl| *pnl != 0.0)
+        .collect()
+}
+
+/// Handle /best and /worst, the five most profitable or most losing closing executions
+async fn send_extreme_trades(
+    bot: &Bot,
+    msg: &Message,
+    pool: &SqlitePool,
+    user_id: i64,
+    args: &str,
+    best: bool,
+) -> ResponseResult<()> {
+    let command = if best { "best" } else { "worst" };
+    let (identifier, days) = split_trailing_days(args);
+    let days = days.unwrap_or(DEFAULT_FILL_STATS_DAYS);
+    if !(1..=MAX_FILL_STATS_DAYS).contains(&days) {
+        bot.send_message(
+            msg.chat.id,
+            format!(
+                "❌ Please provide between 1 and {} days.\n\nUsage: <code>/{} &lt;address|index|note&gt; [days]</code>",
+                MAX_FILL_STATS_DAYS, command
+            ),
+        )
+        .reply_to(msg.id)
+        .parse_mode(ParseMode::Html)
+        .await?;
+        return Ok(());
+    }
+
+    let Some((identifier, assumed)) = identifier_or_last(user_id, identifier) else {
+        bot.send_message(
+            msg.chat.id,
+            format!(
+                "❌ Please provide a wallet address, index (1-10), or note.\n\nUsage: <code>/{} &lt;address|index|note&gt; [days]</code>",
+                command
+            ),
+        )
+        .reply_to(msg.id)
+        .parse_mode(ParseMode::Html)
+        .await?;
+        return Ok(());
+    };
+    let identifier = identifier.as_str();
+
+    let Some((wallet, note)) = resolve_or_raw_address(
+        bot,
+        msg,
+        pool,
+        user_id,
+        identifier,
+        WALLET_NOT_FOUND,
+        "❌ Failed to fetch fills. Please try again.",
+    )
+    .await?
+    else {
+        return Ok(());
+    };
+
+    if assumed {
+        send_assumed_wallet_notice(bot, msg, &wallet, note.as_deref()).await?;
+    }
+
+    let (fills, truncated) =
+        match hyperliquid::fetch_recent_fills(&HTTP_CLIENT, &wallet, days, MAX_FILL_STATS_FILLS)
+            .await
+        {
+            Ok(fills) => fills,
+            Err(e) => {
+                error!("Failed to fetch fills for {}: {}", wallet, e);
+                bot.send_message(msg.chat.id, "❌ Failed to fetch fills. Please try again.")
+                    .reply_to(msg.id)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                return Ok(());
+            }
+        };
+
+    let mut trades: Vec<ClosedTrade> = closed_trades(&fills, &wallet)
+        .into_iter()
+        .filter(|trade| {
+            if best {
+                trade.pnl > 0.0
+            } else {
+                trade.pnl < 0.0
+            }
+        })
+        .collect();
+    if best {
+        trades.sort_by(|a, b| b.pnl.total_cmp(&a.pnl));
+    } else {
+        trades.sort_by(|a, b| a.pnl.total_cmp(&b.pnl));
+    }
+    trades.truncate(EXTREME_TRADES_SHOWN);
+
+    let tz = user_timezone(pool, user_id).await;
+    let title = if best {
+        "🏆 Best Trades"
+    } else {
+        "💀 Worst Trades"
+    };
+    let wallet_display = format_wallet_display(&wallet, note.as_deref(), false);
+    let mut footnote = "<i>Closed PnL from fills before fees, fills of one order combined, \
+                        partial closes count on their own.</i>"
+        .to_string();
+    if truncated {
+        footnote.push_str(&truncated_fills_notice(&fills, &tz));
+    }
+
+    let list = if trades.is_empty() {
+        format!(
+            "<i>No {} closed trades in this period.</i>",
+            if best { "winning" } else { "losing" }
+        )
+    } else {
+        trades
+            .iter()
+            .enumerate()
+            .map(|(i, trade)| {
+                format!(
+                    "{}. <b>{}</b> {} {} · {}{}\n    🕐 {}",
+                    i + 1,
+                    html::escape(&trade.coin),
+                    html::escape(&trade.dir),
+                    hyperliquid::format_size(trade.size),
+                    hyperliquid::format_pnl(trade.pnl),
+                    if trade.liquidated { " 💀" } else { "" },
+                    tz.format_millis(trade.time)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    bot.send_message(
+        msg.chat.id,
+        format!(
+            "<b>{} · {}d</b>\n\n👛 Wallet: {}\n\n{}\n\n{}",
+            title, days, wallet_display, list, footnote
+        ),
+    )
+    .reply_to(msg.id)
+    .parse_mode(ParseMode::Html)
+    .await?;
+    Ok(())
+}
+
+/// Portfolio window name and label of a /roi period keyword
+fn roi_period(keyword: &str) -> Option<(&'static str, &'static str)> {
+    match keyword.to_lowercase().as_str() {
+        "day" | "24h" | "1d" => Some(("day", "24 Hours")),
+        "week" | "7d" => Some(("week", "7 Days")),
+        "month" | "30d" => Some(("month", "30 Days")),
+        "alltime" | "all" => Some(("allTime", "All Time")),
+        _ => None,
+    }
+}
+
+/// The portfolio history of one window for each wallet, in the same order
+async fn fetch_wallet_portfolios(
+    wallets: &[db::TrackedWallet],
+    window: &'static str,
+) -> Vec<anyhow::Result<hyperliquid::PortfolioWindow>> {
+    let handles: Vec<_> = wallets
+        .iter()
+        .map(|w| {
+            let wallet = w.wallet_address.clone();
+            tokio::spawn(async move {
+                hyperliquid::fetch_portfolio(&HTTP_CLIENT, &wallet)
+                    .await?
+                    .into_iter()
+                    .find(|(name, _)| name == window)
+                    .map(|(_, history)| history)
+                    .ok_or_else(|| anyhow::anyhow!("No {} portfolio window", window))
+            })
+        })
+        .collect();
+
+    let mut portfolios = Vec::with_capacity(handles.len());
+    for handle in handles {
+        portfolios.push(handle.await.unwrap_or_else(|e| Err(e.into())));
+    }
+    portfolios
+}
+
+/// Resolve a wallet identifier which can be, in this order:
 /// - An index (1-10) referring to the user's wallet list
 /// - A note name (case-insensitive)
+/// - Part of exactly one note (case-insensitive), several matches resolve to nothing
 /// - A wallet address
+/// - A shortened address ("0x1a2b3c" or "0x1a2b…9f3c") of exactly one tracked wallet
 ///
 /// Returns (wallet_address, note) if found
 async fn resolve_wallet_identifier(
     pool: &SqlitePool,
     user_id: i64,
     identifier: &str,
+) -> anyhow::Result<Option<(String, Option<String>)>> {
+    let resolved = resolve_identifier(pool, user_id, identifier).await?;
+    if let Some((wallet, _)) = &resolved
+        && let Ok(mut last_wallet) = LAST_WALLET.lock()
+    {
+        last_wallet.insert(user_id, (wallet.clone(), Instant::now()));
+    }
+    Ok(resolved)
+}
+
+async fn resolve_identifier(
+    pool: &SqlitePool,
+    user_id: i64,
+    identifier: &str,
 ) -> anyhow::Result<Option<(String, Option<String>)>> {
     // First, try parsing as index (1-10)
     if let Ok(index) = identifier.parse::<usize>()
@@ -555,15 +10140,148 @@ async fn resolve_wallet_identifier(
         return Ok(Some((wallet.wallet_address, wallet.note)));
     }
 
+    // Then part of a note, only when it picks out one wallet; addresses are never read as one
+    let address = normalize_address(identifier);
+    let partial = partial_address(&address);
+    if !is_valid_address(&address)
+        && partial.is_none()
+        && let [(_, wallet)] = db::find_wallets_by_note_part(pool, user_id, identifier)
+            .await?
+            .as_slice()
+    {
+        return Ok(Some((wallet.wallet_address.clone(), wallet.note.clone())));
+    }
+
     // Finally, if it looks like an address, return it as-is
-    if is_valid_address(identifier) {
-        let note = db::get_wallet_note(pool, identifier).await.ok().flatten();
-        return Ok(Some((identifier.to_lowercase(), note)));
+    if is_valid_address(&address) {
+        let note = db::get_wallet_note(pool, user_id, &address)
+            .await
+            .ok()
+            .flatten();
+        return Ok(Some((address.to_lowercase(), note)));
+    }
+
+    // Last, a shortened address of one tracked wallet
+    if let Some((prefix, suffix)) = partial
+        && let [(_, wallet)] = db::find_wallets_by_address_part(pool, user_id, &prefix, &suffix)
+            .await?
+            .as_slice()
+    {
+        return Ok(Some((wallet.wallet_address.clone(), wallet.note.clone())));
     }
 
     Ok(None)
 }
 
+/// Resolve a command's wallet identifier, untracked addresses included.
+///
+/// Replies itself when there's no wallet to use: the not-found reply falls back to
+/// `not_found` (see `wallet_not_found_text`) and `failed` is sent when the lookup errs.
+async fn resolve_or_raw_address(
+    bot: &Bot,
+    msg: &Message,
+    pool: &SqlitePool,
+    user_id: i64,
+    identifier: &str,
+    not_found: &str,
+    failed: &str,
+) -> ResponseResult<Option<(String, Option<String>)>> {
+    match resolve_wallet_identifier(pool, user_id, identifier).await {
+        Ok(Some(wallet)) => Ok(Some(wallet)),
+        Ok(None) => {
+            bot.send_message(
+                msg.chat.id,
+                wallet_not_found_text(pool, user_id, identifier, not_found).await,
+            )
+            .reply_to(msg.id)
+            .parse_mode(ParseMode::Html)
+            .await?;
+            Ok(None)
+        }
+        Err(e) => {
+            error!("Failed to resolve wallet identifier: {}", e);
+            bot.send_message(msg.chat.id, failed)
+                .reply_to(msg.id)
+                .parse_mode(ParseMode::Html)
+                .await?;
+            Ok(None)
+        }
+    }
+}
+
+/// The given identifier, or the wallet from the user's last command if it was left out.
+///
+/// Only for read-only commands, destructive ones must always name their wallet.
+/// The flag is set when the last wallet was used.
+fn identifier_or_last(user_id: i64, identifier: &str) -> Option<(String, bool)> {
+    let identifier = identifier.trim();
+    if !identifier.is_empty() {
+        return Some((identifier.to_string(), false));
+    }
+
+    let mut last_wallet = LAST_WALLET.lock().ok()?;
+    last_wallet.retain(|_, (_, at)| at.elapsed() <= LAST_WALLET_TTL);
+    last_wallet
+        .get(&user_id)
+        .map(|(wallet, _)| (wallet.clone(), true))
+}
+
+/// Tell the user which wallet an argument-less command fell back to
+async fn send_assumed_wallet_notice(
+    bot: &Bot,
+    msg: &Message,
+    wallet: &str,
+    note: Option<&str>,
+) -> ResponseResult<()> {
+    bot.send_message(
+        msg.chat.id,
+        format!(
+            "ℹ️ Using {} from your last command. Pass a wallet to change.",
+            format_wallet_display(wallet, note, false)
+        ),
+    )
+    .reply_to(msg.id)
+    .parse_mode(ParseMode::Html)
+    .await?;
+    Ok(())
+}
+
+/// Confirmation snippet for a wallet a command acted on: its list index and note when
+/// known, then the full address on its own line for copy-paste
+fn format_wallet_confirmation(
+    wallet_address: &str,
+    note: Option<&str>,
+    index: Option<usize>,
+) -> String {
+    let label = match (index, note) {
+        (Some(index), Some(note)) => format!("#{} · {}\n", index, html::escape(note)),
+        (Some(index), None) => format!("#{}\n", index),
+        (None, Some(note)) => format!("{}\n", html::escape(note)),
+        (None, None) => String::new(),
+    };
+    format!(
+        "{}<code>{}</code>",
+        label,
+        html::escape(&wallet_address.to_lowercase())
+    )
+}
+
+/// `format_wallet_confirmation` with the wallet's current index looked up
+async fn wallet_confirmation(
+    pool: &SqlitePool,
+    user_id: i64,
+    wallet_address: &str,
+    note: Option<&str>,
+) -> String {
+    let index = db::get_wallet_index(pool, user_id, wallet_address)
+        .await
+        .unwrap_or_else(|e| {
+            error!("Failed to look up wallet index: {}", e);
+            None
+        });
+    format_wallet_confirmation(wallet_address, note, index)
+}
+
 pub fn format_wallet_display(wallet_address: &str, note: Option<&str>, full: bool) -> String {
     let addr = if full {
         wallet_address.to_string()
@@ -579,3 +10297,159 @@ pub fn format_wallet_display(wallet_address: &str, note: Option<&str>, full: boo
         None => format!("<code>{}</code>", addr),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WALLET: &str = "0xabcdef0123456789abcdef0123456789abcdef01";
+
+    #[test]
+    fn normalizes_explorer_urls() {
+        for url in [
+            "https://hypurrscan.io/address/0xabcdef0123456789abcdef0123456789abcdef01",
+            "https://app.hyperliquid.xyz/explorer/address/0xABCDEF0123456789abcdef0123456789abcdef01",
+            "<https://hyperdash.info/trader/0xabcdef0123456789abcdef0123456789abcdef01?tab=positions>",
+        ] {
+            assert_eq!(normalize_address(url).to_lowercase(), WALLET, "{}", url);
+        }
+    }
+
+    #[test]
+    fn strips_invisible_characters_and_punctuation() {
+        assert_eq!(
+            normalize_address(&format!("\u{200B}{}\u{FEFF}", WALLET)),
+            WALLET
+        );
+        assert_eq!(normalize_address(&format!("`{}`.", WALLET)), WALLET);
+        assert_eq!(normalize_address(&format!("\"{}\",", WALLET)), WALLET);
+        assert_eq!(
+            normalize_address("0Xabcdef0123456789abcdef0123456789abcdef01"),
+            WALLET
+        );
+        // Still invalid addresses come out as parsed, for the error to show
+        assert_eq!(normalize_address("*0xabc*"), "0xabc");
+        assert!(!is_valid_address(&normalize_address("*0xabc*")));
+    }
+
+    const HOSTILE: &str = "<b onclick=\"x\">&</b>";
+
+    fn assert_escaped(text: &str) {
+        assert!(!text.contains(HOSTILE), "unescaped input in {}", text);
+        assert!(
+            text.contains("&lt;b onclick=\"x\"&gt;&amp;&lt;/b&gt;"),
+            "input missing from {}",
+            text
+        );
+    }
+
+    #[test]
+    fn wallet_confirmation_escapes_notes() {
+        let text = format_wallet_confirmation(&WALLET.to_uppercase(), Some(HOSTILE), Some(3));
+        assert_escaped(&text);
+        assert!(text.starts_with("#3 · "));
+        assert!(text.ends_with(&format!(
+            "\n<code>{}</code>",
+            WALLET.to_uppercase().to_lowercase()
+        )));
+        assert_eq!(
+            format_wallet_confirmation(WALLET, None, None),
+            format!("<code>{}</code>", WALLET)
+        );
+        assert_escaped(&format_wallet_display(WALLET, Some(HOSTILE), false));
+    }
+
+    const OTHER: &str = "0x1111111111111111111111111111111111111111";
+    const THIRD: &str = "0x2222222222222222222222222222222222222222";
+
+    async fn resolved(pool: &SqlitePool, identifier: &str) -> Option<String> {
+        resolve_identifier(pool, 1, identifier)
+            .await
+            .unwrap()
+            .map(|(wallet, _)| wallet)
+    }
+
+    #[tokio::test]
+    async fn identifiers_resolve_by_index_note_part_then_address() {
+        let pool = db::tests::test_pool().await;
+        db::add_wallet(&pool, 1, WALLET, Some("2")).await.unwrap();
+        db::add_wallet(&pool, 1, OTHER, Some("Big Whale"))
+            .await
+            .unwrap();
+        db::add_wallet(&pool, 1, THIRD, Some("whale jr"))
+            .await
+            .unwrap();
+
+        // An index wins over a note spelling the same number
+        assert_eq!(resolved(&pool, "2").await.as_deref(), Some(OTHER));
+        // An exact note wins over a part matching several
+        assert_eq!(resolved(&pool, "WHALE JR").await.as_deref(), Some(THIRD));
+        assert_eq!(resolved(&pool, "big").await.as_deref(), Some(OTHER));
+        // A part of several notes is ambiguous
+        assert_eq!(resolved(&pool, "whale").await, None);
+        // Addresses are never read as a note part, tracked or not
+        assert_eq!(
+            resolved(&pool, &WALLET.to_uppercase().replacen("0X", "0x", 1))
+                .await
+                .as_deref(),
+            Some(WALLET)
+        );
+        let untracked = "0x3333333333333333333333333333333333333333";
+        assert_eq!(resolved(&pool, untracked).await.as_deref(), Some(untracked));
+        // Shortened addresses only resolve to tracked wallets
+        assert_eq!(resolved(&pool, "0x1111…1111").await.as_deref(), Some(OTHER));
+        assert_eq!(resolved(&pool, "0x3333…3333").await, None);
+        assert_eq!(resolved(&pool, "nothing").await, None);
+    }
+
+    fn pending_removal(user_id: i64) -> PendingRemoval {
+        PendingRemoval {
+            user_id,
+            targets: Vec::new(),
+            created_at: Instant::now(),
+        }
+    }
+
+    #[test]
+    fn removal_confirmations_are_single_use() {
+        let token = rand::random();
+        PENDING_REMOVALS
+            .lock()
+            .unwrap()
+            .insert(token, pending_removal(1));
+
+        // Someone else pressing the button doesn't use it up
+        assert!(take_pending_removal(token, 2).is_err());
+        let taken = take_pending_removal(token, 1).unwrap().unwrap();
+        assert!(!taken.expired(taken.created_at));
+        assert!(take_pending_removal(token, 1).unwrap().is_none());
+        assert!(take_pending_removal(token, 2).unwrap().is_none());
+    }
+
+    #[test]
+    fn removal_confirmations_expire() {
+        let pending = pending_removal(1);
+        let deadline = pending.created_at + REMOVE_CONFIRM_TTL;
+        assert!(!pending.expired(deadline - Duration::from_secs(5)));
+        assert!(!pending.expired(deadline));
+        assert!(pending.expired(deadline + Duration::from_secs(1)));
+    }
+
+    #[tokio::test]
+    async fn removal_replies_escape_identifiers() {
+        let pool = db::tests::test_pool().await;
+        db::add_wallet(&pool, 1, WALLET, Some(HOSTILE))
+            .await
+            .unwrap();
+        assert_escaped(&wallet_confirmation(&pool, 1, WALLET, Some(HOSTILE)).await);
+
+        // Not found, resolved and repeated identifiers all echo the input
+        let targets = resolve_removals(&pool, 1, &[HOSTILE, "1", "1"]).await;
+        let (text, _) = removal_prompt(1, targets);
+        assert_eq!(text.matches("&lt;b onclick").count(), 2, "{}", text);
+        assert!(!text.contains(HOSTILE), "{}", text);
+        let (text, keyboard) = removal_prompt(1, resolve_removals(&pool, 1, &[HOSTILE]).await);
+        assert!(keyboard.is_some());
+        assert_escaped(&text);
+    }
+}
@@ -0,0 +1,121 @@
+use log::{error, info, warn};
+use sqlx::SqlitePool;
+use std::collections::HashSet;
+use std::sync::LazyLock;
+use teloxide::{prelude::*, types::ParseMode, utils::html};
+use tokio::time::{Duration, sleep};
+
+use crate::db;
+use crate::dry_run;
+
+/// Pause between messages, keeps a broadcast well under Telegram's ~30 messages per second
+const SEND_DELAY: Duration = Duration::from_millis(40);
+
+/// Users allowed to /broadcast, from a comma-separated `ADMIN_IDS`
+static ADMIN_IDS: LazyLock<HashSet<i64>> = LazyLock::new(|| {
+    std::env::var("ADMIN_IDS")
+        .unwrap_or_default()
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter_map(|id| id.trim().parse().ok())
+        .collect()
+});
+
+pub fn is_admin(user_id: i64) -> bool {
+    ADMIN_IDS.contains(&user_id)
+}
+
+/// Every admin id, sorted
+pub fn admin_ids() -> Vec<i64> {
+    let mut ids: Vec<i64> = ADMIN_IDS.iter().copied().collect();
+    ids.sort_unstable();
+    ids
+}
+
+/// Finish broadcasts that were still sending when the bot stopped
+pub async fn resume_broadcasts(pool: SqlitePool, bot: Bot) {
+    let broadcasts = match db::get_unfinished_broadcasts(&pool).await {
+        Ok(broadcasts) => broadcasts,
+        Err(e) => {
+            error!("Failed to fetch unfinished broadcasts: {}", e);
+            return;
+        }
+    };
+
+    for broadcast in broadcasts {
+        info!(
+            "Resuming broadcast {} after user {}",
+            broadcast.id, broadcast.last_user_id
+        );
+        run_broadcast(pool.clone(), bot.clone(), broadcast).await;
+    }
+}
+
+/// Send a broadcast to every remaining recipient, then report the totals to the admin
+pub async fn run_broadcast(pool: SqlitePool, bot: Bot, mut broadcast: db::Broadcast) {
+    let recipients = match db::get_broadcast_recipients(&pool, broadcast.last_user_id).await {
+        Ok(recipients) => recipients,
+        Err(e) => {
+            error!(
+                "Failed to fetch recipients of broadcast {}: {}",
+                broadcast.id, e
+            );
+            return;
+        }
+    };
+
+    let message = format!(
+        "<b>📢 Announcement</b>\n\n{}",
+        html::escape(&broadcast.text)
+    );
+    for user_id in recipients {
+        if dry_run::enabled() {
+            dry_run::record(user_id, &message);
+            broadcast.sent += 1;
+        } else {
+            match bot
+                .send_message(ChatId(user_id), &message)
+                .parse_mode(ParseMode::Html)
+                .await
+            {
+                Ok(_) => broadcast.sent += 1,
+                // Mostly users who blocked the bot
+                Err(e) => {
+                    warn!(
+                        "Failed to send broadcast {} to {}: {}",
+                        broadcast.id, user_id, e
+                    );
+                    broadcast.failed += 1;
+                }
+            }
+        }
+
+        broadcast.last_user_id = user_id;
+        if let Err(e) = db::save_broadcast_progress(&pool, &broadcast).await {
+            error!(
+                "Failed to save progress of broadcast {}: {}",
+                broadcast.id, e
+            );
+        }
+        sleep(SEND_DELAY).await;
+    }
+
+    if let Err(e) = db::finish_broadcast(&pool, broadcast.id).await {
+        error!("Failed to finish broadcast {}: {}", broadcast.id, e);
+    }
+    info!(
+        "Broadcast {} finished: {} sent, {} failed",
+        broadcast.id, broadcast.sent, broadcast.failed
+    );
+
+    let report = format!(
+        "<b>📢 Broadcast finished</b>\n\n✅ Delivered: {}\n❌ Failed: {}",
+        broadcast.sent, broadcast.failed
+    );
+    if let Err(e) = bot
+        .send_message(ChatId(broadcast.admin_chat_id), report)
+        .parse_mode(ParseMode::Html)
+        .await
+    {
+        error!("Failed to report broadcast {}: {}", broadcast.id, e);
+    }
+}
@@ -1,8 +1,14 @@
 use log::info;
+use serde::Serialize;
 use sqlx::{SqlitePool, sqlite::SqlitePoolOptions};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+
+use crate::alerts::BandedAlert;
+use crate::timezone::UserTimezone;
 
 pub const MAX_WALLETS_PER_USER: i64 = 10;
+/// Closed positions kept per wallet for /history
+const MAX_CLOSED_POSITIONS_PER_WALLET: i64 = 200;
 
 pub async fn init_db(database_url: &str) -> anyhow::Result<SqlitePool> {
     let pool = SqlitePoolOptions::new()
@@ -21,6 +27,19 @@ pub struct TrackedWallet {
     pub user_id: i64,
     pub wallet_address: String,
     pub note: Option<String>,
+    /// Still polled and listed, but no notifications are sent for it
+    pub muted: bool,
+    /// Like muted until this time (unix seconds), see `snoozed_for`
+    pub snoozed_until: Option<i64>,
+}
+
+impl TrackedWallet {
+    /// Seconds of snooze left at `now` (unix seconds), `None` once it has run out
+    pub fn snoozed_for(&self, now: i64) -> Option<i64> {
+        self.snoozed_until
+            .map(|until| until - now)
+            .filter(|left| *left > 0)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -35,7 +54,7 @@ pub struct ActivePosition {
 
 pub async fn get_user_wallet_count(pool: &SqlitePool, user_id: i64) -> anyhow::Result<i64> {
     let count = sqlx::query_scalar!(
-        "SELECT COUNT(*) as count FROM tracked_wallets WHERE user_id = ?",
+        "SELECT COUNT(*) as count FROM tracked_wallets WHERE user_id = ? AND removed_at IS NULL",
         user_id
     )
     .fetch_one(pool)
@@ -49,6 +68,15 @@ pub enum AddWalletResult {
     Added,
     Updated,
     AlreadyExistsNoChange,
+    /// Another of the user's wallets already carries the note, nothing was changed
+    NoteInUse,
+}
+
+/// Raised by the tracked_wallets triggers when a user's note is taken
+const NOTE_IN_USE: &str = "note already in use";
+
+fn is_note_in_use(e: &sqlx::Error) -> bool {
+    matches!(e, sqlx::Error::Database(db) if db.message().contains(NOTE_IN_USE))
 }
 
 pub async fn add_wallet(
@@ -75,27 +103,37 @@ pub async fn add_wallet(
                 Ok(AddWalletResult::AlreadyExistsNoChange)
             } else {
                 // Update the note
-                sqlx::query!(
+                let result = sqlx::query!(
                     "UPDATE tracked_wallets SET note = ? WHERE user_id = ? AND wallet_address = ?",
                     note,
                     user_id,
                     wallet_lower
                 )
                 .execute(pool)
-                .await?;
-                Ok(AddWalletResult::Updated)
+                .await;
+                match result {
+                    Ok(_) => Ok(AddWalletResult::Updated),
+                    Err(e) if is_note_in_use(&e) => Ok(AddWalletResult::NoteInUse),
+                    Err(e) => Err(e.into()),
+                }
             }
         }
         None => {
             // Insert new wallet
-            sqlx::query!(
+            let result = sqlx::query!(
                 "INSERT INTO tracked_wallets (user_id, wallet_address, note) VALUES (?, ?, ?)",
                 user_id,
                 wallet_lower,
                 note
             )
             .execute(pool)
-            .await?;
+            .await;
+            match result {
+                Ok(_) => {}
+                Err(e) if is_note_in_use(&e) => return Ok(AddWalletResult::NoteInUse),
+                Err(e) => return Err(e.into()),
+            }
+            start_tracking_period(pool, user_id, &wallet_lower).await?;
             Ok(AddWalletResult::Added)
         }
     }
@@ -115,16 +153,286 @@ pub async fn remove_wallet(
     .execute(pool)
     .await?;
 
+    sqlx::query!(
+        "DELETE FROM logbooks WHERE user_id = ? AND wallet_address = ?",
+        user_id,
+        wallet_lower
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query!(
+        "DELETE FROM notification_threads WHERE user_id = ? AND wallet_address = ?",
+        user_id,
+        wallet_lower
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query!(
+        "DELETE FROM watched_coins WHERE user_id = ? AND wallet_address = ?",
+        user_id,
+        wallet_lower
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query!(
+        "DELETE FROM wallet_tags WHERE user_id = ? AND wallet_address = ?",
+        user_id,
+        wallet_lower
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query!(
+        "DELETE FROM pnl_alerts WHERE user_id = ? AND wallet_address = ?",
+        user_id,
+        wallet_lower
+    )
+    .execute(pool)
+    .await?;
+
+    // Only the tracking row goes, the wallet's history stays for when it is re-added
+    sqlx::query!(
+        "UPDATE tracking_periods SET ended_at = CURRENT_TIMESTAMP WHERE user_id = ? AND wallet_address = ? AND ended_at IS NULL",
+        user_id,
+        wallet_lower
+    )
+    .execute(pool)
+    .await?;
+
     Ok(result.rows_affected() > 0)
 }
 
+/// Result of removing every wallet of a user
+pub struct ClearedWallets {
+    pub removed: usize,
+    /// Removed wallets no other user tracks, their stored positions were dropped too
+    pub orphaned: Vec<String>,
+}
+
+/// Remove all of a user's wallets at once, like `remove_wallet` for each of them
+pub async fn clear_wallets(pool: &SqlitePool, user_id: i64) -> anyhow::Result<ClearedWallets> {
+    let mut tx = pool.begin().await?;
+
+    // Soft-removed wallets go too, they'd otherwise come back on the user's next message
+    let removed = sqlx::query_scalar!(
+        "DELETE FROM tracked_wallets WHERE user_id = ? RETURNING wallet_address",
+        user_id
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    sqlx::query!("DELETE FROM logbooks WHERE user_id = ?", user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query!(
+        "DELETE FROM notification_threads WHERE user_id = ?",
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!("DELETE FROM watched_coins WHERE user_id = ?", user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query!("DELETE FROM wallet_tags WHERE user_id = ?", user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query!("DELETE FROM pnl_alerts WHERE user_id = ?", user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query!(
+        "UPDATE tracking_periods SET ended_at = CURRENT_TIMESTAMP WHERE user_id = ? AND ended_at IS NULL",
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let mut orphaned = Vec::new();
+    for wallet_address in &removed {
+        let tracked = sqlx::query_scalar!(
+            "SELECT COUNT(*) FROM tracked_wallets WHERE wallet_address = ? AND removed_at IS NULL",
+            wallet_address
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        if tracked == 0 {
+            sqlx::query!(
+                "DELETE FROM active_positions WHERE wallet_address = ?",
+                wallet_address
+            )
+            .execute(&mut *tx)
+            .await?;
+            orphaned.push(wallet_address.clone());
+        }
+    }
+
+    tx.commit().await?;
+    Ok(ClearedWallets {
+        removed: removed.len(),
+        orphaned,
+    })
+}
+
+/// Drop the stored positions of a wallet nobody tracks anymore, returns whether it was dropped
+pub async fn forget_orphaned_wallet(
+    pool: &SqlitePool,
+    wallet_address: &str,
+) -> anyhow::Result<bool> {
+    let wallet_lower = wallet_address.to_lowercase();
+    let tracked = sqlx::query_scalar!(
+        "SELECT COUNT(*) FROM tracked_wallets WHERE wallet_address = ? AND removed_at IS NULL",
+        wallet_lower
+    )
+    .fetch_one(pool)
+    .await?;
+    if tracked > 0 {
+        return Ok(false);
+    }
+
+    sqlx::query!(
+        "DELETE FROM active_positions WHERE wallet_address = ?",
+        wallet_lower
+    )
+    .execute(pool)
+    .await?;
+    Ok(true)
+}
+
+async fn start_tracking_period(
+    pool: &SqlitePool,
+    user_id: i64,
+    wallet_address: &str,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        "INSERT INTO tracking_periods (user_id, wallet_address) VALUES (?, ?)",
+        user_id,
+        wallet_address
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// A stretch of time in UTC, like one nobody tracked a wallet
+pub type Gap = (chrono::NaiveDateTime, chrono::NaiveDateTime);
+
+/// Stretches between tracking periods that none of them covers, oldest first.
+///
+/// Periods are (start, end) in SQLite's CURRENT_TIMESTAMP format ordered by start,
+/// an end of `None` is still open.
+fn tracking_gaps(periods: impl IntoIterator<Item = (String, Option<String>)>) -> Vec<Gap> {
+    let parse = |time: &str| chrono::NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M:%S").ok();
+
+    let mut gaps = Vec::new();
+    let mut covered_until: Option<chrono::NaiveDateTime> = None;
+    for (started_at, ended_at) in periods {
+        let Some(started_at) = parse(&started_at) else {
+            continue;
+        };
+        if let Some(until) = covered_until
+            && started_at > until
+        {
+            gaps.push((until, started_at));
+        }
+        // An open period covers everything from here on
+        let Some(ended_at) = ended_at.as_deref().and_then(parse) else {
+            break;
+        };
+        covered_until = Some(covered_until.map_or(ended_at, |until| until.max(ended_at)));
+    }
+    gaps
+}
+
+/// Stretches (UTC, oldest first) between the first and latest tracking of a wallet when no
+/// user tracked it
+pub async fn get_tracking_gaps(
+    pool: &SqlitePool,
+    wallet_address: &str,
+) -> anyhow::Result<Vec<Gap>> {
+    let wallet_lower = wallet_address.to_lowercase();
+    let periods = sqlx::query!(
+        r#"SELECT started_at as "started_at!: String", ended_at as "ended_at: String" FROM tracking_periods
+           WHERE wallet_address = ? ORDER BY started_at"#,
+        wallet_lower
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(tracking_gaps(
+        periods.into_iter().map(|p| (p.started_at, p.ended_at)),
+    ))
+}
+
+/// Stretches (UTC, oldest first) a user stopped tracking each of their wallets in between,
+/// wallets without any are left out
+pub async fn get_user_tracking_gaps(
+    pool: &SqlitePool,
+    user_id: i64,
+) -> anyhow::Result<HashMap<String, Vec<Gap>>> {
+    let periods = sqlx::query!(
+        r#"SELECT wallet_address, started_at as "started_at!: String", ended_at as "ended_at: String"
+           FROM tracking_periods WHERE user_id = ? ORDER BY started_at"#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut by_wallet: HashMap<String, Vec<(String, Option<String>)>> = HashMap::new();
+    for period in periods {
+        by_wallet
+            .entry(period.wallet_address)
+            .or_default()
+            .push((period.started_at, period.ended_at));
+    }
+    Ok(by_wallet
+        .into_iter()
+        .map(|(wallet, periods)| (wallet, tracking_gaps(periods)))
+        .filter(|(_, gaps)| !gaps.is_empty())
+        .collect())
+}
+
+/// The parts of gaps after `since`
+pub fn gaps_since(gaps: &[Gap], since: chrono::NaiveDateTime) -> Vec<Gap> {
+    gaps.iter()
+        .filter(|(_, end)| *end > since)
+        .map(|(start, end)| (*start.max(&since), *end))
+        .collect()
+}
+
+/// Drop the stored positions of wallets nobody tracks anymore.
+///
+/// A wallet re-added later starts from a fresh snapshot instead of diffing against
+/// positions from before the gap, which would record closes that happened unseen.
+pub async fn delete_wallet_positions(
+    pool: &SqlitePool,
+    wallet_address: &str,
+) -> anyhow::Result<()> {
+    let wallet_lower = wallet_address.to_lowercase();
+    sqlx::query!(
+        "DELETE FROM active_positions WHERE wallet_address = ?",
+        wallet_lower
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
 pub async fn get_user_wallets(
     pool: &SqlitePool,
     user_id: i64,
 ) -> anyhow::Result<Vec<TrackedWallet>> {
     let wallets = sqlx::query_as!(
         TrackedWallet,
-        r#"SELECT user_id as "user_id!: i64", wallet_address, note FROM tracked_wallets WHERE user_id = ? ORDER BY id"#,
+        r#"SELECT user_id as "user_id!: i64", wallet_address, note, muted as "muted!: bool", snoozed_until FROM tracked_wallets WHERE user_id = ? AND removed_at IS NULL ORDER BY id"#,
         user_id
     )
     .fetch_all(pool)
@@ -133,10 +441,61 @@ pub async fn get_user_wallets(
     Ok(wallets)
 }
 
+/// A user's tracked wallets with their row ids, in /list order.
+///
+/// Row ids stay the same while wallets around them are removed, unlike indexes.
+pub async fn get_user_wallets_with_ids(
+    pool: &SqlitePool,
+    user_id: i64,
+) -> anyhow::Result<Vec<(i64, TrackedWallet)>> {
+    let rows = sqlx::query!(
+        r#"SELECT id as "id!: i64", user_id as "user_id!: i64", wallet_address, note, muted as "muted!: bool", snoozed_until
+           FROM tracked_wallets WHERE user_id = ? AND removed_at IS NULL ORDER BY id"#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|r| {
+            (
+                r.id,
+                TrackedWallet {
+                    user_id: r.user_id,
+                    wallet_address: r.wallet_address,
+                    note: r.note,
+                    muted: r.muted,
+                    snoozed_until: r.snoozed_until,
+                },
+            )
+        })
+        .collect())
+}
+
+/// One of a user's tracked wallets by row id, `None` once it was removed
+pub async fn get_wallet_by_id(
+    pool: &SqlitePool,
+    user_id: i64,
+    id: i64,
+) -> anyhow::Result<Option<TrackedWallet>> {
+    let wallet = sqlx::query_as!(
+        TrackedWallet,
+        r#"SELECT user_id as "user_id!: i64", wallet_address, note, muted as "muted!: bool", snoozed_until
+           FROM tracked_wallets WHERE id = ? AND user_id = ? AND removed_at IS NULL"#,
+        id,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(wallet)
+}
+
 pub async fn get_all_tracked_wallets(pool: &SqlitePool) -> anyhow::Result<Vec<TrackedWallet>> {
     let wallets = sqlx::query_as!(
         TrackedWallet,
-        r#"SELECT user_id as "user_id!: i64", wallet_address, note FROM tracked_wallets"#
+        r#"SELECT user_id as "user_id!: i64", wallet_address, note, muted as "muted!: bool", snoozed_until FROM tracked_wallets WHERE removed_at IS NULL"#
     )
     .fetch_all(pool)
     .await?;
@@ -217,14 +576,16 @@ pub async fn delete_position(
     Ok(())
 }
 
-/// Get the note for a wallet address (for any user)
+/// Get a user's note for a wallet address
 pub async fn get_wallet_note(
     pool: &SqlitePool,
+    user_id: i64,
     wallet_address: &str,
 ) -> anyhow::Result<Option<String>> {
     let wallet_lower = wallet_address.to_lowercase();
     let result = sqlx::query_scalar!(
-        "SELECT note FROM tracked_wallets WHERE wallet_address = ? LIMIT 1",
+        "SELECT note FROM tracked_wallets WHERE user_id = ? AND wallet_address = ? AND removed_at IS NULL",
+        user_id,
         wallet_lower
     )
     .fetch_optional(pool)
@@ -245,7 +606,7 @@ pub async fn note_exists_for_user(
         Some(wallet) => {
             let wallet_lower = wallet.to_lowercase();
             sqlx::query_scalar!(
-                "SELECT COUNT(*) as count FROM tracked_wallets WHERE user_id = ? AND LOWER(note) = ? AND wallet_address != ?",
+                "SELECT COUNT(*) as count FROM tracked_wallets WHERE user_id = ? AND LOWER(note) = ? AND wallet_address != ? AND removed_at IS NULL",
                 user_id,
                 note_lower,
                 wallet_lower
@@ -254,7 +615,7 @@ pub async fn note_exists_for_user(
             .await?
         }
         None => sqlx::query_scalar!(
-            "SELECT COUNT(*) as count FROM tracked_wallets WHERE user_id = ? AND LOWER(note) = ?",
+            "SELECT COUNT(*) as count FROM tracked_wallets WHERE user_id = ? AND LOWER(note) = ? AND removed_at IS NULL",
             user_id,
             note_lower
         )
@@ -277,7 +638,7 @@ pub async fn get_wallet_by_index(
     let offset = (index - 1) as i64;
     let wallet = sqlx::query_as!(
         TrackedWallet,
-        r#"SELECT user_id as "user_id!: i64", wallet_address, note FROM tracked_wallets WHERE user_id = ? ORDER BY id LIMIT 1 OFFSET ?"#,
+        r#"SELECT user_id as "user_id!: i64", wallet_address, note, muted as "muted!: bool", snoozed_until FROM tracked_wallets WHERE user_id = ? AND removed_at IS NULL ORDER BY id LIMIT 1 OFFSET ?"#,
         user_id,
         offset
     )
@@ -287,6 +648,176 @@ pub async fn get_wallet_by_index(
     Ok(wallet)
 }
 
+/// Position (1-based) of a tracked wallet in the user's list, the inverse of `get_wallet_by_index`
+pub async fn get_wallet_index(
+    pool: &SqlitePool,
+    user_id: i64,
+    wallet_address: &str,
+) -> anyhow::Result<Option<usize>> {
+    let wallet_lower = wallet_address.to_lowercase();
+    let index = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) as "count!: i64" FROM tracked_wallets
+           WHERE user_id = ? AND removed_at IS NULL AND id <= (
+               SELECT id FROM tracked_wallets WHERE user_id = ? AND wallet_address = ? AND removed_at IS NULL
+           )"#,
+        user_id,
+        user_id,
+        wallet_lower
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok((index > 0).then_some(index as usize))
+}
+
+/// Escape LIKE wildcards so user input only matches literally (with `ESCAPE '\'`)
+fn escape_like(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// A user's wallets whose note contains `text` or whose address starts or ends with it,
+/// with their 1-based index. A shortened address like "0x1a2b…9f3c" matches on both ends.
+pub async fn search_user_wallets(
+    pool: &SqlitePool,
+    user_id: i64,
+    text: &str,
+) -> anyhow::Result<Vec<(usize, TrackedWallet)>> {
+    let text = text.trim();
+    let note_pattern = format!("%{}%", escape_like(text));
+    let lower = text.to_lowercase();
+    let (prefix_pattern, suffix_pattern) =
+        match lower.split_once('…').or_else(|| lower.split_once("...")) {
+            Some((start, end)) => {
+                let pattern = format!("{}%{}", escape_like(start.trim()), escape_like(end.trim()));
+                (pattern.clone(), pattern)
+            }
+            None => (
+                format!("{}%", escape_like(&lower)),
+                format!("%{}", escape_like(&lower)),
+            ),
+        };
+
+    let rows = sqlx::query!(
+        r#"SELECT idx as "idx!: i64", user_id as "user_id!: i64", wallet_address as "wallet_address!: String",
+                  note, muted as "muted!: bool", snoozed_until
+           FROM (
+               SELECT ROW_NUMBER() OVER (ORDER BY id) as idx, user_id, wallet_address, note, muted, snoozed_until
+               FROM tracked_wallets WHERE user_id = ? AND removed_at IS NULL
+           )
+           WHERE note LIKE ? ESCAPE '\' OR wallet_address LIKE ? ESCAPE '\' OR wallet_address LIKE ? ESCAPE '\'
+           ORDER BY idx"#,
+        user_id,
+        note_pattern,
+        prefix_pattern,
+        suffix_pattern
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|r| {
+            (
+                r.idx as usize,
+                TrackedWallet {
+                    user_id: r.user_id,
+                    wallet_address: r.wallet_address,
+                    note: r.note,
+                    muted: r.muted,
+                    snoozed_until: r.snoozed_until,
+                },
+            )
+        })
+        .collect())
+}
+
+/// A user's wallets whose note contains `part` (case-insensitive), with their 1-based index
+pub async fn find_wallets_by_note_part(
+    pool: &SqlitePool,
+    user_id: i64,
+    part: &str,
+) -> anyhow::Result<Vec<(usize, TrackedWallet)>> {
+    let part = part.trim();
+    if part.is_empty() {
+        return Ok(Vec::new());
+    }
+    let pattern = format!("%{}%", escape_like(part));
+
+    let rows = sqlx::query!(
+        r#"SELECT idx as "idx!: i64", user_id as "user_id!: i64", wallet_address as "wallet_address!: String",
+                  note, muted as "muted!: bool", snoozed_until
+           FROM (
+               SELECT ROW_NUMBER() OVER (ORDER BY id) as idx, user_id, wallet_address, note, muted, snoozed_until
+               FROM tracked_wallets WHERE user_id = ? AND removed_at IS NULL
+           )
+           WHERE note LIKE ? ESCAPE '\'
+           ORDER BY idx"#,
+        user_id,
+        pattern
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|r| {
+            (
+                r.idx as usize,
+                TrackedWallet {
+                    user_id: r.user_id,
+                    wallet_address: r.wallet_address,
+                    note: r.note,
+                    muted: r.muted,
+                    snoozed_until: r.snoozed_until,
+                },
+            )
+        })
+        .collect())
+}
+
+/// A user's wallets whose address starts with `prefix` and ends with `suffix`, with their 1-based index
+pub async fn find_wallets_by_address_part(
+    pool: &SqlitePool,
+    user_id: i64,
+    prefix: &str,
+    suffix: &str,
+) -> anyhow::Result<Vec<(usize, TrackedWallet)>> {
+    let pattern = format!("{}%{}", escape_like(prefix), escape_like(suffix));
+
+    let rows = sqlx::query!(
+        r#"SELECT idx as "idx!: i64", user_id as "user_id!: i64", wallet_address as "wallet_address!: String",
+                  note, muted as "muted!: bool", snoozed_until
+           FROM (
+               SELECT ROW_NUMBER() OVER (ORDER BY id) as idx, user_id, wallet_address, note, muted, snoozed_until
+               FROM tracked_wallets WHERE user_id = ? AND removed_at IS NULL
+           )
+           WHERE wallet_address LIKE ? ESCAPE '\'
+           ORDER BY idx"#,
+        user_id,
+        pattern
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|r| {
+            (
+                r.idx as usize,
+                TrackedWallet {
+                    user_id: r.user_id,
+                    wallet_address: r.wallet_address,
+                    note: r.note,
+                    muted: r.muted,
+                    snoozed_until: r.snoozed_until,
+                },
+            )
+        })
+        .collect())
+}
+
 /// Get wallet by note (case-insensitive) for a user
 pub async fn get_wallet_by_note(
     pool: &SqlitePool,
@@ -296,7 +827,7 @@ pub async fn get_wallet_by_note(
     let note_lower = note.to_lowercase();
     let wallet = sqlx::query_as!(
         TrackedWallet,
-        r#"SELECT user_id as "user_id!: i64", wallet_address, note FROM tracked_wallets WHERE user_id = ? AND LOWER(note) = ?"#,
+        r#"SELECT user_id as "user_id!: i64", wallet_address, note, muted as "muted!: bool", snoozed_until FROM tracked_wallets WHERE user_id = ? AND LOWER(note) = ? AND removed_at IS NULL"#,
         user_id,
         note_lower
     )
@@ -305,3 +836,2753 @@ pub async fn get_wallet_by_note(
 
     Ok(wallet)
 }
+
+/// Check whether a user is tracking a wallet
+pub async fn is_tracking(
+    pool: &SqlitePool,
+    user_id: i64,
+    wallet_address: &str,
+) -> anyhow::Result<bool> {
+    let wallet_lower = wallet_address.to_lowercase();
+    let count = sqlx::query_scalar!(
+        "SELECT COUNT(*) as count FROM tracked_wallets WHERE user_id = ? AND wallet_address = ? AND removed_at IS NULL",
+        user_id,
+        wallet_lower
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(count > 0)
+}
+
+#[derive(Debug, Clone)]
+pub struct Logbook {
+    pub user_id: i64,
+    pub wallet_address: String,
+    pub pin: bool,
+    pub message_id: Option<i64>,
+    pub body: String,
+}
+
+/// Enable logbook mode for a wallet, keeping the current logbook message if there is one
+pub async fn enable_logbook(
+    pool: &SqlitePool,
+    user_id: i64,
+    wallet_address: &str,
+    pin: bool,
+) -> anyhow::Result<()> {
+    let wallet_lower = wallet_address.to_lowercase();
+    sqlx::query!(
+        r#"INSERT INTO logbooks (user_id, wallet_address, pin)
+           VALUES (?, ?, ?)
+           ON CONFLICT(user_id, wallet_address) DO UPDATE SET pin = excluded.pin"#,
+        user_id,
+        wallet_lower,
+        pin
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Disable logbook mode for a wallet
+pub async fn disable_logbook(
+    pool: &SqlitePool,
+    user_id: i64,
+    wallet_address: &str,
+) -> anyhow::Result<bool> {
+    let wallet_lower = wallet_address.to_lowercase();
+    let result = sqlx::query!(
+        "DELETE FROM logbooks WHERE user_id = ? AND wallet_address = ?",
+        user_id,
+        wallet_lower
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Get the logbook for a wallet, if logbook mode is enabled
+pub async fn get_logbook(
+    pool: &SqlitePool,
+    user_id: i64,
+    wallet_address: &str,
+) -> anyhow::Result<Option<Logbook>> {
+    let wallet_lower = wallet_address.to_lowercase();
+    let logbook = sqlx::query_as!(
+        Logbook,
+        r#"SELECT user_id as "user_id!: i64", wallet_address, pin as "pin!: bool", message_id, body FROM logbooks WHERE user_id = ? AND wallet_address = ?"#,
+        user_id,
+        wallet_lower
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(logbook)
+}
+
+/// Store the message currently holding a logbook along with its entries
+pub async fn update_logbook_message(
+    pool: &SqlitePool,
+    user_id: i64,
+    wallet_address: &str,
+    message_id: i64,
+    body: &str,
+) -> anyhow::Result<()> {
+    let wallet_lower = wallet_address.to_lowercase();
+    sqlx::query!(
+        "UPDATE logbooks SET message_id = ?, body = ? WHERE user_id = ? AND wallet_address = ?",
+        message_id,
+        body,
+        user_id,
+        wallet_lower
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub const DEFAULT_MIRROR_BANKROLL: f64 = 1000.0;
+pub const DEFAULT_MIRROR_TOLERANCE_PCT: f64 = 0.5;
+pub const DEFAULT_LIQUIDATION_PROXIMITY_PCT: f64 = 5.0;
+pub const DEFAULT_MARGIN_BANDS: &str = "60,80,90";
+/// Points below a margin band its alert re-arms at
+pub const MARGIN_BAND_HYSTERESIS: f64 = 5.0;
+/// 08:00 on the user's clock
+pub const DEFAULT_DIGEST_MINUTE: i64 = 8 * 60;
+
+#[derive(Debug, Clone)]
+pub struct UserSettings {
+    pub mirror_enabled: bool,
+    pub mirror_bankroll: f64,
+    pub mirror_tolerance_pct: f64,
+    pub margin_alerts: bool,
+    pub weekly_suggestions: bool,
+    pub threaded_notifications: bool,
+    /// Send notifications as one line like logbook entries
+    pub compact_notifications: bool,
+    /// Opens and size changes of positions worth less than this (USD) aren't notified
+    pub min_notional: f64,
+    /// Warn when the mark gets this close (percent) to a liquidation price, 0 when off
+    pub liquidation_proximity_pct: f64,
+    pub daily_digest: bool,
+    /// Minutes after midnight in the user's timezone the daily digest is sent at
+    pub digest_minute: i64,
+    /// IANA zone or fixed offset times are shown in, see `UserTimezone::parse`
+    pub timezone: String,
+    /// Ask with buttons before /remove deletes a wallet
+    pub confirm_remove: bool,
+    /// Margin usage thresholds (percent) like "60,80,90", see `margin_bands`
+    pub margin_bands: String,
+    /// Set by /pause, see `paused_at`
+    pub notifications_paused: bool,
+    /// Unix timestamp the pause ends at, `None` pauses until /resume
+    pub paused_until: Option<i64>,
+}
+
+impl UserSettings {
+    pub fn tz(&self) -> UserTimezone {
+        UserTimezone::parse(&self.timezone).unwrap_or_default()
+    }
+
+    /// The user's margin alert bands, the defaults if the stored ones don't parse
+    pub fn margin_bands(&self) -> BandedAlert {
+        BandedAlert::parse_percent_bands(&self.margin_bands, MARGIN_BAND_HYSTERESIS)
+            .or_else(|_| {
+                BandedAlert::parse_percent_bands(DEFAULT_MARGIN_BANDS, MARGIN_BAND_HYSTERESIS)
+            })
+            .expect("default margin bands are valid")
+    }
+
+    /// Whether notifications are paused at `now` (unix seconds)
+    pub fn paused_at(&self, now: i64) -> bool {
+        self.notifications_paused && self.paused_until.is_none_or(|until| until > now)
+    }
+}
+
+impl Default for UserSettings {
+    /// Settings for a user who has never changed anything, matching the table defaults
+    fn default() -> Self {
+        Self {
+            mirror_enabled: false,
+            mirror_bankroll: DEFAULT_MIRROR_BANKROLL,
+            mirror_tolerance_pct: DEFAULT_MIRROR_TOLERANCE_PCT,
+            margin_alerts: false,
+            weekly_suggestions: false,
+            threaded_notifications: false,
+            compact_notifications: false,
+            min_notional: 0.0,
+            liquidation_proximity_pct: DEFAULT_LIQUIDATION_PROXIMITY_PCT,
+            daily_digest: false,
+            digest_minute: DEFAULT_DIGEST_MINUTE,
+            timezone: "UTC".to_string(),
+            confirm_remove: true,
+            margin_bands: DEFAULT_MARGIN_BANDS.to_string(),
+            notifications_paused: false,
+            paused_until: None,
+        }
+    }
+}
+
+/// Get a user's settings, falling back to defaults if they have none stored
+pub async fn get_user_settings(pool: &SqlitePool, user_id: i64) -> anyhow::Result<UserSettings> {
+    let settings = sqlx::query_as!(
+        UserSettings,
+        r#"SELECT mirror_enabled as "mirror_enabled!: bool", mirror_bankroll, mirror_tolerance_pct, margin_alerts as "margin_alerts!: bool", weekly_suggestions as "weekly_suggestions!: bool", threaded_notifications as "threaded_notifications!: bool", compact_notifications as "compact_notifications!: bool", min_notional, liquidation_proximity_pct, daily_digest as "daily_digest!: bool", digest_minute, timezone, confirm_remove as "confirm_remove!: bool", margin_bands, notifications_paused as "notifications_paused!: bool", paused_until FROM user_settings WHERE user_id = ?"#,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(settings.unwrap_or_default())
+}
+
+pub async fn set_mirror_enabled(
+    pool: &SqlitePool,
+    user_id: i64,
+    enabled: bool,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        r#"INSERT INTO user_settings (user_id, mirror_enabled) VALUES (?, ?)
+           ON CONFLICT(user_id) DO UPDATE SET mirror_enabled = excluded.mirror_enabled"#,
+        user_id,
+        enabled
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn set_mirror_bankroll(
+    pool: &SqlitePool,
+    user_id: i64,
+    bankroll: f64,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        r#"INSERT INTO user_settings (user_id, mirror_bankroll) VALUES (?, ?)
+           ON CONFLICT(user_id) DO UPDATE SET mirror_bankroll = excluded.mirror_bankroll"#,
+        user_id,
+        bankroll
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn set_mirror_tolerance(
+    pool: &SqlitePool,
+    user_id: i64,
+    tolerance_pct: f64,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        r#"INSERT INTO user_settings (user_id, mirror_tolerance_pct) VALUES (?, ?)
+           ON CONFLICT(user_id) DO UPDATE SET mirror_tolerance_pct = excluded.mirror_tolerance_pct"#,
+        user_id,
+        tolerance_pct
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Record that a user interacted with the bot.
+///
+/// Resets the inactivity clock and restores any wallets that were soft-removed
+/// by the inactivity cleanup. Returns the number of restored wallets.
+pub async fn touch_user(pool: &SqlitePool, user_id: i64) -> anyhow::Result<u64> {
+    sqlx::query!(
+        r#"INSERT INTO users (user_id, last_seen) VALUES (?, CURRENT_TIMESTAMP)
+           ON CONFLICT(user_id) DO UPDATE SET
+             last_seen = CURRENT_TIMESTAMP,
+             inactivity_warned_at = NULL"#,
+        user_id
+    )
+    .execute(pool)
+    .await?;
+
+    let restored_wallets = sqlx::query_scalar!(
+        "UPDATE tracked_wallets SET removed_at = NULL WHERE user_id = ? AND removed_at IS NOT NULL RETURNING wallet_address",
+        user_id
+    )
+    .fetch_all(pool)
+    .await?;
+    for wallet_address in &restored_wallets {
+        start_tracking_period(pool, user_id, wallet_address).await?;
+    }
+
+    Ok(restored_wallets.len() as u64)
+}
+
+/// Users with tracked wallets who haven't been seen for `inactive_days` and weren't warned yet
+pub async fn get_users_to_warn(pool: &SqlitePool, inactive_days: i64) -> anyhow::Result<Vec<i64>> {
+    let cutoff = format!("-{} days", inactive_days);
+    let users = sqlx::query_scalar!(
+        r#"SELECT u.user_id as "user_id!: i64" FROM users u
+           WHERE u.last_seen < datetime('now', ?)
+             AND u.inactivity_warned_at IS NULL
+             AND EXISTS (SELECT 1 FROM tracked_wallets w WHERE w.user_id = u.user_id AND w.removed_at IS NULL)"#,
+        cutoff
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(users)
+}
+
+pub async fn mark_user_warned(pool: &SqlitePool, user_id: i64) -> anyhow::Result<()> {
+    sqlx::query!(
+        "UPDATE users SET inactivity_warned_at = CURRENT_TIMESTAMP WHERE user_id = ?",
+        user_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Users who were warned at least `grace_days` ago and stayed inactive since
+pub async fn get_users_to_clean_up(pool: &SqlitePool, grace_days: i64) -> anyhow::Result<Vec<i64>> {
+    let cutoff = format!("-{} days", grace_days);
+    let users = sqlx::query_scalar!(
+        r#"SELECT user_id as "user_id!: i64" FROM users
+           WHERE inactivity_warned_at IS NOT NULL
+             AND inactivity_warned_at < datetime('now', ?)
+             AND EXISTS (SELECT 1 FROM tracked_wallets w WHERE w.user_id = users.user_id AND w.removed_at IS NULL)"#,
+        cutoff
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(users)
+}
+
+/// Soft-remove all of a user's wallets so they are no longer polled
+pub async fn soft_remove_user_wallets(pool: &SqlitePool, user_id: i64) -> anyhow::Result<u64> {
+    let result = sqlx::query!(
+        "UPDATE tracked_wallets SET removed_at = CURRENT_TIMESTAMP WHERE user_id = ? AND removed_at IS NULL",
+        user_id
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query!(
+        "UPDATE tracking_periods SET ended_at = CURRENT_TIMESTAMP WHERE user_id = ? AND ended_at IS NULL",
+        user_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+pub async fn set_margin_alerts(
+    pool: &SqlitePool,
+    user_id: i64,
+    enabled: bool,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        r#"INSERT INTO user_settings (user_id, margin_alerts) VALUES (?, ?)
+           ON CONFLICT(user_id) DO UPDATE SET margin_alerts = excluded.margin_alerts"#,
+        user_id,
+        enabled
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn set_margin_bands(
+    pool: &SqlitePool,
+    user_id: i64,
+    bands: &BandedAlert,
+) -> anyhow::Result<()> {
+    let bands = bands.to_setting();
+    sqlx::query!(
+        r#"INSERT INTO user_settings (user_id, margin_bands) VALUES (?, ?)
+           ON CONFLICT(user_id) DO UPDATE SET margin_bands = excluded.margin_bands"#,
+        user_id,
+        bands
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn set_weekly_suggestions(
+    pool: &SqlitePool,
+    user_id: i64,
+    enabled: bool,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        r#"INSERT INTO user_settings (user_id, weekly_suggestions) VALUES (?, ?)
+           ON CONFLICT(user_id) DO UPDATE SET weekly_suggestions = excluded.weekly_suggestions"#,
+        user_id,
+        enabled
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn set_threaded_notifications(
+    pool: &SqlitePool,
+    user_id: i64,
+    enabled: bool,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        r#"INSERT INTO user_settings (user_id, threaded_notifications) VALUES (?, ?)
+           ON CONFLICT(user_id) DO UPDATE SET threaded_notifications = excluded.threaded_notifications"#,
+        user_id,
+        enabled
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn set_min_notional(
+    pool: &SqlitePool,
+    user_id: i64,
+    min_notional: f64,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        r#"INSERT INTO user_settings (user_id, min_notional) VALUES (?, ?)
+           ON CONFLICT(user_id) DO UPDATE SET min_notional = excluded.min_notional"#,
+        user_id,
+        min_notional
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn set_liquidation_proximity(
+    pool: &SqlitePool,
+    user_id: i64,
+    pct: f64,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        r#"INSERT INTO user_settings (user_id, liquidation_proximity_pct) VALUES (?, ?)
+           ON CONFLICT(user_id) DO UPDATE SET liquidation_proximity_pct = excluded.liquidation_proximity_pct"#,
+        user_id,
+        pct
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn set_compact_notifications(
+    pool: &SqlitePool,
+    user_id: i64,
+    enabled: bool,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        r#"INSERT INTO user_settings (user_id, compact_notifications) VALUES (?, ?)
+           ON CONFLICT(user_id) DO UPDATE SET compact_notifications = excluded.compact_notifications"#,
+        user_id,
+        enabled
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn set_confirm_remove(
+    pool: &SqlitePool,
+    user_id: i64,
+    enabled: bool,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        r#"INSERT INTO user_settings (user_id, confirm_remove) VALUES (?, ?)
+           ON CONFLICT(user_id) DO UPDATE SET confirm_remove = excluded.confirm_remove"#,
+        user_id,
+        enabled
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Message id of the latest notification sent to a user about a wallet, if sent within `max_age_hours`
+pub async fn get_thread_message(
+    pool: &SqlitePool,
+    user_id: i64,
+    wallet_address: &str,
+    max_age_hours: i64,
+) -> anyhow::Result<Option<i64>> {
+    let wallet_lower = wallet_address.to_lowercase();
+    let cutoff = format!("-{} hours", max_age_hours);
+    let message_id = sqlx::query_scalar!(
+        r#"SELECT message_id as "message_id!: i64" FROM notification_threads
+           WHERE user_id = ? AND wallet_address = ? AND sent_at >= datetime('now', ?)"#,
+        user_id,
+        wallet_lower,
+        cutoff
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(message_id)
+}
+
+pub async fn set_thread_message(
+    pool: &SqlitePool,
+    user_id: i64,
+    wallet_address: &str,
+    message_id: i64,
+) -> anyhow::Result<()> {
+    let wallet_lower = wallet_address.to_lowercase();
+    sqlx::query!(
+        r#"INSERT INTO notification_threads (user_id, wallet_address, message_id, sent_at)
+           VALUES (?, ?, ?, CURRENT_TIMESTAMP)
+           ON CONFLICT(user_id, wallet_address) DO UPDATE SET
+               message_id = excluded.message_id, sent_at = excluded.sent_at"#,
+        user_id,
+        wallet_lower,
+        message_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Users with weekly suggestions on who haven't received any in the last 7 days
+pub async fn get_users_due_suggestions(pool: &SqlitePool) -> anyhow::Result<Vec<i64>> {
+    let users = sqlx::query_scalar!(
+        r#"SELECT user_id as "user_id!: i64" FROM user_settings
+           WHERE weekly_suggestions = 1
+             AND (suggestions_sent_at IS NULL OR suggestions_sent_at <= datetime('now', '-7 days'))"#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(users)
+}
+
+pub async fn mark_suggestions_sent(pool: &SqlitePool, user_id: i64) -> anyhow::Result<()> {
+    sqlx::query!(
+        "UPDATE user_settings SET suggestions_sent_at = CURRENT_TIMESTAMP WHERE user_id = ?",
+        user_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Turn the daily digest on or off, optionally moving it to another time.
+///
+/// Turning it on counts as a digest sent now, so the first one covers the time since
+/// and doesn't go out immediately when today's time has already passed.
+pub async fn set_daily_digest(
+    pool: &SqlitePool,
+    user_id: i64,
+    enabled: bool,
+    digest_minute: Option<i64>,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        r#"INSERT INTO user_settings (user_id, daily_digest, digest_minute, digest_sent_at)
+           VALUES (?1, ?2, COALESCE(?3, ?4), CURRENT_TIMESTAMP)
+           ON CONFLICT(user_id) DO UPDATE SET
+               digest_sent_at = CASE WHEN daily_digest = 0 AND excluded.daily_digest = 1
+                                     THEN CURRENT_TIMESTAMP ELSE digest_sent_at END,
+               daily_digest = excluded.daily_digest,
+               digest_minute = COALESCE(?3, digest_minute)"#,
+        user_id,
+        enabled,
+        digest_minute,
+        DEFAULT_DIGEST_MINUTE
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// A user whose daily digest is due
+#[derive(Debug, Clone)]
+pub struct DueDigest {
+    pub user_id: i64,
+    /// When the last digest went out, the new one covers the time since
+    pub since: String,
+    /// The user's local date the digest is for, see `mark_digest_sent`
+    pub date: String,
+}
+
+/// Users with wallets whose digest time has passed since their last digest.
+///
+/// The time is on each user's own clock, so whether it has passed is worked out here
+/// rather than in SQL. A user who already got the digest for that date isn't due again,
+/// so moving the time to later in the day takes effect the next day.
+pub async fn get_users_due_digest(pool: &SqlitePool) -> anyhow::Result<Vec<DueDigest>> {
+    let rows = sqlx::query!(
+        r#"SELECT user_id as "user_id!: i64", digest_minute, timezone, digest_sent_date,
+                  COALESCE(digest_sent_at, datetime('now', '-1 day')) as "since!: String"
+           FROM user_settings
+           WHERE daily_digest = 1
+             AND EXISTS (SELECT 1 FROM tracked_wallets
+                         WHERE tracked_wallets.user_id = user_settings.user_id AND removed_at IS NULL)"#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let now = chrono::Utc::now();
+    Ok(rows
+        .into_iter()
+        .filter_map(|r| {
+            let tz = UserTimezone::parse(&r.timezone).unwrap_or_default();
+            let scheduled = tz.last_occurrence(r.digest_minute, now);
+            let date = tz.format_date(scheduled);
+            let passed = chrono::NaiveDateTime::parse_from_str(&r.since, "%Y-%m-%d %H:%M:%S")
+                .is_ok_and(|since| since.and_utc() < scheduled);
+            (passed && r.digest_sent_date.as_deref() != Some(date.as_str())).then_some(DueDigest {
+                user_id: r.user_id,
+                since: r.since,
+                date,
+            })
+        })
+        .collect())
+}
+
+pub async fn set_timezone(pool: &SqlitePool, user_id: i64, timezone: &str) -> anyhow::Result<()> {
+    sqlx::query!(
+        r#"INSERT INTO user_settings (user_id, timezone) VALUES (?, ?)
+           ON CONFLICT(user_id) DO UPDATE SET timezone = excluded.timezone"#,
+        user_id,
+        timezone
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Record the digest for the user's local `date` as sent
+pub async fn mark_digest_sent(pool: &SqlitePool, user_id: i64, date: &str) -> anyhow::Result<()> {
+    sqlx::query!(
+        "UPDATE user_settings SET digest_sent_at = CURRENT_TIMESTAMP, digest_sent_date = ? WHERE user_id = ?",
+        date,
+        user_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Unrealized PnL per wallet at the user's last digest
+pub async fn get_digest_snapshots(
+    pool: &SqlitePool,
+    user_id: i64,
+) -> anyhow::Result<HashMap<String, f64>> {
+    let rows = sqlx::query!(
+        r#"SELECT wallet_address, unrealized_pnl FROM digest_snapshots WHERE user_id = ?"#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|r| (r.wallet_address, r.unrealized_pnl))
+        .collect())
+}
+
+pub async fn replace_digest_snapshots(
+    pool: &SqlitePool,
+    user_id: i64,
+    snapshots: &[(String, f64)],
+) -> anyhow::Result<()> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query!("DELETE FROM digest_snapshots WHERE user_id = ?", user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    for (wallet_address, unrealized_pnl) in snapshots {
+        sqlx::query!(
+            "INSERT INTO digest_snapshots (user_id, wallet_address, unrealized_pnl) VALUES (?, ?, ?)",
+            user_id,
+            wallet_address,
+            unrealized_pnl
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+pub struct PositionEvent {
+    pub coin: String,
+    pub kind: String,
+    pub is_long: bool,
+    pub size: f64,
+    pub size_delta: f64,
+    pub entry_price: f64,
+    pub mark_price: Option<f64>,
+    pub created_at: String,
+}
+
+/// Record a detected position change
+#[allow(clippy::too_many_arguments)]
+pub async fn record_position_event(
+    pool: &SqlitePool,
+    wallet_address: &str,
+    coin: &str,
+    kind: &str,
+    is_long: bool,
+    size: f64,
+    size_delta: f64,
+    entry_price: f64,
+    mark_price: Option<f64>,
+    pnl: f64,
+) -> anyhow::Result<()> {
+    let wallet_lower = wallet_address.to_lowercase();
+    sqlx::query!(
+        r#"INSERT INTO position_events (wallet_address, coin, kind, is_long, size, size_delta, entry_price, mark_price, pnl)
+           VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"#,
+        wallet_lower,
+        coin,
+        kind,
+        is_long,
+        size,
+        size_delta,
+        entry_price,
+        mark_price,
+        pnl
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Most recent opens and increases of a coin for a wallet (coin matched case-insensitively)
+pub async fn get_entry_events(
+    pool: &SqlitePool,
+    wallet_address: &str,
+    coin: &str,
+    limit: i64,
+) -> anyhow::Result<Vec<PositionEvent>> {
+    let wallet_lower = wallet_address.to_lowercase();
+    let events = sqlx::query_as!(
+        PositionEvent,
+        r#"SELECT coin, kind, is_long as "is_long!: bool", size, size_delta, entry_price, mark_price, created_at as "created_at!: String"
+           FROM position_events
+           WHERE wallet_address = ? AND LOWER(coin) = LOWER(?) AND kind IN ('opened', 'increased')
+           ORDER BY id DESC LIMIT ?"#,
+        wallet_lower,
+        coin,
+        limit
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(events)
+}
+
+/// Whether an open is the wallet's first recorded trade in a coin.
+///
+/// False while the wallet has no recorded events at all, so the positions found
+/// when a wallet is first tracked aren't all reported as new coins.
+pub async fn is_first_coin_trade(
+    pool: &SqlitePool,
+    wallet_address: &str,
+    coin: &str,
+) -> anyhow::Result<bool> {
+    let wallet_lower = wallet_address.to_lowercase();
+    let row = sqlx::query!(
+        r#"SELECT
+               EXISTS(SELECT 1 FROM position_events WHERE wallet_address = ?) as "has_history!: bool",
+               EXISTS(SELECT 1 FROM position_events WHERE wallet_address = ? AND coin = ?) as "traded_coin!: bool""#,
+        wallet_lower,
+        wallet_lower,
+        coin
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(row.has_history && !row.traded_coin)
+}
+
+/// Notional of each position a wallet opened in the last `days` days
+pub async fn get_open_notionals(
+    pool: &SqlitePool,
+    wallet_address: &str,
+    days: i64,
+) -> anyhow::Result<Vec<f64>> {
+    let wallet_lower = wallet_address.to_lowercase();
+    let since = format!("-{} days", days);
+    let notionals = sqlx::query_scalar!(
+        r#"SELECT ABS(size) * entry_price as "notional!: f64" FROM position_events
+           WHERE wallet_address = ? AND kind = 'opened' AND created_at >= datetime('now', ?)"#,
+        wallet_lower,
+        since
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(notionals)
+}
+
+/// Position change as exposed through the HTTP API
+#[derive(Debug, Clone, Serialize)]
+pub struct WalletEvent {
+    pub id: i64,
+    pub wallet_address: String,
+    pub coin: String,
+    pub kind: String,
+    pub is_long: bool,
+    pub size: f64,
+    pub size_delta: f64,
+    pub entry_price: f64,
+    pub mark_price: Option<f64>,
+    pub pnl: f64,
+    pub created_at: String,
+    /// Reconstructed by /backfill rather than detected live
+    pub backfilled: bool,
+}
+
+/// Store a user's API token hash, replacing (and so revoking) any previous token
+pub async fn set_api_token(
+    pool: &SqlitePool,
+    user_id: i64,
+    token_hash: &str,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        r#"INSERT INTO api_tokens (user_id, token_hash) VALUES (?, ?)
+           ON CONFLICT(user_id) DO UPDATE SET token_hash = excluded.token_hash, created_at = CURRENT_TIMESTAMP"#,
+        user_id,
+        token_hash
+    )
+    .execute(pool)
+    .await?;
+
+    info!("Issued API token for user {}", user_id);
+    Ok(())
+}
+
+/// Revoke a user's API token, returns false if they didn't have one
+pub async fn revoke_api_token(pool: &SqlitePool, user_id: i64) -> anyhow::Result<bool> {
+    let result = sqlx::query!("DELETE FROM api_tokens WHERE user_id = ?", user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Look up the user an API token hash belongs to
+pub async fn get_api_token_user(
+    pool: &SqlitePool,
+    token_hash: &str,
+) -> anyhow::Result<Option<i64>> {
+    let user_id = sqlx::query_scalar!(
+        r#"SELECT user_id as "user_id!: i64" FROM api_tokens WHERE token_hash = ?"#,
+        token_hash
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(user_id)
+}
+
+/// Position changes on a user's tracked wallets recorded at or after `since`, oldest first
+pub async fn get_user_events_since(
+    pool: &SqlitePool,
+    user_id: i64,
+    since: &str,
+    limit: i64,
+) -> anyhow::Result<Vec<WalletEvent>> {
+    let events = sqlx::query_as!(
+        WalletEvent,
+        r#"SELECT e.id as "id!: i64", e.wallet_address, e.coin, e.kind, e.is_long as "is_long!: bool",
+                  e.size, e.size_delta, e.entry_price, e.mark_price, e.pnl, e.created_at as "created_at!: String",
+                  e.backfilled as "backfilled!: bool"
+           FROM position_events e
+           JOIN tracked_wallets w ON w.wallet_address = e.wallet_address
+           WHERE w.user_id = ? AND w.removed_at IS NULL AND e.created_at >= ?
+           ORDER BY e.id LIMIT ?"#,
+        user_id,
+        since,
+        limit
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(events)
+}
+
+#[derive(Debug, Clone)]
+pub struct LedgerEntry {
+    pub time: i64,
+    pub kind: String,
+    pub counterparty: Option<String>,
+    pub incoming: bool,
+    pub amount: f64,
+}
+
+/// Time (ms) of the newest stored ledger update for a wallet
+pub async fn get_latest_ledger_time(
+    pool: &SqlitePool,
+    wallet_address: &str,
+) -> anyhow::Result<Option<i64>> {
+    let wallet_lower = wallet_address.to_lowercase();
+    let time = sqlx::query_scalar!(
+        r#"SELECT MAX(time) as "time: i64" FROM ledger_updates WHERE wallet_address = ?"#,
+        wallet_lower
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(time)
+}
+
+/// Store a ledger update, ignoring ones already stored
+#[allow(clippy::too_many_arguments)]
+pub async fn insert_ledger_update(
+    pool: &SqlitePool,
+    wallet_address: &str,
+    time: i64,
+    hash: &str,
+    kind: &str,
+    counterparty: Option<&str>,
+    incoming: bool,
+    amount: f64,
+) -> anyhow::Result<()> {
+    let wallet_lower = wallet_address.to_lowercase();
+    let counterparty = counterparty.map(|c| c.to_lowercase());
+    sqlx::query!(
+        r#"INSERT OR IGNORE INTO ledger_updates (wallet_address, time, hash, kind, counterparty, incoming, amount)
+           VALUES (?, ?, ?, ?, ?, ?, ?)"#,
+        wallet_lower,
+        time,
+        hash,
+        kind,
+        counterparty,
+        incoming,
+        amount
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Stored ledger updates for a wallet, oldest first
+pub async fn get_ledger_entries(
+    pool: &SqlitePool,
+    wallet_address: &str,
+) -> anyhow::Result<Vec<LedgerEntry>> {
+    let wallet_lower = wallet_address.to_lowercase();
+    let entries = sqlx::query_as!(
+        LedgerEntry,
+        r#"SELECT time, kind, counterparty, incoming as "incoming!: bool", amount
+           FROM ledger_updates WHERE wallet_address = ? ORDER BY time"#,
+        wallet_lower
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(entries)
+}
+
+/// Funding paid or received by an open position, positive when received
+#[derive(Debug, Clone)]
+pub struct PositionFunding {
+    pub coin: String,
+    pub funding: f64,
+    pub since_tracking: bool,
+    pub last_time: i64,
+}
+
+/// Turn funding collection on or off for a tracked wallet, returns false if it isn't tracked
+pub async fn set_collect_funding(
+    pool: &SqlitePool,
+    user_id: i64,
+    wallet_address: &str,
+    enabled: bool,
+) -> anyhow::Result<bool> {
+    let wallet_lower = wallet_address.to_lowercase();
+    let result = sqlx::query!(
+        "UPDATE tracked_wallets SET collect_funding = ? WHERE user_id = ? AND wallet_address = ? AND removed_at IS NULL",
+        enabled,
+        user_id,
+        wallet_lower
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn is_collecting_funding(
+    pool: &SqlitePool,
+    user_id: i64,
+    wallet_address: &str,
+) -> anyhow::Result<bool> {
+    let wallet_lower = wallet_address.to_lowercase();
+    let enabled = sqlx::query_scalar!(
+        r#"SELECT collect_funding as "collect_funding!: bool" FROM tracked_wallets WHERE user_id = ? AND wallet_address = ? AND removed_at IS NULL"#,
+        user_id,
+        wallet_lower
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(enabled.unwrap_or(false))
+}
+
+/// Wallets that at least one user collects funding for
+pub async fn get_funding_wallets(pool: &SqlitePool) -> anyhow::Result<Vec<String>> {
+    let wallets = sqlx::query_scalar!(
+        "SELECT DISTINCT wallet_address FROM tracked_wallets WHERE collect_funding = 1 AND removed_at IS NULL"
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(wallets)
+}
+
+pub async fn wallet_collects_funding(
+    pool: &SqlitePool,
+    wallet_address: &str,
+) -> anyhow::Result<bool> {
+    let wallet_lower = wallet_address.to_lowercase();
+    let count = sqlx::query_scalar!(
+        "SELECT COUNT(*) FROM tracked_wallets WHERE wallet_address = ? AND collect_funding = 1 AND removed_at IS NULL",
+        wallet_lower
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(count > 0)
+}
+
+/// Start accumulating funding for a newly opened position, discarding any previous one in the coin
+pub async fn start_position_funding(
+    pool: &SqlitePool,
+    wallet_address: &str,
+    coin: &str,
+    opened_at: i64,
+    since_tracking: bool,
+) -> anyhow::Result<()> {
+    let wallet_lower = wallet_address.to_lowercase();
+    sqlx::query!(
+        r#"INSERT INTO position_funding (wallet_address, coin, opened_at, funding, since_tracking, last_time)
+           VALUES (?, ?, ?, 0, ?, ?)
+           ON CONFLICT(wallet_address, coin) DO UPDATE SET
+               opened_at = excluded.opened_at,
+               funding = 0,
+               since_tracking = excluded.since_tracking,
+               last_time = excluded.last_time"#,
+        wallet_lower,
+        coin,
+        opened_at,
+        since_tracking,
+        opened_at
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn get_position_fundings(
+    pool: &SqlitePool,
+    wallet_address: &str,
+) -> anyhow::Result<Vec<PositionFunding>> {
+    let wallet_lower = wallet_address.to_lowercase();
+    let fundings = sqlx::query_as!(
+        PositionFunding,
+        r#"SELECT coin, funding, since_tracking as "since_tracking!: bool", last_time
+           FROM position_funding WHERE wallet_address = ?"#,
+        wallet_lower
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(fundings)
+}
+
+/// Add funding payments up to `last_time` to an open position
+pub async fn add_position_funding(
+    pool: &SqlitePool,
+    wallet_address: &str,
+    coin: &str,
+    amount: f64,
+    last_time: i64,
+) -> anyhow::Result<()> {
+    let wallet_lower = wallet_address.to_lowercase();
+    sqlx::query!(
+        "UPDATE position_funding SET funding = funding + ?, last_time = ? WHERE wallet_address = ? AND coin = ?",
+        amount,
+        last_time,
+        wallet_lower,
+        coin
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Remove and return the funding of a position that just closed
+pub async fn take_position_funding(
+    pool: &SqlitePool,
+    wallet_address: &str,
+    coin: &str,
+) -> anyhow::Result<Option<PositionFunding>> {
+    let wallet_lower = wallet_address.to_lowercase();
+    let funding = sqlx::query_as!(
+        PositionFunding,
+        r#"SELECT coin, funding, since_tracking as "since_tracking!: bool", last_time
+           FROM position_funding WHERE wallet_address = ? AND coin = ?"#,
+        wallet_lower,
+        coin
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    sqlx::query!(
+        "DELETE FROM position_funding WHERE wallet_address = ? AND coin = ?",
+        wallet_lower,
+        coin
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(funding)
+}
+
+pub async fn set_liquidation_alerts(
+    pool: &SqlitePool,
+    user_id: i64,
+    wallet_address: &str,
+    enabled: bool,
+) -> anyhow::Result<bool> {
+    let wallet_lower = wallet_address.to_lowercase();
+    let result = sqlx::query!(
+        "UPDATE tracked_wallets SET liquidation_alerts = ? WHERE user_id = ? AND wallet_address = ? AND removed_at IS NULL",
+        enabled,
+        user_id,
+        wallet_lower
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn get_liquidation_alerts(
+    pool: &SqlitePool,
+    user_id: i64,
+    wallet_address: &str,
+) -> anyhow::Result<bool> {
+    let wallet_lower = wallet_address.to_lowercase();
+    let enabled = sqlx::query_scalar!(
+        r#"SELECT liquidation_alerts as "liquidation_alerts!: bool" FROM tracked_wallets WHERE user_id = ? AND wallet_address = ? AND removed_at IS NULL"#,
+        user_id,
+        wallet_lower
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(enabled.unwrap_or(false))
+}
+
+/// When the monitor last processed each wallet, in milliseconds since epoch
+pub async fn get_wallet_processed_times(pool: &SqlitePool) -> anyhow::Result<HashMap<String, i64>> {
+    let rows = sqlx::query!(
+        r#"SELECT wallet_address as "wallet_address!", processed_at FROM wallet_poll_state"#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| (row.wallet_address, row.processed_at))
+        .collect())
+}
+
+pub async fn set_wallet_processed_at(
+    pool: &SqlitePool,
+    wallet_address: &str,
+    processed_at: i64,
+) -> anyhow::Result<()> {
+    let wallet_lower = wallet_address.to_lowercase();
+    sqlx::query!(
+        "INSERT INTO wallet_poll_state (wallet_address, processed_at) VALUES (?, ?) ON CONFLICT(wallet_address) DO UPDATE SET processed_at = excluded.processed_at",
+        wallet_lower,
+        processed_at
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn set_include_vaults(
+    pool: &SqlitePool,
+    user_id: i64,
+    wallet_address: &str,
+    enabled: bool,
+) -> anyhow::Result<bool> {
+    let wallet_lower = wallet_address.to_lowercase();
+    let result = sqlx::query!(
+        "UPDATE tracked_wallets SET include_vaults = ? WHERE user_id = ? AND wallet_address = ? AND removed_at IS NULL",
+        enabled,
+        user_id,
+        wallet_lower
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn get_include_vaults(
+    pool: &SqlitePool,
+    user_id: i64,
+    wallet_address: &str,
+) -> anyhow::Result<bool> {
+    let wallet_lower = wallet_address.to_lowercase();
+    let enabled = sqlx::query_scalar!(
+        r#"SELECT include_vaults as "include_vaults!: bool" FROM tracked_wallets WHERE user_id = ? AND wallet_address = ? AND removed_at IS NULL"#,
+        user_id,
+        wallet_lower
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(enabled.unwrap_or(false))
+}
+
+/// Wallets that at least one user includes vault equity for
+pub async fn get_vault_wallets(pool: &SqlitePool) -> anyhow::Result<Vec<String>> {
+    let wallets = sqlx::query_scalar!(
+        "SELECT DISTINCT wallet_address FROM tracked_wallets WHERE include_vaults = 1 AND removed_at IS NULL"
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(wallets)
+}
+
+#[derive(Debug, Clone)]
+pub struct VaultEquity {
+    pub equity: f64,
+    pub fetched_at: i64,
+}
+
+/// Replace the stored vault equities of a wallet with a fresh fetch
+pub async fn replace_vault_equities(
+    pool: &SqlitePool,
+    wallet_address: &str,
+    equities: &[(String, f64)],
+    fetched_at: i64,
+) -> anyhow::Result<()> {
+    let wallet_lower = wallet_address.to_lowercase();
+    let mut tx = pool.begin().await?;
+
+    sqlx::query!(
+        "DELETE FROM vault_equities WHERE wallet_address = ?",
+        wallet_lower
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    for (vault_address, equity) in equities {
+        let vault_lower = vault_address.to_lowercase();
+        sqlx::query!(
+            "INSERT INTO vault_equities (wallet_address, vault_address, equity, fetched_at) VALUES (?, ?, ?, ?)",
+            wallet_lower,
+            vault_lower,
+            equity,
+            fetched_at
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn get_vault_equities(
+    pool: &SqlitePool,
+    wallet_address: &str,
+) -> anyhow::Result<Vec<VaultEquity>> {
+    let wallet_lower = wallet_address.to_lowercase();
+    let equities = sqlx::query_as!(
+        VaultEquity,
+        r#"SELECT equity as "equity!: f64", fetched_at FROM vault_equities WHERE wallet_address = ? ORDER BY equity DESC"#,
+        wallet_lower
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(equities)
+}
+
+#[derive(Debug, Clone)]
+pub struct BackfillRun {
+    pub id: i64,
+    pub start_time: i64,
+    pub end_time: i64,
+    pub cursor: i64,
+    pub open_trips: String,
+    pub fills: i64,
+    pub completed: bool,
+}
+
+/// A position event reconstructed from fill history
+#[derive(Debug, Clone)]
+pub struct BackfilledEvent {
+    pub coin: String,
+    pub kind: &'static str,
+    pub is_long: bool,
+    pub size: f64,
+    pub size_delta: f64,
+    pub entry_price: f64,
+    pub pnl: f64,
+    /// Milliseconds since epoch
+    pub time: i64,
+}
+
+pub async fn get_backfill_runs(
+    pool: &SqlitePool,
+    wallet_address: &str,
+) -> anyhow::Result<Vec<BackfillRun>> {
+    let wallet_lower = wallet_address.to_lowercase();
+    let runs = sqlx::query_as!(
+        BackfillRun,
+        r#"SELECT id as "id!: i64", start_time, end_time, cursor, open_trips, fills, completed as "completed!: bool"
+           FROM backfill_runs WHERE wallet_address = ? ORDER BY start_time"#,
+        wallet_lower
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(runs)
+}
+
+pub async fn create_backfill_run(
+    pool: &SqlitePool,
+    wallet_address: &str,
+    start_time: i64,
+    end_time: i64,
+) -> anyhow::Result<BackfillRun> {
+    let wallet_lower = wallet_address.to_lowercase();
+    let id = sqlx::query!(
+        "INSERT INTO backfill_runs (wallet_address, start_time, end_time, cursor) VALUES (?, ?, ?, ?)",
+        wallet_lower,
+        start_time,
+        end_time,
+        start_time
+    )
+    .execute(pool)
+    .await?
+    .last_insert_rowid();
+
+    Ok(BackfillRun {
+        id,
+        start_time,
+        end_time,
+        cursor: start_time,
+        open_trips: "{}".to_string(),
+        fills: 0,
+        completed: false,
+    })
+}
+
+/// Store a processed page of fills: its events and the run's new position, atomically
+pub async fn save_backfill_page(
+    pool: &SqlitePool,
+    wallet_address: &str,
+    run: &BackfillRun,
+    events: &[BackfilledEvent],
+) -> anyhow::Result<()> {
+    let wallet_lower = wallet_address.to_lowercase();
+    let mut tx = pool.begin().await?;
+
+    for event in events {
+        sqlx::query!(
+            r#"INSERT INTO position_events (wallet_address, coin, kind, is_long, size, size_delta, entry_price, mark_price, pnl, created_at, backfilled)
+               VALUES (?, ?, ?, ?, ?, ?, ?, NULL, ?, datetime(? / 1000, 'unixepoch'), 1)"#,
+            wallet_lower,
+            event.coin,
+            event.kind,
+            event.is_long,
+            event.size,
+            event.size_delta,
+            event.entry_price,
+            event.pnl,
+            event.time
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    sqlx::query!(
+        "UPDATE backfill_runs SET cursor = ?, open_trips = ?, fills = ?, completed = ? WHERE id = ?",
+        run.cursor,
+        run.open_trips,
+        run.fills,
+        run.completed,
+        run.id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Time (ms) of the first event the monitor recorded live for any wallet
+pub async fn get_first_recorded_event_time(pool: &SqlitePool) -> anyhow::Result<Option<i64>> {
+    let time = sqlx::query_scalar!(
+        r#"SELECT CAST(strftime('%s', MIN(created_at)) AS INTEGER) * 1000 as "time: i64"
+           FROM position_events WHERE backfilled = 0"#
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(time)
+}
+
+/// Time (ms) of the first event the monitor recorded live for a wallet
+pub async fn get_first_live_event_time(
+    pool: &SqlitePool,
+    wallet_address: &str,
+) -> anyhow::Result<Option<i64>> {
+    let wallet_lower = wallet_address.to_lowercase();
+    let time = sqlx::query_scalar!(
+        r#"SELECT CAST(strftime('%s', MIN(created_at)) AS INTEGER) * 1000 as "time: i64"
+           FROM position_events WHERE wallet_address = ? AND backfilled = 0"#,
+        wallet_lower
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(time)
+}
+
+#[derive(Debug, Clone)]
+pub struct ClosedPosition {
+    pub coin: String,
+    pub is_long: bool,
+    pub size: f64,
+    pub entry_price: f64,
+    pub exit_price: Option<f64>,
+    pub realized_pnl: f64,
+    pub opened_at: Option<String>,
+    pub closed_at: String,
+}
+
+/// Store a closed position and prune the wallet's history to the most recent ones.
+///
+/// Must run before the close itself is recorded as an event: the open time is the last
+/// open since the previous close, and stays NULL if the open was never seen.
+#[allow(clippy::too_many_arguments)]
+pub async fn record_closed_position(
+    pool: &SqlitePool,
+    wallet_address: &str,
+    coin: &str,
+    is_long: bool,
+    size: f64,
+    entry_price: f64,
+    exit_price: Option<f64>,
+    realized_pnl: f64,
+) -> anyhow::Result<()> {
+    let wallet_lower = wallet_address.to_lowercase();
+    sqlx::query!(
+        r#"INSERT INTO closed_positions (wallet_address, coin, is_long, size, entry_price, exit_price, realized_pnl, opened_at)
+           VALUES (?, ?, ?, ?, ?, ?, ?,
+                   (SELECT MAX(created_at) FROM position_events
+                    WHERE wallet_address = ? AND coin = ? AND kind = 'opened'
+                      AND created_at > COALESCE((SELECT MAX(created_at) FROM position_events
+                                                 WHERE wallet_address = ? AND coin = ? AND kind = 'closed'), '')))"#,
+        wallet_lower,
+        coin,
+        is_long,
+        size,
+        entry_price,
+        exit_price,
+        realized_pnl,
+        wallet_lower,
+        coin,
+        wallet_lower,
+        coin
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query!(
+        r#"DELETE FROM closed_positions WHERE wallet_address = ? AND id NOT IN
+           (SELECT id FROM closed_positions WHERE wallet_address = ? ORDER BY id DESC LIMIT ?)"#,
+        wallet_lower,
+        wallet_lower,
+        MAX_CLOSED_POSITIONS_PER_WALLET
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Most recent closed positions of a wallet, newest first
+pub async fn get_closed_positions(
+    pool: &SqlitePool,
+    wallet_address: &str,
+    limit: i64,
+) -> anyhow::Result<Vec<ClosedPosition>> {
+    let wallet_lower = wallet_address.to_lowercase();
+    let positions = sqlx::query_as!(
+        ClosedPosition,
+        r#"SELECT coin, is_long as "is_long!: bool", size, entry_price, exit_price, realized_pnl,
+                  opened_at as "opened_at: String", closed_at as "closed_at!: String"
+           FROM closed_positions WHERE wallet_address = ? ORDER BY id DESC LIMIT ?"#,
+        wallet_lower,
+        limit
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(positions)
+}
+
+/// Mute or unmute a wallet for one user, returns false if they don't track it
+pub async fn set_wallet_muted(
+    pool: &SqlitePool,
+    user_id: i64,
+    wallet_address: &str,
+    muted: bool,
+) -> anyhow::Result<bool> {
+    let wallet_lower = wallet_address.to_lowercase();
+    let result = sqlx::query!(
+        "UPDATE tracked_wallets SET muted = ? WHERE user_id = ? AND wallet_address = ? AND removed_at IS NULL",
+        muted,
+        user_id,
+        wallet_lower
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Snooze a wallet's notifications for a user until `snoozed_until` (unix seconds), `None` ends it
+pub async fn set_wallet_snoozed_until(
+    pool: &SqlitePool,
+    user_id: i64,
+    wallet_address: &str,
+    snoozed_until: Option<i64>,
+) -> anyhow::Result<bool> {
+    let wallet_lower = wallet_address.to_lowercase();
+    let result = sqlx::query!(
+        "UPDATE tracked_wallets SET snoozed_until = ? WHERE user_id = ? AND wallet_address = ? AND removed_at IS NULL",
+        snoozed_until,
+        user_id,
+        wallet_lower
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Pause notifications for a user, until `paused_until` (unix seconds) or until they resume
+pub async fn pause_notifications(
+    pool: &SqlitePool,
+    user_id: i64,
+    paused_until: Option<i64>,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        r#"INSERT INTO user_settings (user_id, notifications_paused, paused_until) VALUES (?, 1, ?)
+           ON CONFLICT(user_id) DO UPDATE SET notifications_paused = 1, paused_until = excluded.paused_until"#,
+        user_id,
+        paused_until
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Resume notifications, returns false if they weren't paused (or the pause already ran out)
+pub async fn resume_notifications(pool: &SqlitePool, user_id: i64) -> anyhow::Result<bool> {
+    let now = chrono::Utc::now().timestamp();
+    let result = sqlx::query!(
+        r#"UPDATE user_settings SET notifications_paused = 0, paused_until = NULL
+           WHERE user_id = ? AND notifications_paused = 1 AND (paused_until IS NULL OR paused_until > ?)"#,
+        user_id,
+        now
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Whether a user's notifications are paused right now, with when the pause ends if it does
+pub async fn get_notifications_pause(
+    pool: &SqlitePool,
+    user_id: i64,
+) -> anyhow::Result<Option<Option<i64>>> {
+    let now = chrono::Utc::now().timestamp();
+    let pause = sqlx::query_scalar!(
+        r#"SELECT paused_until FROM user_settings
+           WHERE user_id = ? AND notifications_paused = 1 AND (paused_until IS NULL OR paused_until > ?)"#,
+        user_id,
+        now
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(pause)
+}
+
+/// Users whose notifications are currently paused
+pub async fn get_paused_users(pool: &SqlitePool) -> anyhow::Result<HashSet<i64>> {
+    let now = chrono::Utc::now().timestamp();
+    let users = sqlx::query_scalar!(
+        r#"SELECT user_id as "user_id!: i64" FROM user_settings
+           WHERE notifications_paused = 1 AND (paused_until IS NULL OR paused_until > ?)"#,
+        now
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(users.into_iter().collect())
+}
+
+/// Record perps not seen before as listed now, returning every known listing time (unix seconds).
+///
+/// The first call only takes a baseline, since assets present then were listed at an unknown time.
+pub async fn record_perp_listings(
+    pool: &SqlitePool,
+    coins: &[String],
+) -> anyhow::Result<HashMap<String, i64>> {
+    let mut tx = pool.begin().await?;
+
+    let known = sqlx::query_scalar!(r#"SELECT COUNT(*) as "count!: i64" FROM perp_listings"#)
+        .fetch_one(&mut *tx)
+        .await?;
+    let listed_at = (known > 0).then(|| chrono::Utc::now().timestamp());
+
+    for coin in coins {
+        sqlx::query!(
+            "INSERT OR IGNORE INTO perp_listings (coin, listed_at) VALUES (?, ?)",
+            coin,
+            listed_at
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    let rows = sqlx::query!(
+        r#"SELECT coin as "coin!: String", listed_at as "listed_at!: i64" FROM perp_listings WHERE listed_at IS NOT NULL"#
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(rows.into_iter().map(|r| (r.coin, r.listed_at)).collect())
+}
+
+#[derive(Debug, Clone)]
+pub struct PriceAlert {
+    pub id: i64,
+    pub user_id: i64,
+    pub coin: String,
+    pub above: bool,
+    pub price: f64,
+}
+
+/// Store a price alert, returning its id
+pub async fn create_price_alert(
+    pool: &SqlitePool,
+    user_id: i64,
+    coin: &str,
+    above: bool,
+    price: f64,
+) -> anyhow::Result<i64> {
+    let result = sqlx::query!(
+        "INSERT INTO price_alerts (user_id, coin, above, price) VALUES (?, ?, ?, ?)",
+        user_id,
+        coin,
+        above,
+        price
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.last_insert_rowid())
+}
+
+pub async fn get_user_price_alerts(
+    pool: &SqlitePool,
+    user_id: i64,
+) -> anyhow::Result<Vec<PriceAlert>> {
+    let alerts = sqlx::query_as!(
+        PriceAlert,
+        r#"SELECT id as "id!: i64", user_id, coin, above as "above!: bool", price FROM price_alerts WHERE user_id = ? ORDER BY id"#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(alerts)
+}
+
+pub async fn get_all_price_alerts(pool: &SqlitePool) -> anyhow::Result<Vec<PriceAlert>> {
+    let alerts = sqlx::query_as!(
+        PriceAlert,
+        r#"SELECT id as "id!: i64", user_id, coin, above as "above!: bool", price FROM price_alerts"#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(alerts)
+}
+
+/// Delete one of a user's price alerts, returns false if they have no alert with that id
+pub async fn delete_price_alert(pool: &SqlitePool, user_id: i64, id: i64) -> anyhow::Result<bool> {
+    let result = sqlx::query!(
+        "DELETE FROM price_alerts WHERE id = ? AND user_id = ?",
+        id,
+        user_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Move an existing price alert to a new price, false if it doesn't exist (anymore)
+pub async fn update_price_alert(
+    pool: &SqlitePool,
+    user_id: i64,
+    id: i64,
+    price: f64,
+) -> anyhow::Result<bool> {
+    let result = sqlx::query!(
+        "UPDATE price_alerts SET price = ? WHERE id = ? AND user_id = ?",
+        price,
+        id,
+        user_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+#[derive(Debug, Clone)]
+pub struct PnlAlert {
+    pub user_id: i64,
+    pub wallet_address: String,
+    /// Fires when the wallet's unrealized PnL reaches +threshold or -threshold (USD)
+    pub threshold: f64,
+    pub armed: bool,
+}
+
+/// Arm a PnL alert on a wallet, replacing the user's previous one for it
+pub async fn set_pnl_alert(
+    pool: &SqlitePool,
+    user_id: i64,
+    wallet_address: &str,
+    threshold: f64,
+) -> anyhow::Result<()> {
+    let wallet_lower = wallet_address.to_lowercase();
+    sqlx::query!(
+        r#"INSERT INTO pnl_alerts (user_id, wallet_address, threshold) VALUES (?, ?, ?)
+           ON CONFLICT(user_id, wallet_address) DO UPDATE SET threshold = excluded.threshold, armed = 1"#,
+        user_id,
+        wallet_lower,
+        threshold
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Remove a user's PnL alert on a wallet, false if there was none
+pub async fn delete_pnl_alert(
+    pool: &SqlitePool,
+    user_id: i64,
+    wallet_address: &str,
+) -> anyhow::Result<bool> {
+    let wallet_lower = wallet_address.to_lowercase();
+    let result = sqlx::query!(
+        "DELETE FROM pnl_alerts WHERE user_id = ? AND wallet_address = ?",
+        user_id,
+        wallet_lower
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn set_pnl_alert_armed(
+    pool: &SqlitePool,
+    user_id: i64,
+    wallet_address: &str,
+    armed: bool,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        "UPDATE pnl_alerts SET armed = ? WHERE user_id = ? AND wallet_address = ?",
+        armed,
+        user_id,
+        wallet_address
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn get_user_pnl_alerts(pool: &SqlitePool, user_id: i64) -> anyhow::Result<Vec<PnlAlert>> {
+    let alerts = sqlx::query_as!(
+        PnlAlert,
+        r#"SELECT user_id as "user_id!: i64", wallet_address, threshold, armed as "armed!: bool" FROM pnl_alerts WHERE user_id = ? ORDER BY created_at"#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(alerts)
+}
+
+/// Every PnL alert, grouped by wallet
+pub async fn get_all_pnl_alerts(
+    pool: &SqlitePool,
+) -> anyhow::Result<HashMap<String, Vec<PnlAlert>>> {
+    let alerts = sqlx::query_as!(
+        PnlAlert,
+        r#"SELECT user_id as "user_id!: i64", wallet_address, threshold, armed as "armed!: bool" FROM pnl_alerts"#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut by_wallet: HashMap<String, Vec<PnlAlert>> = HashMap::new();
+    for alert in alerts {
+        by_wallet
+            .entry(alert.wallet_address.clone())
+            .or_default()
+            .push(alert);
+    }
+    Ok(by_wallet)
+}
+
+pub async fn log_notification(
+    pool: &SqlitePool,
+    user_id: i64,
+    wallet_address: Option<&str>,
+    kind: &str,
+) -> anyhow::Result<()> {
+    let wallet_lower = wallet_address.map(str::to_lowercase);
+    sqlx::query!(
+        "INSERT INTO notification_log (user_id, wallet_address, kind) VALUES (?, ?, ?)",
+        user_id,
+        wallet_lower,
+        kind
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Drop logged notifications older than `days`
+pub async fn prune_notification_log(pool: &SqlitePool, days: i64) -> anyhow::Result<u64> {
+    let cutoff = format!("-{} days", days);
+    let result = sqlx::query!(
+        "DELETE FROM notification_log WHERE created_at < datetime('now', ?)",
+        cutoff
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Notifications of one kind a user got in the last day and week
+#[derive(Debug, Clone)]
+pub struct NotificationCount {
+    pub kind: String,
+    pub last_day: i64,
+    pub last_week: i64,
+}
+
+/// A user's notifications of the last 7 days per kind, most frequent first
+pub async fn get_notification_counts(
+    pool: &SqlitePool,
+    user_id: i64,
+) -> anyhow::Result<Vec<NotificationCount>> {
+    let counts = sqlx::query_as!(
+        NotificationCount,
+        r#"SELECT kind,
+                  SUM(created_at >= datetime('now', '-1 day')) as "last_day!: i64",
+                  COUNT(*) as "last_week!: i64"
+           FROM notification_log
+           WHERE user_id = ? AND created_at >= datetime('now', '-7 days')
+           GROUP BY kind ORDER BY COUNT(*) DESC, kind"#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(counts)
+}
+
+/// The wallet behind most of a user's notifications of the last 7 days, with its count
+pub async fn get_noisiest_wallet(
+    pool: &SqlitePool,
+    user_id: i64,
+) -> anyhow::Result<Option<(String, i64)>> {
+    let row = sqlx::query!(
+        r#"SELECT wallet_address as "wallet_address!: String", COUNT(*) as "count!: i64"
+           FROM notification_log
+           WHERE user_id = ? AND wallet_address IS NOT NULL
+             AND created_at >= datetime('now', '-7 days')
+           GROUP BY wallet_address ORDER BY COUNT(*) DESC, wallet_address LIMIT 1"#,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|r| (r.wallet_address, r.count)))
+}
+
+/// Replace the coins a user gets a wallet's notifications for, an empty list removes the filter
+pub async fn set_watched_coins(
+    pool: &SqlitePool,
+    user_id: i64,
+    wallet_address: &str,
+    coins: &[String],
+) -> anyhow::Result<()> {
+    let wallet_lower = wallet_address.to_lowercase();
+    let mut tx = pool.begin().await?;
+
+    sqlx::query!(
+        "DELETE FROM watched_coins WHERE user_id = ? AND wallet_address = ?",
+        user_id,
+        wallet_lower
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    for coin in coins {
+        sqlx::query!(
+            "INSERT OR IGNORE INTO watched_coins (user_id, wallet_address, coin) VALUES (?, ?, ?)",
+            user_id,
+            wallet_lower,
+            coin
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// A user's coin filters by wallet, wallets without one are left out
+pub async fn get_user_watched_coins(
+    pool: &SqlitePool,
+    user_id: i64,
+) -> anyhow::Result<HashMap<String, Vec<String>>> {
+    let rows = sqlx::query!(
+        "SELECT wallet_address, coin FROM watched_coins WHERE user_id = ? ORDER BY coin",
+        user_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut filters: HashMap<String, Vec<String>> = HashMap::new();
+    for row in rows {
+        filters
+            .entry(row.wallet_address)
+            .or_default()
+            .push(row.coin);
+    }
+    Ok(filters)
+}
+
+/// Tag one of a user's wallets, false when it already had the tag
+pub async fn add_wallet_tag(
+    pool: &SqlitePool,
+    user_id: i64,
+    wallet_address: &str,
+    tag: &str,
+) -> anyhow::Result<bool> {
+    let wallet_lower = wallet_address.to_lowercase();
+    let result = sqlx::query!(
+        "INSERT OR IGNORE INTO wallet_tags (user_id, wallet_address, tag) VALUES (?, ?, ?)",
+        user_id,
+        wallet_lower,
+        tag
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Take a tag off one of a user's wallets, false when it didn't have it
+pub async fn remove_wallet_tag(
+    pool: &SqlitePool,
+    user_id: i64,
+    wallet_address: &str,
+    tag: &str,
+) -> anyhow::Result<bool> {
+    let wallet_lower = wallet_address.to_lowercase();
+    let result = sqlx::query!(
+        "DELETE FROM wallet_tags WHERE user_id = ? AND wallet_address = ? AND tag = ?",
+        user_id,
+        wallet_lower,
+        tag
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// A user's tags by wallet, untagged wallets are left out
+pub async fn get_user_wallet_tags(
+    pool: &SqlitePool,
+    user_id: i64,
+) -> anyhow::Result<HashMap<String, Vec<String>>> {
+    let rows = sqlx::query!(
+        "SELECT wallet_address, tag FROM wallet_tags WHERE user_id = ? ORDER BY tag",
+        user_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut tags: HashMap<String, Vec<String>> = HashMap::new();
+    for row in rows {
+        tags.entry(row.wallet_address).or_default().push(row.tag);
+    }
+    Ok(tags)
+}
+
+/// A user's tracked wallets carrying a tag (case-insensitive), in /list order
+pub async fn get_wallets_by_tag(
+    pool: &SqlitePool,
+    user_id: i64,
+    tag: &str,
+) -> anyhow::Result<Vec<TrackedWallet>> {
+    let wallets = sqlx::query_as!(
+        TrackedWallet,
+        r#"SELECT w.user_id as "user_id!: i64", w.wallet_address, w.note, w.muted as "muted!: bool", w.snoozed_until
+           FROM tracked_wallets w
+           JOIN wallet_tags t ON t.user_id = w.user_id AND t.wallet_address = w.wallet_address
+           WHERE w.user_id = ? AND t.tag = ? AND w.removed_at IS NULL
+           ORDER BY w.id"#,
+        user_id,
+        tag
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(wallets)
+}
+
+/// Mute or unmute every tracked wallet of a user carrying a tag, returns how many were changed
+pub async fn set_tag_muted(
+    pool: &SqlitePool,
+    user_id: i64,
+    tag: &str,
+    muted: bool,
+) -> anyhow::Result<u64> {
+    let result = sqlx::query!(
+        "UPDATE tracked_wallets SET muted = ?
+         WHERE user_id = ? AND removed_at IS NULL AND muted != ?
+           AND wallet_address IN (SELECT wallet_address FROM wallet_tags WHERE user_id = ? AND tag = ?)",
+        muted,
+        user_id,
+        muted,
+        user_id,
+        tag
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Replace the coins a user gets notifications for, an empty list leaves whitelist mode
+pub async fn set_only_coins(
+    pool: &SqlitePool,
+    user_id: i64,
+    coins: &[String],
+) -> anyhow::Result<()> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query!("DELETE FROM only_coins WHERE user_id = ?", user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    for coin in coins {
+        sqlx::query!(
+            "INSERT OR IGNORE INTO only_coins (user_id, coin) VALUES (?, ?)",
+            user_id,
+            coin
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn get_user_only_coins(pool: &SqlitePool, user_id: i64) -> anyhow::Result<Vec<String>> {
+    let rows = sqlx::query!(
+        "SELECT coin FROM only_coins WHERE user_id = ? ORDER BY coin",
+        user_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(|r| r.coin).collect())
+}
+
+/// The coin whitelist of every user in whitelist mode
+pub async fn get_all_only_coins(pool: &SqlitePool) -> anyhow::Result<HashMap<i64, Vec<String>>> {
+    let rows = sqlx::query!(r#"SELECT user_id as "user_id!: i64", coin FROM only_coins"#)
+        .fetch_all(pool)
+        .await?;
+
+    let mut only: HashMap<i64, Vec<String>> = HashMap::new();
+    for row in rows {
+        only.entry(row.user_id).or_default().push(row.coin);
+    }
+    Ok(only)
+}
+
+/// Add a coin to a user's ignore list, false if it was already there
+pub async fn add_ignored_coin(pool: &SqlitePool, user_id: i64, coin: &str) -> anyhow::Result<bool> {
+    let result = sqlx::query!(
+        "INSERT OR IGNORE INTO ignored_coins (user_id, coin) VALUES (?, ?)",
+        user_id,
+        coin
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Remove a coin from a user's ignore list (any case), false if it wasn't there
+pub async fn remove_ignored_coin(
+    pool: &SqlitePool,
+    user_id: i64,
+    coin: &str,
+) -> anyhow::Result<bool> {
+    let result = sqlx::query!(
+        "DELETE FROM ignored_coins WHERE user_id = ? AND coin = ?",
+        user_id,
+        coin
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn get_user_ignored_coins(
+    pool: &SqlitePool,
+    user_id: i64,
+) -> anyhow::Result<Vec<String>> {
+    let rows = sqlx::query!(
+        "SELECT coin FROM ignored_coins WHERE user_id = ? ORDER BY coin",
+        user_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(|r| r.coin).collect())
+}
+
+/// Every user's ignored coins
+pub async fn get_all_ignored_coins(pool: &SqlitePool) -> anyhow::Result<HashMap<i64, Vec<String>>> {
+    let rows = sqlx::query!(r#"SELECT user_id as "user_id!: i64", coin FROM ignored_coins"#)
+        .fetch_all(pool)
+        .await?;
+
+    let mut ignored: HashMap<i64, Vec<String>> = HashMap::new();
+    for row in rows {
+        ignored.entry(row.user_id).or_default().push(row.coin);
+    }
+    Ok(ignored)
+}
+
+/// Every coin filter, keyed by (user, wallet)
+pub async fn get_all_watched_coins(
+    pool: &SqlitePool,
+) -> anyhow::Result<HashMap<(i64, String), HashSet<String>>> {
+    let rows = sqlx::query!(
+        r#"SELECT user_id as "user_id!: i64", wallet_address, coin FROM watched_coins"#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut filters: HashMap<(i64, String), HashSet<String>> = HashMap::new();
+    for row in rows {
+        filters
+            .entry((row.user_id, row.wallet_address))
+            .or_default()
+            .insert(row.coin);
+    }
+    Ok(filters)
+}
+
+/// Every (user, coin) referenced by coin watches, filters or price alerts
+pub async fn get_all_configured_coins(pool: &SqlitePool) -> anyhow::Result<Vec<(i64, String)>> {
+    let rows = sqlx::query!(
+        r#"SELECT user_id as "user_id!: i64", coin as "coin!: String" FROM watched_coins
+           UNION SELECT user_id, coin FROM ignored_coins
+           UNION SELECT user_id, coin FROM only_coins
+           UNION SELECT user_id, coin FROM price_alerts"#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(|r| (r.user_id, r.coin)).collect())
+}
+
+/// Point a user's coin watches, filters and price alerts at a coin's new name
+pub async fn rename_user_coin(
+    pool: &SqlitePool,
+    user_id: i64,
+    old_coin: &str,
+    new_coin: &str,
+) -> anyhow::Result<()> {
+    let mut tx = pool.begin().await?;
+
+    // Rows that would collide with one already using the new name are dropped
+    sqlx::query!(
+        "UPDATE OR IGNORE watched_coins SET coin = ? WHERE user_id = ? AND coin = ?",
+        new_coin,
+        user_id,
+        old_coin
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM watched_coins WHERE user_id = ? AND coin = ?",
+        user_id,
+        old_coin
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "UPDATE OR IGNORE ignored_coins SET coin = ? WHERE user_id = ? AND coin = ?",
+        new_coin,
+        user_id,
+        old_coin
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM ignored_coins WHERE user_id = ? AND coin = ?",
+        user_id,
+        old_coin
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "UPDATE OR IGNORE only_coins SET coin = ? WHERE user_id = ? AND coin = ?",
+        new_coin,
+        user_id,
+        old_coin
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM only_coins WHERE user_id = ? AND coin = ?",
+        user_id,
+        old_coin
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "UPDATE price_alerts SET coin = ? WHERE user_id = ? AND coin = ?",
+        new_coin,
+        user_id,
+        old_coin
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Remember that a user was told a coin isn't listed, false if they already were
+pub async fn mark_unlisted_coin_notice(
+    pool: &SqlitePool,
+    user_id: i64,
+    coin: &str,
+) -> anyhow::Result<bool> {
+    let result = sqlx::query!(
+        "INSERT OR IGNORE INTO unlisted_coin_notices (user_id, coin) VALUES (?, ?)",
+        user_id,
+        coin
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// An admin announcement and how far its delivery got
+#[derive(Debug, Clone)]
+pub struct Broadcast {
+    pub id: i64,
+    pub admin_chat_id: i64,
+    pub text: String,
+    pub last_user_id: i64,
+    pub sent: i64,
+    pub failed: i64,
+}
+
+pub async fn create_broadcast(
+    pool: &SqlitePool,
+    admin_chat_id: i64,
+    text: &str,
+) -> anyhow::Result<Broadcast> {
+    let id = sqlx::query!(
+        "INSERT INTO broadcasts (admin_chat_id, text) VALUES (?, ?)",
+        admin_chat_id,
+        text
+    )
+    .execute(pool)
+    .await?
+    .last_insert_rowid();
+
+    Ok(Broadcast {
+        id,
+        admin_chat_id,
+        text: text.to_string(),
+        last_user_id: 0,
+        sent: 0,
+        failed: 0,
+    })
+}
+
+/// Broadcasts a restart interrupted, oldest first
+pub async fn get_unfinished_broadcasts(pool: &SqlitePool) -> anyhow::Result<Vec<Broadcast>> {
+    let broadcasts = sqlx::query_as!(
+        Broadcast,
+        r#"SELECT id as "id!: i64", admin_chat_id, text, last_user_id, sent, failed
+           FROM broadcasts WHERE finished_at IS NULL ORDER BY id"#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(broadcasts)
+}
+
+/// Every user with a wallet after `after_user_id`, in user_id order
+pub async fn get_broadcast_recipients(
+    pool: &SqlitePool,
+    after_user_id: i64,
+) -> anyhow::Result<Vec<i64>> {
+    let users = sqlx::query_scalar!(
+        r#"SELECT DISTINCT user_id as "user_id!: i64" FROM tracked_wallets
+           WHERE user_id > ? ORDER BY user_id"#,
+        after_user_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(users)
+}
+
+pub async fn save_broadcast_progress(
+    pool: &SqlitePool,
+    broadcast: &Broadcast,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        "UPDATE broadcasts SET last_user_id = ?, sent = ?, failed = ? WHERE id = ?",
+        broadcast.last_user_id,
+        broadcast.sent,
+        broadcast.failed,
+        broadcast.id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn finish_broadcast(pool: &SqlitePool, broadcast_id: i64) -> anyhow::Result<()> {
+    sqlx::query!(
+        "UPDATE broadcasts SET finished_at = CURRENT_TIMESTAMP WHERE id = ?",
+        broadcast_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn record_monitor_tick(
+    pool: &SqlitePool,
+    api_calls: i64,
+    api_errors: i64,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        "INSERT INTO monitor_ticks (api_calls, api_errors) VALUES (?, ?)",
+        api_calls,
+        api_errors
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn prune_monitor_ticks(pool: &SqlitePool, days: i64) -> anyhow::Result<u64> {
+    let cutoff = format!("-{} days", days);
+    let result = sqlx::query!(
+        "DELETE FROM monitor_ticks WHERE created_at < datetime('now', ?)",
+        cutoff
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Bot-wide numbers for /globalstats
+#[derive(Debug, Clone)]
+pub struct GlobalStats {
+    pub users: i64,
+    pub wallets: i64,
+    pub distinct_wallets: i64,
+    /// Monitor API calls and failures in the last hour
+    pub api_calls: i64,
+    pub api_errors: i64,
+    pub database_bytes: i64,
+}
+
+pub async fn get_global_stats(pool: &SqlitePool) -> anyhow::Result<GlobalStats> {
+    let wallets = sqlx::query!(
+        r#"SELECT COUNT(DISTINCT user_id) as "users!: i64", COUNT(*) as "wallets!: i64",
+                  COUNT(DISTINCT wallet_address) as "distinct_wallets!: i64"
+           FROM tracked_wallets WHERE removed_at IS NULL"#
+    )
+    .fetch_one(pool)
+    .await?;
+
+    let api = sqlx::query!(
+        r#"SELECT COALESCE(SUM(api_calls), 0) as "calls!: i64", COALESCE(SUM(api_errors), 0) as "errors!: i64"
+           FROM monitor_ticks WHERE created_at >= datetime('now', '-1 hour')"#
+    )
+    .fetch_one(pool)
+    .await?;
+
+    let database_bytes = sqlx::query_scalar!(
+        r#"SELECT page_count * page_size as "bytes!: i64" FROM pragma_page_count(), pragma_page_size()"#
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(GlobalStats {
+        users: wallets.users,
+        wallets: wallets.wallets,
+        distinct_wallets: wallets.distinct_wallets,
+        api_calls: api.calls,
+        api_errors: api.errors,
+        database_bytes,
+    })
+}
+
+/// Notifications sent to anyone in the last day per kind, most frequent first
+pub async fn get_global_notification_counts(
+    pool: &SqlitePool,
+) -> anyhow::Result<Vec<(String, i64)>> {
+    let rows = sqlx::query!(
+        r#"SELECT kind, COUNT(*) as "count!: i64" FROM notification_log
+           WHERE created_at >= datetime('now', '-1 day')
+           GROUP BY kind ORDER BY COUNT(*) DESC, kind"#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(|r| (r.kind, r.count)).collect())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    const WALLET_A: &str = "0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+    const WALLET_B: &str = "0xbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb";
+
+    /// Fresh in-memory database, one connection so every query sees the same one
+    pub(crate) async fn test_pool() -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!().run(&pool).await.unwrap();
+        pool
+    }
+
+    #[tokio::test]
+    async fn duplicate_notes_are_rejected() {
+        let pool = test_pool().await;
+        assert_eq!(
+            add_wallet(&pool, 1, WALLET_A, Some("Whale")).await.unwrap(),
+            AddWalletResult::Added
+        );
+        // Notes compare case-insensitively, on insert and on update
+        assert_eq!(
+            add_wallet(&pool, 1, WALLET_B, Some("whale")).await.unwrap(),
+            AddWalletResult::NoteInUse
+        );
+        assert_eq!(get_user_wallets(&pool, 1).await.unwrap().len(), 1);
+        add_wallet(&pool, 1, WALLET_B, None).await.unwrap();
+        assert_eq!(
+            add_wallet(&pool, 1, WALLET_B, Some("WHALE")).await.unwrap(),
+            AddWalletResult::NoteInUse
+        );
+        // Re-saving a wallet's own note and another user's same note are fine
+        assert_eq!(
+            add_wallet(&pool, 1, WALLET_A, Some("whale")).await.unwrap(),
+            AddWalletResult::Updated
+        );
+        assert_eq!(
+            add_wallet(&pool, 2, WALLET_B, Some("whale")).await.unwrap(),
+            AddWalletResult::Added
+        );
+    }
+
+    #[tokio::test]
+    async fn renaming_a_coin_rewrites_every_reference() {
+        let pool = test_pool().await;
+        set_only_coins(&pool, 1, &["PEPE".to_string(), "kPEPE".to_string()])
+            .await
+            .unwrap();
+        add_ignored_coin(&pool, 1, "PEPE").await.unwrap();
+        add_ignored_coin(&pool, 2, "PEPE").await.unwrap();
+
+        let mut configured = get_all_configured_coins(&pool).await.unwrap();
+        configured.sort();
+        assert_eq!(
+            configured,
+            vec![
+                (1, "PEPE".to_string()),
+                (1, "kPEPE".to_string()),
+                (2, "PEPE".to_string())
+            ]
+        );
+
+        rename_user_coin(&pool, 1, "PEPE", "kPEPE").await.unwrap();
+        // A row that already used the new name absorbs the renamed one
+        assert_eq!(get_user_only_coins(&pool, 1).await.unwrap(), vec!["kPEPE"]);
+        assert_eq!(
+            get_user_ignored_coins(&pool, 1).await.unwrap(),
+            vec!["kPEPE"]
+        );
+        // Other users keep theirs until their own rename
+        assert_eq!(
+            get_user_ignored_coins(&pool, 2).await.unwrap(),
+            vec!["PEPE"]
+        );
+    }
+
+    fn at(time: &str) -> chrono::NaiveDateTime {
+        chrono::NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    fn period(start: &str, end: Option<&str>) -> (String, Option<String>) {
+        (start.to_string(), end.map(str::to_string))
+    }
+
+    #[test]
+    fn tracking_gaps_skip_overlapping_and_open_periods() {
+        let gaps = tracking_gaps([
+            period("2025-01-01 00:00:00", Some("2025-01-10 00:00:00")),
+            // Another user overlapping the first period leaves no gap
+            period("2025-01-05 00:00:00", Some("2025-01-12 00:00:00")),
+            period("2025-02-01 00:00:00", Some("2025-02-03 00:00:00")),
+            period("2025-03-01 00:00:00", None),
+            // Nothing after an open period is a gap
+            period("2025-04-01 00:00:00", None),
+        ]);
+        assert_eq!(
+            gaps,
+            vec![
+                (at("2025-01-12 00:00:00"), at("2025-02-01 00:00:00")),
+                (at("2025-02-03 00:00:00"), at("2025-03-01 00:00:00")),
+            ]
+        );
+        assert!(tracking_gaps([period("2025-01-01 00:00:00", None)]).is_empty());
+    }
+
+    #[test]
+    fn gaps_since_clips_to_the_window() {
+        let gaps = vec![
+            (at("2025-01-01 00:00:00"), at("2025-01-05 00:00:00")),
+            (at("2025-01-08 00:00:00"), at("2025-01-12 00:00:00")),
+        ];
+        assert_eq!(
+            gaps_since(&gaps, at("2025-01-10 00:00:00")),
+            vec![(at("2025-01-10 00:00:00"), at("2025-01-12 00:00:00"))]
+        );
+        assert!(gaps_since(&gaps, at("2025-02-01 00:00:00")).is_empty());
+    }
+
+    /// Move a user's tracking periods of a wallet to fixed times, oldest first
+    async fn backdate_periods(pool: &SqlitePool, periods: &[(&str, Option<&str>)]) {
+        let ids: Vec<i64> = sqlx::query_scalar("SELECT id FROM tracking_periods ORDER BY id")
+            .fetch_all(pool)
+            .await
+            .unwrap();
+        for (id, (start, end)) in ids.iter().zip(periods) {
+            sqlx::query("UPDATE tracking_periods SET started_at = ?, ended_at = ? WHERE id = ?")
+                .bind(start)
+                .bind(end)
+                .bind(id)
+                .execute(pool)
+                .await
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn history_survives_remove_and_re_add() {
+        let pool = test_pool().await;
+        add_wallet(&pool, 1, WALLET_A, None).await.unwrap();
+        record_closed_position(&pool, WALLET_A, "BTC", true, 1.0, 100.0, Some(110.0), 10.0)
+            .await
+            .unwrap();
+        assert!(remove_wallet(&pool, 1, WALLET_A).await.unwrap());
+        add_wallet(&pool, 1, WALLET_A, None).await.unwrap();
+        backdate_periods(
+            &pool,
+            &[
+                ("2025-01-01 00:00:00", Some("2025-01-10 00:00:00")),
+                ("2025-02-01 00:00:00", None),
+            ],
+        )
+        .await;
+
+        // The closed position is still there, with the untracked month as a gap
+        assert_eq!(
+            get_closed_positions(&pool, WALLET_A, 10)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        let gap = (at("2025-01-10 00:00:00"), at("2025-02-01 00:00:00"));
+        assert_eq!(get_tracking_gaps(&pool, WALLET_A).await.unwrap(), vec![gap]);
+        assert_eq!(
+            get_user_tracking_gaps(&pool, 1).await.unwrap(),
+            HashMap::from([(WALLET_A.to_string(), vec![gap])])
+        );
+    }
+
+    #[tokio::test]
+    async fn soft_removal_ends_tracking_until_the_user_returns() {
+        let pool = test_pool().await;
+        add_wallet(&pool, 1, WALLET_A, None).await.unwrap();
+        assert_eq!(soft_remove_user_wallets(&pool, 1).await.unwrap(), 1);
+        assert!(get_all_tracked_wallets(&pool).await.unwrap().is_empty());
+        assert_eq!(touch_user(&pool, 1).await.unwrap(), 1);
+        backdate_periods(
+            &pool,
+            &[
+                ("2025-01-01 00:00:00", Some("2025-01-10 00:00:00")),
+                ("2025-03-01 00:00:00", None),
+            ],
+        )
+        .await;
+
+        assert_eq!(get_all_tracked_wallets(&pool).await.unwrap().len(), 1);
+        assert_eq!(
+            get_tracking_gaps(&pool, WALLET_A).await.unwrap(),
+            vec![(at("2025-01-10 00:00:00"), at("2025-03-01 00:00:00"))]
+        );
+    }
+
+    #[tokio::test]
+    async fn unlisted_coin_notice_is_sent_once() {
+        let pool = test_pool().await;
+        assert!(mark_unlisted_coin_notice(&pool, 1, "FOO").await.unwrap());
+        assert!(!mark_unlisted_coin_notice(&pool, 1, "FOO").await.unwrap());
+        assert!(mark_unlisted_coin_notice(&pool, 2, "FOO").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn stale_notification_threads_start_over() {
+        let pool = test_pool().await;
+        assert_eq!(
+            get_thread_message(&pool, 1, WALLET_A, 24).await.unwrap(),
+            None
+        );
+        set_thread_message(&pool, 1, WALLET_A, 10).await.unwrap();
+        assert_eq!(
+            get_thread_message(&pool, 1, WALLET_A, 24).await.unwrap(),
+            Some(10)
+        );
+
+        sqlx::query("UPDATE notification_threads SET sent_at = datetime('now', '-25 hours')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert_eq!(
+            get_thread_message(&pool, 1, WALLET_A, 24).await.unwrap(),
+            None
+        );
+        // Threads stored before sent_at existed have no age and start over too
+        sqlx::query("UPDATE notification_threads SET sent_at = NULL")
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert_eq!(
+            get_thread_message(&pool, 1, WALLET_A, 24).await.unwrap(),
+            None
+        );
+        // The next notification starts the new chain
+        set_thread_message(&pool, 1, WALLET_A, 11).await.unwrap();
+        assert_eq!(
+            get_thread_message(&pool, 1, WALLET_A, 24).await.unwrap(),
+            Some(11)
+        );
+    }
+}
@@ -0,0 +1,199 @@
+use log::{error, info, warn};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::sync::Arc;
+use teloxide::{prelude::*, types::ParseMode};
+use tokio::sync::RwLock;
+use tokio::time::{Duration, interval};
+
+use crate::bot::{format_wallet_display, split_lines};
+use crate::db;
+use crate::dry_run;
+use crate::hyperliquid::{self, PositionTracker, format_pnl};
+
+const CHECK_INTERVAL_SECS: u64 = 60;
+/// Events read per digest, a day of activity stays well below this
+const MAX_DIGEST_EVENTS: i64 = 2000;
+/// Coins listed per wallet for opens and closes before collapsing into "+N more"
+const MAX_COINS_LISTED: usize = 8;
+
+/// Send each opted-in user a summary of their wallets once a day at their chosen time
+pub async fn run_daily_digest(pool: SqlitePool, bot: Bot, state: Arc<RwLock<PositionTracker>>) {
+    let mut interval = interval(Duration::from_secs(CHECK_INTERVAL_SECS));
+
+    info!("Daily digest started");
+
+    loop {
+        interval.tick().await;
+
+        let users = match db::get_users_due_digest(&pool).await {
+            Ok(users) => users,
+            Err(e) => {
+                error!("Failed to fetch users due a digest: {}", e);
+                continue;
+            }
+        };
+        if users.is_empty() {
+            continue;
+        }
+
+        let paused_users = db::get_paused_users(&pool).await.unwrap_or_else(|e| {
+            error!("Failed to fetch paused users: {}", e);
+            Default::default()
+        });
+
+        for due in users {
+            if !paused_users.contains(&due.user_id)
+                && let Err(e) = send_digest(&bot, &pool, &state, due.user_id, &due.since).await
+            {
+                warn!("Failed to send daily digest to {}: {}", due.user_id, e);
+            }
+            // Mark as sent even on failure so a blocked bot isn't retried every minute
+            if let Err(e) = db::mark_digest_sent(&pool, due.user_id, &due.date).await {
+                error!("Failed to mark digest sent for {}: {}", due.user_id, e);
+            }
+        }
+    }
+}
+
+/// Opens, closes and realized PnL of one wallet since the last digest
+#[derive(Default)]
+struct Activity {
+    opened: Vec<String>,
+    closed: Vec<String>,
+    realized_pnl: f64,
+}
+
+fn coin_list(coins: &[String]) -> String {
+    let mut list = coins
+        .iter()
+        .take(MAX_COINS_LISTED)
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(", ");
+    if coins.len() > MAX_COINS_LISTED {
+        list.push_str(&format!(" +{} more", coins.len() - MAX_COINS_LISTED));
+    }
+    list
+}
+
+async fn send_digest(
+    bot: &Bot,
+    pool: &SqlitePool,
+    state: &Arc<RwLock<PositionTracker>>,
+    user_id: i64,
+    since: &str,
+) -> anyhow::Result<()> {
+    let wallets = db::get_user_wallets(pool, user_id).await?;
+    let events = db::get_user_events_since(pool, user_id, since, MAX_DIGEST_EVENTS).await?;
+    let previous_unrealized = db::get_digest_snapshots(pool, user_id).await?;
+
+    let mut activity: HashMap<&str, Activity> = HashMap::new();
+    // Backfilled events are history imported now, not activity since the last digest
+    for event in events.iter().filter(|e| !e.backfilled) {
+        let entry = activity.entry(event.wallet_address.as_str()).or_default();
+        match event.kind.as_str() {
+            "opened" => entry.opened.push(event.coin.clone()),
+            "closed" => {
+                entry.closed.push(event.coin.clone());
+                entry.realized_pnl += event.pnl;
+            }
+            _ => {}
+        }
+    }
+
+    let tracker = state.read().await;
+    let mut snapshots = Vec::new();
+    let mut sections = Vec::new();
+    let mut total_realized = 0.0;
+    let mut total_unrealized = 0.0;
+    let mut total_unrealized_change = 0.0;
+
+    for wallet in wallets.iter().filter(|w| !w.muted) {
+        let positions = tracker.positions.get(&wallet.wallet_address);
+        let position_count = positions.map_or(0, |p| p.len());
+        let unrealized: f64 = positions
+            .map(|p| {
+                p.values()
+                    .map(|pos| pos.unrealized_pnl.parse::<f64>().unwrap_or(0.0))
+                    .sum()
+            })
+            .unwrap_or(0.0);
+        snapshots.push((wallet.wallet_address.clone(), unrealized));
+
+        let wallet_activity = activity.remove(wallet.wallet_address.as_str());
+        if position_count == 0 && wallet_activity.is_none() {
+            continue;
+        }
+
+        // Wallets without a snapshot are new since the last digest, so there's no change yet
+        let unrealized_change = previous_unrealized
+            .get(&wallet.wallet_address)
+            .map(|previous| unrealized - previous);
+        total_unrealized += unrealized;
+        total_unrealized_change += unrealized_change.unwrap_or(0.0);
+
+        let mut section = format!(
+            "👛 {}\n📊 {} open · Unrealized {}",
+            format_wallet_display(&wallet.wallet_address, wallet.note.as_deref(), false),
+            position_count,
+            format_pnl(unrealized)
+        );
+        if let Some(change) = unrealized_change {
+            section.push_str(&format!(" ({})", format_pnl(change)));
+        }
+        if let Some(wallet_activity) = wallet_activity {
+            if !wallet_activity.opened.is_empty() {
+                section.push_str(&format!(
+                    "\n🟢 Opened: {}",
+                    coin_list(&wallet_activity.opened)
+                ));
+            }
+            if !wallet_activity.closed.is_empty() {
+                section.push_str(&format!(
+                    "\n🔴 Closed: {} · Realized {}",
+                    coin_list(&wallet_activity.closed),
+                    format_pnl(wallet_activity.realized_pnl)
+                ));
+                total_realized += wallet_activity.realized_pnl;
+            }
+        }
+        sections.push(section);
+    }
+    drop(tracker);
+
+    let header = if sections.is_empty() {
+        "<b>🗞 Daily Digest</b>\n\nNo open positions or activity since the last digest.".to_string()
+    } else {
+        format!(
+            "<b>🗞 Daily Digest</b>\n\n\
+             💵 Realized: {}\n\
+             📈 Unrealized: {} ({} since last digest)",
+            format_pnl(total_realized),
+            format_pnl(total_unrealized),
+            format_pnl(total_unrealized_change)
+        )
+    };
+    let mut lines: Vec<String> = sections.into_iter().map(|s| format!("\n{}", s)).collect();
+    lines.push(
+        "\n<i>Change the time with /digest HH:MM or turn it off with /digest off</i>".to_string(),
+    );
+    let messages = split_lines(header, lines);
+
+    // Snapshot before sending so a failed send still starts the next day from here
+    db::replace_digest_snapshots(pool, user_id, &snapshots).await?;
+
+    for message in messages {
+        if dry_run::enabled() {
+            dry_run::record(user_id, &message);
+            continue;
+        }
+        bot.send_message(ChatId(user_id), message)
+            .parse_mode(ParseMode::Html)
+            .await?;
+    }
+
+    hyperliquid::log_delivery(pool, user_id, None, "daily_digest").await;
+    info!("Sent daily digest to user {}", user_id);
+    Ok(())
+}
@@ -0,0 +1,40 @@
+use log::{info, warn};
+use std::sync::LazyLock;
+
+use crate::broadcast;
+
+/// Only an explicit `DRY_RUN=1` turns it on, there is no default or build flag
+static ENABLED: LazyLock<bool> =
+    LazyLock::new(|| std::env::var("DRY_RUN").is_ok_and(|v| v.trim() == "1"));
+
+/// Whether notifications should be logged instead of sent
+pub fn enabled() -> bool {
+    *ENABLED
+}
+
+/// Whether commands from this user should be handled, only admins are answered in dry-run mode
+pub fn allows_user(user_id: i64) -> bool {
+    !enabled() || broadcast::is_admin(user_id)
+}
+
+/// Warn loudly at startup so dry-run mode is never left on unnoticed
+pub fn log_banner() {
+    if !enabled() {
+        return;
+    }
+
+    warn!("==============================================================");
+    warn!("DRY RUN: notifications are logged, nothing is sent to users");
+    let admins = broadcast::admin_ids();
+    if admins.is_empty() {
+        warn!("DRY RUN: ADMIN_IDS is not set, no commands will be answered");
+    } else {
+        warn!("DRY RUN: only answering commands from users {:?}", admins);
+    }
+    warn!("==============================================================");
+}
+
+/// Log a message exactly as it would have been sent
+pub fn record(chat_id: i64, text: &str) {
+    info!("[DRY RUN] Message to chat {}:\n{}", chat_id, text);
+}
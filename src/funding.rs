@@ -0,0 +1,182 @@
+use log::{error, info, warn};
+use reqwest::Client;
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use tokio::time::{Duration, interval};
+
+use crate::db;
+use crate::hyperliquid::{self, ClosedFunding, PositionChange};
+
+const SYNC_INTERVAL_SECS: u64 = 900;
+
+/// Periodically add new funding payments to the open positions of wallets with collection on
+pub async fn run_funding_sync(pool: SqlitePool) {
+    let client = Client::builder()
+        .timeout(Duration::from_secs(30))
+        .build()
+        .expect("Failed to create HTTP client");
+    let mut interval = interval(Duration::from_secs(SYNC_INTERVAL_SECS));
+
+    info!("Funding sync started");
+
+    loop {
+        interval.tick().await;
+
+        let wallets = match db::get_funding_wallets(&pool).await {
+            Ok(wallets) => wallets,
+            Err(e) => {
+                error!("Failed to fetch funding wallets: {}", e);
+                continue;
+            }
+        };
+        if wallets.is_empty() {
+            continue;
+        }
+
+        let positions = match db::get_all_active_positions(&pool).await {
+            Ok(positions) => positions,
+            Err(e) => {
+                error!("Failed to load positions for funding sync: {}", e);
+                continue;
+            }
+        };
+
+        for wallet_address in wallets {
+            let open_coins: Vec<&String> = positions
+                .get(&wallet_address)
+                .map(|coins| coins.keys().collect())
+                .unwrap_or_default();
+            if let Err(e) = sync_wallet(&client, &pool, &wallet_address, &open_coins).await {
+                warn!("Failed to sync funding for {}: {}", wallet_address, e);
+            }
+        }
+    }
+}
+
+async fn sync_wallet(
+    client: &Client,
+    pool: &SqlitePool,
+    wallet_address: &str,
+    open_coins: &[&String],
+) -> anyhow::Result<()> {
+    let fundings: HashMap<String, db::PositionFunding> =
+        db::get_position_fundings(pool, wallet_address)
+            .await?
+            .into_iter()
+            .map(|f| (f.coin.clone(), f))
+            .collect();
+
+    // Positions that were already open when collection started only count funding from now on
+    let now = chrono::Utc::now().timestamp_millis();
+    for coin in open_coins {
+        if !fundings.contains_key(*coin) {
+            db::start_position_funding(pool, wallet_address, coin, now, true).await?;
+        }
+    }
+
+    add_new_payments(client, pool, wallet_address, &fundings).await
+}
+
+/// Fetch payments newer than what each position already has and add them
+async fn add_new_payments(
+    client: &Client,
+    pool: &SqlitePool,
+    wallet_address: &str,
+    fundings: &HashMap<String, db::PositionFunding>,
+) -> anyhow::Result<()> {
+    let Some(start_time) = fundings.values().map(|f| f.last_time).min() else {
+        return Ok(());
+    };
+
+    let payments = hyperliquid::fetch_user_funding(client, wallet_address, start_time + 1).await?;
+
+    let mut totals: HashMap<&str, (f64, i64)> = HashMap::new();
+    for payment in &payments {
+        let Some(funding) = fundings.get(&payment.delta.coin) else {
+            continue;
+        };
+        if payment.time <= funding.last_time {
+            continue;
+        }
+        let amount: f64 = payment.delta.usdc.parse().unwrap_or(0.0);
+        let total = totals
+            .entry(funding.coin.as_str())
+            .or_insert((0.0, funding.last_time));
+        total.0 += amount;
+        total.1 = total.1.max(payment.time);
+    }
+
+    for (coin, (amount, last_time)) in totals {
+        db::add_position_funding(pool, wallet_address, coin, amount, last_time).await?;
+    }
+
+    Ok(())
+}
+
+/// Start accumulating funding for opened positions and attach the total to closed ones
+pub async fn apply_to_changes(
+    client: &Client,
+    pool: &SqlitePool,
+    wallet_address: &str,
+    changes: &mut [PositionChange],
+) {
+    let collecting = db::wallet_collects_funding(pool, wallet_address)
+        .await
+        .unwrap_or_else(|e| {
+            error!(
+                "Failed to check funding collection for {}: {}",
+                wallet_address, e
+            );
+            false
+        });
+
+    for change in changes.iter_mut() {
+        match change {
+            PositionChange::Opened { coin, .. } if collecting => {
+                let now = chrono::Utc::now().timestamp_millis();
+                if let Err(e) =
+                    db::start_position_funding(pool, wallet_address, coin, now, false).await
+                {
+                    error!(
+                        "Failed to start funding for {} {}: {}",
+                        wallet_address, coin, e
+                    );
+                }
+            }
+            PositionChange::Closed { coin, funding, .. } => {
+                // Include payments since the last sync before the position's record is dropped
+                if collecting
+                    && let Ok(fundings) = db::get_position_fundings(pool, wallet_address).await
+                {
+                    let pending: HashMap<String, db::PositionFunding> = fundings
+                        .into_iter()
+                        .filter(|f| f.coin == *coin)
+                        .map(|f| (f.coin.clone(), f))
+                        .collect();
+                    if let Err(e) = add_new_payments(client, pool, wallet_address, &pending).await {
+                        warn!(
+                            "Failed to fetch final funding for {} {}: {}",
+                            wallet_address, coin, e
+                        );
+                    }
+                }
+
+                // Always drop the record so a later position in the coin starts from zero
+                match db::take_position_funding(pool, wallet_address, coin).await {
+                    Ok(Some(position_funding)) if collecting => {
+                        *funding = Some(ClosedFunding {
+                            amount: position_funding.funding,
+                            since_tracking: position_funding.since_tracking,
+                        });
+                    }
+                    Ok(_) => {}
+                    Err(e) => error!(
+                        "Failed to load funding for {} {}: {}",
+                        wallet_address, coin, e
+                    ),
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -2,17 +2,83 @@ use log::{error, info, warn};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use sqlx::SqlitePool;
-use std::collections::HashMap;
-use std::sync::Arc;
-use teloxide::{prelude::*, types::ParseMode};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, LazyLock};
+use teloxide::{
+    ApiError, RequestError,
+    prelude::*,
+    types::{MessageId, ParseMode, ReplyParameters},
+    utils::html,
+};
 use tokio::sync::RwLock;
-use tokio::time::{Duration, interval};
+use tokio::time::{Duration, Instant, interval};
 
-use crate::bot::format_wallet_display;
+use crate::alerts::BandedAlert;
+use crate::bot::{format_duration, format_wallet_display};
 use crate::db;
+use crate::dry_run;
+use crate::funding;
+use crate::logbook;
+use crate::metrics;
+use crate::price_alerts;
+use crate::timezone::UserTimezone;
+use crate::vaults;
 
 const HYPERLIQUID_API: &str = "https://api.hyperliquid.xyz/info";
+const LEADERBOARD_API: &str = "https://stats-data.hyperliquid.xyz/Mainnet/leaderboard";
 const POLL_INTERVAL_SECS: u64 = 10;
+/// Default number of wallets processed per tick, the stalest first
+const DEFAULT_CHUNK_SIZE: usize = 500;
+/// Default position count above which a wallet's changes are sent as one digest per update
+const DEFAULT_LARGE_WALLET_POSITIONS: usize = 50;
+/// Most change lines listed in one digest
+const MAX_DIGEST_LINES: usize = 30;
+
+/// Refresh trigger orders every N ticks for wallets holding positions
+const ORDERS_REFRESH_TICKS: u64 = 3;
+/// Keep triggers that left the order book around this many ticks so fills can still be matched
+const TRIGGER_RETENTION_TICKS: u64 = 12;
+/// Max distance (percent) between the mark price and a trigger to attribute an exit to it
+const TRIGGER_MATCH_PCT: f64 = 1.0;
+/// Refresh asset metadata (szDecimals) every N ticks
+const META_REFRESH_TICKS: u64 = 360;
+/// Days of delivered notifications kept for /stats, which looks back a week
+const NOTIFICATION_LOG_DAYS: i64 = 30;
+/// Days of per-tick API call counts kept, /globalstats only looks at the last hour
+const MONITOR_TICK_DAYS: i64 = 1;
+/// Window /status counts failed API calls over
+pub const HEALTH_FAILURE_WINDOW_SECS: i64 = 600;
+/// Perp prices may have at most this many decimals minus the asset's szDecimals
+const MAX_PRICE_DECIMALS: u32 = 6;
+/// ...and at most this many significant figures (integer prices are always valid)
+const PRICE_SIG_FIGS: i32 = 5;
+/// Share of a PnL alert's threshold the PnL has to come back inside before it can fire again
+const PNL_ALERT_HYSTERESIS: f64 = 0.1;
+/// Identical changes within this many ticks are only notified once
+const DEDUP_WINDOW_TICKS: u64 = 3;
+/// A position closed and reopened with the same size within this window is treated as an API flap
+const FLAP_WINDOW: Duration = Duration::from_secs(60);
+/// Default move (percent of the liquidation price) toward the mark that triggers a liquidation alert
+const DEFAULT_LIQUIDATION_MOVE_PCT: f64 = 10.0;
+/// Default price move since entry (percent) at which an open notification is flagged as stale
+const DEFAULT_STALE_ENTRY_MOVE_PCT: f64 = 3.0;
+/// Most entries a time-range info request returns
+const INFO_PAGE_SIZE: usize = 500;
+/// Most fills a userFillsByTime request returns
+pub const FILLS_PAGE_SIZE: usize = 2000;
+/// Default multiple of a wallet's median open that tags a new position as unusually large
+const DEFAULT_UNUSUAL_SIZE_MULTIPLE: f64 = 3.0;
+/// Opens looked at for a wallet's typical size, and how many it needs before comparing
+const OPEN_SIZE_LOOKBACK_DAYS: i64 = 30;
+const MIN_OPENS_FOR_SIZE_STATS: usize = 5;
+/// Open size statistics are recomputed at most this often per wallet
+const OPEN_SIZE_STATS_TTL: Duration = Duration::from_secs(6 * 3600);
+/// Opens in a perp listed less than this long ago are tagged as early positioning
+const NEW_LISTING_WINDOW_SECS: i64 = 24 * 3600;
+/// Threaded notifications start a new chain when the last one is older than this
+const NOTIFICATION_THREAD_MAX_HOURS: i64 = 24;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Position {
@@ -26,9 +92,32 @@ pub struct Position {
     pub unrealized_pnl: String,
     #[serde(rename = "liquidationPx")]
     pub liquidation_px: Option<String>,
+    #[serde(rename = "marginUsed")]
+    pub margin_used: String,
     pub leverage: Option<Leverage>,
+    /// Missing from positions saved before it was read
+    #[serde(rename = "cumFunding", default)]
+    pub cum_funding: Option<CumFunding>,
+}
+
+/// Funding accumulated by a position in USD, positive when it was paid
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CumFunding {
+    pub all_time: String,
+    pub since_open: String,
+    pub since_change: String,
 }
 
+/// szDecimals per perp coin from the exchange meta, refreshed by the monitor.
+///
+/// Kept outside the tracker so price formatting works from any message builder.
+/// The keys double as the interned coin symbols, see `intern_coin`.
+static SZ_DECIMALS: LazyLock<std::sync::RwLock<HashMap<Arc<str>, u32>>> =
+    LazyLock::new(Default::default);
+/// Spot token names from the spot meta, refreshed along with `SZ_DECIMALS`
+static SPOT_TOKENS: LazyLock<std::sync::RwLock<HashSet<String>>> = LazyLock::new(Default::default);
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Leverage {
     #[serde(rename = "type")]
@@ -46,6 +135,444 @@ pub struct AssetPosition {
 #[serde(rename_all = "camelCase")]
 pub struct UserState {
     pub asset_positions: Vec<AssetPosition>,
+    pub margin_summary: MarginSummary,
+    /// Missing from states saved before these were read
+    #[serde(default)]
+    pub cross_margin_summary: Option<MarginSummary>,
+    #[serde(default)]
+    pub cross_maintenance_margin_used: Option<String>,
+    #[serde(default)]
+    pub withdrawable: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MarginSummary {
+    pub account_value: String,
+    pub total_ntl_pos: String,
+    pub total_raw_usd: String,
+    pub total_margin_used: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Fill {
+    pub coin: String,
+    pub px: String,
+    pub sz: String,
+    /// "B" for buys, "A" for sells
+    pub side: String,
+    /// Milliseconds since epoch
+    pub time: i64,
+    /// e.g. "Open Long" or "Close Short"
+    pub dir: String,
+    /// Signed position size before this fill
+    pub start_position: String,
+    pub closed_pnl: String,
+    pub fee: String,
+    /// Taker fills crossed the book, maker fills rested on it
+    #[serde(default)]
+    pub crossed: bool,
+    /// Set on fills of a liquidation
+    #[serde(default)]
+    pub liquidation: Option<FillLiquidation>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FillLiquidation {
+    /// The wallet that was liquidated, the other side of the fill may be a liquidator
+    #[serde(default)]
+    pub liquidated_user: Option<String>,
+}
+
+impl Fill {
+    /// USD traded in this fill
+    pub fn notional(&self) -> f64 {
+        self.px.parse::<f64>().unwrap_or(0.0) * self.sz.parse::<f64>().unwrap_or(0.0)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FrontendOrder {
+    pub coin: String,
+    pub side: String,
+    pub limit_px: String,
+    pub sz: String,
+    pub oid: u64,
+    pub timestamp: u64,
+    pub is_trigger: bool,
+    pub trigger_px: String,
+    pub reduce_only: bool,
+    pub order_type: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Meta {
+    pub universe: Vec<AssetMeta>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AssetMeta {
+    pub name: String,
+    pub sz_decimals: u32,
+    pub max_leverage: u32,
+}
+
+/// A sub-account of a master wallet, trading from its own address
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubAccount {
+    pub name: String,
+    pub sub_account_user: String,
+    pub clearinghouse_state: UserState,
+}
+
+/// Live market data of a perp, in the same order as the meta universe
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AssetCtx {
+    /// Hourly funding rate
+    pub funding: String,
+    /// In coins, not USD
+    pub open_interest: String,
+    pub prev_day_px: String,
+    pub day_ntl_vlm: String,
+    pub mark_px: String,
+}
+
+/// A running TWAP order
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TwapState {
+    pub coin: String,
+    /// "B" for buy, "A" for sell
+    pub side: String,
+    pub sz: String,
+    pub executed_sz: String,
+    pub executed_ntl: String,
+    pub minutes: u64,
+    pub randomize: bool,
+    pub reduce_only: bool,
+    /// Start time (ms)
+    pub timestamp: i64,
+}
+
+impl TwapState {
+    pub fn is_buy(&self) -> bool {
+        self.side == "B"
+    }
+
+    /// Share of the total size filled so far, in percent
+    pub fn progress_pct(&self) -> f64 {
+        let size: f64 = self.sz.parse().unwrap_or(0.0);
+        let executed: f64 = self.executed_sz.parse().unwrap_or(0.0);
+        if size > 0.0 {
+            (executed / size * 100.0).min(100.0)
+        } else {
+            0.0
+        }
+    }
+
+    /// Average fill price so far, None before the first slice fills
+    pub fn average_price(&self) -> Option<f64> {
+        let executed: f64 = self.executed_sz.parse().ok()?;
+        let notional: f64 = self.executed_ntl.parse().ok()?;
+        (executed > 0.0).then(|| notional / executed)
+    }
+
+    /// Scheduled end time (ms)
+    pub fn ends_at(&self) -> i64 {
+        self.timestamp + self.minutes as i64 * 60_000
+    }
+}
+
+/// The parts of the frontend's webData2 state the bot reads, the rest is ignored
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WebData {
+    /// (twap id, state) pairs
+    #[serde(default)]
+    twap_states: Vec<(u64, TwapState)>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpotMeta {
+    pub tokens: Vec<SpotToken>,
+    pub universe: Vec<SpotPair>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpotToken {
+    pub name: String,
+    pub index: u32,
+    pub sz_decimals: u32,
+}
+
+/// A spot market. Its name is how allMids keys it, "@107" for most pairs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpotPair {
+    pub name: String,
+    /// Base and quote token indices
+    pub tokens: Vec<u32>,
+    pub index: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpotState {
+    pub balances: Vec<SpotBalance>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpotBalance {
+    pub coin: String,
+    pub token: u32,
+    pub total: String,
+    pub hold: String,
+}
+
+/// A spot balance with its token name resolved and its USD value, if the token has a USDC pair
+#[derive(Debug, Clone)]
+pub struct SpotHolding {
+    pub name: String,
+    pub total: f64,
+    pub value: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FundingPayment {
+    pub time: i64,
+    pub hash: String,
+    pub delta: FundingDelta,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FundingDelta {
+    pub coin: String,
+    /// Positive when the wallet received funding
+    pub usdc: String,
+    pub szi: String,
+    pub funding_rate: String,
+}
+
+/// Deposit, withdrawal or transfer. The delta's fields depend on its `type`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LedgerUpdate {
+    pub time: i64,
+    pub hash: String,
+    pub delta: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Leaderboard {
+    pub leaderboard_rows: Vec<LeaderboardRow>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LeaderboardRow {
+    pub eth_address: String,
+    pub account_value: String,
+    /// (window, performance) pairs for "day", "week", "month" and "allTime"
+    pub window_performances: Vec<(String, WindowPerformance)>,
+    pub display_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WindowPerformance {
+    pub pnl: String,
+    pub roi: String,
+    pub vlm: String,
+}
+
+impl LeaderboardRow {
+    pub fn performance(&self, window: &str) -> Option<&WindowPerformance> {
+        self.window_performances
+            .iter()
+            .find(|(name, _)| name == window)
+            .map(|(_, perf)| perf)
+    }
+}
+
+/// One window of a wallet's portfolio history, as (time in ms, USD) points
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PortfolioWindow {
+    pub account_value_history: Vec<(i64, String)>,
+    pub pnl_history: Vec<(i64, String)>,
+}
+
+impl PortfolioWindow {
+    /// PnL made over the window, the history is cumulative from its start
+    pub fn pnl(&self) -> Option<f64> {
+        let first: f64 = self.pnl_history.first()?.1.parse().ok()?;
+        let last: f64 = self.pnl_history.last()?.1.parse().ok()?;
+        Some(last - first)
+    }
+
+    /// PnL as a percentage of the account value at the start of the window
+    pub fn return_pct(&self) -> Option<f64> {
+        let start: f64 = self.account_value_history.first()?.1.parse().ok()?;
+        if start <= 0.0 {
+            return None;
+        }
+        Some(self.pnl()? / start * 100.0)
+    }
+
+    /// Account value at the start and end of the window
+    pub fn account_values(&self) -> Option<(f64, f64)> {
+        let start: f64 = self.account_value_history.first()?.1.parse().ok()?;
+        let end: f64 = self.account_value_history.last()?.1.parse().ok()?;
+        Some((start, end))
+    }
+
+    /// The starting account value plus cumulative PnL at each point of the window.
+    ///
+    /// Deposits and withdrawals move the account value but not the PnL, so they
+    /// don't show up as gains or drawdowns here.
+    pub fn equity_history(&self) -> Option<Vec<(i64, f64)>> {
+        let start: f64 = self.account_value_history.first()?.1.parse().ok()?;
+        let first_pnl: f64 = self.pnl_history.first()?.1.parse().ok()?;
+        Some(
+            self.pnl_history
+                .iter()
+                .filter_map(|(time, pnl)| {
+                    let pnl: f64 = pnl.parse().ok()?;
+                    Some((*time, start + pnl - first_pnl))
+                })
+                .collect(),
+        )
+    }
+
+    /// Largest fall from a peak over the window in percent of that peak, see `equity_history`
+    pub fn max_drawdown_pct(&self) -> Option<f64> {
+        let equity = self.equity_history()?;
+        Some(drawdown(&equity).map_or(0.0, |d| d.max_pct))
+    }
+}
+
+/// Peak-to-trough stats of an equity curve, points are (time in ms, USD)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Drawdown {
+    /// Largest fall from a peak in percent of that peak, 0 when the curve never fell
+    pub max_pct: f64,
+    /// The peak and trough of the largest fall, both the first point when there was none
+    pub peak: (i64, f64),
+    pub trough: (i64, f64),
+    /// Fall of the last point from the highest point before it
+    pub current_pct: f64,
+    pub recent_peak: (i64, f64),
+}
+
+/// Drawdown stats of points in time order, `None` for an empty curve.
+///
+/// Peaks at or below zero can't fall by a percentage, so they count as no drawdown.
+pub fn drawdown(points: &[(i64, f64)]) -> Option<Drawdown> {
+    let first = *points.first()?;
+    let mut result = Drawdown {
+        max_pct: 0.0,
+        peak: first,
+        trough: first,
+        current_pct: 0.0,
+        recent_peak: first,
+    };
+    for &point in points {
+        if point.1 > result.recent_peak.1 {
+            result.recent_peak = point;
+        }
+        let peak = result.recent_peak;
+        result.current_pct = if peak.1 > 0.0 {
+            (peak.1 - point.1) / peak.1 * 100.0
+        } else {
+            0.0
+        };
+        if result.current_pct > result.max_pct {
+            result.max_pct = result.current_pct;
+            result.peak = peak;
+            result.trough = point;
+        }
+    }
+    Some(result)
+}
+
+/// A take-profit or stop order last seen in a wallet's open orders
+#[derive(Debug, Clone)]
+pub struct CachedTrigger {
+    pub coin: String,
+    pub trigger_price: f64,
+    pub is_take_profit: bool,
+    /// Sell orders close longs, buy orders close shorts
+    pub closes_long: bool,
+    pub seen_tick: u64,
+}
+
+/// Funding a closed position paid or received while open, positive when received
+#[derive(Debug, Clone)]
+pub struct ClosedFunding {
+    pub amount: f64,
+    /// The position was already open when funding collection started
+    pub since_tracking: bool,
+}
+
+/// Trigger order an exit was attributed to
+#[derive(Debug, Clone)]
+pub struct TriggerHit {
+    pub is_take_profit: bool,
+    pub trigger_price: f64,
+}
+
+/// A recently notified close, kept to recognise a flap if the position reappears
+#[derive(Debug, Clone)]
+pub struct RecentClose {
+    pub size: f64,
+    pub is_long: bool,
+    pub at: Instant,
+    /// (user, message) notifications to delete if it turns out to be a flap
+    pub messages: Vec<(i64, MessageId)>,
+}
+
+/// Liquidation price a cross position is compared against, reset when its size changes
+#[derive(Debug, Clone)]
+pub struct LiquidationBaseline {
+    pub size: f64,
+    pub liquidation_price: f64,
+    /// Band of `liquidation_move_bands` the move since the baseline is in
+    pub level: usize,
+}
+
+/// Price move between a position's entry and its detection
+#[derive(Debug, Clone)]
+pub struct EntryDrift {
+    /// Change of the mid since the entry price, in percent
+    pub move_pct: f64,
+    /// Time since the previous successful poll, the open happened at most this long ago
+    pub max_delay_secs: Option<i64>,
+}
+
+/// Notional of a wallet's recent opens, recomputed lazily when a new open needs it
+#[derive(Debug, Clone)]
+pub struct OpenSizeStats {
+    pub median: f64,
+    pub p90: f64,
+    pub opens: usize,
+    pub computed_at: Instant,
+}
+
+/// A liquidation price that moved toward the mark without the position itself changing
+#[derive(Debug, Clone)]
+pub struct LiquidationMove {
+    pub coin: String,
+    pub is_long: bool,
+    pub leverage: u32,
+    pub old_price: f64,
+    pub new_price: f64,
+    pub mark_price: f64,
 }
 
 #[derive(Debug, Clone)]
@@ -54,20 +581,170 @@ pub struct CachedPosition {
     pub entry_px: String,
     pub unrealized_pnl: String,
     pub leverage: u32,
+    /// `None` until the position is polled, or when it can't be liquidated
+    pub liquidation_px: Option<f64>,
+}
+
+/// How close the mark is to a position's liquidation price
+#[derive(Debug, Clone)]
+pub struct LiquidationDistance {
+    pub coin: String,
+    pub is_long: bool,
+    pub leverage: u32,
+    pub liquidation_price: f64,
+    pub mark_price: f64,
+    /// Distance from the mark to the liquidation price, in percent of the mark
+    pub distance_pct: f64,
+}
+
+/// Health of the monitor loop, for /status
+#[derive(Debug, Clone)]
+pub struct MonitorHealth {
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    /// End of the last tick in which at least one API call succeeded
+    pub last_success_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Wallets polled successfully and unsuccessfully in that tick
+    pub last_polled: usize,
+    pub last_failed: usize,
+    /// Times of recent failed API calls, oldest first
+    recent_failures: VecDeque<chrono::DateTime<chrono::Utc>>,
+}
+
+impl MonitorHealth {
+    fn new() -> Self {
+        Self {
+            started_at: chrono::Utc::now(),
+            last_success_at: None,
+            last_polled: 0,
+            last_failed: 0,
+            recent_failures: VecDeque::new(),
+        }
+    }
+
+    fn record_failure(&mut self) {
+        let now = chrono::Utc::now();
+        let cutoff = now - chrono::Duration::seconds(HEALTH_FAILURE_WINDOW_SECS);
+        while self.recent_failures.front().is_some_and(|t| *t < cutoff) {
+            self.recent_failures.pop_front();
+        }
+        self.recent_failures.push_back(now);
+    }
+
+    /// Failed API calls in the last `HEALTH_FAILURE_WINDOW_SECS`
+    pub fn recent_failure_count(&self) -> usize {
+        let cutoff = chrono::Utc::now() - chrono::Duration::seconds(HEALTH_FAILURE_WINDOW_SECS);
+        self.recent_failures
+            .iter()
+            .filter(|t| **t >= cutoff)
+            .count()
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct PositionTracker {
-    pub positions: HashMap<String, HashMap<String, CachedPosition>>,
+    /// Cached positions by wallet then coin, coins are interned with `intern_coin`
+    pub positions: HashMap<String, HashMap<Arc<str>, CachedPosition>>,
+    /// Margin utilization band each (user, wallet) is currently in, bands are per user
+    pub margin_levels: HashMap<(i64, String), usize>,
+    /// Mid prices by coin, refreshed once per monitor tick
+    pub mids: HashMap<String, f64>,
+    /// Recent trigger orders per wallet, keyed by order id
+    pub trigger_orders: HashMap<String, HashMap<u64, CachedTrigger>>,
+    /// When each wallet's positions were last fetched successfully
+    pub refreshed_at: HashMap<String, chrono::DateTime<chrono::Utc>>,
+    /// Latest clearinghouse state per wallet, for commands that shouldn't hit the API
+    pub snapshots: HashMap<String, UserState>,
+    /// Consecutive failed polls per wallet, reset on the next success
+    pub poll_failures: HashMap<String, u32>,
+    /// When the monitor last picked up each wallet (successful or not), in milliseconds
+    pub processed_at: HashMap<String, i64>,
+    /// Hash of each wallet's positions at the last diff
+    pub position_fingerprints: HashMap<String, u64>,
+    /// (user, wallet) pairs already told the wallet is too large for separate notifications
+    pub large_wallet_warned: HashSet<(i64, String)>,
+    /// Band level of each (user, wallet, coin) position's liquidation warning, see `liquidation_proximity_bands`
+    pub liquidation_warned: HashMap<(i64, String, String), usize>,
+    /// Liquidation price baselines of cross positions, by wallet then coin
+    pub liquidation_baselines: HashMap<String, HashMap<String, LiquidationBaseline>>,
+    /// Typical open size per wallet
+    pub open_size_stats: HashMap<String, OpenSizeStats>,
+    /// Dedup keys of recently notified changes and the tick they were seen
+    pub recent_changes: HashMap<u64, u64>,
+    /// Recent closes by (wallet, coin)
+    pub recent_closes: HashMap<(String, String), RecentClose>,
+    /// When perps listed since the monitor first ran appeared in the meta (unix seconds).
+    ///
+    /// Only as precise as the meta refresh interval.
+    pub listed_at: HashMap<String, i64>,
+    pub health: MonitorHealth,
 }
 
 impl PositionTracker {
     pub fn new() -> Self {
         Self {
             positions: HashMap::new(),
+            margin_levels: HashMap::new(),
+            mids: HashMap::new(),
+            trigger_orders: HashMap::new(),
+            refreshed_at: HashMap::new(),
+            snapshots: HashMap::new(),
+            poll_failures: HashMap::new(),
+            processed_at: HashMap::new(),
+            position_fingerprints: HashMap::new(),
+            large_wallet_warned: HashSet::new(),
+            liquidation_warned: HashMap::new(),
+            liquidation_baselines: HashMap::new(),
+            open_size_stats: HashMap::new(),
+            recent_changes: HashMap::new(),
+            recent_closes: HashMap::new(),
+            listed_at: HashMap::new(),
+            health: MonitorHealth::new(),
         }
     }
 
+    /// Mean seconds since each wallet's last successful poll
+    pub fn average_data_age_secs(&self) -> Option<f64> {
+        if self.refreshed_at.is_empty() {
+            return None;
+        }
+        let now = chrono::Utc::now();
+        let total: i64 = self
+            .refreshed_at
+            .values()
+            .map(|t| (now - *t).num_seconds())
+            .sum();
+        Some(total as f64 / self.refreshed_at.len() as f64)
+    }
+
+    /// Drop everything cached about a wallet nobody tracks anymore
+    pub fn forget_wallet(&mut self, wallet: &str) {
+        self.positions.remove(wallet);
+        self.position_fingerprints.remove(wallet);
+        self.snapshots.remove(wallet);
+        self.margin_levels
+            .retain(|(_, level_wallet), _| level_wallet != wallet);
+        self.liquidation_baselines.remove(wallet);
+        self.liquidation_warned
+            .retain(|(_, warned_wallet, _), _| warned_wallet != wallet);
+        self.trigger_orders.remove(wallet);
+        self.refreshed_at.remove(wallet);
+        self.poll_failures.remove(wallet);
+        self.processed_at.remove(wallet);
+        self.open_size_stats.remove(wallet);
+        self.recent_closes
+            .retain(|(closed_wallet, _), _| closed_wallet != wallet);
+    }
+
+    /// Drop the warnings already sent to a user, for when they stop tracking everything
+    pub fn forget_user(&mut self, user_id: i64) {
+        self.large_wallet_warned
+            .retain(|(warned_user, _)| *warned_user != user_id);
+        self.liquidation_warned
+            .retain(|(warned_user, _, _), _| *warned_user != user_id);
+        self.margin_levels
+            .retain(|(level_user, _), _| *level_user != user_id);
+    }
+
     /// Load positions from database into memory
     pub fn from_db_positions(
         db_positions: HashMap<String, HashMap<String, db::ActivePosition>>,
@@ -79,12 +756,13 @@ impl PositionTracker {
                     .into_iter()
                     .map(|(coin, pos)| {
                         (
-                            coin,
+                            intern_coin(&coin),
                             CachedPosition {
                                 size: pos.size,
                                 entry_px: pos.entry_px,
                                 unrealized_pnl: pos.unrealized_pnl,
                                 leverage: pos.leverage as u32,
+                                liquidation_px: None,
                             },
                         )
                     })
@@ -93,7 +771,61 @@ impl PositionTracker {
             })
             .collect();
 
-        Self { positions }
+        Self {
+            positions,
+            margin_levels: HashMap::new(),
+            mids: HashMap::new(),
+            trigger_orders: HashMap::new(),
+            refreshed_at: HashMap::new(),
+            snapshots: HashMap::new(),
+            poll_failures: HashMap::new(),
+            processed_at: HashMap::new(),
+            position_fingerprints: HashMap::new(),
+            large_wallet_warned: HashSet::new(),
+            liquidation_warned: HashMap::new(),
+            liquidation_baselines: HashMap::new(),
+            open_size_stats: HashMap::new(),
+            recent_changes: HashMap::new(),
+            recent_closes: HashMap::new(),
+            listed_at: HashMap::new(),
+            health: MonitorHealth::new(),
+        }
+    }
+}
+
+/// How often each wallet should be refreshed and how many wallets a tick may process
+#[derive(Debug, Clone)]
+pub struct MonitorConfig {
+    pub target_age_secs: u64,
+    pub chunk_size: usize,
+    /// Wallets with more open positions get digests instead of per-position notifications
+    pub large_wallet_positions: usize,
+}
+
+impl MonitorConfig {
+    /// Read `MONITOR_TARGET_AGE_SECS`, `MONITOR_CHUNK_SIZE` and `LARGE_WALLET_POSITIONS`,
+    /// every wallet every tick by default
+    pub fn from_env() -> Self {
+        let target_age_secs = std::env::var("MONITOR_TARGET_AGE_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(POLL_INTERVAL_SECS)
+            .max(POLL_INTERVAL_SECS);
+        let chunk_size = std::env::var("MONITOR_CHUNK_SIZE")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|size| *size > 0)
+            .unwrap_or(DEFAULT_CHUNK_SIZE);
+        let large_wallet_positions = std::env::var("LARGE_WALLET_POSITIONS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_LARGE_WALLET_POSITIONS);
+
+        Self {
+            target_age_secs,
+            chunk_size,
+            large_wallet_positions,
+        }
     }
 }
 
@@ -108,7 +840,9 @@ pub async fn monitor_positions(pool: SqlitePool, bot: Bot, state: Arc<RwLock<Pos
         Ok(db_positions) => {
             let position_count: usize = db_positions.values().map(|v| v.len()).sum();
             let mut tracker = state.write().await;
+            let health = tracker.health.clone();
             *tracker = PositionTracker::from_db_positions(db_positions);
+            tracker.health = health;
             info!(
                 "Loaded {} positions for {} wallets from database",
                 position_count,
@@ -120,12 +854,108 @@ pub async fn monitor_positions(pool: SqlitePool, bot: Bot, state: Arc<RwLock<Pos
         }
     }
 
+    // Resume with the wallets that waited longest before the restart
+    match db::get_wallet_processed_times(&pool).await {
+        Ok(processed_at) => state.write().await.processed_at = processed_at,
+        Err(e) => error!("Failed to load wallet processing times: {}", e),
+    }
+
+    let config = MonitorConfig::from_env();
+    info!(
+        "Refreshing each wallet every {}s, up to {} wallets per tick",
+        config.target_age_secs, config.chunk_size
+    );
+    let liquidation_move_pct = liquidation_move_pct();
+
     let mut interval = interval(Duration::from_secs(POLL_INTERVAL_SECS));
 
     info!("Position monitoring started");
 
+    let mut tick: u64 = 0;
     loop {
         interval.tick().await;
+        tick += 1;
+
+        if tick.is_multiple_of(META_REFRESH_TICKS) {
+            if let Err(e) = db::prune_notification_log(&pool, NOTIFICATION_LOG_DAYS).await {
+                error!("Failed to prune the notification log: {}", e);
+            }
+            if let Err(e) = db::prune_monitor_ticks(&pool, MONITOR_TICK_DAYS).await {
+                error!("Failed to prune monitor ticks: {}", e);
+            }
+        }
+
+        if tick == 1 || tick.is_multiple_of(META_REFRESH_TICKS) {
+            match fetch_meta(&client).await {
+                Ok(meta) => {
+                    let coins: Vec<String> = meta
+                        .universe
+                        .iter()
+                        .map(|asset| asset.name.clone())
+                        .collect();
+                    match db::record_perp_listings(&pool, &coins).await {
+                        Ok(listed_at) => {
+                            let mut tracker = state.write().await;
+                            for coin in listed_at.keys() {
+                                if !tracker.listed_at.contains_key(coin) && tick > 1 {
+                                    info!("New perp listed: {}", coin);
+                                }
+                            }
+                            tracker.listed_at = listed_at;
+                        }
+                        Err(e) => error!("Failed to record perp listings: {}", e),
+                    }
+                    if let Ok(mut sz_decimals) = SZ_DECIMALS.write() {
+                        // Keep the symbols already shared with the position cache
+                        *sz_decimals = meta
+                            .universe
+                            .into_iter()
+                            .map(|asset| {
+                                let coin = sz_decimals
+                                    .get_key_value(asset.name.as_str())
+                                    .map(|(coin, _)| coin.clone())
+                                    .unwrap_or_else(|| asset.name.into());
+                                (coin, asset.sz_decimals)
+                            })
+                            .collect();
+                    }
+                    match fetch_spot_meta(&client).await {
+                        Ok(spot_meta) => {
+                            if let Ok(mut spot_tokens) = SPOT_TOKENS.write() {
+                                *spot_tokens =
+                                    spot_meta.tokens.into_iter().map(|t| t.name).collect();
+                            }
+                        }
+                        Err(e) => warn!("Failed to fetch spot metadata: {}", e),
+                    }
+                    migrate_stored_coins(&bot, &pool).await;
+                }
+                Err(e) => {
+                    warn!("Failed to fetch asset metadata: {}", e);
+                    state.write().await.health.record_failure();
+                }
+            }
+        }
+
+        // A tick counts as successful once any API call in it succeeds
+        let mut api_reachable = false;
+        let mut polled = 0;
+        let mut failed = 0;
+        let mut mids_failed = false;
+
+        // Refresh the shared mid prices once per tick
+        match fetch_all_mids(&client).await {
+            Ok(mids) => {
+                api_reachable = true;
+                price_alerts::check_price_alerts(&bot, &pool, &mids).await;
+                state.write().await.mids = mids;
+            }
+            Err(e) => {
+                warn!("Failed to fetch mid prices: {}", e);
+                mids_failed = true;
+                state.write().await.health.record_failure();
+            }
+        }
 
         let wallets = match crate::db::get_all_tracked_wallets(&pool).await {
             Ok(w) => w,
@@ -135,47 +965,1001 @@ pub async fn monitor_positions(pool: SqlitePool, bot: Bot, state: Arc<RwLock<Pos
             }
         };
 
-        let mut wallet_users: HashMap<String, Vec<(i64, Option<String>)>> = HashMap::new();
-        for wallet in &wallets {
-            wallet_users
-                .entry(wallet.wallet_address.clone())
-                .or_default()
-                .push((wallet.user_id, wallet.note.clone()));
+        let coin_filters = db::get_all_watched_coins(&pool).await.unwrap_or_else(|e| {
+            error!("Failed to fetch coin filters: {}", e);
+            HashMap::new()
+        });
+        let ignored_coins = db::get_all_ignored_coins(&pool).await.unwrap_or_else(|e| {
+            error!("Failed to fetch ignored coins: {}", e);
+            HashMap::new()
+        });
+        let only_coins = db::get_all_only_coins(&pool).await.unwrap_or_else(|e| {
+            error!("Failed to fetch coin whitelists: {}", e);
+            HashMap::new()
+        });
+        let pnl_alerts = db::get_all_pnl_alerts(&pool).await.unwrap_or_else(|e| {
+            error!("Failed to fetch PnL alerts: {}", e);
+            HashMap::new()
+        });
+
+        let now = chrono::Utc::now().timestamp();
+        let wallet_users = route_subscriptions(&wallets, now);
+
+        let tick_started = chrono::Utc::now().timestamp_millis();
+        let (due, untracked) = {
+            // Untracked wallets would otherwise look stale forever
+            let mut tracker = state.write().await;
+            tracker
+                .refreshed_at
+                .retain(|wallet, _| wallet_users.contains_key(wallet));
+            tracker
+                .poll_failures
+                .retain(|wallet, _| wallet_users.contains_key(wallet));
+            tracker
+                .processed_at
+                .retain(|wallet, _| wallet_users.contains_key(wallet));
+            tracker
+                .open_size_stats
+                .retain(|wallet, _| wallet_users.contains_key(wallet));
+
+            // Forget the positions of wallets nobody tracks anymore, so a re-added wallet
+            // starts from a fresh snapshot instead of reporting everything that changed
+            // while it was gone
+            let untracked: Vec<String> = tracker
+                .positions
+                .keys()
+                .filter(|wallet| !wallet_users.contains_key(*wallet))
+                .cloned()
+                .collect();
+            for wallet in &untracked {
+                tracker.forget_wallet(wallet);
+            }
+
+            // Wallets past their target age, never-processed first, then the stalest,
+            // so a chunk limit slows every wallet down evenly instead of starving some.
+            // Half a tick of slack keeps wallets due every tick at the default settings.
+            let target_ms = (config.target_age_secs * 1000) as i64;
+            let slack_ms = (POLL_INTERVAL_SECS * 1000 / 2) as i64;
+            let mut due: Vec<_> = wallet_users
+                .into_iter()
+                .filter(|(wallet, _)| {
+                    tracker
+                        .processed_at
+                        .get(wallet)
+                        .is_none_or(|t| tick_started - t + slack_ms >= target_ms)
+                })
+                .collect();
+            due.sort_by_key(|(wallet, _)| tracker.processed_at.get(wallet).copied());
+            if due.len() > config.chunk_size {
+                info!(
+                    "{} wallets due, processing the {} stalest this tick",
+                    due.len(),
+                    config.chunk_size
+                );
+                due.truncate(config.chunk_size);
+            }
+            (due, untracked)
+        };
+
+        for wallet in untracked {
+            if let Err(e) = db::delete_wallet_positions(&pool, &wallet).await {
+                error!("Failed to delete positions of untracked {}: {}", wallet, e);
+            }
         }
 
-        for (wallet_address, user_infos) in wallet_users {
+        let mut delivered = DeliverySet::default();
+        for (wallet_address, user_infos) in due {
+            let coin_filter = |user_id: i64| CoinFilter {
+                watched: coin_filters.get(&(user_id, wallet_address.clone())),
+                only: only_coins.get(&user_id).map(Vec::as_slice),
+                ignored: ignored_coins
+                    .get(&user_id)
+                    .map(Vec::as_slice)
+                    .unwrap_or_default(),
+            };
+            state
+                .write()
+                .await
+                .processed_at
+                .insert(wallet_address.clone(), tick_started);
+            if let Err(e) = db::set_wallet_processed_at(&pool, &wallet_address, tick_started).await
+            {
+                error!(
+                    "Failed to store processing time for {}: {}",
+                    wallet_address, e
+                );
+            }
+
             match fetch_user_state(&client, &wallet_address).await {
                 Ok(user_state) => {
+                    polled += 1;
+                    let previous_refresh = {
+                        let mut tracker = state.write().await;
+                        let previous_refresh = tracker
+                            .refreshed_at
+                            .insert(wallet_address.clone(), chrono::Utc::now());
+                        tracker.poll_failures.remove(&wallet_address);
+                        tracker
+                            .snapshots
+                            .insert(wallet_address.clone(), user_state.clone());
+                        previous_refresh
+                    };
+
                     let changes =
                         detect_position_changes(&pool, &state, &wallet_address, &user_state).await;
+                    let mut changes =
+                        filter_repeated_changes(&bot, &state, &wallet_address, changes, tick).await;
 
-                    for change in changes {
-                        for (user_id, note) in &user_infos {
-                            if let Err(e) = send_position_notification(
+                    if !changes.is_empty() {
+                        attribute_trigger_hits(&state, &wallet_address, &mut changes).await;
+                        mark_first_trades(&pool, &wallet_address, &mut changes).await;
+                        mark_unusual_sizes(&pool, &state, &wallet_address, &mut changes).await;
+                        mark_entry_drift(&state, previous_refresh, &mut changes).await;
+                        mark_new_listings(&state, &mut changes).await;
+                        funding::apply_to_changes(&client, &pool, &wallet_address, &mut changes)
+                            .await;
+
+                        let mids = state.read().await.mids.clone();
+                        for change in &changes {
+                            let mark_price = mids.get(change.coin()).copied();
+                            if let Err(e) =
+                                record_position_event(&pool, &wallet_address, change, mark_price)
+                                    .await
+                            {
+                                error!("Failed to record position event: {}", e);
+                            }
+                        }
+                    }
+
+                    // Wallets with too many positions for separate messages get one
+                    // digest per update, logbooks are compact enough to keep as they are
+                    let position_count = user_state.asset_positions.len();
+                    let mut digest_users = HashSet::new();
+                    if position_count > config.large_wallet_positions && !changes.is_empty() {
+                        for subscriber in &user_infos {
+                            let (user_id, note) = (&subscriber.user_id, &subscriber.note);
+                            let has_logbook = db::get_logbook(&pool, *user_id, &wallet_address)
+                                .await
+                                .map(|logbook| logbook.is_some())
+                                .unwrap_or(false);
+                            if has_logbook {
+                                continue;
+                            }
+                            digest_users.insert(*user_id);
+
+                            let settings = db::get_user_settings(&pool, *user_id)
+                                .await
+                                .unwrap_or_else(|e| {
+                                    error!("Failed to load settings for {}: {}", user_id, e);
+                                    db::UserSettings::default()
+                                });
+                            let user_changes: Vec<&PositionChange> = changes
+                                .iter()
+                                .filter(|change| {
+                                    should_notify(
+                                        &settings,
+                                        subscriber,
+                                        &coin_filter(*user_id),
+                                        change,
+                                        now,
+                                    ) && delivered.claim(*user_id, &wallet_address, change)
+                                })
+                                .collect();
+                            if user_changes.is_empty() {
+                                continue;
+                            }
+
+                            let first_digest = state
+                                .write()
+                                .await
+                                .large_wallet_warned
+                                .insert((*user_id, wallet_address.clone()));
+                            match send_change_digest(
                                 &bot,
                                 *user_id,
                                 &wallet_address,
                                 note.as_deref(),
-                                &change,
+                                &user_changes,
+                                first_digest.then_some(position_count),
+                                &settings.tz(),
                             )
                             .await
                             {
-                                error!("Failed to send notification to {}: {}", user_id, e);
+                                // Count each change of the digest under its own kind
+                                Ok(()) => {
+                                    for change in &user_changes {
+                                        log_delivery(
+                                            &pool,
+                                            *user_id,
+                                            Some(&wallet_address),
+                                            change.kind(),
+                                        )
+                                        .await;
+                                    }
+                                }
+                                Err(e) => {
+                                    error!("Failed to send change digest to {}: {}", user_id, e)
+                                }
+                            }
+                        }
+                    }
+
+                    for change in changes {
+                        let mut sent_messages = Vec::new();
+                        for subscriber in &user_infos {
+                            let (user_id, note) = (&subscriber.user_id, &subscriber.note);
+                            if digest_users.contains(user_id) {
+                                continue;
+                            }
+                            let settings = db::get_user_settings(&pool, *user_id)
+                                .await
+                                .unwrap_or_else(|e| {
+                                    error!("Failed to load settings for {}: {}", user_id, e);
+                                    db::UserSettings::default()
+                                });
+                            if !should_notify(
+                                &settings,
+                                subscriber,
+                                &coin_filter(*user_id),
+                                &change,
+                                now,
+                            ) || !delivered.claim(*user_id, &wallet_address, &change)
+                            {
+                                continue;
+                            }
+                            let logbook = db::get_logbook(&pool, *user_id, &wallet_address)
+                                .await
+                                .unwrap_or_else(|e| {
+                                    error!("Failed to load logbook for {}: {}", user_id, e);
+                                    None
+                                });
+
+                            let result = match logbook {
+                                Some(logbook) => logbook::append_entry(
+                                    &bot,
+                                    &pool,
+                                    &logbook,
+                                    note.as_deref(),
+                                    &change,
+                                    &settings.tz(),
+                                )
+                                .await
+                                .map(|_| None),
+                                None => send_position_notification(
+                                    &bot,
+                                    &pool,
+                                    *user_id,
+                                    &wallet_address,
+                                    note.as_deref(),
+                                    &change,
+                                    &settings,
+                                )
+                                .await
+                                .map(Some),
+                            };
+
+                            match result {
+                                Ok(sent) => {
+                                    if let Some(message_id) = sent {
+                                        sent_messages.push((*user_id, message_id));
+                                    }
+                                    log_delivery(
+                                        &pool,
+                                        *user_id,
+                                        Some(&wallet_address),
+                                        change.kind(),
+                                    )
+                                    .await;
+                                }
+                                Err(e) => {
+                                    error!("Failed to send notification to {}: {}", user_id, e)
+                                }
+                            }
+                        }
+
+                        if let PositionChange::Closed {
+                            coin,
+                            was_long,
+                            size,
+                            ..
+                        } = &change
+                        {
+                            state.write().await.recent_closes.insert(
+                                (wallet_address.clone(), coin.clone()),
+                                RecentClose {
+                                    size: *size,
+                                    is_long: *was_long,
+                                    at: Instant::now(),
+                                    messages: sent_messages,
+                                },
+                            );
+                        }
+                    }
+
+                    // Refresh after matching so triggers that just filled are still cached
+                    if tick.is_multiple_of(ORDERS_REFRESH_TICKS) {
+                        refresh_trigger_orders(
+                            &client,
+                            &state,
+                            &wallet_address,
+                            !user_state.asset_positions.is_empty(),
+                            tick,
+                        )
+                        .await;
+                    }
+
+                    if let Some(alerts) = pnl_alerts.get(&wallet_address) {
+                        check_pnl_alerts(
+                            &bot,
+                            &pool,
+                            &wallet_address,
+                            &user_infos,
+                            &user_state,
+                            alerts,
+                            now,
+                        )
+                        .await;
+                    }
+
+                    if let Some(utilization) = margin_utilization(&user_state.margin_summary) {
+                        for subscriber in &user_infos {
+                            let (user_id, note) = (&subscriber.user_id, &subscriber.note);
+                            let settings = match db::get_user_settings(&pool, *user_id).await {
+                                Ok(settings)
+                                    if settings.margin_alerts
+                                        && subscriber.receives(&settings, now) =>
+                                {
+                                    settings
+                                }
+                                _ => continue,
+                            };
+                            let Some(threshold) = check_margin_bands(
+                                &state,
+                                *user_id,
+                                &wallet_address,
+                                &settings.margin_bands(),
+                                utilization,
+                            )
+                            .await
+                            else {
+                                continue;
+                            };
+                            let vault_summary =
+                                vaults::included_equity(&pool, *user_id, &wallet_address).await;
+                            match send_margin_alert(
+                                &bot,
+                                *user_id,
+                                &wallet_address,
+                                note.as_deref(),
+                                &user_state,
+                                threshold,
+                                vault_summary,
+                            )
+                            .await
+                            {
+                                Ok(()) => {
+                                    log_delivery(&pool, *user_id, Some(&wallet_address), "margin")
+                                        .await
+                                }
+                                Err(e) => {
+                                    error!("Failed to send margin alert to {}: {}", user_id, e)
+                                }
+                            }
+                        }
+                    }
+
+                    let liquidation_moves = check_liquidation_moves(
+                        &state,
+                        &wallet_address,
+                        &user_state,
+                        liquidation_move_pct,
+                    )
+                    .await;
+                    if !liquidation_moves.is_empty() {
+                        for subscriber in &user_infos {
+                            let (user_id, note) = (&subscriber.user_id, &subscriber.note);
+                            let receives = db::get_user_settings(&pool, *user_id)
+                                .await
+                                .is_ok_and(|settings| subscriber.receives(&settings, now));
+                            let enabled =
+                                db::get_liquidation_alerts(&pool, *user_id, &wallet_address)
+                                    .await
+                                    .unwrap_or(false);
+                            if !receives || !enabled {
+                                continue;
+                            }
+                            for liquidation_move in liquidation_moves
+                                .iter()
+                                .filter(|m| coin_filter(*user_id).allows(&m.coin))
+                            {
+                                match send_liquidation_alert(
+                                    &bot,
+                                    *user_id,
+                                    &wallet_address,
+                                    note.as_deref(),
+                                    liquidation_move,
+                                )
+                                .await
+                                {
+                                    Ok(()) => {
+                                        log_delivery(
+                                            &pool,
+                                            *user_id,
+                                            Some(&wallet_address),
+                                            "liquidation_moved",
+                                        )
+                                        .await
+                                    }
+                                    Err(e) => error!(
+                                        "Failed to send liquidation alert to {}: {}",
+                                        user_id, e
+                                    ),
+                                }
+                            }
+                        }
+                    }
+
+                    let distances = liquidation_distances(&state, &wallet_address).await;
+                    for subscriber in &user_infos {
+                        let (user_id, note) = (&subscriber.user_id, &subscriber.note);
+                        let threshold = match db::get_user_settings(&pool, *user_id).await {
+                            Ok(settings) if subscriber.receives(&settings, now) => {
+                                settings.liquidation_proximity_pct
+                            }
+                            _ => continue,
+                        };
+                        let warnings = update_liquidation_warnings(
+                            &state,
+                            *user_id,
+                            &wallet_address,
+                            &distances,
+                            threshold,
+                        )
+                        .await;
+                        for distance in warnings
+                            .into_iter()
+                            .filter(|d| coin_filter(*user_id).allows(&d.coin))
+                        {
+                            match send_liquidation_warning(
+                                &bot,
+                                *user_id,
+                                &wallet_address,
+                                note.as_deref(),
+                                distance,
+                            )
+                            .await
+                            {
+                                Ok(()) => {
+                                    log_delivery(
+                                        &pool,
+                                        *user_id,
+                                        Some(&wallet_address),
+                                        "near_liquidation",
+                                    )
+                                    .await
+                                }
+                                Err(e) => error!(
+                                    "Failed to send liquidation warning to {}: {}",
+                                    user_id, e
+                                ),
                             }
                         }
                     }
                 }
-                Err(e) => {
-                    warn!("Failed to fetch positions for {}: {}", wallet_address, e);
-                }
+                Err(e) => {
+                    warn!("Failed to fetch positions for {}: {}", wallet_address, e);
+                    failed += 1;
+                    let mut tracker = state.write().await;
+                    *tracker
+                        .poll_failures
+                        .entry(wallet_address.clone())
+                        .or_default() += 1;
+                    tracker.health.record_failure();
+                }
             }
         }
+
+        if api_reachable || polled > 0 {
+            let mut tracker = state.write().await;
+            tracker.health.last_success_at = Some(chrono::Utc::now());
+            tracker.health.last_polled = polled;
+            tracker.health.last_failed = failed;
+        }
+
+        // The mids request plus one per wallet polled
+        let api_calls = 1 + polled + failed;
+        let api_errors = failed + usize::from(mids_failed);
+        if let Err(e) = db::record_monitor_tick(&pool, api_calls as i64, api_errors as i64).await {
+            error!("Failed to record monitor tick: {}", e);
+        }
     }
 }
 
-pub async fn fetch_user_state(client: &Client, wallet_address: &str) -> anyhow::Result<UserState> {
+pub async fn fetch_user_state(client: &Client, wallet_address: &str) -> anyhow::Result<UserState> {
+    let request_body = serde_json::json!({
+        "type": "clearinghouseState",
+        "user": wallet_address
+    });
+
+    let response = client
+        .post(HYPERLIQUID_API)
+        .json(&request_body)
+        .send()
+        .await?;
+
+    let user_state: UserState = response.json().await?;
+    Ok(user_state)
+}
+
+/// Fetch mid prices for all perp coins
+pub async fn fetch_all_mids(client: &Client) -> anyhow::Result<HashMap<String, f64>> {
+    let request_body = serde_json::json!({
+        "type": "allMids"
+    });
+
+    let response = client
+        .post(HYPERLIQUID_API)
+        .json(&request_body)
+        .send()
+        .await?;
+
+    let mids: HashMap<String, String> = response.json().await?;
+    Ok(mids
+        .into_iter()
+        .filter_map(|(coin, price)| price.parse().ok().map(|price| (coin, price)))
+        .collect())
+}
+
+/// Fetch the full leaderboard. It's a large response, avoid calling this often.
+pub async fn fetch_leaderboard(client: &Client) -> anyhow::Result<Vec<LeaderboardRow>> {
+    let response = client.get(LEADERBOARD_API).send().await?;
+    let leaderboard: Leaderboard = response.json().await?;
+    Ok(leaderboard.leaderboard_rows)
+}
+
+/// Fetch perp asset metadata
+pub async fn fetch_meta(client: &Client) -> anyhow::Result<Meta> {
+    let request_body = serde_json::json!({
+        "type": "meta"
+    });
+
+    let response = client
+        .post(HYPERLIQUID_API)
+        .json(&request_body)
+        .send()
+        .await?;
+
+    let meta: Meta = response.json().await?;
+    Ok(meta)
+}
+
+/// Fetch a master wallet's sub-accounts, empty when it has none
+pub async fn fetch_sub_accounts(
+    client: &Client,
+    wallet_address: &str,
+) -> anyhow::Result<Vec<SubAccount>> {
+    let request_body = serde_json::json!({
+        "type": "subAccounts",
+        "user": wallet_address
+    });
+
+    let response = client
+        .post(HYPERLIQUID_API)
+        .json(&request_body)
+        .send()
+        .await?;
+
+    // Wallets without sub-accounts get null rather than an empty list
+    let sub_accounts: Option<Vec<SubAccount>> = response.json().await?;
+    Ok(sub_accounts.unwrap_or_default())
+}
+
+/// Fetch every perp with its live market data
+pub async fn fetch_asset_ctxs(client: &Client) -> anyhow::Result<Vec<(AssetMeta, AssetCtx)>> {
+    let request_body = serde_json::json!({
+        "type": "metaAndAssetCtxs"
+    });
+
+    let response = client
+        .post(HYPERLIQUID_API)
+        .json(&request_body)
+        .send()
+        .await?;
+
+    let (meta, ctxs): (Meta, Vec<AssetCtx>) = response.json().await?;
+    Ok(meta.universe.into_iter().zip(ctxs).collect())
+}
+
+/// Fetch a wallet's running TWAP orders by id, oldest first
+pub async fn fetch_twap_states(
+    client: &Client,
+    wallet_address: &str,
+) -> anyhow::Result<Vec<(u64, TwapState)>> {
+    let request_body = serde_json::json!({
+        "type": "webData2",
+        "user": wallet_address
+    });
+
+    let response = client
+        .post(HYPERLIQUID_API)
+        .json(&request_body)
+        .send()
+        .await?;
+
+    let web_data: WebData = response.json().await?;
+    let mut twaps = web_data.twap_states;
+    twaps.sort_by_key(|(id, twap)| (twap.timestamp, *id));
+    Ok(twaps)
+}
+
+/// Fetch spot token and pair metadata
+pub async fn fetch_spot_meta(client: &Client) -> anyhow::Result<SpotMeta> {
+    let request_body = serde_json::json!({
+        "type": "spotMeta"
+    });
+
+    let response = client
+        .post(HYPERLIQUID_API)
+        .json(&request_body)
+        .send()
+        .await?;
+
+    let meta: SpotMeta = response.json().await?;
+    Ok(meta)
+}
+
+/// Fetch a wallet's spot token balances
+pub async fn fetch_spot_state(client: &Client, wallet_address: &str) -> anyhow::Result<SpotState> {
+    let request_body = serde_json::json!({
+        "type": "spotClearinghouseState",
+        "user": wallet_address
+    });
+
+    let response = client
+        .post(HYPERLIQUID_API)
+        .json(&request_body)
+        .send()
+        .await?;
+
+    let spot_state: SpotState = response.json().await?;
+    Ok(spot_state)
+}
+
+/// Name and USD value of each non-zero spot balance, most valuable first.
+///
+/// Tokens are priced through their USDC pair in `mids`, USDC itself counts as $1.
+pub fn spot_holdings(
+    spot_state: &SpotState,
+    meta: &SpotMeta,
+    mids: &HashMap<String, f64>,
+) -> Vec<SpotHolding> {
+    let usdc = meta
+        .tokens
+        .iter()
+        .find(|t| t.name == "USDC")
+        .map(|t| t.index);
+    let names: HashMap<u32, &str> = meta
+        .tokens
+        .iter()
+        .map(|t| (t.index, t.name.as_str()))
+        .collect();
+    let prices: HashMap<u32, f64> = meta
+        .universe
+        .iter()
+        .filter_map(|pair| match pair.tokens.as_slice() {
+            [base, quote] if Some(*quote) == usdc => {
+                mids.get(&pair.name).map(|price| (*base, *price))
+            }
+            _ => None,
+        })
+        .collect();
+
+    let mut holdings: Vec<SpotHolding> = spot_state
+        .balances
+        .iter()
+        .filter_map(|balance| {
+            let total: f64 = balance.total.parse().ok()?;
+            if total == 0.0 {
+                return None;
+            }
+            let price = if Some(balance.token) == usdc {
+                Some(1.0)
+            } else {
+                prices.get(&balance.token).copied()
+            };
+            Some(SpotHolding {
+                name: names
+                    .get(&balance.token)
+                    .map_or_else(|| balance.coin.clone(), |name| name.to_string()),
+                total,
+                value: price.map(|price| total * price),
+            })
+        })
+        .collect();
+    // Unpriced tokens last, ties on name so the order is stable
+    holdings.sort_by(|a, b| {
+        b.value
+            .unwrap_or(f64::NEG_INFINITY)
+            .total_cmp(&a.value.unwrap_or(f64::NEG_INFINITY))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    holdings
+}
+
+/// Fetch a wallet's funding payments since `start_time` (ms)
+pub async fn fetch_user_funding(
+    client: &Client,
+    wallet_address: &str,
+    start_time: i64,
+) -> anyhow::Result<Vec<FundingPayment>> {
+    let request_body = serde_json::json!({
+        "type": "userFunding",
+        "user": wallet_address,
+        "startTime": start_time
+    });
+
+    let response = client
+        .post(HYPERLIQUID_API)
+        .json(&request_body)
+        .send()
+        .await?;
+
+    let payments: Vec<FundingPayment> = response.json().await?;
+    Ok(payments)
+}
+
+/// Fetch all of a wallet's funding payments since `start_time` (ms), following pages.
+///
+/// Time-range info requests return at most `INFO_PAGE_SIZE` entries, so keep asking
+/// from the last payment seen until a short page comes back.
+pub async fn fetch_all_user_funding(
+    client: &Client,
+    wallet_address: &str,
+    start_time: i64,
+) -> anyhow::Result<Vec<FundingPayment>> {
+    let mut payments = Vec::new();
+    let mut start_time = start_time;
+    loop {
+        let page = fetch_user_funding(client, wallet_address, start_time).await?;
+        let full = page.len() >= INFO_PAGE_SIZE;
+        let Some(last_time) = page.last().map(|p| p.time) else {
+            break;
+        };
+        payments.extend(page);
+        if !full {
+            break;
+        }
+        start_time = last_time + 1;
+    }
+
+    Ok(payments)
+}
+
+/// Fetch a wallet's most recent fills, newest first
+pub async fn fetch_user_fills(client: &Client, wallet_address: &str) -> anyhow::Result<Vec<Fill>> {
+    let request_body = serde_json::json!({
+        "type": "userFills",
+        "user": wallet_address
+    });
+
+    let response = client
+        .post(HYPERLIQUID_API)
+        .json(&request_body)
+        .send()
+        .await?;
+
+    let fills: Vec<Fill> = response.json().await?;
+    Ok(fills)
+}
+
+/// Fetch a wallet's fills between `start_time` and `end_time` (ms), at most `FILLS_PAGE_SIZE`
+pub async fn fetch_user_fills_by_time(
+    client: &Client,
+    wallet_address: &str,
+    start_time: i64,
+    end_time: i64,
+) -> anyhow::Result<Vec<Fill>> {
+    let request_body = serde_json::json!({
+        "type": "userFillsByTime",
+        "user": wallet_address,
+        "startTime": start_time,
+        "endTime": end_time
+    });
+
+    let response = client
+        .post(HYPERLIQUID_API)
+        .json(&request_body)
+        .send()
+        .await?;
+
+    let fills: Vec<Fill> = response.json().await?;
+    Ok(fills)
+}
+
+/// Fetch a wallet's fills between `start_time` and `end_time` (ms), oldest first, following
+/// pages until `max_fills`. Returns whether it stopped there before reaching `end_time`.
+///
+/// A full page may cut fills sharing its last millisecond, so the next page starts at
+/// that millisecond again and the partial one is dropped, unless the whole page is it.
+pub async fn fetch_all_user_fills_by_time(
+    client: &Client,
+    wallet_address: &str,
+    start_time: i64,
+    end_time: i64,
+    max_fills: usize,
+) -> anyhow::Result<(Vec<Fill>, bool)> {
+    let mut fills = Vec::new();
+    let mut start_time = start_time;
+    while start_time <= end_time {
+        if fills.len() >= max_fills {
+            fills.truncate(max_fills);
+            return Ok((fills, true));
+        }
+        let mut page =
+            fetch_user_fills_by_time(client, wallet_address, start_time, end_time).await?;
+        page.sort_by_key(|f| f.time);
+        let full = page.len() >= FILLS_PAGE_SIZE;
+        let (Some(first_time), Some(last_time)) =
+            (page.first().map(|f| f.time), page.last().map(|f| f.time))
+        else {
+            break;
+        };
+        if full && first_time < last_time {
+            page.retain(|f| f.time < last_time);
+            start_time = last_time;
+        } else {
+            start_time = last_time + 1;
+        }
+        fills.extend(page);
+        if !full {
+            break;
+        }
+    }
+
+    Ok((fills, false))
+}
+
+/// Fetch a wallet's fills of the last `days`, oldest first, see `fetch_all_user_fills_by_time`
+pub async fn fetch_recent_fills(
+    client: &Client,
+    wallet_address: &str,
+    days: i64,
+    max_fills: usize,
+) -> anyhow::Result<(Vec<Fill>, bool)> {
+    let now = chrono::Utc::now().timestamp_millis();
+    let start = now - days * 24 * 3600 * 1000;
+    fetch_all_user_fills_by_time(client, wallet_address, start, now, max_fills).await
+}
+
+/// Fetch a wallet's deposits, withdrawals and transfers since `start_time` (ms)
+pub async fn fetch_ledger_updates(
+    client: &Client,
+    wallet_address: &str,
+    start_time: i64,
+) -> anyhow::Result<Vec<LedgerUpdate>> {
+    let request_body = serde_json::json!({
+        "type": "userNonFundingLedgerUpdates",
+        "user": wallet_address,
+        "startTime": start_time
+    });
+
+    let response = client
+        .post(HYPERLIQUID_API)
+        .json(&request_body)
+        .send()
+        .await?;
+
+    let updates: Vec<LedgerUpdate> = response.json().await?;
+    Ok(updates)
+}
+
+/// Fetch a wallet's open orders, including trigger orders
+pub async fn fetch_open_orders(
+    client: &Client,
+    wallet_address: &str,
+) -> anyhow::Result<Vec<FrontendOrder>> {
+    let request_body = serde_json::json!({
+        "type": "frontendOpenOrders",
+        "user": wallet_address
+    });
+
+    let response = client
+        .post(HYPERLIQUID_API)
+        .json(&request_body)
+        .send()
+        .await?;
+
+    let orders: Vec<FrontendOrder> = response.json().await?;
+    Ok(orders)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserVaultEquity {
+    pub vault_address: String,
+    pub equity: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VaultDetails {
+    pub name: String,
+    pub vault_address: String,
+    pub leader: String,
+    /// Annualised return as a fraction, 0.12 for 12%
+    #[serde(default)]
+    pub apr: f64,
+    #[serde(default)]
+    pub followers: Vec<VaultFollower>,
+    #[serde(default)]
+    pub is_closed: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VaultFollower {
+    pub user: String,
+    pub vault_equity: String,
+}
+
+/// Fetch a vault's details, None when the address isn't a vault.
+///
+/// The API answers non-vault addresses with `null` (or nothing at all) instead of an error.
+pub async fn fetch_vault_details(
+    client: &Client,
+    vault_address: &str,
+) -> anyhow::Result<Option<VaultDetails>> {
+    let request_body = serde_json::json!({
+        "type": "vaultDetails",
+        "vaultAddress": vault_address
+    });
+
+    let response = client
+        .post(HYPERLIQUID_API)
+        .json(&request_body)
+        .send()
+        .await?;
+
+    let body = response.text().await?;
+    let value: serde_json::Value = if body.trim().is_empty() {
+        serde_json::Value::Null
+    } else {
+        serde_json::from_str(&body)?
+    };
+    if value.is_null() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_value(value)?))
+}
+
+/// Current value of each vault a wallet has deposited into
+pub async fn fetch_user_vault_equities(
+    client: &Client,
+    wallet_address: &str,
+) -> anyhow::Result<Vec<UserVaultEquity>> {
+    let request_body = serde_json::json!({
+        "type": "userVaultEquities",
+        "user": wallet_address
+    });
+
+    let response = client
+        .post(HYPERLIQUID_API)
+        .json(&request_body)
+        .send()
+        .await?;
+
+    let equities: Vec<UserVaultEquity> = response.json().await?;
+    Ok(equities)
+}
+
+/// Fetch a wallet's portfolio history as (window, history) pairs for "day", "week",
+/// "month" and "allTime", plus their perp-only "perp" prefixed variants
+pub async fn fetch_portfolio(
+    client: &Client,
+    wallet_address: &str,
+) -> anyhow::Result<Vec<(String, PortfolioWindow)>> {
     let request_body = serde_json::json!({
-        "type": "clearinghouseState",
+        "type": "portfolio",
         "user": wallet_address
     });
 
@@ -185,8 +1969,697 @@ pub async fn fetch_user_state(client: &Client, wallet_address: &str) -> anyhow::
         .send()
         .await?;
 
-    let user_state: UserState = response.json().await?;
-    Ok(user_state)
+    let portfolio: Vec<(String, PortfolioWindow)> = response.json().await?;
+    Ok(portfolio)
+}
+
+/// Update the cached take-profit and stop orders for a wallet, dropping ones not seen recently
+async fn refresh_trigger_orders(
+    client: &Client,
+    state: &Arc<RwLock<PositionTracker>>,
+    wallet_address: &str,
+    has_positions: bool,
+    tick: u64,
+) {
+    let orders = if has_positions {
+        match fetch_open_orders(client, wallet_address).await {
+            Ok(orders) => orders,
+            Err(e) => {
+                warn!("Failed to fetch open orders for {}: {}", wallet_address, e);
+                return;
+            }
+        }
+    } else {
+        Vec::new()
+    };
+
+    let mut state = state.write().await;
+    let triggers = state
+        .trigger_orders
+        .entry(wallet_address.to_string())
+        .or_default();
+
+    for order in orders.iter().filter(|o| o.is_trigger) {
+        let is_take_profit = order.order_type.starts_with("Take Profit");
+        if !is_take_profit && !order.order_type.starts_with("Stop") {
+            continue;
+        }
+        let Ok(trigger_price) = order.trigger_px.parse::<f64>() else {
+            continue;
+        };
+        triggers.insert(
+            order.oid,
+            CachedTrigger {
+                coin: order.coin.clone(),
+                trigger_price,
+                is_take_profit,
+                closes_long: order.side == "A",
+                seen_tick: tick,
+            },
+        );
+    }
+
+    triggers.retain(|_, t| t.seen_tick + TRIGGER_RETENTION_TICKS >= tick);
+    if triggers.is_empty() {
+        state.trigger_orders.remove(wallet_address);
+    }
+}
+
+/// Flag opens in coins the wallet has no recorded history in, before this tick's events are stored
+async fn mark_first_trades(
+    pool: &SqlitePool,
+    wallet_address: &str,
+    changes: &mut [PositionChange],
+) {
+    for change in changes {
+        if let PositionChange::Opened {
+            coin, first_trade, ..
+        } = change
+        {
+            *first_trade = db::is_first_coin_trade(pool, wallet_address, coin)
+                .await
+                .unwrap_or_else(|e| {
+                    error!("Failed to check coin history for {}: {}", wallet_address, e);
+                    false
+                });
+        }
+    }
+}
+
+/// Flag opens in perps listed within the last day with how long ago the listing was
+async fn mark_new_listings(state: &Arc<RwLock<PositionTracker>>, changes: &mut [PositionChange]) {
+    let now = chrono::Utc::now().timestamp();
+    let tracker = state.read().await;
+    for change in changes {
+        if let PositionChange::Opened {
+            coin, listed_ago, ..
+        } = change
+            && let Some(listed_at) = tracker.listed_at.get(coin.as_str())
+            && now - listed_at < NEW_LISTING_WINDOW_SECS
+        {
+            *listed_ago = Some(now - listed_at);
+        }
+    }
+}
+
+/// Price move since entry (percent) that flags an open as stale, from `STALE_ENTRY_MOVE_PCT`
+pub fn stale_entry_move_pct() -> f64 {
+    std::env::var("STALE_ENTRY_MOVE_PCT")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|pct: &f64| *pct > 0.0)
+        .unwrap_or(DEFAULT_STALE_ENTRY_MOVE_PCT)
+}
+
+/// Compare new positions' entries with the current mids, so a late notification says
+/// whether the price already ran away
+async fn mark_entry_drift(
+    state: &Arc<RwLock<PositionTracker>>,
+    previous_refresh: Option<chrono::DateTime<chrono::Utc>>,
+    changes: &mut [PositionChange],
+) {
+    let max_delay_secs = previous_refresh.map(|t| (chrono::Utc::now() - t).num_seconds());
+    let tracker = state.read().await;
+    for change in changes {
+        if let PositionChange::Opened {
+            coin,
+            entry_price,
+            drift,
+            ..
+        } = change
+        {
+            let Some(mid) = tracker.mids.get(coin.as_str()) else {
+                continue;
+            };
+            if *entry_price <= 0.0 {
+                continue;
+            }
+            *drift = Some(EntryDrift {
+                move_pct: (mid - *entry_price) / *entry_price * 100.0,
+                max_delay_secs,
+            });
+        }
+    }
+}
+
+/// Multiple of a wallet's typical open that tags a new position, from `UNUSUAL_SIZE_MULTIPLE`
+pub fn unusual_size_multiple() -> f64 {
+    std::env::var("UNUSUAL_SIZE_MULTIPLE")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|multiple: &f64| *multiple > 1.0)
+        .unwrap_or(DEFAULT_UNUSUAL_SIZE_MULTIPLE)
+}
+
+/// Tag opens that are large compared to the wallet's own recent opens.
+///
+/// An open counts as unusual when it is both the configured multiple of the median
+/// and above the 90th percentile, so wallets with very uneven sizes aren't tagged
+/// on every larger trade.
+async fn mark_unusual_sizes(
+    pool: &SqlitePool,
+    state: &Arc<RwLock<PositionTracker>>,
+    wallet_address: &str,
+    changes: &mut [PositionChange],
+) {
+    if !changes
+        .iter()
+        .any(|c| matches!(c, PositionChange::Opened { .. }))
+    {
+        return;
+    }
+
+    let cached = state
+        .read()
+        .await
+        .open_size_stats
+        .get(wallet_address)
+        .filter(|s| s.computed_at.elapsed() < OPEN_SIZE_STATS_TTL)
+        .cloned();
+    let stats = match cached {
+        Some(stats) => stats,
+        None => {
+            let mut notionals =
+                match db::get_open_notionals(pool, wallet_address, OPEN_SIZE_LOOKBACK_DAYS).await {
+                    Ok(notionals) => notionals,
+                    Err(e) => {
+                        error!("Failed to load open sizes for {}: {}", wallet_address, e);
+                        return;
+                    }
+                };
+            notionals.sort_by(f64::total_cmp);
+            let percentile = |p: f64| {
+                notionals
+                    .get(((notionals.len() as f64 - 1.0) * p).round() as usize)
+                    .copied()
+                    .unwrap_or(0.0)
+            };
+            let stats = OpenSizeStats {
+                median: percentile(0.5),
+                p90: percentile(0.9),
+                opens: notionals.len(),
+                computed_at: Instant::now(),
+            };
+            state
+                .write()
+                .await
+                .open_size_stats
+                .insert(wallet_address.to_string(), stats.clone());
+            stats
+        }
+    };
+
+    if stats.opens < MIN_OPENS_FOR_SIZE_STATS || stats.median <= 0.0 {
+        return;
+    }
+
+    let threshold = unusual_size_multiple();
+    for change in changes {
+        if let PositionChange::Opened {
+            position_value,
+            size_multiple,
+            ..
+        } = change
+        {
+            let multiple = *position_value / stats.median;
+            if multiple >= threshold && *position_value > stats.p90 {
+                *size_multiple = Some(multiple);
+            }
+        }
+    }
+}
+
+/// Key identifying a change by wallet, coin, kind and rounded sizes
+fn dedup_key(wallet_address: &str, change: &PositionChange) -> u64 {
+    let (size, price) = match change {
+        PositionChange::Opened {
+            size, entry_price, ..
+        }
+        | PositionChange::Closed {
+            size, entry_price, ..
+        } => (*size, *entry_price),
+        PositionChange::Increased {
+            new_size,
+            entry_price,
+            ..
+        }
+        | PositionChange::Decreased {
+            new_size,
+            entry_price,
+            ..
+        } => (*new_size, *entry_price),
+    };
+
+    let mut hasher = DefaultHasher::new();
+    wallet_address.hash(&mut hasher);
+    change.coin().hash(&mut hasher);
+    change.kind().hash(&mut hasher);
+    ((size * 10000.0).round() as i64).hash(&mut hasher);
+    ((price * 100.0).round() as i64).hash(&mut hasher);
+    hasher.finish()
+}
+
+/// A chat's subscription to a wallet for one monitor tick
+#[derive(Debug, Clone, PartialEq)]
+pub struct Subscriber {
+    pub user_id: i64,
+    pub note: Option<String>,
+    /// Muted or snoozed, the wallet is still polled but nothing is delivered
+    pub muted: bool,
+}
+
+impl Subscriber {
+    /// Whether anything about the wallet reaches the chat right now.
+    ///
+    /// Paused users and muted wallets keep being polled, so resuming doesn't
+    /// replay old changes.
+    pub fn receives(&self, settings: &db::UserSettings, now: i64) -> bool {
+        !self.muted && !settings.paused_at(now)
+    }
+}
+
+/// Chats subscribed to each wallet, keyed by the lowercased address.
+///
+/// Each chat appears once per wallet however many subscriptions route it there,
+/// the subscription carrying a note wins as the richer format, and the chat is
+/// only muted when every one of them is.
+fn route_subscriptions(
+    wallets: &[db::TrackedWallet],
+    now: i64,
+) -> HashMap<String, Vec<Subscriber>> {
+    let mut wallet_users: HashMap<String, Vec<Subscriber>> = HashMap::new();
+    for wallet in wallets {
+        let subscribers = wallet_users
+            .entry(wallet.wallet_address.to_lowercase())
+            .or_default();
+        let muted = wallet.muted || wallet.snoozed_for(now).is_some();
+        match subscribers
+            .iter_mut()
+            .find(|subscriber| subscriber.user_id == wallet.user_id)
+        {
+            Some(subscriber) => {
+                if subscriber.note.is_none() {
+                    subscriber.note = wallet.note.clone();
+                }
+                subscriber.muted &= muted;
+            }
+            None => subscribers.push(Subscriber {
+                user_id: wallet.user_id,
+                note: wallet.note.clone(),
+                muted,
+            }),
+        }
+    }
+    wallet_users
+}
+
+/// Changes already delivered this tick, by (chat, wallet, change)
+#[derive(Default)]
+struct DeliverySet(HashSet<(i64, String, u64)>);
+
+impl DeliverySet {
+    /// Whether a chat should still get a change, marking it delivered if so
+    fn claim(&mut self, chat_id: i64, wallet_address: &str, change: &PositionChange) -> bool {
+        let wallet = wallet_address.to_lowercase();
+        let key = dedup_key(&wallet, change);
+        self.0.insert((chat_id, wallet, key))
+    }
+}
+
+/// Drop changes that repeat one notified a few ticks ago, and cancel close-then-reopen flaps.
+///
+/// When a position reappears with the same size shortly after being closed, the
+/// close notifications already sent are deleted and the reopen is not notified.
+async fn filter_repeated_changes(
+    bot: &Bot,
+    state: &Arc<RwLock<PositionTracker>>,
+    wallet_address: &str,
+    changes: Vec<PositionChange>,
+    tick: u64,
+) -> Vec<PositionChange> {
+    let mut tracker = state.write().await;
+    tracker
+        .recent_changes
+        .retain(|_, seen_tick| *seen_tick + DEDUP_WINDOW_TICKS >= tick);
+    tracker
+        .recent_closes
+        .retain(|_, close| close.at.elapsed() <= FLAP_WINDOW);
+
+    let mut kept = Vec::new();
+    let mut cancelled_messages = Vec::new();
+    for change in changes {
+        if let PositionChange::Opened {
+            coin,
+            size,
+            is_long,
+            ..
+        } = &change
+        {
+            let key = (wallet_address.to_string(), coin.clone());
+            let is_flap = tracker.recent_closes.get(&key).is_some_and(|close| {
+                close.is_long == *is_long && (close.size - size).abs() < 0.0001
+            });
+            if is_flap && let Some(close) = tracker.recent_closes.remove(&key) {
+                warn!(
+                    "Flap for {} {}: closed and reopened with size {} after {}s, cancelling both notifications",
+                    wallet_address,
+                    coin,
+                    size,
+                    close.at.elapsed().as_secs()
+                );
+                metrics::FLAPS_SUPPRESSED.fetch_add(1, Ordering::Relaxed);
+                cancelled_messages.extend(close.messages);
+                continue;
+            }
+        }
+
+        let key = dedup_key(wallet_address, &change);
+        if tracker.recent_changes.contains_key(&key) {
+            info!(
+                "Suppressed duplicate {} notification for {} {}",
+                change.kind(),
+                wallet_address,
+                change.coin()
+            );
+            metrics::DUPLICATES_SUPPRESSED.fetch_add(1, Ordering::Relaxed);
+            continue;
+        }
+        tracker.recent_changes.insert(key, tick);
+        kept.push(change);
+    }
+    drop(tracker);
+
+    for (user_id, message_id) in cancelled_messages {
+        if dry_run::enabled() {
+            info!(
+                "[DRY RUN] Would delete flapped notification {} in chat {}",
+                message_id.0, user_id
+            );
+            continue;
+        }
+        if let Err(e) = bot.delete_message(ChatId(user_id), message_id).await {
+            warn!(
+                "Failed to delete flapped notification for {}: {}",
+                user_id, e
+            );
+        }
+    }
+
+    kept
+}
+
+/// Attach the trigger order that most likely caused each exit, based on the current mark price
+async fn attribute_trigger_hits(
+    state: &Arc<RwLock<PositionTracker>>,
+    wallet_address: &str,
+    changes: &mut [PositionChange],
+) {
+    let mut state = state.write().await;
+    let PositionTracker {
+        mids,
+        trigger_orders,
+        ..
+    } = &mut *state;
+    let Some(triggers) = trigger_orders.get_mut(wallet_address) else {
+        return;
+    };
+
+    for change in changes.iter_mut() {
+        let (coin, is_long, trigger) = match change {
+            PositionChange::Closed {
+                coin,
+                was_long,
+                trigger,
+                ..
+            } => (coin, *was_long, trigger),
+            PositionChange::Decreased {
+                coin,
+                is_long,
+                trigger,
+                ..
+            } => (coin, *is_long, trigger),
+            _ => continue,
+        };
+        let Some(mark_price) = mids.get(coin.as_str()).copied() else {
+            continue;
+        };
+
+        let matched = triggers
+            .iter()
+            .filter(|(_, t)| t.coin == *coin && t.closes_long == is_long && t.trigger_price > 0.0)
+            .map(|(oid, t)| {
+                let distance = (mark_price - t.trigger_price).abs() / t.trigger_price * 100.0;
+                (*oid, distance)
+            })
+            .filter(|(_, distance)| *distance <= TRIGGER_MATCH_PCT)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(oid, _)| oid);
+
+        // A trigger only fills once, don't attribute a later exit to it as well
+        if let Some(hit) = matched.and_then(|oid| triggers.remove(&oid)) {
+            *trigger = Some(TriggerHit {
+                is_take_profit: hit.is_take_profit,
+                trigger_price: hit.trigger_price,
+            });
+        }
+    }
+}
+
+/// Share of account value committed as margin, in percent
+pub fn margin_utilization(summary: &MarginSummary) -> Option<f64> {
+    let account_value: f64 = summary.account_value.parse().ok()?;
+    let margin_used: f64 = summary.total_margin_used.parse().ok()?;
+    if account_value <= 0.0 {
+        return None;
+    }
+    Some(margin_used / account_value * 100.0)
+}
+
+/// Move a user's view of the wallet through their margin utilization bands, returning the
+/// threshold crossed upward if any
+async fn check_margin_bands(
+    state: &Arc<RwLock<PositionTracker>>,
+    user_id: i64,
+    wallet_address: &str,
+    bands: &BandedAlert,
+    utilization: f64,
+) -> Option<f64> {
+    let mut state = state.write().await;
+    let key = (user_id, wallet_address.to_string());
+
+    match state.margin_levels.get(&key).copied() {
+        // First sighting (new wallet, restart or changed bands): record the band without alerting
+        None => {
+            state
+                .margin_levels
+                .insert(key, bands.level_for(utilization));
+            None
+        }
+        Some(level) => {
+            let (level, crossed) = bands.update(level, utilization);
+            state.margin_levels.insert(key, level);
+            crossed
+        }
+    }
+}
+
+/// Move toward the mark (percent) that triggers a liquidation alert, from `LIQUIDATION_MOVE_PCT`
+pub fn liquidation_move_pct() -> f64 {
+    std::env::var("LIQUIDATION_MOVE_PCT")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|pct: &f64| *pct > 0.0)
+        .unwrap_or(DEFAULT_LIQUIDATION_MOVE_PCT)
+}
+
+/// A single band at `move_pct` of movement toward the mark.
+///
+/// The baseline moves to the new price once it fires, so each further `move_pct`
+/// of drift alerts again.
+fn liquidation_move_bands(move_pct: f64) -> BandedAlert {
+    BandedAlert::rising(vec![move_pct], 0.0)
+}
+
+/// Distance to liquidation of a wallet's cached positions, measured from this tick's mids
+/// and falling back to the mark implied by the position value
+async fn liquidation_distances(
+    state: &Arc<RwLock<PositionTracker>>,
+    wallet_address: &str,
+) -> Vec<LiquidationDistance> {
+    let tracker = state.read().await;
+    let Some(positions) = tracker.positions.get(wallet_address) else {
+        return Vec::new();
+    };
+
+    let mut distances = Vec::new();
+    for (coin, position) in positions {
+        let size: f64 = position.size.parse().unwrap_or(0.0);
+        let Some(liquidation_price) = position.liquidation_px.filter(|p| *p > 0.0) else {
+            continue;
+        };
+        if size == 0.0 {
+            continue;
+        }
+        let mark_price = match tracker.mids.get(&**coin) {
+            Some(mid) => *mid,
+            None => {
+                let entry_price: f64 = position.entry_px.parse().unwrap_or(0.0);
+                let unrealized_pnl: f64 = position.unrealized_pnl.parse().unwrap_or(0.0);
+                entry_price + unrealized_pnl / size
+            }
+        };
+        if mark_price <= 0.0 {
+            continue;
+        }
+        distances.push(LiquidationDistance {
+            coin: coin.to_string(),
+            is_long: size > 0.0,
+            leverage: position.leverage,
+            liquidation_price,
+            mark_price,
+            distance_pct: (mark_price - liquidation_price).abs() / mark_price * 100.0,
+        });
+    }
+    distances
+}
+
+/// Positions a user should now be warned about being near liquidation.
+///
+/// Each position is warned about once, and re-armed when its distance recovers past twice
+/// the threshold or the position goes away. A threshold of 0 turns warnings off.
+async fn update_liquidation_warnings<'a>(
+    state: &Arc<RwLock<PositionTracker>>,
+    user_id: i64,
+    wallet_address: &str,
+    distances: &'a [LiquidationDistance],
+    threshold: f64,
+) -> Vec<&'a LiquidationDistance> {
+    let mut tracker = state.write().await;
+    tracker
+        .liquidation_warned
+        .retain(|(warned_user, wallet, coin), _| {
+            *warned_user != user_id
+                || wallet != wallet_address
+                || distances.iter().any(|d| d.coin == *coin)
+        });
+    if threshold <= 0.0 {
+        return Vec::new();
+    }
+
+    let bands = liquidation_proximity_bands(threshold);
+    let mut warnings = Vec::new();
+    for distance in distances {
+        let key = (user_id, wallet_address.to_string(), distance.coin.clone());
+        let level = tracker.liquidation_warned.get(&key).copied().unwrap_or(0);
+        let (level, crossed) = bands.update(level, distance.distance_pct);
+        if crossed.is_some() {
+            warnings.push(distance);
+        }
+        if level == 0 {
+            tracker.liquidation_warned.remove(&key);
+        } else {
+            tracker.liquidation_warned.insert(key, level);
+        }
+    }
+    warnings
+}
+
+/// Fires when the distance to liquidation drops to `threshold` percent and re-arms
+/// once it recovers past twice the threshold
+fn liquidation_proximity_bands(threshold: f64) -> BandedAlert {
+    BandedAlert::falling(vec![threshold], threshold)
+}
+
+/// Find cross positions whose liquidation price moved at least `move_pct` toward the mark.
+///
+/// Isolated positions are skipped, their liquidation price only moves with the position
+/// itself. Baselines reset when the size changes and follow moves away from the mark,
+/// so only drift caused by the rest of the account since the last alert is reported.
+async fn check_liquidation_moves(
+    state: &Arc<RwLock<PositionTracker>>,
+    wallet_address: &str,
+    user_state: &UserState,
+    move_pct: f64,
+) -> Vec<LiquidationMove> {
+    let bands = liquidation_move_bands(move_pct);
+    let mut state = state.write().await;
+    let baselines = state
+        .liquidation_baselines
+        .entry(wallet_address.to_string())
+        .or_default();
+
+    let mut moves = Vec::new();
+    let mut seen = Vec::new();
+    for ap in &user_state.asset_positions {
+        let position = &ap.position;
+        let size: f64 = position.szi.parse().unwrap_or(0.0);
+        let Some(leverage) = position.leverage.as_ref() else {
+            continue;
+        };
+        let Some(liquidation_price) = position
+            .liquidation_px
+            .as_ref()
+            .and_then(|p| p.parse::<f64>().ok())
+            .filter(|p| *p > 0.0)
+        else {
+            continue;
+        };
+        if size == 0.0 || leverage.leverage_type != "cross" {
+            continue;
+        }
+        seen.push(position.coin.clone());
+
+        let baseline = baselines
+            .entry(position.coin.clone())
+            .or_insert(LiquidationBaseline {
+                size,
+                liquidation_price,
+                level: 0,
+            });
+        if (baseline.size - size).abs() > 0.0001 {
+            *baseline = LiquidationBaseline {
+                size,
+                liquidation_price,
+                level: 0,
+            };
+            continue;
+        }
+
+        let is_long = size > 0.0;
+        let toward_mark = if is_long {
+            liquidation_price - baseline.liquidation_price
+        } else {
+            baseline.liquidation_price - liquidation_price
+        };
+        if toward_mark <= 0.0 {
+            baseline.liquidation_price = liquidation_price;
+            baseline.level = 0;
+            continue;
+        }
+        let (level, crossed) = bands.update(
+            baseline.level,
+            toward_mark / baseline.liquidation_price * 100.0,
+        );
+        baseline.level = level;
+        if crossed.is_none() {
+            continue;
+        }
+
+        let position_value: f64 = position.position_value.parse().unwrap_or(0.0);
+        moves.push(LiquidationMove {
+            coin: position.coin.clone(),
+            is_long,
+            leverage: leverage.value,
+            old_price: baseline.liquidation_price,
+            new_price: liquidation_price,
+            mark_price: position_value / size.abs(),
+        });
+        // The next move is measured from here, which re-arms the band on the next tick
+        baseline.liquidation_price = liquidation_price;
+    }
+    baselines.retain(|coin, _| seen.contains(coin));
+
+    moves
 }
 
 #[derive(Debug)]
@@ -198,6 +2671,16 @@ pub enum PositionChange {
         leverage: u32,
         position_value: f64,
         is_long: bool,
+        liquidation_price: Option<f64>,
+        account_value: f64,
+        /// No earlier trade in this coin was recorded since tracking began
+        first_trade: bool,
+        /// Multiple of the wallet's median open, set when the open is unusually large for it
+        size_multiple: Option<f64>,
+        /// How far the mid moved from the entry by the time the open was detected
+        drift: Option<EntryDrift>,
+        /// Seconds since the perp was listed, set within its first day
+        listed_ago: Option<i64>,
     },
     Closed {
         coin: String,
@@ -206,6 +2689,8 @@ pub enum PositionChange {
         was_long: bool,
         leverage: u32,
         size: f64,
+        trigger: Option<TriggerHit>,
+        funding: Option<ClosedFunding>,
     },
     Increased {
         coin: String,
@@ -216,6 +2701,8 @@ pub enum PositionChange {
         is_long: bool,
         unrealized_pnl: f64,
         position_value: f64,
+        liquidation_price: Option<f64>,
+        account_value: f64,
     },
     Decreased {
         coin: String,
@@ -226,9 +2713,153 @@ pub enum PositionChange {
         is_long: bool,
         unrealized_pnl: f64,
         position_value: f64,
+        trigger: Option<TriggerHit>,
     },
 }
 
+impl PositionChange {
+    pub fn coin(&self) -> &str {
+        match self {
+            PositionChange::Opened { coin, .. }
+            | PositionChange::Closed { coin, .. }
+            | PositionChange::Increased { coin, .. }
+            | PositionChange::Decreased { coin, .. } => coin,
+        }
+    }
+
+    /// Whether a user's minimum position value filters this change out.
+    ///
+    /// Closes are always delivered, and so are opens in freshly listed perps since they're rare.
+    pub fn below_min_notional(&self, min_notional: f64) -> bool {
+        match self {
+            PositionChange::Opened {
+                listed_ago: Some(_),
+                ..
+            }
+            | PositionChange::Closed { .. } => false,
+            PositionChange::Opened { position_value, .. }
+            | PositionChange::Increased { position_value, .. }
+            | PositionChange::Decreased { position_value, .. } => *position_value < min_notional,
+        }
+    }
+
+    /// Whether this opens a position in a perp listed within the last day
+    pub fn is_listing_open(&self) -> bool {
+        matches!(
+            self,
+            PositionChange::Opened {
+                listed_ago: Some(_),
+                ..
+            }
+        )
+    }
+
+    /// Short name used when storing the change
+    pub fn kind(&self) -> &'static str {
+        match self {
+            PositionChange::Opened { .. } => "opened",
+            PositionChange::Closed { .. } => "closed",
+            PositionChange::Increased { .. } => "increased",
+            PositionChange::Decreased { .. } => "decreased",
+        }
+    }
+}
+
+/// Log a delivered notification for /stats, a failure here never blocks notifications
+pub async fn log_delivery(
+    pool: &SqlitePool,
+    user_id: i64,
+    wallet_address: Option<&str>,
+    kind: &str,
+) {
+    if let Err(e) = db::log_notification(pool, user_id, wallet_address, kind).await {
+        error!("Failed to log notification for {}: {}", user_id, e);
+    }
+}
+
+/// Store a detected change along with the mark price at detection time
+async fn record_position_event(
+    pool: &SqlitePool,
+    wallet_address: &str,
+    change: &PositionChange,
+    mark_price: Option<f64>,
+) -> anyhow::Result<()> {
+    if let PositionChange::Closed {
+        coin,
+        realized_pnl,
+        entry_price,
+        was_long,
+        size,
+        ..
+    } = change
+    {
+        db::record_closed_position(
+            pool,
+            wallet_address,
+            coin,
+            *was_long,
+            *size,
+            *entry_price,
+            mark_price,
+            *realized_pnl,
+        )
+        .await?;
+    }
+
+    let (is_long, size, size_delta, entry_price, pnl) = match change {
+        PositionChange::Opened {
+            size,
+            entry_price,
+            is_long,
+            ..
+        } => (*is_long, *size, *size, *entry_price, 0.0),
+        PositionChange::Closed {
+            realized_pnl,
+            entry_price,
+            was_long,
+            size,
+            ..
+        } => (*was_long, 0.0, *size, *entry_price, *realized_pnl),
+        PositionChange::Increased {
+            old_size,
+            new_size,
+            entry_price,
+            is_long,
+            unrealized_pnl,
+            ..
+        }
+        | PositionChange::Decreased {
+            old_size,
+            new_size,
+            entry_price,
+            is_long,
+            unrealized_pnl,
+            ..
+        } => (
+            *is_long,
+            *new_size,
+            // Avoid float artifacts like 0.30000000000000004 in stored deltas
+            ((new_size - old_size).abs() * 1e8).round() / 1e8,
+            *entry_price,
+            *unrealized_pnl,
+        ),
+    };
+
+    db::record_position_event(
+        pool,
+        wallet_address,
+        change.coin(),
+        change.kind(),
+        is_long,
+        size,
+        size_delta,
+        entry_price,
+        mark_price,
+        pnl,
+    )
+    .await
+}
+
 async fn detect_position_changes(
     pool: &SqlitePool,
     state: &Arc<RwLock<PositionTracker>>,
@@ -236,24 +2867,39 @@ async fn detect_position_changes(
     user_state: &UserState,
 ) -> Vec<PositionChange> {
     let mut changes = Vec::new();
+    let account_value: f64 = user_state
+        .margin_summary
+        .account_value
+        .parse()
+        .unwrap_or(0.0);
     let mut state = state.write().await;
 
+    // Nothing to diff when the positions are exactly as last tick, which
+    // saves the bulk of the work for wallets holding many positions
+    let fingerprint = positions_fingerprint(user_state);
+    if state.position_fingerprints.get(wallet_address) == Some(&fingerprint) {
+        return changes;
+    }
+    state
+        .position_fingerprints
+        .insert(wallet_address.to_string(), fingerprint);
+
     let old_positions = state
         .positions
         .entry(wallet_address.to_string())
         .or_default();
 
-    let current_map: HashMap<String, &Position> = user_state
+    let current_map: HashMap<&str, &Position> = user_state
         .asset_positions
         .iter()
         .filter(|ap| ap.position.szi.parse::<f64>().unwrap_or(0.0) != 0.0)
-        .map(|ap| (ap.position.coin.clone(), &ap.position))
+        .map(|ap| (ap.position.coin.as_str(), &ap.position))
         .collect();
 
     // Check for closed positions
-    let old_coins: Vec<String> = old_positions.keys().cloned().collect();
+    let old_coins: Vec<Arc<str>> = old_positions.keys().cloned().collect();
     for coin in old_coins {
-        if !current_map.contains_key(&coin)
+        if !current_map.contains_key(&*coin)
             && let Some(old_pos) = old_positions.remove(&coin)
         {
             let was_long = !old_pos.size.starts_with('-');
@@ -267,18 +2913,20 @@ async fn detect_position_changes(
             }
 
             changes.push(PositionChange::Closed {
-                coin,
+                coin: coin.to_string(),
                 realized_pnl: unrealized_pnl,
                 entry_price,
                 was_long,
                 leverage: old_pos.leverage,
                 size,
+                trigger: None,
+                funding: None,
             });
         }
     }
 
     // Check for new or updated positions
-    for (coin, position) in &current_map {
+    for (&coin, position) in &current_map {
         let new_size: f64 = position.szi.parse().unwrap_or(0.0);
         let is_long = new_size > 0.0;
         let entry_price: f64 = position
@@ -288,6 +2936,10 @@ async fn detect_position_changes(
             .unwrap_or(0.0);
         let position_value: f64 = position.position_value.parse().unwrap_or(0.0);
         let leverage = position.leverage.as_ref().map(|l| l.value).unwrap_or(1);
+        let liquidation_price: Option<f64> = position
+            .liquidation_px
+            .as_ref()
+            .and_then(|p| p.parse().ok());
         let entry_px_str = position.entry_px.clone().unwrap_or_default();
 
         let has_changed = if let Some(old_pos) = old_positions.get(coin) {
@@ -299,7 +2951,7 @@ async fn detect_position_changes(
                 if new_size.abs() > old_size.abs() {
                     let unrealized_pnl: f64 = position.unrealized_pnl.parse().unwrap_or(0.0);
                     changes.push(PositionChange::Increased {
-                        coin: coin.clone(),
+                        coin: coin.to_string(),
                         old_size: old_size.abs(),
                         new_size: new_size.abs(),
                         entry_price,
@@ -307,12 +2959,14 @@ async fn detect_position_changes(
                         is_long,
                         unrealized_pnl,
                         position_value,
+                        liquidation_price,
+                        account_value,
                     });
                 } else {
                     let unrealized_pnl: f64 = position.unrealized_pnl.parse().unwrap_or(0.0);
 
                     changes.push(PositionChange::Decreased {
-                        coin: coin.clone(),
+                        coin: coin.to_string(),
                         old_size: old_size.abs(),
                         new_size: new_size.abs(),
                         entry_price,
@@ -320,6 +2974,7 @@ async fn detect_position_changes(
                         is_long,
                         unrealized_pnl,
                         position_value,
+                        trigger: None,
                     });
                 }
                 true
@@ -328,112 +2983,879 @@ async fn detect_position_changes(
             }
         } else {
             changes.push(PositionChange::Opened {
-                coin: coin.clone(),
+                coin: coin.to_string(),
                 size: new_size.abs(),
                 entry_price,
                 leverage,
                 position_value,
                 is_long,
+                liquidation_price,
+                account_value,
+                first_trade: false,
+                size_multiple: None,
+                drift: None,
+                listed_ago: None,
             });
             true
         };
 
-        // Update in-memory cache
-        old_positions.insert(
-            coin.clone(),
-            CachedPosition {
-                size: position.szi.clone(),
-                entry_px: entry_px_str.clone(),
-                unrealized_pnl: position.unrealized_pnl.clone(),
-                leverage,
-            },
-        );
+        // Update in-memory cache, in place when the coin is already cached
+        match old_positions.get_mut(coin) {
+            Some(cached) => {
+                cached.size.clone_from(&position.szi);
+                cached.entry_px.clone_from(&entry_px_str);
+                cached.unrealized_pnl.clone_from(&position.unrealized_pnl);
+                cached.leverage = leverage;
+                cached.liquidation_px = liquidation_price;
+            }
+            None => {
+                old_positions.insert(
+                    intern_coin(coin),
+                    CachedPosition {
+                        size: position.szi.clone(),
+                        entry_px: entry_px_str.clone(),
+                        unrealized_pnl: position.unrealized_pnl.clone(),
+                        leverage,
+                        liquidation_px: liquidation_price,
+                    },
+                );
+            }
+        }
+
+        // Only persist to DB when position has meaningful changes
+        if has_changed
+            && let Err(e) = db::upsert_position(
+                pool,
+                wallet_address,
+                coin,
+                &position.szi,
+                &entry_px_str,
+                &position.unrealized_pnl,
+                leverage as i64,
+            )
+            .await
+        {
+            error!("Failed to upsert position to DB: {}", e);
+        }
+    }
+
+    changes
+}
+
+/// Hash of the position fields the diff and cache depend on
+fn positions_fingerprint(user_state: &UserState) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for ap in &user_state.asset_positions {
+        let position = &ap.position;
+        position.coin.hash(&mut hasher);
+        position.szi.hash(&mut hasher);
+        position.entry_px.hash(&mut hasher);
+        position.unrealized_pnl.hash(&mut hasher);
+        position.liquidation_px.hash(&mut hasher);
+        position
+            .leverage
+            .as_ref()
+            .map(|l| l.value)
+            .hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// A user's coin filters as they apply to one wallet
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CoinFilter<'a> {
+    /// The wallet's /watchcoin list, wins over the user-wide lists when set
+    pub watched: Option<&'a HashSet<String>>,
+    /// The /onlycoins whitelist, `None` outside whitelist mode
+    pub only: Option<&'a [String]>,
+    /// Coins from /ignorecoin
+    pub ignored: &'a [String],
+}
+
+impl CoinFilter<'_> {
+    /// Whether notifications about this coin get through.
+    ///
+    /// A wallet's /watchcoin list decides alone; otherwise the coin has to be on the
+    /// /onlycoins whitelist (when there is one) and not on the /ignorecoin list.
+    pub fn allows(&self, coin: &str) -> bool {
+        if let Some(watched) = self.watched {
+            return watched.contains(coin);
+        }
+        self.only
+            .is_none_or(|only| only.iter().any(|o| coin_matches(o, coin)))
+            && !self.ignored.iter().any(|i| coin_matches(i, coin))
+    }
+}
+
+/// Whether a subscriber hears about a change of the wallet.
+///
+/// The whole delivery chain: nothing reaches paused users or muted and snoozed
+/// wallets, then the /threshold notional and the coin filters apply. Opens in
+/// freshly listed perps skip the coin filters like they skip the threshold, a
+/// new coin can't be on anyone's lists yet.
+pub fn should_notify(
+    settings: &db::UserSettings,
+    subscriber: &Subscriber,
+    coins: &CoinFilter,
+    change: &PositionChange,
+    now: i64,
+) -> bool {
+    subscriber.receives(settings, now)
+        && !change.below_min_notional(settings.min_notional)
+        && (change.is_listing_open() || coins.allows(change.coin()))
+}
+
+/// Case-insensitive coin match that also accepts the bare name of k-prefixed coins ("PEPE" for kPEPE)
+pub fn coin_matches(query: &str, coin: &str) -> bool {
+    query.eq_ignore_ascii_case(coin)
+        || coin
+            .strip_prefix('k')
+            .is_some_and(|base| query.eq_ignore_ascii_case(base))
+}
+
+pub fn format_pnl(pnl: f64) -> String {
+    if pnl >= 0.0 {
+        format!("+${:.2}", pnl)
+    } else {
+        format!("-${:.2}", pnl.abs())
+    }
+}
+
+/// Drop trailing zeros after the decimal point, leaving integers untouched
+fn trim_decimals(s: String) -> String {
+    if s.contains('.') {
+        s.trim_end_matches('0').trim_end_matches('.').to_string()
+    } else {
+        s
+    }
+}
+
+pub fn format_price(price: f64) -> String {
+    trim_decimals(format!("${}", price))
+}
+
+pub fn format_size(size: f64) -> String {
+    trim_decimals(format!("{}", size))
+}
+
+/// Shared symbol of a coin, so every cached position of it points at one allocation.
+///
+/// Listed perps reuse the key of the asset metadata, anything else (delisted
+/// coins, or all of them before the metadata is loaded) gets its own.
+pub fn intern_coin(coin: &str) -> Arc<str> {
+    SZ_DECIMALS
+        .read()
+        .ok()
+        .and_then(|sz_decimals| {
+            sz_decimals
+                .get_key_value(coin)
+                .map(|(interned, _)| interned.clone())
+        })
+        .unwrap_or_else(|| coin.into())
+}
+
+/// Whether a coin is a listed perp, `None` until the asset metadata is loaded
+pub fn is_listed_perp(coin: &str) -> Option<bool> {
+    let sz_decimals = SZ_DECIMALS.read().ok()?;
+    (!sz_decimals.is_empty()).then(|| sz_decimals.contains_key(coin))
+}
+
+/// Current perp name of a coin that isn't listed as given.
+///
+/// Covers case differences and the k-prefix used for assets quoted per 1000 units
+/// (PEPE → kPEPE and back).
+pub fn renamed_perp(coin: &str) -> Option<String> {
+    let sz_decimals = SZ_DECIMALS.read().ok()?;
+    if sz_decimals.contains_key(coin) {
+        return None;
+    }
+
+    // Candidates in order of preference, so "kas" finds KAS before AS
+    let stripped = coin.strip_prefix(['k', 'K']).unwrap_or(coin);
+    let candidates = [coin.to_string(), format!("k{}", coin), stripped.to_string()];
+    candidates.iter().find_map(|candidate| {
+        sz_decimals
+            .keys()
+            .find(|name| name.eq_ignore_ascii_case(candidate))
+            .map(|name| name.to_string())
+    })
+}
+
+/// Whether a coin that isn't a listed perp is a spot token, where position filters never match
+pub fn is_spot_only(coin: &str) -> bool {
+    if is_listed_perp(coin) != Some(false) || renamed_perp(coin).is_some() {
+        return false;
+    }
+    SPOT_TOKENS
+        .read()
+        .is_ok_and(|tokens| tokens.iter().any(|name| name.eq_ignore_ascii_case(coin)))
+}
+
+/// Point stored coin watches, filters and price alerts at renamed perps.
+///
+/// Run after each meta refresh. Coins that can't be mapped to a listed perp are
+/// reported to their owner once instead.
+async fn migrate_stored_coins(bot: &Bot, pool: &SqlitePool) {
+    let coins = match db::get_all_configured_coins(pool).await {
+        Ok(coins) => coins,
+        Err(e) => {
+            error!("Failed to fetch configured coins: {}", e);
+            return;
+        }
+    };
+
+    for (user_id, coin) in coins {
+        if is_listed_perp(&coin) != Some(false) {
+            continue;
+        }
+        let message = match renamed_perp(&coin) {
+            Some(renamed) => {
+                if let Err(e) = db::rename_user_coin(pool, user_id, &coin, &renamed).await {
+                    error!(
+                        "Failed to rename {} to {} for user {}: {}",
+                        coin, renamed, user_id, e
+                    );
+                    continue;
+                }
+                info!("Renamed {} to {} for user {}", coin, renamed, user_id);
+                format!(
+                    "🔁 <b>{}</b> is now listed as <b>{}</b> on Hyperliquid. Your coin filters, watches and price alerts were updated to match.",
+                    html::escape(&coin),
+                    html::escape(&renamed)
+                )
+            }
+            None => {
+                match db::mark_unlisted_coin_notice(pool, user_id, &coin).await {
+                    Ok(true) => {}
+                    Ok(false) => continue,
+                    Err(e) => {
+                        error!("Failed to record unlisted coin notice: {}", e);
+                        continue;
+                    }
+                }
+                let reason = if is_spot_only(&coin) {
+                    "only trades on spot"
+                } else {
+                    "is no longer a listed perp"
+                };
+                format!(
+                    "⚠️ <b>{}</b> {} on Hyperliquid, so your coin filters, watches and price alerts for it won't match any more.\n\nIf it was renamed, update them with /watchcoin, /onlycoins, /unignorecoin or /alerts.",
+                    html::escape(&coin),
+                    reason
+                )
+            }
+        };
+
+        if dry_run::enabled() {
+            dry_run::record(user_id, &message);
+            continue;
+        }
+        if let Err(e) = bot
+            .send_message(ChatId(user_id), message)
+            .parse_mode(ParseMode::Html)
+            .await
+        {
+            error!("Failed to send coin notice to {}: {}", user_id, e);
+        }
+    }
+}
+
+/// Decimals a price can have on Hyperliquid given the asset's szDecimals
+pub fn price_decimals(price: f64, sz_decimals: u32) -> u32 {
+    let max_decimals = MAX_PRICE_DECIMALS.saturating_sub(sz_decimals);
+    if price == 0.0 {
+        return max_decimals;
+    }
+    let int_digits = price.abs().log10().floor() as i32 + 1;
+    ((PRICE_SIG_FIGS - int_digits).max(0) as u32).min(max_decimals)
+}
+
+/// Round a price to the decimals Hyperliquid accepts for it, see `price_decimals`
+pub fn round_price(price: f64, sz_decimals: u32) -> f64 {
+    let factor = 10f64.powi(price_decimals(price, sz_decimals) as i32);
+    (price * factor).round() / factor
+}
+
+/// `round_price` for a coin, `None` until the asset metadata is loaded
+pub fn round_coin_price(coin: &str, price: f64) -> Option<f64> {
+    let sz_decimals = SZ_DECIMALS.read().ok()?.get(coin).copied()?;
+    Some(round_price(price, sz_decimals))
+}
+
+/// Format a coin's price at the precision Hyperliquid quotes it,
+/// falling back to plain formatting until the asset metadata is loaded
+pub fn format_coin_price(coin: &str, price: f64) -> String {
+    let sz_decimals = SZ_DECIMALS
+        .read()
+        .ok()
+        .and_then(|sz_decimals| sz_decimals.get(coin).copied());
+    match sz_decimals {
+        Some(sz_decimals) => {
+            let decimals = price_decimals(price, sz_decimals) as usize;
+            trim_decimals(format!("${:.*}", decimals, price))
+        }
+        None => format_price(price),
+    }
+}
+
+pub fn direction_str(is_long: bool) -> &'static str {
+    if is_long { "Long" } else { "Short" }
+}
+
+fn calculate_current_price_info(entry_price: f64, position_value: f64, size: f64) -> (f64, String) {
+    let current_price = if size > 0.0 {
+        position_value / size
+    } else {
+        0.0
+    };
+    let current_price_rounded = (current_price * 10000000000.0).round() / 10000000000.0;
+    let price_diff = current_price_rounded - entry_price;
+    let price_diff_str = if price_diff >= 0.0 {
+        format!("+${:.2}", price_diff)
+    } else {
+        format!("-${:.2}", price_diff.abs())
+    };
+    (current_price_rounded, price_diff_str)
+}
+
+fn calculate_pnl_percent(entry_price: f64, size: f64, unrealized_pnl: f64) -> String {
+    let entry_value = entry_price * size;
+    let pnl_pct = if entry_value > 0.0 {
+        (unrealized_pnl / entry_value) * 100.0
+    } else {
+        0.0
+    };
+    if pnl_pct >= 0.0 {
+        format!("+{:.2}%", pnl_pct)
+    } else {
+        format!("{:.2}%", pnl_pct)
+    }
+}
+
+/// Format a dollar amount compactly, e.g. $5k or $1.2M
+pub fn format_usd_compact(amount: f64) -> String {
+    let sign = if amount < 0.0 { "-" } else { "" };
+    let amount = amount.abs();
+    let (value, suffix) = if amount >= 1e9 {
+        (amount / 1e9, "B")
+    } else if amount >= 1e6 {
+        (amount / 1e6, "M")
+    } else if amount >= 1e3 {
+        (amount / 1e3, "k")
+    } else {
+        (amount, "")
+    };
+    let value = format!("{:.1}", value);
+    format!(
+        "{}${}{}",
+        sign,
+        value.trim_end_matches('0').trim_end_matches('.'),
+        suffix
+    )
+}
+
+/// Scale a position to the user's bankroll, committing the same share of account value as the wallet did
+fn mirror_size(bankroll: f64, notional: f64, account_value: f64, price: f64) -> Option<f64> {
+    if account_value <= 0.0 || price <= 0.0 {
+        return None;
+    }
+    let size = bankroll * (notional / account_value) / price;
+    Some((size * 10000.0).round() / 10000.0)
+}
+
+/// Copy-pastable block with the parameters needed to mirror an opened or increased position
+fn format_mirror_block(change: &PositionChange, settings: &db::UserSettings) -> Option<String> {
+    let (coin, is_long, leverage, fill_price, notional, liquidation_price, account_value, label) =
+        match change {
+            PositionChange::Opened {
+                coin,
+                entry_price,
+                leverage,
+                position_value,
+                is_long,
+                liquidation_price,
+                account_value,
+                ..
+            } => (
+                coin,
+                *is_long,
+                *leverage,
+                *entry_price,
+                *position_value,
+                *liquidation_price,
+                *account_value,
+                "Size:",
+            ),
+            PositionChange::Increased {
+                coin,
+                old_size,
+                new_size,
+                leverage,
+                is_long,
+                position_value,
+                liquidation_price,
+                account_value,
+                ..
+            } => {
+                // The added size was filled around the current price, not the average entry
+                let current_price = if *new_size > 0.0 {
+                    position_value / new_size
+                } else {
+                    0.0
+                };
+                (
+                    coin,
+                    *is_long,
+                    *leverage,
+                    current_price,
+                    (new_size - old_size) * current_price,
+                    *liquidation_price,
+                    *account_value,
+                    "Add:",
+                )
+            }
+            _ => return None,
+        };
+
+    let tolerance = fill_price * settings.mirror_tolerance_pct / 100.0;
+    let stop = liquidation_price
+        .map(|p| format!("{} (liq)", format_coin_price(coin, p)))
+        .unwrap_or_else(|| "n/a".to_string());
+    let size = mirror_size(
+        settings.mirror_bankroll,
+        notional,
+        account_value,
+        fill_price,
+    )
+    .map(|size| {
+        format!(
+            "at {}: {} {}",
+            format_usd_compact(settings.mirror_bankroll),
+            format_size(size),
+            coin
+        )
+    })
+    .unwrap_or_else(|| "n/a".to_string());
+
+    Some(format!(
+        "\n<b>🪞 Mirror</b>\n<pre>\
+         Coin:     {}\n\
+         Side:     {}\n\
+         Leverage: {}x\n\
+         Entry:    {} – {}\n\
+         Stop:     {}\n\
+         {:<10}{}</pre>\n",
+        coin,
+        direction_str(is_long),
+        leverage,
+        format_coin_price(coin, fill_price - tolerance),
+        format_coin_price(coin, fill_price + tolerance),
+        stop,
+        label,
+        size
+    ))
+}
+
+/// Header naming the take-profit or stop-loss an exit was attributed to
+pub fn trigger_header(trigger: &TriggerHit, leverage: u32, coin: &str, is_long: bool) -> String {
+    let (emoji, label) = if trigger.is_take_profit {
+        ("🎯", "Take-profit")
+    } else {
+        ("🛑", "Stop-loss")
+    };
+    format!(
+        "{} {}x {} {} {} hit at {}",
+        emoji,
+        leverage,
+        coin,
+        direction_str(is_long),
+        label,
+        format_coin_price(coin, trigger.trigger_price)
+    )
+}
+
+fn hypertracker_link(wallet_address: &str) -> String {
+    format!(
+        "🌐 <a href=\"https://app.coinmarketman.com/hypertracker/wallet/{}\">Hypertracker</a>",
+        wallet_address
+    )
+}
+
+/// Summarise all changes of an update in one message, for wallets with many positions.
+///
+/// `warn_positions` is set for the first digest a user gets for the wallet.
+async fn send_change_digest(
+    bot: &Bot,
+    user_id: i64,
+    wallet_address: &str,
+    note: Option<&str>,
+    changes: &[&PositionChange],
+    warn_positions: Option<usize>,
+    tz: &UserTimezone,
+) -> anyhow::Result<()> {
+    let mut lines: Vec<String> = changes
+        .iter()
+        .take(MAX_DIGEST_LINES)
+        .map(|change| logbook::format_entry(change, tz))
+        .collect();
+    if changes.len() > MAX_DIGEST_LINES {
+        lines.push(format!("…and {} more", changes.len() - MAX_DIGEST_LINES));
+    }
+
+    let warning = match warn_positions {
+        Some(count) => format!(
+            "\n\n<i>⚠️ This wallet holds {} positions, too many for a notification per position. Its changes are summarised per update instead.</i>",
+            count
+        ),
+        None => String::new(),
+    };
+
+    let message = format!(
+        "<b>📦 {} Position Change{}</b>\n\n\
+         👛 Wallet: {}\n\n\
+         {}{}\n\n\
+         {}",
+        changes.len(),
+        if changes.len() == 1 { "" } else { "s" },
+        format_wallet_display(wallet_address, note, false),
+        lines.join("\n"),
+        warning,
+        hypertracker_link(wallet_address)
+    );
+
+    if dry_run::enabled() {
+        dry_run::record(user_id, &message);
+        return Ok(());
+    }
+
+    bot.send_message(ChatId(user_id), message)
+        .parse_mode(ParseMode::Html)
+        .await?;
+
+    info!(
+        "Sent digest of {} changes to user {} for wallet {}",
+        changes.len(),
+        user_id,
+        wallet_address
+    );
+    Ok(())
+}
+
+/// Low-priority alert, sent silently since nothing happened to the position itself
+async fn send_liquidation_alert(
+    bot: &Bot,
+    user_id: i64,
+    wallet_address: &str,
+    note: Option<&str>,
+    liquidation_move: &LiquidationMove,
+) -> anyhow::Result<()> {
+    let LiquidationMove {
+        coin,
+        is_long,
+        leverage,
+        old_price,
+        new_price,
+        mark_price,
+    } = liquidation_move;
+    let distance_pct = if *mark_price > 0.0 {
+        (mark_price - new_price).abs() / mark_price * 100.0
+    } else {
+        0.0
+    };
+
+    let message = format!(
+        "<b>🩸 {}x {} {} Liquidation Price Moved</b>\n\n\
+         👛 Wallet: {}\n\
+         💀 Liquidation: {} → {}\n\
+         📏 Distance to mark: {:.1}% (mark {})\n\n\
+         <i>The position didn't change, losses or withdrawals elsewhere in the cross margin account moved it.</i>\n\n\
+         {}",
+        leverage,
+        coin,
+        direction_str(*is_long),
+        format_wallet_display(wallet_address, note, false),
+        format_coin_price(coin, *old_price),
+        format_coin_price(coin, *new_price),
+        distance_pct,
+        format_coin_price(coin, *mark_price),
+        hypertracker_link(wallet_address)
+    );
+
+    if dry_run::enabled() {
+        dry_run::record(user_id, &message);
+        return Ok(());
+    }
+
+    bot.send_message(ChatId(user_id), message)
+        .parse_mode(ParseMode::Html)
+        .disable_notification(true)
+        .await?;
+
+    info!(
+        "Sent liquidation alert to user {} for wallet {} {}",
+        user_id, wallet_address, coin
+    );
+    Ok(())
+}
+
+async fn send_liquidation_warning(
+    bot: &Bot,
+    user_id: i64,
+    wallet_address: &str,
+    note: Option<&str>,
+    distance: &LiquidationDistance,
+) -> anyhow::Result<()> {
+    let message = format!(
+        "<b>⚠️ {}x {} {} Near Liquidation</b>\n\n\
+         👛 Wallet: {}\n\
+         📏 Distance: {:.1}%\n\
+         📍 Current: {}\n\
+         💀 Liquidation: {}\n\n\
+         {}",
+        distance.leverage,
+        distance.coin,
+        direction_str(distance.is_long),
+        format_wallet_display(wallet_address, note, false),
+        distance.distance_pct,
+        format_coin_price(&distance.coin, distance.mark_price),
+        format_coin_price(&distance.coin, distance.liquidation_price),
+        hypertracker_link(wallet_address)
+    );
+
+    if dry_run::enabled() {
+        dry_run::record(user_id, &message);
+        return Ok(());
+    }
+
+    bot.send_message(ChatId(user_id), message)
+        .parse_mode(ParseMode::Html)
+        .await?;
+
+    info!(
+        "Sent liquidation warning to user {} for wallet {} {}",
+        user_id, wallet_address, distance.coin
+    );
+    Ok(())
+}
+
+/// Fire armed PnL alerts whose band the wallet's unrealized PnL has left, and re-arm
+/// fired ones once it's back inside by `PNL_ALERT_HYSTERESIS`
+async fn check_pnl_alerts(
+    bot: &Bot,
+    pool: &SqlitePool,
+    wallet_address: &str,
+    user_infos: &[Subscriber],
+    user_state: &UserState,
+    alerts: &[db::PnlAlert],
+    now: i64,
+) {
+    let unrealized: f64 = user_state
+        .asset_positions
+        .iter()
+        .map(|ap| ap.position.unrealized_pnl.parse::<f64>().unwrap_or(0.0))
+        .sum();
 
-        // Only persist to DB when position has meaningful changes
-        if has_changed
-            && let Err(e) = db::upsert_position(
-                pool,
+    for alert in alerts {
+        // Paused users and muted wallets don't get alerts, their state waits for them
+        let Some(subscriber) = user_infos.iter().find(|s| s.user_id == alert.user_id) else {
+            continue;
+        };
+        let receives = db::get_user_settings(pool, alert.user_id)
+            .await
+            .is_ok_and(|settings| subscriber.receives(&settings, now));
+        if !receives {
+            continue;
+        }
+        let note = &subscriber.note;
+
+        let armed = if alert.armed && unrealized.abs() >= alert.threshold {
+            if let Err(e) = send_pnl_alert(
+                bot,
+                alert.user_id,
                 wallet_address,
-                coin,
-                &position.szi,
-                &entry_px_str,
-                &position.unrealized_pnl,
-                leverage as i64,
+                note.as_deref(),
+                unrealized,
+                alert.threshold,
             )
             .await
+            {
+                error!("Failed to send PnL alert to {}: {}", alert.user_id, e);
+                continue;
+            }
+            log_delivery(pool, alert.user_id, Some(wallet_address), "pnl_alert").await;
+            false
+        } else if !alert.armed && unrealized.abs() < alert.threshold * (1.0 - PNL_ALERT_HYSTERESIS)
         {
-            error!("Failed to upsert position to DB: {}", e);
+            true
+        } else {
+            continue;
+        };
+
+        if let Err(e) = db::set_pnl_alert_armed(pool, alert.user_id, wallet_address, armed).await {
+            error!("Failed to update PnL alert for {}: {}", alert.user_id, e);
         }
     }
-
-    changes
 }
 
-fn format_pnl(pnl: f64) -> String {
-    if pnl >= 0.0 {
-        format!("+${:.2}", pnl)
+async fn send_pnl_alert(
+    bot: &Bot,
+    user_id: i64,
+    wallet_address: &str,
+    note: Option<&str>,
+    unrealized: f64,
+    threshold: f64,
+) -> anyhow::Result<()> {
+    let wallet_display = format_wallet_display(wallet_address, note, false);
+    let (icon, direction) = if unrealized >= 0.0 {
+        ("🤑", "above")
     } else {
-        format!("-${:.2}", pnl.abs())
+        ("🩸", "below")
+    };
+    let sign = if unrealized >= 0.0 { "+" } else { "-" };
+    let message = format!(
+        "{} <b>PnL alert</b>\n\n\
+         👛 {}\n\
+         💵 Unrealized PnL is {} ({} {}${:.2})\n\n\
+         <i>It fires again once the PnL is back within ±${:.2}. Use <code>/alertpnl list</code> to manage alerts.</i>",
+        icon,
+        wallet_display,
+        format_pnl(unrealized),
+        direction,
+        sign,
+        threshold,
+        threshold * (1.0 - PNL_ALERT_HYSTERESIS)
+    );
+
+    if dry_run::enabled() {
+        dry_run::record(user_id, &message);
+        return Ok(());
     }
-}
+    bot.send_message(ChatId(user_id), message)
+        .parse_mode(ParseMode::Html)
+        .await?;
 
-fn format_price(price: f64) -> String {
-    let s = format!("${}", price);
-    s.trim_end_matches('0').trim_end_matches('.').to_string()
+    info!(
+        "Sent PnL alert to user {} for wallet {}",
+        user_id, wallet_address
+    );
+    Ok(())
 }
 
-fn format_size(size: f64) -> String {
-    let s = format!("{}", size);
-    s.trim_end_matches('0').trim_end_matches('.').to_string()
-}
+async fn send_margin_alert(
+    bot: &Bot,
+    user_id: i64,
+    wallet_address: &str,
+    note: Option<&str>,
+    user_state: &UserState,
+    threshold: f64,
+    vault_summary: Option<(f64, String)>,
+) -> anyhow::Result<()> {
+    let wallet_display = format_wallet_display(wallet_address, note, false);
+    let account_value: f64 = user_state
+        .margin_summary
+        .account_value
+        .parse()
+        .unwrap_or(0.0);
+    let margin_used: f64 = user_state
+        .margin_summary
+        .total_margin_used
+        .parse()
+        .unwrap_or(0.0);
+    let utilization = margin_utilization(&user_state.margin_summary).unwrap_or(0.0);
 
-fn direction_str(is_long: bool) -> &'static str {
-    if is_long { "Long" } else { "Short" }
-}
+    // Positions committing the most margin
+    let mut contributors: Vec<(&Position, f64)> = user_state
+        .asset_positions
+        .iter()
+        .map(|ap| (&ap.position, ap.position.margin_used.parse().unwrap_or(0.0)))
+        .filter(|(_, margin)| *margin > 0.0)
+        .collect();
+    contributors.sort_by(|a, b| b.1.total_cmp(&a.1));
 
-fn calculate_current_price_info(entry_price: f64, position_value: f64, size: f64) -> (f64, String) {
-    let current_price = if size > 0.0 {
-        position_value / size
-    } else {
-        0.0
-    };
-    let current_price_rounded = (current_price * 10000000000.0).round() / 10000000000.0;
-    let price_diff = current_price_rounded - entry_price;
-    let price_diff_str = if price_diff >= 0.0 {
-        format!("+${:.2}", price_diff)
-    } else {
-        format!("-${:.2}", price_diff.abs())
-    };
-    (current_price_rounded, price_diff_str)
-}
+    let contributor_lines = contributors
+        .iter()
+        .take(3)
+        .map(|(pos, margin)| {
+            let size: f64 = pos.szi.parse().unwrap_or(0.0);
+            let leverage = pos.leverage.as_ref().map(|l| l.value).unwrap_or(1);
+            let share = if margin_used > 0.0 {
+                margin / margin_used * 100.0
+            } else {
+                0.0
+            };
+            format!(
+                "• {}x {} {}: ${:.2} ({:.0}%)",
+                leverage,
+                pos.coin,
+                direction_str(size > 0.0),
+                margin,
+                share
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
 
-fn calculate_pnl_percent(entry_price: f64, size: f64, unrealized_pnl: f64) -> String {
-    let entry_value = entry_price * size;
-    let pnl_pct = if entry_value > 0.0 {
-        (unrealized_pnl / entry_value) * 100.0
-    } else {
-        0.0
+    // Vault deposits aren't margin, but they're part of what the wallet can lose
+    let vault_lines = match vault_summary {
+        Some((vault_equity, line)) => format!(
+            "\n{}\n💎 Total equity: ${:.2}",
+            line,
+            account_value.max(0.0) + vault_equity
+        ),
+        None => String::new(),
     };
-    if pnl_pct >= 0.0 {
-        format!("+{:.2}%", pnl_pct)
-    } else {
-        format!("{:.2}%", pnl_pct)
+
+    let message = format!(
+        "<b>⚠️ Margin Usage Above {:.0}%</b>\n\n\
+         👛 Wallet: {}\n\
+         📊 Margin used: ${:.2} of ${:.2} ({:.1}%){}\n\n\
+         <b>Largest margin:</b>\n\
+         {}\n\n\
+         {}",
+        threshold,
+        wallet_display,
+        margin_used,
+        account_value,
+        utilization,
+        vault_lines,
+        contributor_lines,
+        hypertracker_link(wallet_address)
+    );
+
+    if dry_run::enabled() {
+        dry_run::record(user_id, &message);
+        return Ok(());
     }
+
+    bot.send_message(ChatId(user_id), message)
+        .parse_mode(ParseMode::Html)
+        .await?;
+
+    info!(
+        "Sent margin alert to user {} for wallet {} ({:.1}%)",
+        user_id, wallet_address, utilization
+    );
+    Ok(())
 }
 
 async fn send_position_notification(
     bot: &Bot,
+    pool: &SqlitePool,
     user_id: i64,
     wallet_address: &str,
     note: Option<&str>,
     change: &PositionChange,
-) -> anyhow::Result<()> {
+    settings: &db::UserSettings,
+) -> anyhow::Result<MessageId> {
     let wallet_display = format_wallet_display(wallet_address, note, false);
 
-    let hyperdash_link = format!(
-        "🌐 <a href=\"https://app.coinmarketman.com/hypertracker/wallet/{}\">Hypertracker</a>",
-        wallet_address
-    );
+    let mirror_block = if settings.mirror_enabled {
+        format_mirror_block(change, settings).unwrap_or_default()
+    } else {
+        String::new()
+    };
+
+    let hyperdash_link = hypertracker_link(wallet_address);
 
     let message = match change {
         PositionChange::Opened {
@@ -443,13 +3865,52 @@ async fn send_position_notification(
             leverage,
             position_value,
             is_long,
+            first_trade,
+            size_multiple,
+            drift,
+            listed_ago,
+            ..
         } => {
+            let (stale_warning, drift_line) = match drift {
+                Some(drift) => {
+                    let warning = if drift.move_pct.abs() >= stale_entry_move_pct() {
+                        let delay = drift
+                            .max_delay_secs
+                            .map(|secs| format!("stale by up to {}s, ", secs))
+                            .unwrap_or_default();
+                        format!(
+                            "⏱ <i>{}price moved {:.1}%</i>\n\n",
+                            delay,
+                            drift.move_pct.abs()
+                        )
+                    } else {
+                        String::new()
+                    };
+                    (
+                        warning,
+                        format!("📍 Since entry: {:+.1}%\n", drift.move_pct),
+                    )
+                }
+                None => (String::new(), String::new()),
+            };
+            let first_trade_line = if *first_trade {
+                "🆕 First time trading this coin (since tracking began)\n"
+            } else {
+                ""
+            };
+            let size_line = size_multiple
+                .map(|m| format!("📢 {:.1}× this wallet's typical size\n", m))
+                .unwrap_or_default();
+            let listing_line = listed_ago
+                .map(|secs| format!("🌱 Listed {} ago\n", format_duration(secs)))
+                .unwrap_or_default();
             format!(
-                "<b>📈 {}x {} {} Opened</b>\n\n\
+                "{}<b>📈 {}x {} {} Opened</b>\n\n\
                  👛 Wallet: {}\n\
                  📊 Size: {} {} (${:.2})\n\
                  💰 Entry: {}\n\
-                 {}",
+                 {}{}{}{}{}{}",
+                stale_warning,
                 leverage,
                 coin,
                 direction_str(*is_long),
@@ -457,7 +3918,12 @@ async fn send_position_notification(
                 format_size(*size),
                 coin,
                 position_value,
-                format_price(*entry_price),
+                format_coin_price(coin, *entry_price),
+                drift_line,
+                first_trade_line,
+                size_line,
+                listing_line,
+                mirror_block,
                 hyperdash_link
             )
         }
@@ -468,6 +3934,8 @@ async fn send_position_notification(
             was_long,
             leverage,
             size,
+            trigger,
+            funding,
         } => {
             // Calculate exit price from PnL
             // For longs: exit_price = entry_price + (pnl / size)
@@ -489,21 +3957,46 @@ async fn send_position_notification(
                 format!("-${:.2}", price_diff.abs())
             };
 
+            let funding_line = funding
+                .as_ref()
+                .map(|f| {
+                    format!(
+                        "🏦 Net after funding: {} (funding {}{})\n",
+                        format_pnl(realized_pnl + f.amount),
+                        format_pnl(f.amount),
+                        if f.since_tracking {
+                            " since tracking"
+                        } else {
+                            ""
+                        }
+                    )
+                })
+                .unwrap_or_default();
+
+            let header = match trigger {
+                Some(trigger) => trigger_header(trigger, *leverage, coin, *was_long),
+                None => format!(
+                    "📉 {}x {} {} Closed",
+                    leverage,
+                    coin,
+                    direction_str(*was_long)
+                ),
+            };
+
             format!(
-                "<b>📉 {}x {} {} Closed</b>\n\n\
+                "<b>{}</b>\n\n\
                  👛 Wallet: {}\n\
                  💰 Entry: {}\n\
                  📍 Exit: {} ({})\n\
                  💵 PnL: {}\n\
-                 {}",
-                leverage,
-                coin,
-                direction_str(*was_long),
+                 {}{}",
+                header,
                 wallet_display,
-                format_price(*entry_price),
-                format_price(exit_price_rounded),
+                format_coin_price(coin, *entry_price),
+                format_coin_price(coin, exit_price_rounded),
                 price_diff_str,
                 format_pnl(*realized_pnl),
+                funding_line,
                 hyperdash_link
             )
         }
@@ -516,6 +4009,7 @@ async fn send_position_notification(
             is_long,
             unrealized_pnl,
             position_value,
+            ..
         } => {
             let (current_price_rounded, price_diff_str) =
                 calculate_current_price_info(*entry_price, *position_value, *new_size);
@@ -532,7 +4026,7 @@ async fn send_position_notification(
                  💰 Entry: {}\n\
                  📍 Current: {} ({})\n\
                  💵 PnL: {} ({})\n\
-                 {}",
+                 {}{}",
                 leverage,
                 coin,
                 direction_str(*is_long),
@@ -541,11 +4035,12 @@ async fn send_position_notification(
                 format_size(*new_size),
                 coin,
                 size_change_pct,
-                format_price(*entry_price),
-                format_price(current_price_rounded),
+                format_coin_price(coin, *entry_price),
+                format_coin_price(coin, current_price_rounded),
                 price_diff_str,
                 format_pnl(*unrealized_pnl),
                 pnl_pct_str,
+                mirror_block,
                 hyperdash_link
             )
         }
@@ -558,6 +4053,7 @@ async fn send_position_notification(
             is_long,
             unrealized_pnl,
             position_value,
+            trigger,
         } => {
             let (current_price_rounded, price_diff_str) =
                 calculate_current_price_info(*entry_price, *position_value, *new_size);
@@ -567,24 +4063,31 @@ async fn send_position_notification(
             } else {
                 0.0
             };
+            let header = match trigger {
+                Some(trigger) => trigger_header(trigger, *leverage, coin, *is_long),
+                None => format!(
+                    "⬇️ {}x {} {} Decreased",
+                    leverage,
+                    coin,
+                    direction_str(*is_long)
+                ),
+            };
             format!(
-                "<b>⬇️ {}x {} {} Decreased</b>\n\n\
+                "<b>{}</b>\n\n\
                  👛 Wallet: {}\n\
                  📊 Size: {} → {} {} (-{:.2}%)\n\
                  💰 Entry: {}\n\
                  📍 Current: {} ({})\n\
                  💵 PnL: {} ({})\n\
                  {}",
-                leverage,
-                coin,
-                direction_str(*is_long),
+                header,
                 wallet_display,
                 format_size(*old_size),
                 format_size(*new_size),
                 coin,
                 size_change_pct,
-                format_price(*entry_price),
-                format_price(current_price_rounded),
+                format_coin_price(coin, *entry_price),
+                format_coin_price(coin, current_price_rounded),
                 price_diff_str,
                 format_pnl(*unrealized_pnl),
                 pnl_pct_str,
@@ -592,14 +4095,601 @@ async fn send_position_notification(
             )
         }
     };
+    // Compact mode keeps just the logbook line, dropping the mirror block and links
+    let message = if settings.compact_notifications {
+        format!(
+            "👛 {}\n{}",
+            wallet_display,
+            logbook::format_entry(change, &settings.tz())
+        )
+    } else {
+        message
+    };
 
-    bot.send_message(ChatId(user_id), message)
-        .parse_mode(ParseMode::Html)
-        .await?;
+    if dry_run::enabled() {
+        dry_run::record(user_id, &message);
+        return Ok(MessageId(0));
+    }
+
+    let chat_id = ChatId(user_id);
+    let thread = if settings.threaded_notifications {
+        db::get_thread_message(pool, user_id, wallet_address, NOTIFICATION_THREAD_MAX_HOURS)
+            .await
+            .unwrap_or_else(|e| {
+                error!("Failed to load notification thread for {}: {}", user_id, e);
+                None
+            })
+    } else {
+        None
+    };
+
+    let sent = match thread {
+        Some(thread_id) => match bot
+            .send_message(chat_id, message.clone())
+            .parse_mode(ParseMode::Html)
+            .reply_parameters(
+                ReplyParameters::new(MessageId(thread_id as i32)).allow_sending_without_reply(),
+            )
+            .await
+        {
+            Ok(sent) => sent,
+            Err(
+                e
+                @ RequestError::Api(ApiError::MessageToReplyNotFound | ApiError::MessageIdInvalid),
+            ) => {
+                // The message to reply to is gone, start a new chain with a plain message
+                warn!(
+                    "Failed to reply in thread for user {} wallet {}: {}",
+                    user_id, wallet_address, e
+                );
+                bot.send_message(chat_id, message)
+                    .parse_mode(ParseMode::Html)
+                    .await?
+            }
+            // Anything else would fail a plain send too, leave it to the caller
+            Err(e) => return Err(e.into()),
+        },
+        None => {
+            bot.send_message(chat_id, message)
+                .parse_mode(ParseMode::Html)
+                .await?
+        }
+    };
+
+    if settings.threaded_notifications
+        && let Err(e) =
+            db::set_thread_message(pool, user_id, wallet_address, sent.id.0 as i64).await
+    {
+        error!("Failed to store notification thread for {}: {}", user_id, e);
+    }
 
     info!(
         "Sent notification to user {} for wallet {}",
         user_id, wallet_address
     );
-    Ok(())
+    Ok(sent.id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn price_decimals_follow_significant_figures() {
+        // BTC (szDecimals 5): six integer digits leave no decimals
+        assert_eq!(price_decimals(104_250.0, 5), 0);
+        // SOL (szDecimals 2): five significant figures, at most 4 decimals
+        assert_eq!(price_decimals(151.37, 2), 2);
+        assert_eq!(price_decimals(9.5, 2), 4);
+        // Sub-cent coins (szDecimals 0) are capped at 6 decimals
+        assert_eq!(price_decimals(0.001234, 0), 6);
+        assert_eq!(price_decimals(0.0, 0), 6);
+    }
+
+    #[test]
+    fn round_price_snaps_to_tick() {
+        assert_eq!(round_price(100_000.000001, 5), 100_000.0);
+        assert_eq!(round_price(104_250.6, 5), 104_251.0);
+        assert_eq!(round_price(151.3749, 2), 151.37);
+        assert_eq!(round_price(0.0012345678, 0), 0.001235);
+        // Too small for the precision rounds to zero
+        assert_eq!(round_price(0.0000001, 0), 0.0);
+    }
+
+    #[test]
+    fn format_coin_price_uses_tick_precision() {
+        SZ_DECIMALS
+            .write()
+            .unwrap()
+            .extend([("BTC".into(), 5), ("SOL".into(), 2)]);
+        assert_eq!(format_coin_price("BTC", 104_250.4), "$104250");
+        assert_eq!(format_coin_price("SOL", 151.3749), "$151.37");
+    }
+
+    #[test]
+    fn intern_coin_shares_meta_symbols() {
+        SZ_DECIMALS
+            .write()
+            .unwrap()
+            .entry("ETH".into())
+            .or_insert(4);
+        assert!(Arc::ptr_eq(&intern_coin("ETH"), &intern_coin("ETH")));
+        // Coins missing from the meta get a symbol of their own
+        assert_eq!(&*intern_coin("DELISTED"), "DELISTED");
+    }
+
+    /// Trimmed response of the portfolio endpoint, with a deposit of 500 in the week
+    const PORTFOLIO: &str = r#"[
+        ["day", {"accountValueHistory": [], "pnlHistory": [], "vlm": "0.0"}],
+        ["week", {
+            "accountValueHistory": [[1000, "1000.0"], [2000, "1600.0"], [3000, "1450.0"]],
+            "pnlHistory": [[1000, "20.0"], [2000, "120.0"], [3000, "-30.0"]],
+            "vlm": "52000.5"
+        }]
+    ]"#;
+
+    fn portfolio_window(name: &str) -> PortfolioWindow {
+        let portfolio: Vec<(String, PortfolioWindow)> = serde_json::from_str(PORTFOLIO).unwrap();
+        portfolio
+            .into_iter()
+            .find(|(window, _)| window == name)
+            .unwrap()
+            .1
+    }
+
+    #[test]
+    fn portfolio_window_returns_ignore_deposits() {
+        let week = portfolio_window("week");
+        assert_eq!(week.pnl(), Some(-50.0));
+        assert_eq!(week.return_pct(), Some(-5.0));
+        assert_eq!(week.account_values(), Some((1000.0, 1450.0)));
+        assert_eq!(
+            week.equity_history(),
+            Some(vec![(1000, 1000.0), (2000, 1100.0), (3000, 950.0)])
+        );
+    }
+
+    #[test]
+    fn empty_portfolio_window_has_no_returns() {
+        let day = portfolio_window("day");
+        assert_eq!(day.pnl(), None);
+        assert_eq!(day.return_pct(), None);
+        assert_eq!(day.account_values(), None);
+        assert_eq!(day.equity_history(), None);
+        // Nothing to divide a return by
+        let broke = PortfolioWindow {
+            account_value_history: vec![(0, "0.0".to_string())],
+            pnl_history: vec![(0, "0.0".to_string()), (1, "5.0".to_string())],
+        };
+        assert_eq!(broke.return_pct(), None);
+    }
+
+    #[test]
+    fn drawdown_of_growing_curve_is_zero() {
+        assert_eq!(drawdown(&[]), None);
+        let growing = [(1, 100.0), (2, 100.0), (3, 150.0)];
+        let stats = drawdown(&growing).unwrap();
+        assert_eq!(stats.max_pct, 0.0);
+        assert_eq!(stats.peak, (1, 100.0));
+        assert_eq!(stats.trough, (1, 100.0));
+        assert_eq!(stats.current_pct, 0.0);
+        assert_eq!(stats.recent_peak, (3, 150.0));
+    }
+
+    #[test]
+    fn drawdown_finds_largest_fall_and_current_one() {
+        let curve = [
+            (1, 100.0),
+            (2, 200.0),
+            (3, 150.0),
+            (4, 250.0),
+            (5, 100.0),
+            (6, 200.0),
+        ];
+        let stats = drawdown(&curve).unwrap();
+        assert_eq!(stats.max_pct, 60.0);
+        assert_eq!(stats.peak, (4, 250.0));
+        assert_eq!(stats.trough, (5, 100.0));
+        assert_eq!(stats.current_pct, 20.0);
+        assert_eq!(stats.recent_peak, (4, 250.0));
+        // A wiped out account can't fall further by a percentage
+        assert_eq!(drawdown(&[(1, 0.0), (2, -10.0)]).unwrap().max_pct, 0.0);
+    }
+
+    const WHALE: &str = "0x1234567890abcdef1234567890abcdef12345678";
+
+    fn subscription(user_id: i64, wallet_address: &str, note: Option<&str>) -> db::TrackedWallet {
+        db::TrackedWallet {
+            user_id,
+            wallet_address: wallet_address.to_string(),
+            note: note.map(str::to_string),
+            muted: false,
+            snoozed_until: None,
+        }
+    }
+
+    fn opened(coin: &str, size: f64) -> PositionChange {
+        PositionChange::Opened {
+            coin: coin.to_string(),
+            size,
+            entry_price: 100.0,
+            leverage: 5,
+            position_value: size * 100.0,
+            is_long: true,
+            liquidation_price: None,
+            account_value: 10_000.0,
+            first_trade: false,
+            size_multiple: None,
+            drift: None,
+            listed_ago: None,
+        }
+    }
+
+    fn subscriber(user_id: i64, note: Option<&str>, muted: bool) -> Subscriber {
+        Subscriber {
+            user_id,
+            note: note.map(str::to_string),
+            muted,
+        }
+    }
+
+    #[test]
+    fn mirror_size_commits_the_same_share_of_bankroll() {
+        // The wallet put 20% of its account into the position
+        assert_eq!(mirror_size(1_000.0, 2_000.0, 10_000.0, 100.0), Some(2.0));
+        // Rounded to 4 decimals
+        assert_eq!(mirror_size(1_000.0, 1_000.0, 30_000.0, 700.0), Some(0.0476));
+        // Leveraged positions can exceed the bankroll
+        assert_eq!(mirror_size(500.0, 50_000.0, 10_000.0, 25.0), Some(100.0));
+        assert_eq!(mirror_size(1_000.0, 2_000.0, 0.0, 100.0), None);
+        assert_eq!(mirror_size(1_000.0, 2_000.0, 10_000.0, 0.0), None);
+    }
+
+    #[test]
+    fn mirror_block_sizes_opens_and_adds() {
+        let settings = db::UserSettings {
+            mirror_bankroll: 1_000.0,
+            ..Default::default()
+        };
+        let block = format_mirror_block(&opened("MIRROR", 20.0), &settings).unwrap();
+        assert!(block.contains("Size:     at $1k: 2 MIRROR"), "{}", block);
+        assert!(block.contains("Leverage: 5x"), "{}", block);
+
+        // Only the added size is mirrored, at the current price rather than the entry
+        let increased = PositionChange::Increased {
+            coin: "MIRROR".to_string(),
+            old_size: 10.0,
+            new_size: 20.0,
+            entry_price: 90.0,
+            leverage: 5,
+            is_long: true,
+            unrealized_pnl: 0.0,
+            position_value: 2_000.0,
+            liquidation_price: None,
+            account_value: 10_000.0,
+        };
+        let block = format_mirror_block(&increased, &settings).unwrap();
+        assert!(block.contains("Add:      at $1k: 1 MIRROR"), "{}", block);
+    }
+
+    #[test]
+    fn wallet_reached_twice_routes_to_chat_once() {
+        // Tracked personally with a note and again through a shared list without one
+        let wallets = vec![
+            subscription(1, &WHALE.to_uppercase().replacen("0X", "0x", 1), None),
+            subscription(1, WHALE, Some("whale")),
+            subscription(2, WHALE, None),
+        ];
+        let routes = route_subscriptions(&wallets, 0);
+        assert_eq!(routes.len(), 1);
+        assert_eq!(
+            routes[WHALE],
+            vec![
+                subscriber(1, Some("whale"), false),
+                subscriber(2, None, false)
+            ]
+        );
+    }
+
+    #[test]
+    fn muted_subscriptions_are_still_routed() {
+        let mut muted = subscription(1, WHALE, None);
+        muted.muted = true;
+        let mut snoozed = subscription(2, WHALE, None);
+        snoozed.snoozed_until = Some(100);
+        let wallets = vec![muted.clone(), snoozed, subscription(3, WHALE, None)];
+        assert_eq!(
+            route_subscriptions(&wallets, 50)[WHALE],
+            vec![
+                subscriber(1, None, true),
+                subscriber(2, None, true),
+                subscriber(3, None, false)
+            ]
+        );
+        // A snooze that ran out no longer mutes
+        assert!(!route_subscriptions(&wallets, 150)[WHALE][1].muted);
+        // A chat is only muted when every subscription routing the wallet there is
+        let wallets = vec![muted, subscription(1, WHALE, None)];
+        assert!(!route_subscriptions(&wallets, 0)[WHALE][0].muted);
+    }
+
+    #[test]
+    fn should_notify_applies_pause_mute_threshold_and_coins() {
+        let settings = db::UserSettings {
+            min_notional: 1_000.0,
+            ..Default::default()
+        };
+        let paused = db::UserSettings {
+            notifications_paused: true,
+            paused_until: Some(100),
+            ..settings.clone()
+        };
+        let listening = subscriber(1, None, false);
+        let muted = subscriber(1, None, true);
+        let ignored = ["ETH".to_string()];
+        let all_coins = CoinFilter {
+            watched: None,
+            only: None,
+            ignored: &[],
+        };
+        let no_eth = CoinFilter {
+            watched: None,
+            only: None,
+            ignored: &ignored,
+        };
+        let big_btc = opened("BTC", 20.0);
+        let small_btc = opened("BTC", 1.0);
+        let big_eth = opened("ETH", 20.0);
+
+        assert!(should_notify(
+            &settings, &listening, &all_coins, &big_btc, 0
+        ));
+        assert!(!should_notify(&settings, &muted, &all_coins, &big_btc, 0));
+        assert!(!should_notify(
+            &paused, &listening, &all_coins, &big_btc, 50
+        ));
+        assert!(!should_notify(&paused, &muted, &all_coins, &big_btc, 50));
+        // The pause ends on its own
+        assert!(should_notify(
+            &paused, &listening, &all_coins, &big_btc, 150
+        ));
+        assert!(!should_notify(&paused, &muted, &all_coins, &big_btc, 150));
+        assert!(!should_notify(
+            &settings, &listening, &all_coins, &small_btc, 0
+        ));
+        assert!(!should_notify(&settings, &listening, &no_eth, &big_eth, 0));
+        assert!(should_notify(&settings, &listening, &no_eth, &big_btc, 0));
+
+        // Opens in freshly listed perps get past the coin filters, like the threshold
+        let mut listed_eth = opened("ETH", 1.0);
+        if let PositionChange::Opened { listed_ago, .. } = &mut listed_eth {
+            *listed_ago = Some(3_600);
+        }
+        let watched = HashSet::from(["BTC".to_string()]);
+        let only_btc = CoinFilter {
+            watched: Some(&watched),
+            only: None,
+            ignored: &[],
+        };
+        assert!(should_notify(
+            &settings,
+            &listening,
+            &no_eth,
+            &listed_eth,
+            0
+        ));
+        assert!(should_notify(
+            &settings,
+            &listening,
+            &only_btc,
+            &listed_eth,
+            0
+        ));
+        assert!(!should_notify(
+            &settings, &listening, &only_btc, &big_eth, 0
+        ));
+        assert!(!should_notify(&settings, &muted, &no_eth, &listed_eth, 0));
+
+        let until_resume = db::UserSettings {
+            notifications_paused: true,
+            paused_until: None,
+            ..settings.clone()
+        };
+        assert!(!should_notify(
+            &until_resume,
+            &listening,
+            &all_coins,
+            &big_btc,
+            i64::MAX
+        ));
+    }
+
+    #[test]
+    fn coin_filters_combine_watch_only_and_ignore() {
+        let watched = HashSet::from(["ETH".to_string()]);
+        let only = ["BTC".to_string(), "PEPE".to_string()];
+        let ignored = ["kPEPE".to_string()];
+        // A wallet's watch list decides alone
+        let filter = CoinFilter {
+            watched: Some(&watched),
+            only: Some(&only),
+            ignored: &ignored,
+        };
+        assert!(filter.allows("ETH"));
+        assert!(!filter.allows("BTC"));
+        // Otherwise whitelisted and not ignored, matching k-prefixed coins by their bare name
+        let filter = CoinFilter {
+            watched: None,
+            only: Some(&only),
+            ignored: &[],
+        };
+        assert!(filter.allows("BTC"));
+        assert!(filter.allows("kPEPE"));
+        assert!(!filter.allows("ETH"));
+        let filter = CoinFilter {
+            watched: None,
+            only: Some(&only),
+            ignored: &ignored,
+        };
+        assert!(!filter.allows("kPEPE"));
+    }
+
+    #[test]
+    fn dedup_key_ignores_float_noise() {
+        assert_eq!(
+            dedup_key(WHALE, &opened("BTC", 1.0)),
+            dedup_key(WHALE, &opened("BTC", 1.000001))
+        );
+        assert_ne!(
+            dedup_key(WHALE, &opened("BTC", 1.0)),
+            dedup_key(WHALE, &opened("BTC", 1.001))
+        );
+        assert_ne!(
+            dedup_key(WHALE, &opened("BTC", 1.0)),
+            dedup_key(WHALE, &opened("ETH", 1.0))
+        );
+        assert_ne!(
+            dedup_key(WHALE, &opened("BTC", 1.0)),
+            dedup_key("0xother", &opened("BTC", 1.0))
+        );
+    }
+
+    #[tokio::test]
+    async fn repeated_changes_are_dropped_within_the_window() {
+        let bot = Bot::new("0:test");
+        let state = Arc::new(RwLock::new(PositionTracker::new()));
+        let changes = || vec![opened("BTC", 1.0), opened("ETH", 1.0)];
+
+        assert_eq!(
+            filter_repeated_changes(&bot, &state, WHALE, changes(), 10)
+                .await
+                .len(),
+            2
+        );
+        let kept = filter_repeated_changes(&bot, &state, WHALE, vec![opened("BTC", 1.0)], 11).await;
+        assert!(kept.is_empty());
+        // Another wallet making the same trade is not a repeat
+        let kept = filter_repeated_changes(&bot, &state, "0xother", changes(), 11).await;
+        assert_eq!(kept.len(), 2);
+        // Once the window passed it's a new change
+        let kept =
+            filter_repeated_changes(&bot, &state, WHALE, changes(), 10 + DEDUP_WINDOW_TICKS + 1)
+                .await;
+        assert_eq!(kept.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn reopen_after_close_is_a_flap() {
+        let bot = Bot::new("0:test");
+        let state = Arc::new(RwLock::new(PositionTracker::new()));
+        state.write().await.recent_closes.insert(
+            (WHALE.to_string(), "BTC".to_string()),
+            RecentClose {
+                size: 1.0,
+                is_long: true,
+                at: Instant::now(),
+                messages: Vec::new(),
+            },
+        );
+
+        // A different size is a real new position
+        let kept = filter_repeated_changes(&bot, &state, WHALE, vec![opened("BTC", 2.0)], 1).await;
+        assert_eq!(kept.len(), 1);
+        let kept = filter_repeated_changes(&bot, &state, WHALE, vec![opened("BTC", 1.0)], 5).await;
+        assert!(kept.is_empty());
+        // The close is used up by the flap
+        assert!(state.read().await.recent_closes.is_empty());
+    }
+
+    /// A market maker's state with one long per coin
+    fn large_user_state(positions: usize) -> UserState {
+        let asset_positions = (0..positions)
+            .map(|i| AssetPosition {
+                position: Position {
+                    coin: format!("COIN{}", i),
+                    szi: format!("{}.5", i + 1),
+                    entry_px: Some("10.0".to_string()),
+                    position_value: format!("{}", (i + 1) * 10),
+                    unrealized_pnl: "0.0".to_string(),
+                    liquidation_px: None,
+                    margin_used: "1.0".to_string(),
+                    leverage: Some(Leverage {
+                        leverage_type: "cross".to_string(),
+                        value: 10,
+                    }),
+                    cum_funding: None,
+                },
+            })
+            .collect();
+        UserState {
+            asset_positions,
+            margin_summary: MarginSummary {
+                account_value: "100000.0".to_string(),
+                total_ntl_pos: "0.0".to_string(),
+                total_raw_usd: "0.0".to_string(),
+                total_margin_used: "0.0".to_string(),
+            },
+            cross_margin_summary: None,
+            cross_maintenance_margin_used: None,
+            withdrawable: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn diffing_200_positions_stays_cheap() {
+        let pool = db::tests::test_pool().await;
+        let state = Arc::new(RwLock::new(PositionTracker::new()));
+        let mut user_state = large_user_state(200);
+
+        let changes = detect_position_changes(&pool, &state, WHALE, &user_state).await;
+        assert_eq!(changes.len(), 200);
+
+        // An unchanged response is skipped by its fingerprint
+        for _ in 0..10 {
+            assert!(
+                detect_position_changes(&pool, &state, WHALE, &user_state)
+                    .await
+                    .is_empty()
+            );
+        }
+
+        // A changed response still diffs every position but reports only what moved
+        for tick in 0..10 {
+            user_state.asset_positions[7].position.unrealized_pnl = format!("{}.0", tick);
+            assert!(
+                detect_position_changes(&pool, &state, WHALE, &user_state)
+                    .await
+                    .is_empty()
+            );
+        }
+
+        user_state.asset_positions[7].position.szi = "20.0".to_string();
+        user_state.asset_positions.pop();
+        let changes = detect_position_changes(&pool, &state, WHALE, &user_state).await;
+        let mut kinds: Vec<_> = changes
+            .iter()
+            .map(|change| (change.kind(), change.coin().to_string()))
+            .collect();
+        kinds.sort();
+        assert_eq!(
+            kinds,
+            vec![
+                ("closed", "COIN199".to_string()),
+                ("increased", "COIN7".to_string())
+            ]
+        );
+        assert_eq!(state.read().await.positions[WHALE].len(), 199);
+    }
+
+    #[test]
+    fn delivery_set_sends_each_change_once_per_chat() {
+        let mut delivered = DeliverySet::default();
+        let change = opened("BTC", 1.0);
+        assert!(delivered.claim(1, WHALE, &change));
+        // The same change reaching the chat through another subscription is dropped
+        assert!(!delivered.claim(1, &WHALE.to_uppercase(), &change));
+        assert!(delivered.claim(2, WHALE, &change));
+        assert!(delivered.claim(1, WHALE, &opened("BTC", 2.0)));
+        assert!(delivered.claim(1, WHALE, &opened("ETH", 1.0)));
+    }
 }
@@ -0,0 +1,291 @@
+use log::{error, info, warn};
+use reqwest::Client;
+use serde_json::Value;
+use sqlx::SqlitePool;
+use std::collections::{HashMap, HashSet};
+use tokio::time::{Duration, interval};
+
+use crate::bot::format_wallet_display;
+use crate::db;
+use crate::hyperliquid;
+use crate::timezone::UserTimezone;
+
+const SYNC_INTERVAL_SECS: u64 = 1800;
+/// How far back the first sync of a wallet looks
+const INITIAL_LOOKBACK_DAYS: i64 = 90;
+/// A withdrawal and a deposit this close in time and amount count as the same funds moving
+const MATCH_WINDOW_MS: i64 = 3600 * 1000;
+const MATCH_AMOUNT_PCT: f64 = 1.0;
+/// Evidence lines listed per linked pair
+const MAX_EVIDENCE: usize = 5;
+
+/// Periodically store new deposits, withdrawals and transfers of all tracked wallets
+pub async fn run_ledger_sync(pool: SqlitePool) {
+    let client = Client::builder()
+        .timeout(Duration::from_secs(30))
+        .build()
+        .expect("Failed to create HTTP client");
+    let mut interval = interval(Duration::from_secs(SYNC_INTERVAL_SECS));
+
+    info!("Ledger sync started");
+
+    loop {
+        interval.tick().await;
+
+        let wallets = match db::get_all_tracked_wallets(&pool).await {
+            Ok(wallets) => wallets,
+            Err(e) => {
+                error!("Failed to fetch tracked wallets: {}", e);
+                continue;
+            }
+        };
+        let addresses: HashSet<String> = wallets.into_iter().map(|w| w.wallet_address).collect();
+
+        for wallet_address in addresses {
+            if let Err(e) = sync_wallet(&client, &pool, &wallet_address).await {
+                warn!("Failed to sync ledger for {}: {}", wallet_address, e);
+            }
+        }
+    }
+}
+
+async fn sync_wallet(
+    client: &Client,
+    pool: &SqlitePool,
+    wallet_address: &str,
+) -> anyhow::Result<()> {
+    let start_time = match db::get_latest_ledger_time(pool, wallet_address).await? {
+        Some(time) => time + 1,
+        None => chrono::Utc::now().timestamp_millis() - INITIAL_LOOKBACK_DAYS * 24 * 3600 * 1000,
+    };
+
+    let updates = hyperliquid::fetch_ledger_updates(client, wallet_address, start_time).await?;
+    for update in &updates {
+        let Some((kind, counterparty, incoming, amount)) =
+            parse_delta(wallet_address, &update.delta)
+        else {
+            continue;
+        };
+        db::insert_ledger_update(
+            pool,
+            wallet_address,
+            update.time,
+            &update.hash,
+            kind,
+            counterparty.as_deref(),
+            incoming,
+            amount,
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Extract (kind, counterparty, incoming, amount) from deposits, withdrawals and transfers
+fn parse_delta(
+    wallet_address: &str,
+    delta: &Value,
+) -> Option<(&'static str, Option<String>, bool, f64)> {
+    let amount = ["usdc", "usdcValue"]
+        .iter()
+        .filter_map(|key| delta.get(*key))
+        .find_map(|v| {
+            v.as_str()
+                .and_then(|s| s.parse().ok())
+                .or_else(|| v.as_f64())
+        })
+        .unwrap_or(0.0_f64)
+        .abs();
+
+    match delta.get("type")?.as_str()? {
+        "deposit" => Some(("deposit", None, true, amount)),
+        "withdraw" => Some(("withdraw", None, false, amount)),
+        "internalTransfer" | "spotTransfer" | "subAccountTransfer" | "send" => {
+            let user = delta.get("user")?.as_str()?.to_lowercase();
+            let destination = delta.get("destination")?.as_str()?.to_lowercase();
+            if user == wallet_address.to_lowercase() {
+                Some(("transfer", Some(destination), false, amount))
+            } else {
+                Some(("transfer", Some(user), true, amount))
+            }
+        }
+        // Vault, spot/perp class transfers etc. say nothing about who funds the wallet
+        _ => None,
+    }
+}
+
+fn format_date(time: i64, tz: &UserTimezone) -> String {
+    chrono::DateTime::from_timestamp_millis(time)
+        .map(|t| tz.format_date(t))
+        .unwrap_or_default()
+}
+
+fn short_address(address: &str) -> String {
+    format_wallet_display(address, None, false)
+}
+
+/// Evidence that two wallets might be controlled by the same entity
+fn link_evidence(
+    labels: (&str, &str),
+    a: (&str, &[db::LedgerEntry]),
+    b: (&str, &[db::LedgerEntry]),
+    tz: &UserTimezone,
+) -> Vec<String> {
+    let mut evidence = Vec::new();
+
+    // Direct transfers, each one appears as outgoing in the sender's ledger
+    for ((from_label, to_label), (_, entries), (to_address, _)) in
+        [(labels, a, b), ((labels.1, labels.0), b, a)]
+    {
+        for entry in entries
+            .iter()
+            .filter(|e| !e.incoming && e.counterparty.as_deref() == Some(to_address))
+        {
+            evidence.push(format!(
+                "Direct transfer: ${:.2} from {} to {} ({})",
+                entry.amount,
+                from_label,
+                to_label,
+                format_date(entry.time, tz)
+            ));
+        }
+    }
+
+    // Third-party accounts that sent funds to both
+    let funders = |entries: &[db::LedgerEntry]| -> HashSet<String> {
+        entries
+            .iter()
+            .filter(|e| e.incoming)
+            .filter_map(|e| e.counterparty.clone())
+            .filter(|c| c != a.0 && c != b.0)
+            .collect()
+    };
+    let mut shared: Vec<String> = funders(a.1).intersection(&funders(b.1)).cloned().collect();
+    shared.sort();
+    for funder in shared {
+        evidence.push(format!("Both funded by {}", short_address(&funder)));
+    }
+
+    // Funds withdrawn from one wallet and deposited into the other shortly after
+    for ((from_label, to_label), (_, from_entries), (_, to_entries)) in
+        [(labels, a, b), ((labels.1, labels.0), b, a)]
+    {
+        for withdrawal in from_entries.iter().filter(|e| e.kind == "withdraw") {
+            let deposit = to_entries.iter().find(|d| {
+                d.kind == "deposit"
+                    && d.time >= withdrawal.time
+                    && d.time - withdrawal.time <= MATCH_WINDOW_MS
+                    && withdrawal.amount > 0.0
+                    && (d.amount - withdrawal.amount).abs() / withdrawal.amount * 100.0
+                        <= MATCH_AMOUNT_PCT
+            });
+            if let Some(deposit) = deposit {
+                evidence.push(format!(
+                    "${:.2} withdrawn from {} and ${:.2} deposited to {} {} min later ({})",
+                    withdrawal.amount,
+                    from_label,
+                    deposit.amount,
+                    to_label,
+                    (deposit.time - withdrawal.time) / 60_000,
+                    format_date(withdrawal.time, tz)
+                ));
+            }
+        }
+    }
+
+    evidence
+}
+
+/// Report pairs of wallets with transfers between them or a shared funding source
+pub fn format_cluster_report(
+    wallets: &[db::TrackedWallet],
+    ledgers: &HashMap<String, Vec<db::LedgerEntry>>,
+    tz: &UserTimezone,
+) -> String {
+    let label = |i: usize| format!("#{}", i + 1);
+
+    let with_data: Vec<usize> = (0..wallets.len())
+        .filter(|i| {
+            ledgers
+                .get(&wallets[*i].wallet_address)
+                .is_some_and(|l| !l.is_empty())
+        })
+        .collect();
+    let insufficient: Vec<String> = (0..wallets.len())
+        .filter(|i| !with_data.contains(i))
+        .map(|i| {
+            format!(
+                "{} {}",
+                label(i),
+                format_wallet_display(
+                    &wallets[i].wallet_address,
+                    wallets[i].note.as_deref(),
+                    false
+                )
+            )
+        })
+        .collect();
+
+    let mut links = Vec::new();
+    for (n, &i) in with_data.iter().enumerate() {
+        for &j in &with_data[n + 1..] {
+            let (a, b) = (&wallets[i], &wallets[j]);
+            let (label_a, label_b) = (label(i), label(j));
+            let evidence = link_evidence(
+                (&label_a, &label_b),
+                (&a.wallet_address, &ledgers[&a.wallet_address]),
+                (&b.wallet_address, &ledgers[&b.wallet_address]),
+                tz,
+            );
+            if evidence.is_empty() {
+                continue;
+            }
+
+            let more = if evidence.len() > MAX_EVIDENCE {
+                format!("\n• …and {} more", evidence.len() - MAX_EVIDENCE)
+            } else {
+                String::new()
+            };
+            links.push(format!(
+                "🔗 <b>{}</b> {} ↔ <b>{}</b> {}\n{}{}",
+                label_a,
+                format_wallet_display(&a.wallet_address, a.note.as_deref(), false),
+                label_b,
+                format_wallet_display(&b.wallet_address, b.note.as_deref(), false),
+                evidence
+                    .iter()
+                    .take(MAX_EVIDENCE)
+                    .map(|e| format!("• {}", e))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+                more
+            ));
+        }
+    }
+
+    let links = if links.is_empty() {
+        if with_data.len() < 2 {
+            "Not enough wallets with ledger history to compare yet.".to_string()
+        } else {
+            "✅ No transfers or shared funding sources found between your wallets.".to_string()
+        }
+    } else {
+        links.join("\n\n")
+    };
+    let insufficient = if insufficient.is_empty() {
+        String::new()
+    } else {
+        format!(
+            "\n\n⚪ <b>Insufficient data:</b>\n{}",
+            insufficient.join("\n")
+        )
+    };
+
+    format!(
+        "<b>🕸 Wallet Clusters</b>\n\n\
+         {}{}\n\n\
+         <i>⚠️ Heuristic only: based on the deposits, withdrawals and transfers the bot has stored for your wallets (up to {} days back). A link suggests, but doesn't prove, common ownership.</i>",
+        links, insufficient, INITIAL_LOOKBACK_DAYS
+    )
+}
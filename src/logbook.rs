@@ -0,0 +1,263 @@
+use log::{info, warn};
+use sqlx::SqlitePool;
+use teloxide::{
+    ApiError, RequestError,
+    prelude::*,
+    types::{MessageId, ParseMode},
+};
+
+use crate::bot::format_wallet_display;
+use crate::db;
+use crate::dry_run;
+use crate::hyperliquid::{
+    PositionChange, direction_str, format_coin_price, format_pnl, format_size, trigger_header,
+};
+use crate::timezone::UserTimezone;
+
+/// Telegram rejects messages above 4096 characters, roll over before reaching it
+const MAX_LOGBOOK_LEN: usize = 4000;
+
+/// Append a position change to the user's logbook message for this wallet.
+///
+/// The logbook is edited in place while it fits in a single message. When it
+/// grows too long, or the old message can no longer be edited, a fresh message
+/// is started (and pinned if requested).
+pub async fn append_entry(
+    bot: &Bot,
+    pool: &SqlitePool,
+    logbook: &db::Logbook,
+    note: Option<&str>,
+    change: &PositionChange,
+    tz: &UserTimezone,
+) -> anyhow::Result<()> {
+    let entry = format_entry(change, tz);
+
+    if dry_run::enabled() {
+        let body = match logbook.message_id {
+            Some(_) => format!("{}\n{}", logbook.body, entry),
+            None => entry,
+        };
+        dry_run::record(
+            logbook.user_id,
+            &render_logbook(&logbook.wallet_address, note, &body),
+        );
+        return Ok(());
+    }
+
+    if let Some(message_id) = logbook.message_id {
+        let body = format!("{}\n{}", logbook.body, entry);
+        let text = render_logbook(&logbook.wallet_address, note, &body);
+
+        if text.encode_utf16().count() <= MAX_LOGBOOK_LEN {
+            match bot
+                .edit_message_text(ChatId(logbook.user_id), MessageId(message_id as i32), text)
+                .parse_mode(ParseMode::Html)
+                .await
+            {
+                // Telegram already shows this text, nothing was lost
+                Ok(_) | Err(RequestError::Api(ApiError::MessageNotModified)) => {}
+                Err(
+                    e @ RequestError::Api(
+                        ApiError::MessageToEditNotFound
+                        | ApiError::MessageCantBeEdited
+                        | ApiError::MessageIdInvalid,
+                    ),
+                ) => {
+                    // The message was deleted or is too old to edit, recreate it with the same entries
+                    warn!(
+                        "Failed to edit logbook for user {} wallet {}, recreating: {}",
+                        logbook.user_id, logbook.wallet_address, e
+                    );
+                    return start_logbook_message(bot, pool, logbook, note, &body).await;
+                }
+                Err(e) => {
+                    // Likely transient, keep the entry so the next successful edit shows it
+                    warn!(
+                        "Failed to edit logbook for user {} wallet {}, keeping the entry for the next edit: {}",
+                        logbook.user_id, logbook.wallet_address, e
+                    );
+                }
+            }
+            db::update_logbook_message(
+                pool,
+                logbook.user_id,
+                &logbook.wallet_address,
+                message_id,
+                &body,
+            )
+            .await?;
+            return Ok(());
+        }
+
+        // Full, unpin the old message and roll over to a new one
+        if logbook.pin
+            && let Err(e) = bot
+                .unpin_chat_message(ChatId(logbook.user_id))
+                .message_id(MessageId(message_id as i32))
+                .await
+        {
+            warn!("Failed to unpin old logbook message: {}", e);
+        }
+    }
+
+    start_logbook_message(bot, pool, logbook, note, &entry).await
+}
+
+async fn start_logbook_message(
+    bot: &Bot,
+    pool: &SqlitePool,
+    logbook: &db::Logbook,
+    note: Option<&str>,
+    body: &str,
+) -> anyhow::Result<()> {
+    let chat_id = ChatId(logbook.user_id);
+    let message = bot
+        .send_message(chat_id, render_logbook(&logbook.wallet_address, note, body))
+        .parse_mode(ParseMode::Html)
+        .await?;
+
+    if logbook.pin
+        && let Err(e) = bot
+            .pin_chat_message(chat_id, message.id)
+            .disable_notification(true)
+            .await
+    {
+        warn!("Failed to pin logbook message: {}", e);
+    }
+
+    db::update_logbook_message(
+        pool,
+        logbook.user_id,
+        &logbook.wallet_address,
+        message.id.0 as i64,
+        body,
+    )
+    .await?;
+
+    info!(
+        "Started new logbook message for user {} wallet {}",
+        logbook.user_id, logbook.wallet_address
+    );
+    Ok(())
+}
+
+fn render_logbook(wallet_address: &str, note: Option<&str>, body: &str) -> String {
+    format!(
+        "<b>📒 Trade Log</b>\n\
+         👛 Wallet: {}\n\n\
+         {}",
+        format_wallet_display(wallet_address, note, false),
+        body
+    )
+}
+
+/// Format a position change as a single logbook line
+pub fn format_entry(change: &PositionChange, tz: &UserTimezone) -> String {
+    let time = tz.format(chrono::Utc::now(), "%H:%M");
+
+    let text = match change {
+        PositionChange::Opened {
+            coin,
+            size,
+            entry_price,
+            leverage,
+            is_long,
+            first_trade,
+            size_multiple,
+            listed_ago,
+            ..
+        } => format!(
+            "📈 {}x {} {} opened: {} @ {}{}{}{}",
+            leverage,
+            coin,
+            direction_str(*is_long),
+            format_size(*size),
+            format_coin_price(coin, *entry_price),
+            if *first_trade { " 🆕" } else { "" },
+            size_multiple
+                .map(|m| format!(" 📢{:.1}×", m))
+                .unwrap_or_default(),
+            if listed_ago.is_some() { " 🌱" } else { "" }
+        ),
+        PositionChange::Closed {
+            coin,
+            realized_pnl,
+            was_long,
+            leverage,
+            trigger: Some(trigger),
+            ..
+        } => format!(
+            "{}: PnL {}",
+            trigger_header(trigger, *leverage, coin, *was_long),
+            format_pnl(*realized_pnl)
+        ),
+        PositionChange::Closed {
+            coin,
+            realized_pnl,
+            was_long,
+            leverage,
+            ..
+        } => format!(
+            "📉 {}x {} {} closed: PnL {}",
+            leverage,
+            coin,
+            direction_str(*was_long),
+            format_pnl(*realized_pnl)
+        ),
+        PositionChange::Increased {
+            coin,
+            old_size,
+            new_size,
+            is_long,
+            ..
+        } => format!(
+            "⬆️ {} {} increased: {} → {}",
+            coin,
+            direction_str(*is_long),
+            format_size(*old_size),
+            format_size(*new_size)
+        ),
+        PositionChange::Decreased {
+            coin,
+            old_size,
+            new_size,
+            is_long,
+            leverage,
+            trigger: Some(trigger),
+            ..
+        } => format!(
+            "{}: {} → {}",
+            trigger_header(trigger, *leverage, coin, *is_long),
+            format_size(*old_size),
+            format_size(*new_size)
+        ),
+        PositionChange::Decreased {
+            coin,
+            old_size,
+            new_size,
+            is_long,
+            ..
+        } => format!(
+            "⬇️ {} {} decreased: {} → {}",
+            coin,
+            direction_str(*is_long),
+            format_size(*old_size),
+            format_size(*new_size)
+        ),
+    };
+
+    // Net result for closes of wallets with funding collection on
+    let funding = match change {
+        PositionChange::Closed {
+            realized_pnl,
+            funding: Some(funding),
+            ..
+        } => format!(
+            " (net after funding {})",
+            format_pnl(realized_pnl + funding.amount)
+        ),
+        _ => String::new(),
+    };
+
+    format!("<code>{}</code> {}{}", time, text, funding)
+}
@@ -1,19 +1,37 @@
+mod alerts;
+mod backfill;
 mod bot;
+mod broadcast;
 mod db;
+mod digest;
+mod dry_run;
+mod funding;
 mod hyperliquid;
+mod ledger;
+mod logbook;
 mod logging;
+mod metrics;
+mod price_alerts;
+mod retention;
+mod server;
+mod suggestions;
+mod timezone;
+mod vaults;
+mod wallet_file;
 
 use log::info;
-use std::sync::Arc;
+use std::sync::{Arc, LazyLock};
 use teloxide::prelude::*;
 use tokio::sync::RwLock;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    LazyLock::force(&bot::STARTED_AT);
     dotenvy::dotenv().ok();
     logging::setup_logging()?;
 
     info!("Starting Hyperliquid Telegram Bot...");
+    dry_run::log_banner();
 
     let database_url =
         std::env::var("DATABASE_URL").unwrap_or_else(|_| "sqlite:bot.db".to_string());
@@ -31,8 +49,68 @@ async fn main() -> anyhow::Result<()> {
         hyperliquid::monitor_positions(monitor_pool, monitor_bot, monitor_state).await;
     });
 
+    // Spawn inactive wallet cleanup if the operator enabled it
+    if let Some(config) = retention::RetentionConfig::from_env() {
+        let cleanup_pool = pool.clone();
+        let cleanup_bot = bot.clone();
+        tokio::spawn(async move {
+            retention::run_cleanup(cleanup_pool, cleanup_bot, config).await;
+        });
+    }
+
+    // Spawn ledger sync, used to look for links between wallets
+    let ledger_pool = pool.clone();
+    tokio::spawn(async move {
+        ledger::run_ledger_sync(ledger_pool).await;
+    });
+
+    // Spawn funding sync for wallets that have funding collection on
+    let funding_pool = pool.clone();
+    tokio::spawn(async move {
+        funding::run_funding_sync(funding_pool).await;
+    });
+
+    // Spawn vault equity sync for wallets that include their vault deposits
+    let vaults_pool = pool.clone();
+    tokio::spawn(async move {
+        vaults::run_vault_sync(vaults_pool).await;
+    });
+
+    // Spawn weekly wallet suggestions (each user opts in with /suggest)
+    let suggestions_pool = pool.clone();
+    let suggestions_bot = bot.clone();
+    let suggestions_state = state.clone();
+    tokio::spawn(async move {
+        suggestions::run_weekly_suggestions(suggestions_pool, suggestions_bot, suggestions_state)
+            .await;
+    });
+
+    // Spawn the daily digest (each user opts in with /digest)
+    let digest_pool = pool.clone();
+    let digest_bot = bot.clone();
+    let digest_state = state.clone();
+    tokio::spawn(async move {
+        digest::run_daily_digest(digest_pool, digest_bot, digest_state).await;
+    });
+
+    // Finish announcements a restart interrupted
+    let broadcast_pool = pool.clone();
+    let broadcast_bot = bot.clone();
+    tokio::spawn(async move {
+        broadcast::resume_broadcasts(broadcast_pool, broadcast_bot).await;
+    });
+
+    // Spawn the HTTP API if a listen address is configured
+    if let Some(config) = server::ServerConfig::from_env() {
+        let server_pool = pool.clone();
+        let server_state = state.clone();
+        tokio::spawn(async move {
+            server::run(server_pool, server_state, config).await;
+        });
+    }
+
     // Start the bot
-    bot::run(bot, pool).await;
+    bot::run(bot, pool, state).await;
 
     Ok(())
 }
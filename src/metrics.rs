@@ -0,0 +1,110 @@
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::hyperliquid::PositionTracker;
+
+/// Length of the address prefix used as the wallet label
+const WALLET_LABEL_LEN: usize = 10;
+
+/// Notifications dropped because the same change was sent moments before
+pub static DUPLICATES_SUPPRESSED: AtomicU64 = AtomicU64::new(0);
+/// Close-then-reopen pairs for an unchanged position that were cancelled
+pub static FLAPS_SUPPRESSED: AtomicU64 = AtomicU64::new(0);
+
+/// Render all counters in the Prometheus text format
+pub fn render() -> String {
+    let counters = [
+        (
+            "hyperliquid_bot_duplicates_suppressed_total",
+            "Identical notifications suppressed within the dedup window",
+            &DUPLICATES_SUPPRESSED,
+        ),
+        (
+            "hyperliquid_bot_flaps_suppressed_total",
+            "Close-then-reopen flaps cancelled, a sign of API instability",
+            &FLAPS_SUPPRESSED,
+        ),
+    ];
+
+    counters
+        .iter()
+        .map(|(name, help, value)| {
+            format!(
+                "# HELP {} {}\n# TYPE {} counter\n{} {}\n",
+                name,
+                help,
+                name,
+                name,
+                value.load(Ordering::Relaxed)
+            )
+        })
+        .collect()
+}
+
+/// Render per-wallet poll freshness for the `limit` most stale wallets.
+///
+/// Wallets are labelled by a truncated address to keep the payload small.
+/// Wallets that never polled successfully only get a failure count.
+pub fn render_wallet_freshness(tracker: &PositionTracker, limit: usize) -> String {
+    let now = chrono::Utc::now();
+    let wallets: HashSet<&String> = tracker
+        .refreshed_at
+        .keys()
+        .chain(tracker.poll_failures.keys())
+        .collect();
+
+    let mut rows: Vec<(String, Option<i64>, u32)> = wallets
+        .into_iter()
+        .map(|wallet| {
+            (
+                wallet.chars().take(WALLET_LABEL_LEN).collect(),
+                tracker
+                    .refreshed_at
+                    .get(wallet)
+                    .map(|t| (now - *t).num_seconds()),
+                tracker.poll_failures.get(wallet).copied().unwrap_or(0),
+            )
+        })
+        .collect();
+    // Never refreshed sorts first, then oldest poll, then most failures
+    rows.sort_by(|a, b| {
+        a.1.map_or(i64::MIN, |age| -age)
+            .cmp(&b.1.map_or(i64::MIN, |age| -age))
+            .then(b.2.cmp(&a.2))
+            .then(a.0.cmp(&b.0))
+    });
+    rows.truncate(limit);
+
+    let mut out = String::new();
+    if let Some(average) = tracker.average_data_age_secs() {
+        out.push_str(&format!(
+            "# HELP hyperliquid_bot_average_data_age_seconds Mean seconds since each wallet was last polled successfully\n\
+             # TYPE hyperliquid_bot_average_data_age_seconds gauge\n\
+             hyperliquid_bot_average_data_age_seconds {:.1}\n",
+            average
+        ));
+    }
+    out.push_str(
+        "# HELP hyperliquid_bot_wallet_poll_age_seconds Seconds since the wallet was last polled successfully\n\
+         # TYPE hyperliquid_bot_wallet_poll_age_seconds gauge\n",
+    );
+    for (label, age, _) in &rows {
+        if let Some(age) = age {
+            out.push_str(&format!(
+                "hyperliquid_bot_wallet_poll_age_seconds{{wallet=\"{}\"}} {}\n",
+                label, age
+            ));
+        }
+    }
+    out.push_str(
+        "# HELP hyperliquid_bot_wallet_poll_failures Consecutive failed polls of the wallet\n\
+         # TYPE hyperliquid_bot_wallet_poll_failures gauge\n",
+    );
+    for (label, _, failures) in &rows {
+        out.push_str(&format!(
+            "hyperliquid_bot_wallet_poll_failures{{wallet=\"{}\"}} {}\n",
+            label, failures
+        ));
+    }
+    out
+}
@@ -0,0 +1,116 @@
+use log::{error, info};
+use sqlx::SqlitePool;
+use std::collections::{HashMap, HashSet};
+use teloxide::{prelude::*, types::ParseMode};
+
+use crate::db;
+use crate::dry_run;
+use crate::hyperliquid::{self, format_coin_price};
+
+/// Most active price alerts a user can have
+pub const MAX_ALERTS_PER_USER: usize = 20;
+
+/// Whether a mid has reached an alert's price from the side it was set on
+pub fn is_triggered(above: bool, target: f64, mid: f64) -> bool {
+    if above { mid >= target } else { mid <= target }
+}
+
+/// An alert price rounded to the coin's tick, or a message saying why it can't be used
+pub fn snap_threshold(coin: &str, price: f64) -> Result<f64, String> {
+    let Some(snapped) = hyperliquid::round_coin_price(coin, price) else {
+        return Err(
+            "❌ The asset list isn't loaded yet. Please try again in a minute.".to_string(),
+        );
+    };
+    if snapped <= 0.0 {
+        return Err(format!(
+            "❌ {} is below the smallest price step {} is quoted in.",
+            price, coin
+        ));
+    }
+    Ok(snapped)
+}
+
+pub fn direction_str(above: bool) -> &'static str {
+    if above { "above" } else { "below" }
+}
+
+/// Fire the alerts the current mids have reached, deleting each one as it goes out.
+///
+/// Alerts of paused users stay armed and fire once they resume, if the price is still there.
+pub async fn check_price_alerts(bot: &Bot, pool: &SqlitePool, mids: &HashMap<String, f64>) {
+    let alerts = match db::get_all_price_alerts(pool).await {
+        Ok(alerts) => alerts,
+        Err(e) => {
+            error!("Failed to fetch price alerts: {}", e);
+            return;
+        }
+    };
+    if alerts.is_empty() {
+        return;
+    }
+
+    let paused_users = db::get_paused_users(pool).await.unwrap_or_else(|e| {
+        error!("Failed to fetch paused users: {}", e);
+        HashSet::new()
+    });
+
+    for alert in alerts {
+        let Some(mid) = mids.get(&alert.coin).copied() else {
+            continue;
+        };
+        if !is_triggered(alert.above, alert.price, mid) || paused_users.contains(&alert.user_id) {
+            continue;
+        }
+
+        // Delete first so a failed send can't make the alert fire every tick
+        match db::delete_price_alert(pool, alert.user_id, alert.id).await {
+            Ok(true) => {}
+            Ok(false) => continue,
+            Err(e) => {
+                error!("Failed to delete price alert {}: {}", alert.id, e);
+                continue;
+            }
+        }
+
+        match send_price_alert(bot, &alert, mid).await {
+            Ok(()) => hyperliquid::log_delivery(pool, alert.user_id, None, "price_alert").await,
+            Err(e) => error!(
+                "Failed to send price alert {} to {}: {}",
+                alert.id, alert.user_id, e
+            ),
+        }
+    }
+}
+
+async fn send_price_alert(bot: &Bot, alert: &db::PriceAlert, mid: f64) -> anyhow::Result<()> {
+    let message = format!(
+        "<b>🔔 {} Price Alert</b>\n\n\
+         {} is {} {}\n\
+         📍 Current: {}\n\n\
+         <i>This alert has been removed, set a new one with /alert.</i>",
+        alert.coin,
+        alert.coin,
+        direction_str(alert.above),
+        format_coin_price(&alert.coin, alert.price),
+        format_coin_price(&alert.coin, mid)
+    );
+
+    if dry_run::enabled() {
+        dry_run::record(alert.user_id, &message);
+        return Ok(());
+    }
+
+    bot.send_message(ChatId(alert.user_id), message)
+        .parse_mode(ParseMode::Html)
+        .await?;
+    info!(
+        "Sent price alert {} ({} {} {}) to user {}",
+        alert.id,
+        alert.coin,
+        direction_str(alert.above),
+        alert.price,
+        alert.user_id
+    );
+    Ok(())
+}
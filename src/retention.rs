@@ -0,0 +1,130 @@
+use log::{error, info, warn};
+use sqlx::SqlitePool;
+use teloxide::{prelude::*, types::ParseMode};
+use tokio::time::{Duration, interval};
+
+use crate::bot::format_wallet_display;
+use crate::db;
+use crate::dry_run;
+
+const CLEANUP_INTERVAL_SECS: u64 = 3600;
+const DEFAULT_INACTIVE_DAYS: i64 = 120;
+const DEFAULT_GRACE_DAYS: i64 = 14;
+
+#[derive(Debug, Clone)]
+pub struct RetentionConfig {
+    pub inactive_days: i64,
+    pub grace_days: i64,
+}
+
+impl RetentionConfig {
+    /// Read the cleanup policy from the environment.
+    ///
+    /// Returns `None` unless `INACTIVITY_CLEANUP` is set to `1` or `true`, so
+    /// self-hosted instances never drop wallets unless the operator opts in.
+    pub fn from_env() -> Option<Self> {
+        let enabled = std::env::var("INACTIVITY_CLEANUP")
+            .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+            .unwrap_or(false);
+        if !enabled {
+            return None;
+        }
+
+        let inactive_days = std::env::var("INACTIVITY_DAYS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_INACTIVE_DAYS);
+        let grace_days = std::env::var("INACTIVITY_GRACE_DAYS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_GRACE_DAYS);
+
+        Some(Self {
+            inactive_days,
+            grace_days,
+        })
+    }
+}
+
+/// Periodically warn inactive users and soft-remove their wallets once the grace period ends
+pub async fn run_cleanup(pool: SqlitePool, bot: Bot, config: RetentionConfig) {
+    let mut interval = interval(Duration::from_secs(CLEANUP_INTERVAL_SECS));
+
+    info!(
+        "Inactivity cleanup started (warn after {} days, remove {} days later)",
+        config.inactive_days, config.grace_days
+    );
+
+    loop {
+        interval.tick().await;
+
+        match db::get_users_to_warn(&pool, config.inactive_days).await {
+            Ok(users) => {
+                for user_id in users {
+                    if let Err(e) = send_inactivity_warning(&bot, &pool, user_id, &config).await {
+                        warn!("Failed to send inactivity warning to {}: {}", user_id, e);
+                    }
+                    // Mark as warned even if the DM failed (e.g. the bot was blocked),
+                    // otherwise the grace period would never start
+                    if let Err(e) = db::mark_user_warned(&pool, user_id).await {
+                        error!("Failed to mark user {} as warned: {}", user_id, e);
+                    }
+                }
+            }
+            Err(e) => error!("Failed to fetch inactive users: {}", e),
+        }
+
+        match db::get_users_to_clean_up(&pool, config.grace_days).await {
+            Ok(users) => {
+                for user_id in users {
+                    match db::soft_remove_user_wallets(&pool, user_id).await {
+                        Ok(count) => info!(
+                            "Stopped tracking {} wallets of inactive user {}",
+                            count, user_id
+                        ),
+                        Err(e) => error!("Failed to remove wallets of user {}: {}", user_id, e),
+                    }
+                }
+            }
+            Err(e) => error!("Failed to fetch users to clean up: {}", e),
+        }
+    }
+}
+
+async fn send_inactivity_warning(
+    bot: &Bot,
+    pool: &SqlitePool,
+    user_id: i64,
+    config: &RetentionConfig,
+) -> anyhow::Result<()> {
+    let wallets = db::get_user_wallets(pool, user_id).await?;
+    let wallet_list = wallets
+        .iter()
+        .map(|w| {
+            format!(
+                "• {}",
+                format_wallet_display(&w.wallet_address, w.note.as_deref(), false)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let message = format!(
+        "<b>💤 Still there?</b>\n\n\
+         You haven't used the bot in {} days. To save resources, these wallets will stop being tracked in {} days:\n\n\
+         {}\n\n\
+         <i>Send any command (e.g. /list) to keep them. If they are removed, using the bot again restores them.</i>",
+        config.inactive_days, config.grace_days, wallet_list
+    );
+    if dry_run::enabled() {
+        dry_run::record(user_id, &message);
+        return Ok(());
+    }
+
+    bot.send_message(ChatId(user_id), message)
+        .parse_mode(ParseMode::Html)
+        .await?;
+
+    info!("Sent inactivity warning to user {}", user_id);
+    Ok(())
+}
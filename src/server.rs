@@ -0,0 +1,263 @@
+use axum::{
+    Json, Router,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use log::{error, info};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+use crate::db;
+use crate::hyperliquid::PositionTracker;
+use crate::metrics;
+
+/// Most events returned by a single /api/events request
+const MAX_EVENTS_PER_REQUEST: i64 = 500;
+/// Default look-back for /api/events when `since` is omitted
+const DEFAULT_EVENTS_LOOKBACK_SECS: i64 = 24 * 3600;
+/// Default number of wallets exported in the per-wallet freshness metrics
+const DEFAULT_METRICS_WALLET_LIMIT: usize = 100;
+
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub listen_addr: String,
+    /// Most stale wallets exported in the per-wallet metrics
+    pub metrics_wallet_limit: usize,
+}
+
+impl ServerConfig {
+    /// Read the listen address from `HTTP_LISTEN_ADDR`, the server stays off when unset
+    pub fn from_env() -> Option<Self> {
+        let listen_addr = std::env::var("HTTP_LISTEN_ADDR")
+            .ok()
+            .filter(|addr| !addr.trim().is_empty())?;
+        let metrics_wallet_limit = std::env::var("METRICS_WALLET_LIMIT")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_METRICS_WALLET_LIMIT);
+
+        Some(Self {
+            listen_addr,
+            metrics_wallet_limit,
+        })
+    }
+}
+
+#[derive(Clone)]
+struct AppState {
+    pool: SqlitePool,
+    tracker: Arc<RwLock<PositionTracker>>,
+    metrics_wallet_limit: usize,
+}
+
+struct ApiError(StatusCode, &'static str);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(json!({ "error": self.1 }))).into_response()
+    }
+}
+
+#[derive(Deserialize)]
+struct WalletsQuery {
+    user: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct EventsQuery {
+    /// Unix timestamp in seconds, inclusive
+    since: Option<i64>,
+}
+
+/// Serve the health check, metrics and the read-only JSON API
+pub async fn run(pool: SqlitePool, tracker: Arc<RwLock<PositionTracker>>, config: ServerConfig) {
+    let app = Router::new()
+        .route("/health", get(health))
+        .route("/metrics", get(prometheus_metrics))
+        .route("/api/wallets", get(api_wallets))
+        .route("/api/positions/{wallet}", get(api_positions))
+        .route("/api/events", get(api_events))
+        .with_state(AppState {
+            pool,
+            tracker,
+            metrics_wallet_limit: config.metrics_wallet_limit,
+        });
+
+    let listener = match tokio::net::TcpListener::bind(&config.listen_addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!(
+                "Failed to bind HTTP server to {}: {}",
+                config.listen_addr, e
+            );
+            return;
+        }
+    };
+
+    info!("HTTP server listening on {}", config.listen_addr);
+    if let Err(e) = axum::serve(listener, app).await {
+        error!("HTTP server stopped: {}", e);
+    }
+}
+
+/// Generate a random API token to hand out to a user
+pub fn generate_token() -> String {
+    let bytes: [u8; 32] = rand::random();
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Tokens are only stored hashed
+pub fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Resolve the bearer token on a request to the user it was issued to
+async fn authenticate(pool: &SqlitePool, headers: &HeaderMap) -> Result<i64, ApiError> {
+    let token = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::trim)
+        .ok_or(ApiError(StatusCode::UNAUTHORIZED, "missing bearer token"))?;
+
+    match db::get_api_token_user(pool, &hash_token(token)).await {
+        Ok(Some(user_id)) => Ok(user_id),
+        Ok(None) => Err(ApiError(StatusCode::UNAUTHORIZED, "invalid token")),
+        Err(e) => {
+            error!("Failed to look up API token: {}", e);
+            Err(ApiError(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "internal error",
+            ))
+        }
+    }
+}
+
+async fn health() -> &'static str {
+    "ok"
+}
+
+async fn prometheus_metrics(State(app): State<AppState>) -> String {
+    let tracker = app.tracker.read().await;
+    metrics::render() + &metrics::render_wallet_freshness(&tracker, app.metrics_wallet_limit)
+}
+
+async fn api_wallets(
+    State(app): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<WalletsQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = authenticate(&app.pool, &headers).await?;
+    if query.user.is_some_and(|user| user != user_id) {
+        return Err(ApiError(
+            StatusCode::FORBIDDEN,
+            "token does not belong to this user",
+        ));
+    }
+
+    let wallets = db::get_user_wallets(&app.pool, user_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch wallets for API user {}: {}", user_id, e);
+            ApiError(StatusCode::INTERNAL_SERVER_ERROR, "internal error")
+        })?;
+
+    let wallets: Vec<Value> = wallets
+        .iter()
+        .enumerate()
+        .map(|(i, w)| {
+            json!({
+                "index": i + 1,
+                "address": w.wallet_address,
+                "note": w.note,
+            })
+        })
+        .collect();
+
+    Ok(Json(json!({ "user_id": user_id, "wallets": wallets })))
+}
+
+async fn api_positions(
+    State(app): State<AppState>,
+    headers: HeaderMap,
+    Path(wallet): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = authenticate(&app.pool, &headers).await?;
+    let wallet = wallet.to_lowercase();
+
+    let tracking = db::is_tracking(&app.pool, user_id, &wallet)
+        .await
+        .map_err(|e| {
+            error!(
+                "Failed to check tracked wallet for API user {}: {}",
+                user_id, e
+            );
+            ApiError(StatusCode::INTERNAL_SERVER_ERROR, "internal error")
+        })?;
+    if !tracking {
+        return Err(ApiError(StatusCode::NOT_FOUND, "wallet is not tracked"));
+    }
+
+    let tracker = app.tracker.read().await;
+    let positions: Vec<Value> = tracker
+        .positions
+        .get(&wallet)
+        .map(|coins| {
+            coins
+                .iter()
+                .map(|(coin, pos)| {
+                    json!({
+                        "coin": &**coin,
+                        "size": pos.size,
+                        "entry_px": pos.entry_px,
+                        "unrealized_pnl": pos.unrealized_pnl,
+                        "leverage": pos.leverage,
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let refreshed_at = tracker.refreshed_at.get(&wallet).copied();
+
+    Ok(Json(json!({
+        "wallet": wallet,
+        "positions": positions,
+        "updated_at": refreshed_at.map(|t| t.to_rfc3339()),
+        "age_secs": refreshed_at.map(|t| (chrono::Utc::now() - t).num_seconds()),
+    })))
+}
+
+async fn api_events(
+    State(app): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<EventsQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = authenticate(&app.pool, &headers).await?;
+
+    let since = query
+        .since
+        .unwrap_or_else(|| chrono::Utc::now().timestamp() - DEFAULT_EVENTS_LOOKBACK_SECS);
+    let since = chrono::DateTime::from_timestamp(since, 0)
+        .ok_or(ApiError(StatusCode::BAD_REQUEST, "invalid since timestamp"))?
+        // Same format SQLite uses for CURRENT_TIMESTAMP
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string();
+
+    let events = db::get_user_events_since(&app.pool, user_id, &since, MAX_EVENTS_PER_REQUEST)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch events for API user {}: {}", user_id, e);
+            ApiError(StatusCode::INTERNAL_SERVER_ERROR, "internal error")
+        })?;
+
+    Ok(Json(json!({ "events": events })))
+}
@@ -0,0 +1,306 @@
+use log::{error, info, warn};
+use reqwest::Client;
+use sqlx::SqlitePool;
+use std::collections::HashSet;
+use std::sync::Arc;
+use teloxide::{
+    prelude::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup, ParseMode},
+};
+use tokio::sync::RwLock;
+use tokio::time::{Duration, interval};
+
+use crate::bot::format_wallet_display;
+use crate::db;
+use crate::dry_run;
+use crate::hyperliquid::{self, PositionTracker, direction_str, format_pnl, format_usd_compact};
+
+const CHECK_INTERVAL_SECS: u64 = 3600;
+/// Top weekly leaderboard wallets whose positions are fetched each run
+const CANDIDATE_POOL: usize = 15;
+const MAX_SUGGESTIONS: usize = 3;
+/// Skip tiny accounts, they dominate the leaderboard on luck alone
+const MIN_ACCOUNT_VALUE: f64 = 10_000.0;
+/// Average position sizes within this factor of each other count as a similar style
+const SIZE_MATCH_FACTOR: f64 = 3.0;
+
+struct Candidate {
+    address: String,
+    display_name: Option<String>,
+    week_pnl: f64,
+    account_value: f64,
+    /// (coin, is_long, notional), largest first
+    positions: Vec<(String, bool, f64)>,
+}
+
+impl Candidate {
+    fn avg_notional(&self) -> Option<f64> {
+        if self.positions.is_empty() {
+            return None;
+        }
+        Some(self.positions.iter().map(|(_, _, n)| n).sum::<f64>() / self.positions.len() as f64)
+    }
+}
+
+/// Coins and average position size across a user's tracked wallets
+struct Style {
+    coins: HashSet<String>,
+    avg_notional: Option<f64>,
+}
+
+/// Once a week, suggest top leaderboard wallets to users who opted in
+pub async fn run_weekly_suggestions(
+    pool: SqlitePool,
+    bot: Bot,
+    state: Arc<RwLock<PositionTracker>>,
+) {
+    let client = Client::builder()
+        .timeout(Duration::from_secs(60))
+        .build()
+        .expect("Failed to create HTTP client");
+    let mut interval = interval(Duration::from_secs(CHECK_INTERVAL_SECS));
+
+    info!("Weekly wallet suggestions started");
+
+    loop {
+        interval.tick().await;
+
+        let users = match db::get_users_due_suggestions(&pool).await {
+            Ok(users) => users,
+            Err(e) => {
+                error!("Failed to fetch users due suggestions: {}", e);
+                continue;
+            }
+        };
+        if users.is_empty() {
+            continue;
+        }
+
+        // The leaderboard and candidate states are shared by every user due this run
+        let candidates = match load_candidates(&client).await {
+            Ok(candidates) => candidates,
+            Err(e) => {
+                warn!("Failed to load suggestion candidates: {}", e);
+                continue;
+            }
+        };
+
+        for user_id in users {
+            if let Err(e) = send_suggestions(&bot, &pool, &state, user_id, &candidates).await {
+                warn!("Failed to send suggestions to {}: {}", user_id, e);
+            }
+            // Mark as sent even on failure so a blocked bot isn't retried every hour
+            if let Err(e) = db::mark_suggestions_sent(&pool, user_id).await {
+                error!("Failed to mark suggestions sent for {}: {}", user_id, e);
+            }
+        }
+    }
+}
+
+/// Top wallets by 7d PnL with their current positions
+async fn load_candidates(client: &Client) -> anyhow::Result<Vec<Candidate>> {
+    let rows = hyperliquid::fetch_leaderboard(client).await?;
+
+    let mut ranked: Vec<(f64, f64, hyperliquid::LeaderboardRow)> = rows
+        .into_iter()
+        .filter_map(|row| {
+            let week_pnl: f64 = row.performance("week")?.pnl.parse().ok()?;
+            let account_value: f64 = row.account_value.parse().ok()?;
+            (week_pnl > 0.0 && account_value >= MIN_ACCOUNT_VALUE).then_some((
+                week_pnl,
+                account_value,
+                row,
+            ))
+        })
+        .collect();
+    // Break ties on address so the ranking is fully deterministic
+    ranked.sort_by(|a, b| {
+        b.0.total_cmp(&a.0)
+            .then_with(|| a.2.eth_address.cmp(&b.2.eth_address))
+    });
+
+    let mut candidates = Vec::new();
+    for (week_pnl, account_value, row) in ranked.into_iter().take(CANDIDATE_POOL) {
+        let address = row.eth_address.to_lowercase();
+        let user_state = match hyperliquid::fetch_user_state(client, &address).await {
+            Ok(user_state) => user_state,
+            Err(e) => {
+                warn!("Failed to fetch positions for candidate {}: {}", address, e);
+                continue;
+            }
+        };
+
+        let mut positions: Vec<(String, bool, f64)> = user_state
+            .asset_positions
+            .iter()
+            .filter_map(|ap| {
+                let size: f64 = ap.position.szi.parse().ok()?;
+                let notional: f64 = ap.position.position_value.parse().ok()?;
+                (size != 0.0).then(|| (ap.position.coin.clone(), size > 0.0, notional))
+            })
+            .collect();
+        positions.sort_by(|a, b| b.2.total_cmp(&a.2));
+
+        candidates.push(Candidate {
+            address,
+            display_name: row.display_name,
+            week_pnl,
+            account_value,
+            positions,
+        });
+    }
+
+    info!("Loaded {} wallet suggestion candidates", candidates.len());
+    Ok(candidates)
+}
+
+/// Derive a user's trading style from the monitor's cached positions
+async fn user_style(state: &Arc<RwLock<PositionTracker>>, wallets: &[db::TrackedWallet]) -> Style {
+    let state = state.read().await;
+    let mut coins = HashSet::new();
+    let mut notionals = Vec::new();
+
+    for wallet in wallets {
+        let Some(positions) = state.positions.get(&wallet.wallet_address) else {
+            continue;
+        };
+        for (coin, pos) in positions {
+            let size: f64 = pos.size.parse().unwrap_or(0.0);
+            let entry_px: f64 = pos.entry_px.parse().unwrap_or(0.0);
+            coins.insert(coin.to_string());
+            notionals.push(size.abs() * entry_px);
+        }
+    }
+
+    let avg_notional =
+        (!notionals.is_empty()).then(|| notionals.iter().sum::<f64>() / notionals.len() as f64);
+    Style {
+        coins,
+        avg_notional,
+    }
+}
+
+async fn send_suggestions(
+    bot: &Bot,
+    pool: &SqlitePool,
+    state: &Arc<RwLock<PositionTracker>>,
+    user_id: i64,
+    candidates: &[Candidate],
+) -> anyhow::Result<()> {
+    let wallets = db::get_user_wallets(pool, user_id).await?;
+    let tracked: HashSet<&str> = wallets.iter().map(|w| w.wallet_address.as_str()).collect();
+    let style = user_style(state, &wallets).await;
+
+    // Keep leaderboard order, only filtering on style so results stay predictable
+    let suggestions: Vec<(&Candidate, Option<String>)> = candidates
+        .iter()
+        .filter(|c| !tracked.contains(c.address.as_str()))
+        .filter_map(|c| {
+            let shared: Vec<&str> = c
+                .positions
+                .iter()
+                .map(|(coin, _, _)| coin.as_str())
+                .filter(|coin| style.coins.contains(*coin))
+                .collect();
+            let similar_size = match (style.avg_notional, c.avg_notional()) {
+                (Some(mine), Some(theirs)) if mine > 0.0 && theirs > 0.0 => {
+                    let ratio = theirs / mine;
+                    (1.0 / SIZE_MATCH_FACTOR..=SIZE_MATCH_FACTOR).contains(&ratio)
+                }
+                _ => false,
+            };
+
+            if !shared.is_empty() {
+                Some((c, Some(format!("Also trades {}", shared.join(", ")))))
+            } else if similar_size {
+                Some((c, Some("Similar position sizes".to_string())))
+            } else if style.coins.is_empty() {
+                // Nothing to compare against yet, fall back to the plain ranking
+                Some((c, None))
+            } else {
+                None
+            }
+        })
+        .take(MAX_SUGGESTIONS)
+        .collect();
+
+    if suggestions.is_empty() {
+        info!("No matching wallet suggestions for user {}", user_id);
+        return Ok(());
+    }
+
+    let entries = suggestions
+        .iter()
+        .enumerate()
+        .map(|(i, (c, reason))| {
+            let top_positions = if c.positions.is_empty() {
+                "No open positions".to_string()
+            } else {
+                c.positions
+                    .iter()
+                    .take(3)
+                    .map(|(coin, is_long, notional)| {
+                        format!(
+                            "{} {} {}",
+                            coin,
+                            direction_str(*is_long),
+                            format_usd_compact(*notional)
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            let reason = reason
+                .as_ref()
+                .map(|r| format!("\n<i>{}</i>", r))
+                .unwrap_or_default();
+            format!(
+                "<b>{}.</b> {}\n\
+                 📈 7d PnL: {} · 💰 Account: {}\n\
+                 📊 {}{}",
+                i + 1,
+                format_wallet_display(&c.address, c.display_name.as_deref(), true),
+                format_pnl(c.week_pnl),
+                format_usd_compact(c.account_value),
+                top_positions,
+                reason
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n");
+
+    let buttons: Vec<InlineKeyboardButton> = suggestions
+        .iter()
+        .enumerate()
+        .map(|(i, (c, _))| {
+            InlineKeyboardButton::callback(
+                format!("➕ Track #{}", i + 1),
+                format!("track:{}", c.address),
+            )
+        })
+        .collect();
+
+    let message = format!(
+        "<b>🔭 Wallets Worth Watching</b>\n\n\
+         Top traders this week that match your tracked wallets:\n\n\
+         {}\n\n\
+         <i>Sent weekly. Turn off with /suggest off</i>",
+        entries
+    );
+    if dry_run::enabled() {
+        dry_run::record(user_id, &message);
+        return Ok(());
+    }
+
+    bot.send_message(ChatId(user_id), message)
+        .parse_mode(ParseMode::Html)
+        .reply_markup(InlineKeyboardMarkup::new(vec![buttons]))
+        .await?;
+
+    info!(
+        "Sent {} wallet suggestions to user {}",
+        suggestions.len(),
+        user_id
+    );
+    Ok(())
+}
@@ -0,0 +1,142 @@
+use chrono::{DateTime, Duration, FixedOffset, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+/// Largest UTC offset accepted, real zones stay within ±14 hours
+const MAX_OFFSET_MINUTES: i32 = 14 * 60;
+
+/// The timezone a user sees times in, an IANA zone or a fixed UTC offset
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UserTimezone {
+    Named(Tz),
+    Fixed(FixedOffset),
+}
+
+impl Default for UserTimezone {
+    fn default() -> Self {
+        Self::Named(Tz::UTC)
+    }
+}
+
+impl UserTimezone {
+    /// Parse an IANA name like "Europe/Warsaw" (any case) or an offset like "UTC+2", "+05:30" or "-3"
+    pub fn parse(input: &str) -> Option<Self> {
+        let input = input.trim();
+        if let Ok(tz) = input.parse::<Tz>() {
+            return Some(Self::Named(tz));
+        }
+        if let Some(tz) = chrono_tz::TZ_VARIANTS
+            .iter()
+            .find(|tz| tz.name().eq_ignore_ascii_case(input))
+        {
+            return Some(Self::Named(*tz));
+        }
+
+        let upper = input.to_ascii_uppercase();
+        let offset = upper
+            .strip_prefix("UTC")
+            .or_else(|| upper.strip_prefix("GMT"))
+            .unwrap_or(&upper);
+        if offset.is_empty() {
+            return Some(Self::default());
+        }
+        parse_offset(offset).map(Self::Fixed)
+    }
+
+    /// Canonical form stored in user_settings, parses back to the same timezone
+    pub fn name(&self) -> String {
+        match self {
+            Self::Named(tz) => tz.name().to_string(),
+            Self::Fixed(offset) => format!("UTC{}", offset),
+        }
+    }
+
+    /// Render a UTC time on the user's clock with a chrono format string
+    pub fn format(&self, time: DateTime<Utc>, fmt: &str) -> String {
+        match self {
+            Self::Named(tz) => time.with_timezone(tz).format(fmt).to_string(),
+            Self::Fixed(offset) => time.with_timezone(offset).format(fmt).to_string(),
+        }
+    }
+
+    /// Date, time and zone abbreviation, like "2024-03-01 14:05 CET"
+    pub fn format_datetime(&self, time: DateTime<Utc>) -> String {
+        self.format(time, "%Y-%m-%d %H:%M %Z")
+    }
+
+    /// Same as `format_datetime` for milliseconds since the epoch
+    pub fn format_millis(&self, millis: i64) -> String {
+        DateTime::from_timestamp_millis(millis)
+            .map(|t| self.format_datetime(t))
+            .unwrap_or_default()
+    }
+
+    /// Render a SQLite CURRENT_TIMESTAMP (UTC) value, as stored if it doesn't parse
+    pub fn format_stored(&self, stored: &str) -> String {
+        NaiveDateTime::parse_from_str(stored, "%Y-%m-%d %H:%M:%S")
+            .map(|t| self.format_datetime(t.and_utc()))
+            .unwrap_or_else(|_| stored.to_string())
+    }
+
+    /// The user's calendar date of a UTC time
+    pub fn format_date(&self, time: DateTime<Utc>) -> String {
+        self.format(time, "%Y-%m-%d")
+    }
+
+    /// The last moment at or before `now` when the user's clock read `minute` minutes after midnight
+    pub fn last_occurrence(&self, minute: i64, now: DateTime<Utc>) -> DateTime<Utc> {
+        match self {
+            Self::Named(tz) => last_occurrence(tz, minute, now),
+            Self::Fixed(offset) => last_occurrence(offset, minute, now),
+        }
+    }
+}
+
+fn last_occurrence<Z: TimeZone>(zone: &Z, minute: i64, now: DateTime<Utc>) -> DateTime<Utc> {
+    let today = now.with_timezone(zone).date_naive();
+    let at_day = |day: chrono::NaiveDate| -> DateTime<Utc> {
+        let local: NaiveDateTime = day.and_time(chrono::NaiveTime::MIN) + Duration::minutes(minute);
+        // A time skipped by a DST change happens an hour later that day
+        zone.from_local_datetime(&local)
+            .earliest()
+            .or_else(|| {
+                zone.from_local_datetime(&(local + Duration::hours(1)))
+                    .earliest()
+            })
+            .map(|t| t.with_timezone(&Utc))
+            .unwrap_or_else(|| local.and_utc())
+    };
+    let scheduled = at_day(today);
+    if scheduled <= now {
+        scheduled
+    } else {
+        at_day(today - Duration::days(1))
+    }
+}
+
+/// Parse "+2", "-03", "+5:30" or "+0530"
+fn parse_offset(input: &str) -> Option<FixedOffset> {
+    let (sign, rest) = match input.chars().next()? {
+        '+' => (1, &input[1..]),
+        '-' => (-1, &input[1..]),
+        _ => return None,
+    };
+    let (hours, minutes) = match rest.split_once(':') {
+        Some((hours, minutes)) => (hours, minutes),
+        None if rest.len() == 4 && rest.is_ascii() => rest.split_at(2),
+        None => (rest, "0"),
+    };
+    let digits = |s: &str| !s.is_empty() && s.len() <= 2 && s.chars().all(|c| c.is_ascii_digit());
+    if !digits(hours) || !digits(minutes) {
+        return None;
+    }
+    let hours: i32 = hours.parse().ok()?;
+    let minutes: i32 = minutes.parse().ok()?;
+    if minutes >= 60 {
+        return None;
+    }
+    let total = hours * 60 + minutes;
+    if total > MAX_OFFSET_MINUTES {
+        return None;
+    }
+    FixedOffset::east_opt(sign * total * 60)
+}
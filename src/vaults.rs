@@ -0,0 +1,141 @@
+use log::{error, info, warn};
+use reqwest::Client;
+use sqlx::SqlitePool;
+use std::sync::LazyLock;
+use tokio::time::{Duration, interval};
+
+use crate::db;
+use crate::hyperliquid;
+
+const SYNC_INTERVAL_SECS: u64 = 1800;
+/// Stored equities older than this are shown with their age
+const STALE_AFTER_SECS: i64 = 2 * SYNC_INTERVAL_SECS as i64;
+
+/// Shared by the periodic sync and the immediate syncs /vaults starts.
+///
+/// First used when the sync starts, so a client that can't be built fails at startup.
+static CLIENT: LazyLock<Client> = LazyLock::new(|| {
+    Client::builder()
+        .timeout(Duration::from_secs(30))
+        .build()
+        .expect("Failed to create HTTP client")
+});
+
+/// Periodically refresh the vault equities of wallets that have them included
+pub async fn run_vault_sync(pool: SqlitePool) {
+    let client = &*CLIENT;
+    let mut interval = interval(Duration::from_secs(SYNC_INTERVAL_SECS));
+
+    info!("Vault equity sync started");
+
+    loop {
+        interval.tick().await;
+
+        let wallets = match db::get_vault_wallets(&pool).await {
+            Ok(wallets) => wallets,
+            Err(e) => {
+                error!("Failed to fetch vault wallets: {}", e);
+                continue;
+            }
+        };
+
+        for wallet_address in wallets {
+            // On failure the last known values stay in place and show their age
+            if let Err(e) = sync_wallet(client, &pool, &wallet_address).await {
+                warn!("Failed to sync vault equity for {}: {}", wallet_address, e);
+            }
+        }
+    }
+}
+
+/// Fetch and store the vault equities of a single wallet
+pub async fn sync_wallet(
+    client: &Client,
+    pool: &SqlitePool,
+    wallet_address: &str,
+) -> anyhow::Result<()> {
+    let equities: Vec<(String, f64)> =
+        hyperliquid::fetch_user_vault_equities(client, wallet_address)
+            .await?
+            .into_iter()
+            .filter_map(|v| Some((v.vault_address, v.equity.parse().ok()?)))
+            .filter(|(_, equity)| *equity > 0.0)
+            .collect();
+
+    db::replace_vault_equities(
+        pool,
+        wallet_address,
+        &equities,
+        chrono::Utc::now().timestamp_millis(),
+    )
+    .await
+}
+
+/// Refresh a wallet's vault equities in the background, e.g. right after they're included
+pub fn spawn_sync(pool: SqlitePool, wallet_address: String) {
+    tokio::spawn(async move {
+        if let Err(e) = sync_wallet(&CLIENT, &pool, &wallet_address).await {
+            warn!("Failed to sync vault equity for {}: {}", wallet_address, e);
+        }
+    });
+}
+
+/// Vault equity and summary line of a wallet whose vaults the user included, see `summarize`
+pub async fn included_equity(
+    pool: &SqlitePool,
+    user_id: i64,
+    wallet_address: &str,
+) -> Option<(f64, String)> {
+    match db::get_include_vaults(pool, user_id, wallet_address).await {
+        Ok(true) => match db::get_vault_equities(pool, wallet_address).await {
+            Ok(equities) => summarize(&equities),
+            Err(e) => {
+                error!(
+                    "Failed to load vault equities for {}: {}",
+                    wallet_address, e
+                );
+                None
+            }
+        },
+        Ok(false) => None,
+        Err(e) => {
+            error!(
+                "Failed to check vault equity setting for {}: {}",
+                wallet_address, e
+            );
+            None
+        }
+    }
+}
+
+/// Total vault equity of a wallet and its summary line, None when it has no vault deposits
+pub fn summarize(equities: &[db::VaultEquity]) -> Option<(f64, String)> {
+    if equities.is_empty() {
+        return None;
+    }
+
+    let total: f64 = equities.iter().map(|v| v.equity).sum();
+    let vaults = if equities.len() == 1 {
+        "1 vault".to_string()
+    } else {
+        format!("{} vaults", equities.len())
+    };
+
+    let oldest = equities.iter().map(|v| v.fetched_at).min().unwrap_or(0);
+    let age_secs = (chrono::Utc::now().timestamp_millis() - oldest) / 1000;
+    let age = if age_secs > STALE_AFTER_SECS {
+        format!(" <i>(as of {}h ago)</i>", age_secs / 3600)
+    } else {
+        String::new()
+    };
+
+    Some((
+        total,
+        format!(
+            "🏦 Vault equity: {} across {}{}",
+            hyperliquid::format_usd_compact(total),
+            vaults,
+            age
+        ),
+    ))
+}
@@ -0,0 +1,128 @@
+use serde::{Deserialize, Serialize};
+
+use crate::db;
+
+/// One wallet of an /export file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportedWallet {
+    pub address: String,
+    #[serde(default)]
+    pub note: Option<String>,
+    #[serde(default)]
+    pub muted: bool,
+}
+
+impl From<&db::TrackedWallet> for ExportedWallet {
+    fn from(wallet: &db::TrackedWallet) -> Self {
+        Self {
+            address: wallet.wallet_address.to_lowercase(),
+            note: wallet.note.clone(),
+            muted: wallet.muted,
+        }
+    }
+}
+
+pub fn to_json(wallets: &[ExportedWallet]) -> anyhow::Result<String> {
+    Ok(serde_json::to_string_pretty(wallets)?)
+}
+
+/// CSV with an `address,note,muted` header, notes quoted when they need it
+pub fn to_csv(wallets: &[ExportedWallet]) -> String {
+    let mut csv = String::from("address,note,muted\n");
+    for wallet in wallets {
+        csv.push_str(&format!(
+            "{},{},{}\n",
+            wallet.address,
+            csv_field(wallet.note.as_deref().unwrap_or("")),
+            wallet.muted
+        ));
+    }
+    csv
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Wallets from an /export file (JSON or CSV) or a pasted list of "address [note]" lines.
+///
+/// Addresses are returned as written, callers validate them.
+pub fn parse(contents: &str) -> anyhow::Result<Vec<ExportedWallet>> {
+    let contents = contents.trim_start_matches('\u{FEFF}').trim();
+    if contents.starts_with('[') {
+        return Ok(serde_json::from_str(contents)?);
+    }
+
+    let records = csv_records(contents);
+    let is_csv = records
+        .first()
+        .and_then(|header| header.first())
+        .is_some_and(|field| field.trim().eq_ignore_ascii_case("address"));
+    if is_csv {
+        return Ok(records
+            .into_iter()
+            .skip(1)
+            .filter(|record| record.first().is_some_and(|f| !f.trim().is_empty()))
+            .map(|record| ExportedWallet {
+                address: record[0].trim().to_string(),
+                note: record
+                    .get(1)
+                    .map(|note| note.trim().to_string())
+                    .filter(|note| !note.is_empty()),
+                muted: record
+                    .get(2)
+                    .is_some_and(|muted| muted.trim().eq_ignore_ascii_case("true")),
+            })
+            .collect());
+    }
+
+    Ok(contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let (address, note) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let note = note.trim();
+            ExportedWallet {
+                address: address.to_string(),
+                note: (!note.is_empty()).then(|| note.to_string()),
+                muted: false,
+            }
+        })
+        .collect())
+}
+
+/// Split CSV text into records, honouring quoted fields with commas, quotes and newlines
+fn csv_records(text: &str) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => record.push(std::mem::take(&mut field)),
+            '\n' if !in_quotes => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            '\r' if !in_quotes => {}
+            c => field.push(c),
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    records
+}
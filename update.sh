@@ -1,2 +1,2 @@
 git pull
-docker compose up -d --build
\ No newline at end of file
+GIT_HASH=$(git rev-parse --short HEAD) docker compose up -d --build
\ No newline at end of file